@@ -0,0 +1,8 @@
+#![no_main]
+
+use acme::{test_support::fuzz::check_round_trip, wire::problem::AcmeProblem};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    check_round_trip::<AcmeProblem>(data);
+});
@@ -5,8 +5,31 @@ macro_rules! context_client_request {
 
 pub mod account;
 pub mod account_context;
-pub mod client;
+pub mod account_pool;
+#[cfg(feature = "acme-dns")]
+pub mod acme_dns;
 pub mod authorization;
+pub mod caa;
+pub mod certified_key;
 pub mod challenge;
+pub mod client;
+pub mod diagnostics;
+#[cfg(feature = "dns-check")]
+pub mod dns_check;
 pub mod dns_identifier;
+pub mod dns_provider;
+#[cfg(feature = "http01-server")]
+pub mod http01_server;
+#[cfg(any(feature = "x509", feature = "x509-rcgen"))]
+pub mod issuance;
+pub mod new_order;
+pub mod observer;
 pub mod order;
+pub mod policy;
+#[cfg(feature = "rfc2136")]
+pub mod rfc2136;
+#[cfg(any(feature = "x509", feature = "x509-rcgen"))]
+pub mod scheduler;
+pub mod schema;
+pub mod solver;
+pub mod store;
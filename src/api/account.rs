@@ -1,22 +1,47 @@
-use std::sync::Arc;
+use std::{fmt::Debug, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
 
 use crate::{
     crypto::account_key::AccountKey,
-    error::AcmeResult,
+    error::{AcmeError, AcmeResult},
+    lets_encrypt,
     wire::{
         account::{AccountResource, AccountStatus},
+        authorization::NewAuthorizationResource,
+        challenge::ChallengeType,
         client::AcmeClient,
-        common::LocationResource,
+        common::{LocationResource, SignedResponse},
+        delegation::DelegationResource,
         identifier::AcmeIdentifier,
         order::NewOrderResource,
     },
 };
 
-use super::{account_context::AccountContext, order::Order};
+use super::{
+    account_context::{AccountContext, AccountHandle},
+    authorization::{Authorization, SavedAuthorization},
+    caa::{caa_records, CaaRecord},
+    new_order::NewOrderBuilder,
+    order::{Order, SavedOrder},
+    policy::IssuancePolicy,
+};
+
+#[cfg(any(feature = "x509", feature = "x509-rcgen"))]
+use super::{
+    solver::ChallengeSolver,
+    store::{CertificateBundle, CertificateStore},
+};
 
+/// Clones share the key, client and nonce pool, and may be moved to other
+/// tasks or threads; each holds its own copy of the account resource, which
+/// `refresh` updates.
+#[derive(Clone)]
 pub struct Account {
     context: Arc<AccountContext>,
     resource: AccountResource,
+    issuance_policy: IssuancePolicy,
 }
 
 impl Account {
@@ -33,13 +58,23 @@ impl Account {
         Ok(Self {
             context: Arc::new(context),
             resource,
+            issuance_policy: Default::default(),
         })
     }
 
+    pub(crate) fn context(&self) -> &Arc<AccountContext> {
+        &self.context
+    }
+
     pub fn client(&self) -> &AcmeClient {
         &self.context.client
     }
 
+    /// A handle for sending other signed requests as this account.
+    pub fn handle(&self) -> AccountHandle {
+        AccountHandle::new(self.context.clone())
+    }
+
     pub fn key(&self) -> &impl AccountKey {
         &self.context.account_key
     }
@@ -56,7 +91,101 @@ impl Account {
         self.resource.status
     }
 
+    /// The policy for issuance with this account, unless overridden by
+    /// `IssuanceOptions::policy`.
+    pub fn issuance_policy(&self) -> &IssuancePolicy {
+        &self.issuance_policy
+    }
+
+    pub fn set_issuance_policy(&mut self, policy: IssuancePolicy) {
+        self.issuance_policy = policy;
+    }
+
+    /// Returns everything needed to rebuild this account offline with
+    /// `Client::load_account`, including the private key.
+    pub fn credentials(&self) -> AcmeResult<AccountCredentials> {
+        let private_jwk = self
+            .context
+            .account_key
+            .private_jwk()
+            .map_err(AcmeError::CryptoError)?;
+        Ok(AccountCredentials {
+            directory_url: self.client().directory_url().map(str::to_string),
+            account_url: self.url().to_string(),
+            private_jwk: private_jwk.to_string(),
+        })
+    }
+
+    /// Fetches the current account resource from the server. An account
+    /// that was deactivated or revoked (which makes other requests fail with
+    /// `AcmeError::AccountUnusable`) returns that status rather than an
+    /// error, so that a long-running client can notice and re-register.
+    /// Servers may refuse even this request from such an account, in which
+    /// case it fails with their unauthorized problem.
+    pub async fn refresh(&mut self) -> AcmeResult<AccountStatus> {
+        match context_client_request!(self.context, get_account).await {
+            Ok(resource) => self.resource = resource,
+            Err(err) => match err.into_without_context() {
+                AcmeError::AccountUnusable(status) => self.resource.status = status,
+                err => return Err(err),
+            },
+        }
+        Ok(self.status())
+    }
+
+    /// CAA records that lock issuance for `domain` to this account (and
+    /// `validation_methods`, if any); see `Client::caa_records_for`.
+    pub fn caa_records_for(
+        &self,
+        domain: &str,
+        validation_methods: &[ChallengeType],
+    ) -> AcmeResult<Vec<CaaRecord>> {
+        caa_records(
+            &self.client().directory().meta.caa_identities,
+            domain,
+            Some(self.url()),
+            validation_methods,
+        )
+    }
+
+    /// The URLs of the delegations an Identifier Owner has configured for
+    /// this account (RFC 9115).
+    pub async fn delegations(&self) -> AcmeResult<Vec<String>> {
+        let delegations_url = self
+            .resource
+            .delegations
+            .as_deref()
+            .ok_or(AcmeError::MissingExpectedField("delegations"))?;
+        let list = context_client_request!(self.context, get_delegations, delegations_url).await?;
+        Ok(list.delegations)
+    }
+
+    /// Fetches a delegation configuration, whose CSR template a delegated
+    /// order must be finalized with.
+    pub async fn get_delegation(
+        &self,
+        delegation_url: impl AsRef<str>,
+    ) -> AcmeResult<DelegationResource> {
+        context_client_request!(self.context, get_delegation, delegation_url.as_ref()).await
+    }
+
+    /// POST-as-GETs `url` with this account, returning the response as
+    /// received; see `AcmeClient::post_as_get`.
+    pub async fn post_as_get(&self, url: impl AsRef<str>) -> AcmeResult<SignedResponse> {
+        context_client_request!(self.context, post_as_get, url.as_ref()).await
+    }
+
+    /// Starts building a new order, e.g.
+    /// `account.order().dns("example.com").dns("*.example.com").send().await`
+    pub fn order(&self) -> NewOrderBuilder<'_> {
+        NewOrderBuilder::new(self)
+    }
+
     pub async fn new_order(&self, new_order: &NewOrderResource) -> AcmeResult<Order> {
+        lets_encrypt::warn_on_test_domains(
+            &self.context.client.directory(),
+            &new_order.identifiers,
+        );
         let order = context_client_request!(self.context, new_order, new_order).await?;
         Order::from_resource(self.context.clone(), order)
     }
@@ -69,17 +198,132 @@ impl Account {
         self.new_order(new_order).await
     }
 
+    /// Orders a certificate for both `domain` and `*.domain`. The two
+    /// authorizations share a dns-01 TXT name, so both values must be
+    /// presented together, as `issuance::issue` does for such orders.
+    pub async fn new_wildcard_order(&self, domain: impl Into<String>) -> AcmeResult<Order> {
+        let domain = domain.into();
+        self.order()
+            .dns(format!("*.{}", domain))
+            .dns(domain)
+            .send()
+            .await
+    }
+
+    /// Pre-authorizes `identifier`, before ordering. With
+    /// `subdomain_auth_allowed` (if the server supports RFC 9444; see
+    /// `DirectoryMetadata::subdomain_auth_allowed`), the authorization also
+    /// covers orders for subdomains that name it as their ancestor domain;
+    /// see `NewOrderBuilder::dns_under`.
+    pub async fn new_authorization(
+        &self,
+        identifier: AcmeIdentifier,
+        subdomain_auth_allowed: bool,
+    ) -> AcmeResult<Authorization> {
+        let new_authorization = &NewAuthorizationResource {
+            identifier,
+            subdomain_auth_allowed,
+        };
+        let mut resource =
+            context_client_request!(self.context, new_authorization, new_authorization).await?;
+        let url = resource.take_location()?;
+        Ok(Authorization::from_resource(
+            self.context.clone(),
+            url,
+            resource,
+        ))
+    }
+
     pub async fn get_order(&self, order_url: impl AsRef<str>) -> AcmeResult<Order> {
-        let order = context_client_request!(self.context, get_resource, order_url.as_ref()).await?;
+        let order = context_client_request!(self.context, get_order, order_url.as_ref()).await?;
         Order::from_resource(self.context.clone(), order)
     }
 
+    /// Runs a complete issuance for `new_order`; see `issuance::issue`.
+    #[cfg(any(feature = "x509", feature = "x509-rcgen"))]
+    pub async fn issue(
+        &self,
+        new_order: &NewOrderResource,
+        solver: &(impl ChallengeSolver + ?Sized),
+    ) -> AcmeResult<CertificateBundle> {
+        super::issuance::issue(self, new_order, solver).await
+    }
+
+    /// Like `issue`, then verifies the chain; see `issuance::issue_verified`.
+    #[cfg(feature = "x509")]
+    pub async fn issue_verified(
+        &self,
+        new_order: &NewOrderResource,
+        solver: &(impl ChallengeSolver + ?Sized),
+    ) -> AcmeResult<(CertificateBundle, crate::x509::IssuedCertificate)> {
+        super::issuance::issue_verified(self, new_order, solver).await
+    }
+
+    /// Like `issue`, then saves the result to `store`.
+    #[cfg(any(feature = "x509", feature = "x509-rcgen"))]
+    pub async fn issue_and_store(
+        &self,
+        new_order: &NewOrderResource,
+        solver: &(impl ChallengeSolver + ?Sized),
+        store: &(impl CertificateStore + ?Sized),
+    ) -> AcmeResult<CertificateBundle> {
+        let bundle = self.issue(new_order, solver).await?;
+        store.save(&bundle).await.map_err(AcmeError::StoreError)?;
+        Ok(bundle)
+    }
+
+    /// Restores a checkpointed order and refreshes it from the server.
+    pub async fn resume_order(&self, saved: SavedOrder) -> AcmeResult<Order> {
+        let mut order = Order::from_saved(self, saved);
+        order.refresh().await?;
+        Ok(order)
+    }
+
+    /// Restores a checkpointed authorization and refreshes it from the
+    /// server.
+    pub async fn resume_authorization(
+        &self,
+        saved: SavedAuthorization,
+    ) -> AcmeResult<Authorization> {
+        let mut authorization = Authorization::from_saved(self, saved);
+        authorization.refresh().await?;
+        Ok(authorization)
+    }
+
+    /// Agrees to the server's current terms of service. Call this (after
+    /// review) when a request fails with `AcmeError::TermsOfServiceChanged`.
+    pub async fn agree_to_terms(&mut self) -> AcmeResult<()> {
+        self.resource = context_client_request!(self.context, agree_to_terms).await?;
+        Ok(())
+    }
+
     pub async fn deactivate(&mut self) -> AcmeResult<()> {
         self.resource = context_client_request!(self.context, account_deactivate).await?;
         Ok(())
     }
+
+    /// The account's contacts, as last returned by the server.
+    pub fn contacts(&self) -> Vec<Contact> {
+        self.resource
+            .contact
+            .iter()
+            .map(|uri| Contact::from_uri(uri))
+            .collect()
+    }
+
+    /// Replaces the account's contacts; an empty list removes them all.
+    pub async fn update_contacts(&mut self, contacts: Vec<Contact>) -> AcmeResult<()> {
+        let contact = contacts
+            .into_iter()
+            .map(Contact::validated_uri)
+            .collect::<AcmeResult<Vec<_>>>()?;
+        self.resource =
+            context_client_request!(self.context, update_account_contacts, &contact).await?;
+        Ok(())
+    }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Contact {
     Email(String),
     Uri(String),
@@ -93,4 +337,271 @@ impl Contact {
             Self::Uri(uri) => uri,
         }
     }
+
+    /// Like `uri`, but rejects mailto URIs that servers must not accept:
+    /// those with hfields (e.g. "?subject=") or more than one address.
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-7.3
+    pub(crate) fn validated_uri(self) -> AcmeResult<String> {
+        let uri = self.uri();
+        if let Some(addr) = uri.strip_prefix("mailto:") {
+            if addr.is_empty() || addr.contains(['?', ',']) || !addr.contains('@') {
+                return Err(AcmeError::InvalidContact(uri));
+            }
+        }
+        Ok(uri)
+    }
+
+    fn from_uri(uri: &str) -> Self {
+        match uri.strip_prefix("mailto:") {
+            Some(email) => Self::Email(email.to_string()),
+            None => Self::Uri(uri.to_string()),
+        }
+    }
+}
+
+/// Serializable account session; see `Account::credentials`.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountCredentials {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub directory_url: Option<String>,
+    pub account_url: String,
+    pub private_jwk: String,
+}
+
+impl Debug for AccountCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AccountCredentials")
+            .field("directory_url", &self.directory_url)
+            .field("account_url", &self.account_url)
+            .field("private_jwk", &"<redacted>")
+            .finish()
+    }
+}
+
+impl Drop for AccountCredentials {
+    fn drop(&mut self) {
+        self.private_jwk.zeroize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        api::challenge::Challenge, test_support::mock_server::MockAcmeServer,
+        wire::problem::AcmeProblemType,
+    };
+
+    use super::*;
+
+    #[async_std::test]
+    async fn new_wildcard_order() {
+        let server = MockAcmeServer::new();
+        let client = server.client().await.unwrap();
+        let account = client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+        let order = account.new_wildcard_order("example.com").await.unwrap();
+        assert_eq!(
+            order.resource().identifiers,
+            [
+                AcmeIdentifier::dns("*.example.com"),
+                AcmeIdentifier::dns("example.com")
+            ]
+        );
+
+        let mut authorizations = Vec::new();
+        for authorization in order.get_authorizations() {
+            authorizations.push(authorization.await.unwrap());
+        }
+        let wildcards: Vec<_> = authorizations
+            .iter()
+            .map(|authorization| authorization.resource().wildcard)
+            .collect();
+        assert_eq!(wildcards, [true, false]);
+        let record_names: Vec<_> = authorizations
+            .iter()
+            .map(|authorization| {
+                Challenge::dns01_record_name(authorization.dns_identifier().unwrap())
+            })
+            .collect();
+        assert_eq!(record_names, ["_acme-challenge.example.com"; 2]);
+    }
+
+    #[async_std::test]
+    async fn handle() {
+        let server = MockAcmeServer::new();
+        let client = server.client().await.unwrap();
+        let account = client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+        let handle = account.handle();
+        assert_eq!(handle.account_url(), account.url());
+
+        let resp = handle.signed_get(account.url()).await.unwrap();
+        let resource: AccountResource = resp.json().unwrap();
+        assert_eq!(resource.contact, account.resource().contact);
+
+        let update = serde_json::json!({"contact": ["mailto:ops@example.com"]});
+        let resp = handle.signed_post(account.url(), &update).await.unwrap();
+        let resource: AccountResource = resp.json().unwrap();
+        assert_eq!(resource.contact, ["mailto:ops@example.com"]);
+    }
+
+    #[async_std::test]
+    async fn unusable_account() {
+        let server = MockAcmeServer::new();
+        let client = server.client().await.unwrap();
+        let mut account = client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+        let other = client
+            .register_account("other@example.com".to_string(), true)
+            .await
+            .unwrap();
+
+        // Refused for another reason
+        let order = account.new_dns_order("example.com").await.unwrap();
+        match other
+            .get_order(order.url())
+            .await
+            .map_err(AcmeError::into_without_context)
+        {
+            Err(AcmeError::AcmeProblem(_)) => (),
+            res => panic!("expected unauthorized problem, got {:?}", res.err()),
+        }
+
+        server.revoke_account(account.url());
+        match account
+            .new_dns_order("example.com")
+            .await
+            .map_err(AcmeError::into_without_context)
+        {
+            Err(AcmeError::AccountUnusable(AccountStatus::Revoked)) => (),
+            res => panic!("expected unusable account, got {:?}", res.err()),
+        }
+        assert_eq!(account.refresh().await.unwrap(), AccountStatus::Revoked);
+
+        let mut other = other;
+        other.deactivate().await.unwrap();
+        assert_eq!(other.status(), AccountStatus::Deactivated);
+        match other
+            .new_dns_order("example.com")
+            .await
+            .map_err(AcmeError::into_without_context)
+        {
+            Err(AcmeError::AccountUnusable(AccountStatus::Deactivated)) => (),
+            res => panic!("expected unusable account, got {:?}", res.err()),
+        }
+
+        // A server that won't say leaves the unauthorized problem
+        server.set_refuse_unusable_account_fetch(true);
+        match other
+            .new_dns_order("example.com")
+            .await
+            .map_err(AcmeError::into_without_context)
+        {
+            Err(AcmeError::AcmeProblem(problem))
+                if problem.has_type(AcmeProblemType::Unauthorized) => {}
+            res => panic!("expected unauthorized problem, got {:?}", res.err()),
+        }
+        assert!(other.refresh().await.is_err());
+    }
+
+    #[async_std::test]
+    async fn new_authorization() {
+        let server = MockAcmeServer::new();
+        server.set_subdomain_auth_allowed(true);
+        let client = server.client().await.unwrap();
+        let account = client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+
+        let authorization = account
+            .new_authorization(AcmeIdentifier::dns("example.com"), true)
+            .await
+            .unwrap();
+        assert!(authorization.resource().subdomain_auth_allowed);
+
+        let order = account
+            .order()
+            .dns_under("www.example.com", "example.com")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            order.authorization_urls().collect::<Vec<_>>(),
+            [authorization.url()]
+        );
+    }
+
+    #[async_std::test]
+    async fn update_contacts() {
+        let server = MockAcmeServer::new();
+        let client = server.client().await.unwrap();
+        let mut account = client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+        assert_eq!(
+            account.contacts(),
+            [Contact::Email("admin@example.com".to_string())]
+        );
+
+        let contacts = vec![
+            Contact::Email("ops@example.com".to_string()),
+            Contact::Uri("tel:+15555550100".to_string()),
+        ];
+        account.update_contacts(contacts.clone()).await.unwrap();
+        assert_eq!(account.contacts(), contacts);
+        account.update_contacts(vec![]).await.unwrap();
+        assert!(account.contacts().is_empty());
+
+        for invalid in [
+            "admin@example.com?subject=hi",
+            "a@example.com,b@example.com",
+            "mailto:",
+        ] {
+            let err = account
+                .update_contacts(vec![Contact::Email(invalid.to_string())])
+                .await
+                .unwrap_err();
+            assert!(matches!(err, AcmeError::InvalidContact(_)), "{:?}", err);
+        }
+    }
+
+    #[async_std::test]
+    async fn agree_to_changed_terms() {
+        let server = MockAcmeServer::new();
+        let client = server.client().await.unwrap();
+        let mut account = client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+
+        server.change_terms_of_service("https://acme.test/terms/v2");
+        match account
+            .new_dns_order("example.com")
+            .await
+            .map_err(AcmeError::into_without_context)
+        {
+            Err(AcmeError::TermsOfServiceChanged {
+                terms_url,
+                instance_url,
+                ..
+            }) => {
+                assert_eq!(terms_url, "https://acme.test/terms/v2");
+                assert_eq!(instance_url.unwrap(), "https://acme.test/agreement");
+            }
+            res => panic!("expected terms of service error, got {:?}", res.err()),
+        }
+
+        account.agree_to_terms().await.unwrap();
+        assert_eq!(account.resource().terms_of_service_agreed, Some(true));
+        account.new_dns_order("example.com").await.unwrap();
+    }
 }
@@ -1,7 +1,50 @@
-use crate::{crypto::account_key::AccountKey, wire::client::AcmeClient};
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::{
+    crypto::account_key::AccountKey,
+    error::AcmeResult,
+    wire::{client::AcmeClient, common::SignedResponse},
+};
 
 pub(crate) struct AccountContext {
     pub client: AcmeClient,
     pub account_key: Box<dyn AccountKey>,
     pub account_url: String,
 }
+
+/// Signs requests as an account, for resource wrappers outside this crate,
+/// e.g. for CA-specific endpoints. Only `Account::handle` makes one; clones
+/// share the account's key, client and nonce pool.
+#[derive(Clone)]
+pub struct AccountHandle(Arc<AccountContext>);
+
+impl AccountHandle {
+    pub(crate) fn new(context: Arc<AccountContext>) -> Self {
+        Self(context)
+    }
+
+    pub fn client(&self) -> &AcmeClient {
+        &self.0.client
+    }
+
+    pub fn account_url(&self) -> &str {
+        &self.0.account_url
+    }
+
+    /// POST-as-GETs `url`; see `AcmeClient::post_as_get`.
+    pub async fn signed_get(&self, url: impl AsRef<str>) -> AcmeResult<SignedResponse> {
+        context_client_request!(self.0, post_as_get, url.as_ref()).await
+    }
+
+    /// POSTs `payload`, signed with the account key, to `url`; see
+    /// `AcmeClient::post`.
+    pub async fn signed_post(
+        &self,
+        url: impl AsRef<str>,
+        payload: &impl Serialize,
+    ) -> AcmeResult<SignedResponse> {
+        context_client_request!(self.0, post, url.as_ref(), payload).await
+    }
+}
@@ -0,0 +1,162 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    error::{AcmeError, AcmeResult},
+    wire::identifier::AcmeIdentifier,
+};
+
+use super::account::Account;
+
+/// How an `AccountPool` picks the account for a set of identifiers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccountSelection {
+    /// Each account in turn.
+    RoundRobin,
+
+    /// By a hash of the identifiers, so that the same names always go to the
+    /// same account (keeping e.g. authorization reuse and duplicate
+    /// certificate limits per account).
+    HashIdentifiers,
+}
+
+/// Several accounts that share the load of a large deployment. Accounts
+/// registered or loaded through one `Client` also share its nonce pool.
+pub struct AccountPool {
+    accounts: Vec<Account>,
+    selection: AccountSelection,
+    next: AtomicUsize,
+}
+
+impl AccountPool {
+    pub fn new(accounts: Vec<Account>, selection: AccountSelection) -> AcmeResult<Self> {
+        if accounts.is_empty() {
+            return Err(AcmeError::InvalidState(
+                "account pool needs at least one account".to_string(),
+            ));
+        }
+        Ok(Self {
+            accounts,
+            selection,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn accounts(&self) -> &[Account] {
+        &self.accounts
+    }
+
+    pub fn selection(&self) -> AccountSelection {
+        self.selection
+    }
+
+    /// The account to order a certificate for `identifiers` with.
+    pub fn select(&self, identifiers: &[AcmeIdentifier]) -> &Account {
+        let idx = match self.selection {
+            AccountSelection::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed),
+            AccountSelection::HashIdentifiers => self.hash_index(identifiers),
+        };
+        &self.accounts[idx % self.accounts.len()]
+    }
+
+    /// The accounts that `select` could pick for `identifiers`, preferred
+    /// first, without advancing a round robin; see `selected`.
+    #[cfg(any(feature = "x509", feature = "x509-rcgen"))]
+    pub(crate) fn candidates(&self, identifiers: &[AcmeIdentifier]) -> Vec<(usize, &Account)> {
+        match self.selection {
+            AccountSelection::RoundRobin => {
+                let start = self.next.load(Ordering::Relaxed);
+                (0..self.accounts.len())
+                    .map(|offset| (start + offset) % self.accounts.len())
+                    .map(|idx| (idx, &self.accounts[idx]))
+                    .collect()
+            }
+            AccountSelection::HashIdentifiers => {
+                let idx = self.hash_index(identifiers) % self.accounts.len();
+                vec![(idx, &self.accounts[idx])]
+            }
+        }
+    }
+
+    /// Records that the candidate at `idx` was used, continuing a round robin
+    /// after it.
+    #[cfg(any(feature = "x509", feature = "x509-rcgen"))]
+    pub(crate) fn selected(&self, idx: usize) {
+        if self.selection == AccountSelection::RoundRobin {
+            self.next.store(idx + 1, Ordering::Relaxed);
+        }
+    }
+
+    fn hash_index(&self, identifiers: &[AcmeIdentifier]) -> usize {
+        let mut values: Vec<_> = identifiers
+            .iter()
+            .map(|identifier| identifier.value.to_ascii_lowercase())
+            .collect();
+        values.sort();
+        values.dedup();
+        // A stable hash, so that the mapping survives restarts
+        let digest = Sha256::digest(values.join("\n").as_bytes());
+        let mut prefix = [0; 8];
+        prefix.copy_from_slice(&digest[..8]);
+        u64::from_be_bytes(prefix) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::mock_server::{Endpoint, MockAcmeServer};
+
+    use super::*;
+
+    async fn pool(server: &MockAcmeServer, selection: AccountSelection) -> AccountPool {
+        let client = server.client().await.unwrap();
+        let mut accounts = Vec::new();
+        for idx in 0..3 {
+            let email = format!("admin{}@example.com", idx);
+            accounts.push(client.register_account(email, true).await.unwrap());
+        }
+        AccountPool::new(accounts, selection).unwrap()
+    }
+
+    #[async_std::test]
+    async fn round_robin_shares_nonces() {
+        let server = MockAcmeServer::new();
+        let pool = pool(&server, AccountSelection::RoundRobin).await;
+        let identifiers = [AcmeIdentifier::dns("example.com")];
+        let urls: Vec<_> = (0..4)
+            .map(|_| pool.select(&identifiers).url().to_string())
+            .collect();
+        assert_eq!(urls[0], pool.accounts()[0].url());
+        assert_eq!(urls[2], pool.accounts()[2].url());
+        assert_eq!(urls[3], urls[0]);
+
+        for account in pool.accounts() {
+            account.new_dns_order("example.com").await.unwrap();
+        }
+        // Every request's response carried a nonce for the next
+        assert_eq!(server.request_count(Endpoint::NewNonce), 1);
+    }
+
+    #[async_std::test]
+    async fn hash_identifiers_is_stable() {
+        let server = MockAcmeServer::new();
+        let pool = pool(&server, AccountSelection::HashIdentifiers).await;
+        let identifiers = [
+            AcmeIdentifier::dns("www.example.com"),
+            AcmeIdentifier::dns("example.com"),
+        ];
+        let reordered = [
+            AcmeIdentifier::dns("EXAMPLE.com"),
+            AcmeIdentifier::dns("www.example.com"),
+        ];
+        assert_eq!(
+            pool.select(&identifiers).url(),
+            pool.select(&reordered).url()
+        );
+
+        AccountPool::new(Vec::new(), AccountSelection::RoundRobin)
+            .err()
+            .unwrap();
+    }
+}
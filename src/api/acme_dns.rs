@@ -0,0 +1,201 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::dns_provider::DnsProvider;
+use crate::transport::{Body, HttpClient, Request, Response};
+
+/// Credentials for one acme-dns subdomain, as returned by `/register`.
+/// Persist these; acme-dns can't recover them.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AcmeDnsAccount {
+    pub username: String,
+    pub password: String,
+    /// The name to point the `_acme-challenge` CNAME at.
+    pub fulldomain: String,
+    pub subdomain: String,
+    #[serde(default)]
+    pub allowfrom: Vec<String>,
+}
+
+impl std::fmt::Debug for AcmeDnsAccount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AcmeDnsAccount")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .field("fulldomain", &self.fulldomain)
+            .field("subdomain", &self.subdomain)
+            .field("allowfrom", &self.allowfrom)
+            .finish()
+    }
+}
+
+/// Writes TXT records through an acme-dns server's HTTP API.
+/// https://github.com/joohoi/acme-dns
+///
+/// Each domain needs its own acme-dns account, with
+/// `_acme-challenge.<domain>` delegated by CNAME to the account's
+/// `fulldomain`. acme-dns keeps the two most recent values per account
+/// (enough for a domain and its wildcard) and can't delete them, so
+/// `delete_txt` does nothing.
+#[derive(Clone)]
+pub struct AcmeDnsProvider {
+    http: Arc<dyn HttpClient>,
+    server_url: String,
+    accounts: HashMap<String, AcmeDnsAccount>,
+}
+
+impl AcmeDnsProvider {
+    pub fn new(http: impl Into<Arc<dyn HttpClient>>, server_url: impl Into<String>) -> Self {
+        Self {
+            http: http.into(),
+            server_url: server_url.into().trim_end_matches('/').to_string(),
+            accounts: HashMap::new(),
+        }
+    }
+
+    /// Uses `account` for `domain` (e.g. "example.com") and its wildcard.
+    pub fn with_account(mut self, domain: impl Into<String>, account: AcmeDnsAccount) -> Self {
+        self.accounts.insert(domain.into(), account);
+        self
+    }
+
+    /// Creates a new acme-dns account; `allow_from` optionally restricts
+    /// updates to the given CIDR ranges.
+    pub async fn register(&self, allow_from: Vec<String>) -> anyhow::Result<AcmeDnsAccount> {
+        let mut req = Request::post(format!("{}/register", self.server_url).as_str());
+        if !allow_from.is_empty() {
+            let body = Body::from_json(&serde_json::json!({ "allowfrom": allow_from }))
+                .map_err(|err| err.into_inner())?;
+            req.set_body(body);
+        }
+        let resp = self.send(req).await?;
+        let mut resp = check_status(resp, "register").await?;
+        resp.body_json().await.map_err(|err| err.into_inner())
+    }
+
+    /// Finds the account for a TXT record name, which is either the
+    /// `_acme-challenge` name of a configured domain or, when the solver
+    /// followed the CNAME, an account's `fulldomain`.
+    fn account_for(&self, name: &str) -> anyhow::Result<&AcmeDnsAccount> {
+        let name = name.trim_end_matches('.');
+        let domain = name.strip_prefix("_acme-challenge.").unwrap_or(name);
+        self.accounts
+            .get(domain)
+            .or_else(|| {
+                self.accounts
+                    .values()
+                    .find(|account| account.fulldomain.eq_ignore_ascii_case(name))
+            })
+            .ok_or_else(|| anyhow!("no acme-dns account for {}", name))
+    }
+
+    async fn send(&self, req: Request) -> anyhow::Result<Response> {
+        self.http.send(req).await.map_err(|err| err.into_inner())
+    }
+}
+
+#[async_trait]
+impl DnsProvider for AcmeDnsProvider {
+    async fn find_zone(&self, name: &str) -> anyhow::Result<String> {
+        Ok(self.account_for(name)?.fulldomain.clone())
+    }
+
+    async fn create_txt(&self, _zone: &str, name: &str, value: &str) -> anyhow::Result<()> {
+        let account = self.account_for(name)?;
+        let mut req = Request::post(format!("{}/update", self.server_url).as_str());
+        req.insert_header("X-Api-User", account.username.as_str());
+        req.insert_header("X-Api-Key", account.password.as_str());
+        let body = Body::from_json(&serde_json::json!({
+            "subdomain": account.subdomain,
+            "txt": value,
+        }))
+        .map_err(|err| err.into_inner())?;
+        req.set_body(body);
+        let resp = self.send(req).await?;
+        check_status(resp, "update").await?;
+        Ok(())
+    }
+
+    async fn delete_txt(&self, _zone: &str, name: &str, _value: &str) -> anyhow::Result<()> {
+        log::debug!("acme-dns can't delete TXT records; leaving {}", name);
+        Ok(())
+    }
+}
+
+async fn check_status(mut resp: Response, action: &str) -> anyhow::Result<Response> {
+    if !resp.status().is_success() {
+        let body = resp.body_string().await.unwrap_or_default();
+        bail!("acme-dns {} failed: [{}] {}", action, resp.status(), body);
+    }
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use crate::transport::Error;
+
+    use super::*;
+
+    /// Accepts registrations and updates, remembering the updated values.
+    #[derive(Debug, Default)]
+    struct FakeAcmeDns {
+        updates: Mutex<Vec<serde_json::Value>>,
+    }
+
+    #[async_trait]
+    impl HttpClient for FakeAcmeDns {
+        async fn send(&self, mut req: Request) -> Result<Response, Error> {
+            let mut resp = Response::new(200);
+            match req.url().path() {
+                "/register" => resp.set_body(serde_json::json!({
+                    "username": "user",
+                    "password": "pass",
+                    "fulldomain": "d420c923.auth.example.org",
+                    "subdomain": "d420c923",
+                    "allowfrom": [],
+                })),
+                "/update" => {
+                    if req.header("X-Api-Key").map(|v| v.as_str()) != Some("pass") {
+                        return Ok(Response::new(401));
+                    }
+                    let body: serde_json::Value = req.body_json().await?;
+                    resp.set_body(serde_json::json!({ "txt": body["txt"] }));
+                    self.updates.lock().unwrap().push(body);
+                }
+                _ => return Ok(Response::new(404)),
+            }
+            Ok(resp)
+        }
+    }
+
+    #[async_std::test]
+    async fn register_and_update() {
+        let fake = Arc::new(FakeAcmeDns::default());
+        let provider = AcmeDnsProvider::new(
+            fake.clone() as Arc<dyn HttpClient>,
+            "https://auth.example.org/",
+        );
+        let account = provider.register(vec![]).await.unwrap();
+        assert_eq!(account.fulldomain, "d420c923.auth.example.org");
+
+        let provider = provider.with_account("example.com", account);
+        for name in ["_acme-challenge.example.com", "d420c923.auth.example.org"] {
+            let zone = provider.find_zone(name).await.unwrap();
+            provider.create_txt(&zone, name, "value").await.unwrap();
+        }
+        assert!(provider
+            .find_zone("_acme-challenge.example.net")
+            .await
+            .is_err());
+
+        let updates = fake.updates.lock().unwrap();
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[0]["subdomain"], "d420c923");
+        assert_eq!(updates[0]["txt"], "value");
+    }
+}
@@ -1,8 +1,12 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    error::AcmeResult,
-    wire::challenge::ChallengeResource,
+    cancel::CancellationToken,
+    error::{AcmeError, AcmeOp, AcmeResult},
+    wire::challenge::{ChallengeResource, ChallengeType},
     wire::{
         authorization::{AuthorizationResource, AuthorizationStatus},
         common::ResourceStatus,
@@ -10,8 +14,18 @@ use crate::{
     },
 };
 
-use super::{account_context::AccountContext, challenge::Challenge, dns_identifier::DnsIdentifier};
+use super::{
+    account::Account,
+    account_context::AccountContext,
+    challenge::{Challenge, ChallengeState},
+    dns_identifier::DnsIdentifier,
+    policy::IssuancePolicy,
+    solver::{ChallengeParams, ChallengeSolver},
+};
 
+/// Like `Account`, clones share the account and each hold their own copy of
+/// the authorization resource.
+#[derive(Clone)]
 pub struct Authorization {
     context: Arc<AccountContext>,
     resource: AuthorizationResource,
@@ -22,17 +36,78 @@ pub struct Authorization {
 
 impl Authorization {
     pub(crate) async fn get(context: Arc<AccountContext>, url: &str) -> AcmeResult<Self> {
-        let mut resource = context_client_request!(context, get_authorization, url).await?;
+        let resource = context_client_request!(context, get_authorization, url).await?;
+        Ok(Self::from_resource(context, url.to_string(), resource))
+    }
+
+    pub(crate) fn from_resource(
+        context: Arc<AccountContext>,
+        url: String,
+        mut resource: AuthorizationResource,
+    ) -> Self {
         let dns_identifier =
             DnsIdentifier::from_acme_identifier(&resource.identifier, resource.wildcard);
-        let challenges = (&mut resource.challenges).drain(..).map(Arc::new).collect();
-        Ok(Self {
+        let challenges = resource.challenges.drain(..).map(Arc::new).collect();
+        Self {
             context,
             resource,
-            url: url.to_string(),
+            url,
             dns_identifier,
             challenges,
-        })
+        }
+    }
+
+    /// Restores an authorization checkpointed with `save` without contacting
+    /// the server.
+    pub fn from_saved(account: &Account, saved: SavedAuthorization) -> Self {
+        Self::from_resource(account.context().clone(), saved.url, saved.resource)
+    }
+
+    /// Returns a serializable checkpoint of this authorization.
+    pub fn save(&self) -> SavedAuthorization {
+        let mut resource = self.resource.clone();
+        resource.challenges = self
+            .challenges
+            .iter()
+            .map(|challenge| challenge.as_ref().clone())
+            .collect();
+        SavedAuthorization {
+            url: self.url.clone(),
+            resource,
+        }
+    }
+
+    pub async fn refresh(&mut self) -> AcmeResult<AuthorizationStatus> {
+        let resource = context_client_request!(self.context, get_authorization, &self.url).await?;
+        *self = Self::from_resource(self.context.clone(), self.url.clone(), resource);
+        Ok(self.status())
+    }
+
+    /// Deactivates this authorization, e.g. to give up on a pending one.
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-7.5.2
+    pub async fn deactivate(&mut self) -> AcmeResult<AuthorizationStatus> {
+        let resource =
+            context_client_request!(self.context, deactivate_authorization, &self.url).await?;
+        *self = Self::from_resource(self.context.clone(), self.url.clone(), resource);
+        Ok(self.status())
+    }
+
+    pub async fn status_changed(&mut self) -> AcmeResult<AuthorizationStatus> {
+        self.status_changed_cancellable(&CancellationToken::new())
+            .await
+    }
+
+    /// Like `status_changed`, giving up with `AcmeError::Cancelled` once
+    /// `cancel` is cancelled.
+    pub async fn status_changed_cancellable(
+        &mut self,
+        cancel: &CancellationToken,
+    ) -> AcmeResult<AuthorizationStatus> {
+        let status = self.status();
+        while self.refresh().await? == status {
+            cancel.run(self.context.client.poll_sleep()).await?;
+        }
+        Ok(self.status())
     }
 
     pub fn resource(&self) -> &AuthorizationResource {
@@ -51,6 +126,16 @@ impl Authorization {
         self.status().as_result()
     }
 
+    /// When the authorization stops being usable; required once it is
+    /// valid.
+    pub fn expires_at(&self) -> Option<DateTime<FixedOffset>> {
+        self.resource.expires
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at().is_some_and(|expires| expires <= now)
+    }
+
     pub fn identifier(&self) -> &AcmeIdentifier {
         &self.resource.identifier
     }
@@ -59,19 +144,156 @@ impl Authorization {
         self.dns_identifier.as_ref()
     }
 
-    pub fn challenges(&self) -> impl Iterator + '_ {
+    pub fn challenges(&self) -> impl Iterator<Item = Challenge> + '_ {
         self.challenges
             .iter()
             .map(|resource| Challenge::new(self.context.clone(), resource.clone()))
     }
 
-    pub fn find_challenge_type(&self, challenge_type: &str) -> Option<Challenge> {
+    pub fn find_challenge_type(&self, challenge_type: &ChallengeType) -> Option<Challenge> {
         self.challenges.iter().find_map(|resource| {
-            if resource.type_ == challenge_type {
+            if resource.type_ == *challenge_type {
                 Some(Challenge::new(self.context.clone(), resource.clone()))
             } else {
                 None
             }
         })
     }
+
+    /// Finds the first challenge that `solver` supports, in the solver's order
+    /// of preference.
+    pub fn find_solvable_challenge(
+        &self,
+        solver: &(impl ChallengeSolver + ?Sized),
+    ) -> Option<Challenge> {
+        self.find_challenge_in(solver.supported_types())
+    }
+
+    /// The offered challenge of the first of `supported` types, ordered by
+    /// `policy`'s preferred challenge types, or a `NoSupportedChallenge`
+    /// error listing the offered types.
+    pub fn best_challenge(
+        &self,
+        supported: &[ChallengeType],
+        policy: &IssuancePolicy,
+    ) -> AcmeResult<Challenge> {
+        self.find_challenge_in(policy.challenge_preference(supported))
+            .ok_or_else(|| AcmeError::NoSupportedChallenge {
+                identifier: self.identifier().value.clone(),
+                offered: self
+                    .challenges
+                    .iter()
+                    .map(|resource| resource.type_.clone())
+                    .collect(),
+            })
+    }
+
+    fn find_challenge_in(&self, challenge_types: Vec<ChallengeType>) -> Option<Challenge> {
+        challenge_types
+            .into_iter()
+            .find_map(|challenge_type| self.find_challenge_type(&challenge_type))
+    }
+
+    /// Solves this authorization with `solver`, if it is pending, and waits
+    /// for the server to validate it. Challenge responses are always cleaned
+    /// up.
+    pub async fn solve(&mut self, solver: &(impl ChallengeSolver + ?Sized)) -> AcmeResult<()> {
+        if self.status() != AuthorizationStatus::Pending {
+            self.status_result()?;
+            return Ok(());
+        }
+
+        let (challenge, params) = self.solvable_challenge(solver, &IssuancePolicy::default())?;
+
+        solver
+            .present(&params)
+            .await
+            .map_err(AcmeError::SolverError)?;
+        let res = match solver.wait_until_ready(&params).await {
+            Ok(()) => {
+                self.respond_and_wait(challenge, None, &CancellationToken::new())
+                    .await
+            }
+            Err(err) => Err(AcmeError::SolverError(err)),
+        };
+        if let Err(err) = solver.cleanup(&params).await {
+            log::warn!("challenge cleanup for {} failed: {}", params.domain, err);
+        }
+        res
+    }
+
+    /// `solve`, returning the authorization, e.g. for solving each of an
+    /// order's authorizations in a task of its own.
+    pub async fn solved(mut self, solver: &(impl ChallengeSolver + ?Sized)) -> AcmeResult<Self> {
+        self.solve(solver).await?;
+        Ok(self)
+    }
+
+    /// Picks the challenge `solver` will present, preferring types as
+    /// `policy` says, with its parameters.
+    pub(crate) fn solvable_challenge(
+        &self,
+        solver: &(impl ChallengeSolver + ?Sized),
+        policy: &IssuancePolicy,
+    ) -> AcmeResult<(Challenge, ChallengeParams)> {
+        let challenge = self.best_challenge(&solver.supported_types(), policy)?;
+        let params = self.challenge_params(&challenge)?;
+        Ok((challenge, params))
+    }
+
+    /// Responds to a challenge whose response is in place and waits until
+    /// the authorization is no longer pending, for at most `max_wait` and
+    /// until `cancel` is cancelled.
+    pub(crate) async fn respond_and_wait(
+        &mut self,
+        mut challenge: Challenge,
+        max_wait: Option<Duration>,
+        cancel: &CancellationToken,
+    ) -> AcmeResult<()> {
+        cancel.check()?;
+        if let ChallengeState::Pending(pending) = challenge.state() {
+            pending.respond().await?;
+        }
+        let clock = self.context.client.clock().clone();
+        let deadline = max_wait
+            .and_then(|wait| chrono::Duration::from_std(wait).ok())
+            .and_then(|wait| clock.now().checked_add_signed(wait));
+        while self.refresh().await? == AuthorizationStatus::Pending {
+            if deadline.is_some_and(|deadline| clock.now() >= deadline) {
+                return Err(AcmeError::Timeout {
+                    operation: AcmeOp::Validation,
+                });
+            }
+            cancel.run(self.context.client.poll_sleep()).await?;
+        }
+        if self.status() == AuthorizationStatus::Invalid {
+            // Prefer the specific validation error over a generic status error
+            if let Some(problem) = self
+                .challenges()
+                .find_map(|challenge| challenge.resource().error.clone())
+            {
+                return Err(AcmeError::from_problem(problem));
+            }
+        }
+        self.status_result()?;
+        Ok(())
+    }
+
+    pub fn challenge_params(&self, challenge: &Challenge) -> AcmeResult<ChallengeParams> {
+        let token = challenge.resource().validated_token()?;
+        Ok(ChallengeParams {
+            domain: self.identifier().value.clone(),
+            wildcard: self.resource.wildcard,
+            challenge_type: challenge.challenge_type().clone(),
+            token: token.to_string(),
+            key_authorization: challenge.key_authorization()?,
+        })
+    }
+}
+
+/// A serializable checkpoint of an `Authorization`; see `Authorization::save`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SavedAuthorization {
+    pub url: String,
+    pub resource: AuthorizationResource,
 }
@@ -0,0 +1,107 @@
+//! CAA records (RFC 8659) that restrict issuance for a domain to one CA, and
+//! optionally one ACME account and set of validation methods (RFC 8657).
+
+use std::fmt;
+
+use crate::{
+    error::{AcmeError, AcmeResult},
+    wire::challenge::ChallengeType,
+};
+
+use super::dns_identifier::IdentifierValidator;
+
+/// A CAA resource record; its `Display` form is a zone file line, e.g.
+/// `example.com. CAA 0 issue "letsencrypt.org; accounturi=https://..."`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CaaRecord {
+    /// The owner name, in A-label form without a trailing dot.
+    pub name: String,
+
+    pub flags: u8,
+
+    /// "issue" here; it also governs wildcard certificates unless an
+    /// "issuewild" record is present (RFC 8659 section 4.3).
+    pub tag: String,
+
+    pub value: String,
+}
+
+impl fmt::Display for CaaRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}. CAA {} {} \"{}\"",
+            self.name, self.flags, self.tag, self.value
+        )
+    }
+}
+
+/// One "issue" record per CAA identity, each limited to `account_url` and
+/// `validation_methods` if given. A domain's records apply to its subdomains
+/// too, unless they have CAA records of their own.
+pub fn caa_records(
+    caa_identities: &[String],
+    domain: &str,
+    account_url: Option<&str>,
+    validation_methods: &[ChallengeType],
+) -> AcmeResult<Vec<CaaRecord>> {
+    if caa_identities.is_empty() {
+        return Err(AcmeError::InvalidState(
+            "the directory lists no CAA identities".to_string(),
+        ));
+    }
+    let name = IdentifierValidator::default().normalize_dns_name(domain)?;
+    let name = name.strip_prefix("*.").unwrap_or(&name).to_string();
+
+    let mut parameters = Vec::new();
+    if let Some(account_url) = account_url {
+        parameters.push(format!("accounturi={}", account_url));
+    }
+    if !validation_methods.is_empty() {
+        let methods: Vec<&str> = validation_methods
+            .iter()
+            .map(ChallengeType::as_str)
+            .collect();
+        parameters.push(format!("validationmethods={}", methods.join(",")));
+    }
+    Ok(caa_identities
+        .iter()
+        .map(|issuer| CaaRecord {
+            name: name.clone(),
+            flags: 0,
+            tag: "issue".to_string(),
+            value: std::iter::once(issuer.as_str())
+                .chain(parameters.iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join("; "),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_for_account() {
+        let identities = ["letsencrypt.org".to_string()];
+        let records = caa_records(
+            &identities,
+            "*.Example.COM.",
+            Some("https://acme-v02.api.letsencrypt.org/acme/acct/1234"),
+            &[ChallengeType::Dns01, ChallengeType::Http01],
+        )
+        .unwrap();
+        assert_eq!(
+            records[0].to_string(),
+            "example.com. CAA 0 issue \"letsencrypt.org; \
+             accounturi=https://acme-v02.api.letsencrypt.org/acme/acct/1234; \
+             validationmethods=dns-01,http-01\""
+        );
+
+        let records = caa_records(&identities, "example.com", None, &[]).unwrap();
+        assert_eq!(records[0].value, "letsencrypt.org");
+        caa_records(&[], "example.com", None, &[]).unwrap_err();
+        caa_records(&identities, "exa mple.com", None, &[]).unwrap_err();
+    }
+}
@@ -0,0 +1,226 @@
+use std::sync::{Arc, Mutex, RwLock};
+
+use futures_channel::mpsc::{unbounded, UnboundedSender};
+use futures_util::Stream;
+
+use super::store::CertificateBundle;
+
+/// The certificate a long-running server is currently serving, replaced
+/// atomically on renewal.
+///
+/// TLS stacks call `load` on every handshake; whatever renews the certificate
+/// calls `store`. `K` is whatever the TLS stack wants to hold, e.g. a
+/// `CertificateBundle` or an already-parsed `rustls::sign::CertifiedKey`.
+pub struct CertifiedKeyHandle<K = CertificateBundle> {
+    inner: Arc<Inner<K>>,
+}
+
+struct Inner<K> {
+    current: RwLock<Arc<K>>,
+    subscribers: Mutex<Vec<UnboundedSender<Arc<K>>>>,
+}
+
+impl<K> Clone for CertifiedKeyHandle<K> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<K> CertifiedKeyHandle<K> {
+    pub fn new(key: K) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                current: RwLock::new(Arc::new(key)),
+                subscribers: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// The current key. This only clones an `Arc`, so is cheap enough for
+    /// every handshake.
+    pub fn load(&self) -> Arc<K> {
+        self.inner.current.read().unwrap().clone()
+    }
+
+    /// Replaces the current key, notifies subscribers and returns the key
+    /// that was replaced. Handshakes already holding the old key finish
+    /// with it.
+    pub fn store(&self, key: K) -> Arc<K> {
+        let key = Arc::new(key);
+        let previous = std::mem::replace(&mut *self.inner.current.write().unwrap(), key.clone());
+        self.inner
+            .subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.unbounded_send(key.clone()).is_ok());
+        previous
+    }
+
+    /// Yields each key stored after this call, e.g. to refresh OCSP
+    /// responses or log rotations. Dropping the stream unsubscribes.
+    pub fn subscribe(&self) -> impl Stream<Item = Arc<K>> + Send + Unpin
+    where
+        K: Send + Sync,
+    {
+        let (sender, receiver) = unbounded();
+        self.inner.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+}
+
+impl<K: std::fmt::Debug> std::fmt::Debug for CertifiedKeyHandle<K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("CertifiedKeyHandle")
+            .field(&self.load())
+            .finish()
+    }
+}
+
+/// Serves the current key to every handshake, so that a rustls server
+/// picks up renewed certificates without a restart:
+///
+/// ```ignore
+/// let handle = CertifiedKeyHandle::new(bundle.rustls_certified_key(&provider)?);
+/// let config = rustls::ServerConfig::builder()
+///     .with_no_client_auth()
+///     .with_cert_resolver(Arc::new(handle.clone()));
+/// // on renewal:
+/// handle.store(renewed.rustls_certified_key(&provider)?);
+/// ```
+#[cfg(feature = "rustls")]
+impl rustls::server::ResolvesServerCert for CertifiedKeyHandle<rustls::sign::CertifiedKey> {
+    fn resolve(
+        &self,
+        _client_hello: rustls::server::ClientHello<'_>,
+    ) -> Option<Arc<rustls::sign::CertifiedKey>> {
+        Some(self.load())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::StreamExt;
+
+    use super::*;
+
+    #[async_std::test]
+    async fn store_notifies_subscribers() {
+        let handle = CertifiedKeyHandle::new("first");
+        let mut rotations = handle.subscribe();
+        let dropped = handle.subscribe();
+        drop(dropped);
+
+        let server_handle = handle.clone();
+        assert_eq!(*handle.store("second"), "first");
+        assert_eq!(*server_handle.load(), "second");
+        assert_eq!(*rotations.next().await.unwrap(), "second");
+        assert_eq!(handle.inner.subscribers.lock().unwrap().len(), 1);
+    }
+
+    #[cfg(feature = "rustls")]
+    mod rustls_resolver {
+        use std::sync::Arc;
+
+        use chrono::Utc;
+        use rcgen::{CertificateParams, KeyPair};
+        use rustls::{
+            crypto::{ring, CryptoProvider},
+            pki_types::{CertificateDer, ServerName},
+            ClientConfig, ClientConnection, RootCertStore, ServerConfig, ServerConnection,
+        };
+        use zeroize::Zeroizing;
+
+        use crate::{
+            api::{
+                certified_key::CertifiedKeyHandle,
+                store::{CertificateBundle, CertificateMetadata},
+            },
+            wire::identifier::AcmeIdentifier,
+        };
+
+        /// A self-signed certificate for example.com with a fresh key.
+        fn issue() -> CertificateBundle {
+            let key = KeyPair::generate().unwrap();
+            let cert = CertificateParams::new(vec!["example.com".to_string()])
+                .unwrap()
+                .self_signed(&key)
+                .unwrap();
+            CertificateBundle {
+                private_key_pem: Zeroizing::new(key.serialize_pem()),
+                fullchain_pem: cert.pem(),
+                metadata: CertificateMetadata {
+                    identifiers: vec![AcmeIdentifier::dns("example.com")],
+                    order_url: "https://acme.test/order/1".to_string(),
+                    issued_at: Utc::now().into(),
+                },
+            }
+        }
+
+        /// Completes a handshake in memory and returns the server's leaf.
+        fn handshake(server_config: &Arc<ServerConfig>, trusted: &[&CertificateBundle]) -> Vec<u8> {
+            let mut roots = RootCertStore::empty();
+            for bundle in trusted {
+                roots
+                    .add(bundle.rustls_cert_chain().unwrap().remove(0))
+                    .unwrap();
+            }
+            let client_config = ClientConfig::builder_with_provider(provider())
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            let mut client = ClientConnection::new(
+                Arc::new(client_config),
+                ServerName::try_from("example.com").unwrap(),
+            )
+            .unwrap();
+            let mut server = ServerConnection::new(server_config.clone()).unwrap();
+            while client.is_handshaking() || server.is_handshaking() {
+                let mut buf = Vec::new();
+                client.write_tls(&mut buf).unwrap();
+                server.read_tls(&mut buf.as_slice()).unwrap();
+                server.process_new_packets().unwrap();
+                let mut buf = Vec::new();
+                server.write_tls(&mut buf).unwrap();
+                client.read_tls(&mut buf.as_slice()).unwrap();
+                client.process_new_packets().unwrap();
+            }
+            let certs: &[CertificateDer] = client.peer_certificates().unwrap();
+            certs[0].to_vec()
+        }
+
+        fn provider() -> Arc<CryptoProvider> {
+            Arc::new(ring::default_provider())
+        }
+
+        #[test]
+        fn serves_renewed_certificate() {
+            let first = issue();
+            let handle = CertifiedKeyHandle::new(first.rustls_certified_key(&provider()).unwrap());
+            let server_config = Arc::new(
+                ServerConfig::builder_with_provider(provider())
+                    .with_safe_default_protocol_versions()
+                    .unwrap()
+                    .with_no_client_auth()
+                    .with_cert_resolver(Arc::new(handle.clone())),
+            );
+
+            let renewed = issue();
+            let first_der = first.rustls_cert_chain().unwrap().remove(0).to_vec();
+            let renewed_der = renewed.rustls_cert_chain().unwrap().remove(0).to_vec();
+            assert_eq!(handshake(&server_config, &[&first, &renewed]), first_der);
+            handle.store(renewed.rustls_certified_key(&provider()).unwrap());
+            assert_eq!(handshake(&server_config, &[&first, &renewed]), renewed_der);
+        }
+
+        #[test]
+        fn rejects_mismatched_key() {
+            let mut bundle = issue();
+            bundle.private_key_pem = issue().private_key_pem;
+            let certified_key = bundle.rustls_certified_key(&provider()).unwrap();
+            certified_key.keys_match().unwrap_err();
+        }
+    }
+}
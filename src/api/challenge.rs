@@ -1,18 +1,27 @@
 use std::sync::Arc;
 
 use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
 
 use crate::{
+    base64url,
+    crypto::account_key::AccountKey,
     error::{AcmeError, AcmeResult},
     wire::{
-        challenge::{ChallengeResource, ChallengeStatus},
+        challenge::{
+            ChallengeDetails, ChallengeResource, ChallengeResponse, ChallengeStatus, ChallengeType,
+            ValidationRecord,
+        },
         common::ResourceStatus,
         problem::AcmeProblem,
     },
 };
 
-use super::account_context::AccountContext;
+use super::{account::Account, account_context::AccountContext, dns_identifier::DnsIdentifier};
 
+#[derive(Clone)]
 pub struct Challenge {
     context: Arc<AccountContext>,
     resource: Arc<ChallengeResource>,
@@ -23,6 +32,25 @@ impl Challenge {
         Self { context, resource }
     }
 
+    /// Restores a challenge checkpointed with `save` without contacting the
+    /// server.
+    pub fn from_saved(account: &Account, saved: SavedChallenge) -> Self {
+        Self::new(account.context().clone(), Arc::new(saved.resource))
+    }
+
+    /// Returns a serializable checkpoint of this challenge.
+    pub fn save(&self) -> SavedChallenge {
+        SavedChallenge {
+            resource: self.resource().clone(),
+        }
+    }
+
+    pub async fn refresh(&mut self) -> AcmeResult<ChallengeStatus> {
+        let resource = context_client_request!(self.context, get_resource, self.url()).await?;
+        self.resource = Arc::new(resource);
+        Ok(self.status())
+    }
+
     pub fn resource(&self) -> &ChallengeResource {
         self.resource.as_ref()
     }
@@ -39,14 +67,61 @@ impl Challenge {
         self.status().as_result()
     }
 
-    pub fn challenge_type(&self) -> &str {
+    pub fn challenge_type(&self) -> &ChallengeType {
         &self.resource.type_
     }
 
+    /// The type-specific fields; see `ChallengeResource::details`.
+    pub fn details(&self) -> AcmeResult<ChallengeDetails<'_>> {
+        self.resource.details()
+    }
+
+    /// The token as sent by the server; `key_authorization` and
+    /// `http01_path` check it with `wire::challenge::validate_token`.
     pub fn token(&self) -> Option<&str> {
         self.resource.token.as_deref()
     }
 
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-8.1
+    pub fn key_authorization(&self) -> AcmeResult<String> {
+        let token = self.resource.validated_token()?;
+        let thumbprint = self
+            .context
+            .account_key
+            .thumbprint()
+            .map_err(AcmeError::CryptoError)?;
+        Ok(format!("{}.{}", token, thumbprint))
+    }
+
+    /// The value of the "_acme-challenge" TXT record for a dns-01 challenge.
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-8.4
+    pub fn dns01_txt_value(&self) -> AcmeResult<String> {
+        Ok(dns01_txt_value(&self.key_authorization()?))
+    }
+
+    /// The name of the TXT record to create for a dns-01 challenge for
+    /// `identifier`: "_acme-challenge." and the name without any wildcard
+    /// label, so "*.example.com" and "example.com" share
+    /// "_acme-challenge.example.com". A trailing dot is dropped and the name
+    /// lowercased. If "_acme-challenge" is delegated with a CNAME, the record
+    /// goes at its target instead; see `dns_provider`.
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-8.4
+    pub fn dns01_record_name(identifier: &DnsIdentifier) -> String {
+        dns01_record_name(identifier.without_wildcard())
+    }
+
+    /// The path at which an http-01 challenge response must be served.
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-8.3
+    pub fn http01_path(&self) -> AcmeResult<String> {
+        Ok(http01_path(self.resource.validated_token()?))
+    }
+
+    /// The body that must be served at `http01_path` for an http-01 challenge.
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-8.3
+    pub fn http01_body(&self) -> AcmeResult<String> {
+        self.key_authorization()
+    }
+
     pub fn state(&mut self) -> ChallengeState<'_> {
         use ChallengeStatus::*;
         match self.status() {
@@ -63,6 +138,13 @@ impl Challenge {
     }
 }
 
+/// A serializable checkpoint of a `Challenge`; see `Challenge::save`. The
+/// challenge URL is part of the resource.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SavedChallenge {
+    pub resource: ChallengeResource,
+}
+
 pub enum ChallengeState<'a> {
     Pending(ChallengeStatePending<'a>),
     Processing,
@@ -73,12 +155,46 @@ pub enum ChallengeState<'a> {
 pub struct ChallengeStatePending<'a>(&'a mut Challenge);
 
 impl<'a> ChallengeStatePending<'a> {
-    pub async fn respond(&'a mut self) -> AcmeResult<ChallengeState<'a>> {
+    pub async fn respond(self) -> AcmeResult<ChallengeState<'a>> {
         let resource =
             context_client_request!(self.0.context, respond_challenge, self.0.url(), None).await?;
         self.0.resource = Arc::new(resource);
         Ok(self.0.state())
     }
+
+    /// Responds with a CSR, as for onion-csr-01; see
+    /// `x509::CsrBuilder::onion_csr01_nonces`.
+    /// https://www.rfc-editor.org/rfc/rfc9799.html#section-3.2
+    pub async fn respond_with_csr(
+        self,
+        csr_der: impl AsRef<[u8]>,
+    ) -> AcmeResult<ChallengeState<'a>> {
+        self.respond_with(ChallengeResponse::onion_csr01(csr_der))
+            .await
+    }
+
+    /// Responds with a type-specific `payload`, e.g. a `ChallengeResponse`,
+    /// which must serialize to a JSON object.
+    pub async fn respond_with(self, payload: impl Serialize) -> AcmeResult<ChallengeState<'a>> {
+        let response = match serde_json::to_value(payload)? {
+            Value::Object(response) => response,
+            other => {
+                return Err(AcmeError::InvalidState(format!(
+                    "challenge response must be a JSON object, not {}",
+                    other
+                )))
+            }
+        };
+        let resource = context_client_request!(
+            self.0.context,
+            respond_challenge,
+            self.0.url(),
+            Some(response)
+        )
+        .await?;
+        self.0.resource = Arc::new(resource);
+        Ok(self.0.state())
+    }
 }
 
 pub struct ChallengeStateValid<'a>(&'a Challenge);
@@ -98,4 +214,111 @@ impl<'a> ChallengeStateInvalid<'a> {
     pub fn error(&self) -> Option<&AcmeProblem> {
         self.0.resource.error.as_ref()
     }
+
+    /// What the server saw while validating, e.g. which addresses it
+    /// connected to; see `ValidationRecord`.
+    pub fn validation_records(&self) -> &[ValidationRecord] {
+        &self.0.resource.validation_record
+    }
+}
+
+pub(crate) fn dns01_txt_value(key_authorization: &str) -> String {
+    base64url::encode(Sha256::digest(key_authorization.as_bytes()))
+}
+
+pub(crate) fn dns01_record_name(domain: &str) -> String {
+    let domain = domain.strip_prefix("*.").unwrap_or(domain);
+    format!(
+        "_acme-challenge.{}",
+        domain.trim_end_matches('.').to_ascii_lowercase()
+    )
+}
+
+pub(crate) fn http01_path(token: &str) -> String {
+    format!("/.well-known/acme-challenge/{}", token)
+}
+
+#[cfg(all(test, any(feature = "x509", feature = "x509-rcgen")))]
+mod tests {
+    use crate::{
+        test_support::mock_server::MockAcmeServer,
+        wire::authorization::AuthorizationStatus,
+        x509::{CsrBuilder, KeyType},
+    };
+
+    use super::*;
+
+    #[async_std::test]
+    async fn respond_with_payload() {
+        let server = MockAcmeServer::new();
+        let account = server
+            .client()
+            .await
+            .unwrap()
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+        let order = account.new_dns_order("example.com").await.unwrap();
+        let authorization = order.get_only_authorization().await.unwrap();
+        let mut challenge = authorization
+            .find_challenge_type(&ChallengeType::Dns01)
+            .unwrap();
+
+        match challenge.state() {
+            ChallengeState::Pending(pending) => {
+                assert!(matches!(
+                    pending.respond_with(["not", "an", "object"]).await,
+                    Err(AcmeError::InvalidState(_))
+                ))
+            }
+            _ => panic!("expected pending challenge"),
+        };
+        match challenge.state() {
+            ChallengeState::Pending(pending) => pending
+                .respond_with(serde_json::json!({"extension": true}))
+                .await
+                .unwrap(),
+            _ => panic!("expected pending challenge"),
+        };
+        assert_ne!(challenge.status(), ChallengeStatus::Pending);
+    }
+
+    const ONION_NAME: &str = "pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd.onion";
+
+    #[async_std::test]
+    async fn onion_csr01() {
+        let server = MockAcmeServer::new();
+        let account = server
+            .client()
+            .await
+            .unwrap()
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+
+        let order = account.new_dns_order(ONION_NAME).await.unwrap();
+        let mut authorization = order.get_only_authorization().await.unwrap();
+        let mut challenge = authorization
+            .find_challenge_type(&ChallengeType::OnionCsr01)
+            .unwrap();
+        let nonce = match challenge.details().unwrap() {
+            ChallengeDetails::OnionCsr01 { nonce, .. } => nonce.to_string(),
+            details => panic!("unexpected {:?}", details),
+        };
+        let (_, csr_der) = CsrBuilder::new()
+            .dns(ONION_NAME)
+            .key_type(KeyType::Ed25519)
+            .onion_csr01_nonces(&nonce)
+            .unwrap()
+            .build()
+            .unwrap();
+        match challenge.state() {
+            ChallengeState::Pending(pending) => pending.respond_with_csr(csr_der).await.unwrap(),
+            _ => panic!("expected pending challenge"),
+        };
+        assert_eq!(
+            authorization.status_changed().await.unwrap(),
+            AuthorizationStatus::Valid
+        );
+    }
 }
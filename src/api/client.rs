@@ -1,41 +1,198 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::time::Duration;
 
-use http_client::HttpClient;
-use serde_json::Value;
+use chrono::{DateTime, Utc};
 use serde_json::value::RawValue;
+use serde_json::Value;
 
+use crate::clock::{system_clock, Clock};
 use crate::crypto::account_key::AccountKey;
-use crate::crypto::generate_account_key;
-use crate::error::AcmeError;
+use crate::crypto::{account_key_from_jwk, generate_account_key_with, KeyAlgorithm};
 use crate::error::AcmeResult;
-use crate::wire::account::NewAccountResource;
-use crate::wire::client::AcmeClient;
+use crate::error::{AcmeError, AcmeOp};
+use crate::sleep::{self, default_sleeper, Sleeper};
+use crate::transport::HttpClient;
+#[cfg(feature = "reqwest-rustls")]
+use crate::transport::{ProxyConfig, ReqwestClient, TransportConfig};
+use crate::transport::{UserAgent, DEFAULT_USER_AGENT};
+use crate::wire::account::{AccountResource, NewAccountResource};
+use crate::wire::challenge::ChallengeType;
+use crate::wire::client::{
+    AcmeClient, BodyLimits, NonceSource, RetryPolicy, Timeouts, DEFAULT_POLL_INTERVAL,
+};
 use crate::wire::directory::DirectoryMetadata;
 use crate::wire::directory::DirectoryResource;
+use crate::wire::quirks::Quirks;
+use crate::wire::renewal_info::RenewalInfo;
+use crate::wire::transcript::Transcript;
 
 use super::account::Account;
+use super::account::AccountCredentials;
 use super::account::Contact;
+use super::caa::{caa_records, CaaRecord};
 
 pub struct Client {
-    http: Arc<dyn HttpClient>,
     directory: DirectoryResource,
+    // Shares its nonce pool with every account's client
+    shared: AcmeClient,
+    retry_policy: RetryPolicy,
+    timeouts: Timeouts,
+    body_limits: BodyLimits,
+    directory_url: Option<String>,
+    directory_expires: Option<DateTime<Utc>>,
+    auto_refresh_directory: bool,
+    sleeper: Arc<dyn Sleeper>,
+    clock: Arc<dyn Clock>,
+    poll_interval: Duration,
+    transcript: Option<Arc<Transcript>>,
+    quirks: Quirks,
+    strict: bool,
+    nonce_source: Option<Arc<dyn NonceSource>>,
 }
 
 impl Client {
     pub fn new(http: impl Into<Arc<dyn HttpClient>>, directory: DirectoryResource) -> Self {
+        let quirks = Quirks::detect(&directory);
         Self {
-            http: http.into(),
+            shared: AcmeClient::new(http, directory.clone()),
             directory,
+            retry_policy: Default::default(),
+            timeouts: Default::default(),
+            body_limits: Default::default(),
+            directory_url: None,
+            directory_expires: None,
+            auto_refresh_directory: false,
+            sleeper: default_sleeper(),
+            clock: system_clock(),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            transcript: None,
+            quirks,
+            strict: false,
+            nonce_source: None,
         }
     }
 
+    /// Configures a client in one go, e.g.
+    /// `Client::builder().directory_url(url).http(http).nonce_prefetch(4).build().await`.
+    /// `new` and `for_directory_url` remain shortcuts for the defaults.
+    pub fn builder() -> ClientBuilder {
+        Default::default()
+    }
+
+    /// Sets the retry policy for accounts subsequently created or loaded by
+    /// this client.
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
+
+    /// Limits how long requests of accounts subsequently created or loaded
+    /// by this client, and directory refreshes, may take. Exceeding a limit
+    /// is an `AcmeError::Timeout`.
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) {
+        self.timeouts = timeouts;
+    }
+
+    /// Limits the size of the responses that accounts subsequently created
+    /// or loaded by this client, and directory refreshes, read. Exceeding a
+    /// limit is an `AcmeError::ResponseTooLarge`.
+    pub fn set_body_limits(&mut self, body_limits: BodyLimits) {
+        self.body_limits = body_limits;
+    }
+
+    /// Sets the timer that accounts created or loaded by this client use
+    /// between polls. Defaults to `sleep::default_sleeper()`.
+    pub fn set_sleeper(&mut self, sleeper: impl Sleeper + 'static) {
+        self.sleeper = Arc::new(sleeper);
+    }
+
+    /// Sets the clock that accounts created or loaded by this client check
+    /// expiry times and schedule issuance against. Defaults to the system
+    /// time.
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) {
+        self.clock = Arc::new(clock);
+    }
+
+    /// Sets how long to wait between polls of pending authorizations and
+    /// orders (default 2 seconds).
+    pub fn set_poll_interval(&mut self, poll_interval: Duration) {
+        self.poll_interval = poll_interval;
+    }
+
+    /// Records the requests of accounts subsequently created or loaded by
+    /// this client in `transcript`, e.g. for a support request to the CA.
+    pub fn set_transcript(&mut self, transcript: Arc<Transcript>) {
+        self.transcript = Some(transcript);
+    }
+
+    /// Replaces the workarounds for the server's deviations from RFC 8555
+    /// that were detected from the directory, for accounts subsequently
+    /// created or loaded by this client.
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn quirks(&self) -> &Quirks {
+        &self.quirks
+    }
+
+    /// Sets where accounts subsequently created or loaded by this client get
+    /// the nonces for their requests; see `AcmeClient::with_nonce_source`.
+    pub fn set_nonce_source(&mut self, nonce_source: Arc<dyn NonceSource>) {
+        self.nonce_source = Some(nonce_source);
+    }
+
+    /// Makes accounts subsequently created or loaded by this client reject
+    /// responses that break RFC 8555; see `AcmeClient::with_strict`.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
     pub async fn for_directory_url(
         http: impl Into<Arc<dyn HttpClient + 'static>>,
         directory_url: impl AsRef<str>,
     ) -> AcmeResult<Self> {
         let http_arc = http.into();
-        let directory = AcmeClient::get_directory(http_arc.as_ref(), directory_url).await?;
-        Ok(Self::new(http_arc, directory))
+        let directory_url = directory_url.as_ref();
+        let (directory, expires) =
+            AcmeClient::fetch_directory(http_arc.as_ref(), directory_url).await?;
+        let mut client = Self::new(http_arc, directory);
+        client.directory_url = Some(directory_url.to_string());
+        client.directory_expires = expires;
+        Ok(client)
+    }
+
+    /// Re-fetches the directory, e.g. to pick up changed terms of service.
+    /// It's replaced in place, so accounts already created or loaded by this
+    /// client use it too, and the nonce pool, nonce source and quirks are
+    /// kept; see `AcmeClient::refresh_directory`.
+    pub async fn refresh_directory(&mut self) -> AcmeResult<()> {
+        let client = self.acme_client();
+        let directory = client.refresh_directory().await?;
+        self.directory = DirectoryResource::clone(&directory);
+        self.directory_expires = client.directory_expires();
+        Ok(())
+    }
+
+    /// When the directory stops being fresh, per its Cache-Control or Expires
+    /// header.
+    pub fn directory_expires(&self) -> Option<DateTime<Utc>> {
+        self.directory_expires
+    }
+
+    /// Makes accounts subsequently created or loaded by this client re-fetch
+    /// the directory when it becomes stale. Off by default.
+    pub fn set_directory_auto_refresh(&mut self, auto_refresh: bool) {
+        self.auto_refresh_directory = auto_refresh;
+    }
+
+    /// The URL the directory was fetched from, if known.
+    pub fn directory_url(&self) -> Option<&str> {
+        self.directory_url.as_deref()
+    }
+
+    pub fn directory(&self) -> &DirectoryResource {
+        &self.directory
     }
 
     pub fn metadata(&self) -> &DirectoryMetadata {
@@ -46,6 +203,56 @@ impl Client {
         self.directory.meta.terms_of_service.as_deref()
     }
 
+    /// Whether new accounts must be bound to an account with the CA, via
+    /// `RegisterAccountConfig::external_account_binding`.
+    pub fn requires_external_account_binding(&self) -> bool {
+        self.metadata().external_account_required == Some(true)
+    }
+
+    /// Certificate profiles offered by the server, mapped to their
+    /// descriptions. Empty if the server doesn't support profiles.
+    pub fn supported_profiles(&self) -> &BTreeMap<String, String> {
+        &self.directory.meta.profiles
+    }
+
+    /// CAA records that allow only this CA to issue for `domain` (and its
+    /// subdomains), optionally only to the account at `account_url` and
+    /// with `validation_methods` (RFC 8657); see also
+    /// `Account::caa_records_for`. Fails if the directory has no
+    /// caaIdentities.
+    pub fn caa_records_for(
+        &self,
+        domain: &str,
+        account_url: Option<&str>,
+        validation_methods: &[ChallengeType],
+    ) -> AcmeResult<Vec<CaaRecord>> {
+        caa_records(
+            &self.directory.meta.caa_identities,
+            domain,
+            account_url,
+            validation_methods,
+        )
+    }
+
+    pub fn supports_renewal_info(&self) -> bool {
+        self.directory.renewal_info.is_some()
+    }
+
+    /// Fetches ACME Renewal Information for a certificate identified by its ARI
+    /// CertID (see `wire::renewal_info::ari_cert_id`).
+    pub async fn get_renewal_info(&self, cert_id: &str) -> AcmeResult<RenewalInfo> {
+        self.acme_client().get_renewal_info(cert_id).await
+    }
+
+    #[cfg(feature = "x509")]
+    pub async fn get_certificate_renewal_info(
+        &self,
+        cert_pem: impl AsRef<[u8]>,
+    ) -> AcmeResult<RenewalInfo> {
+        let cert_id = crate::x509::ari_cert_id(cert_pem)?;
+        self.get_renewal_info(&cert_id).await
+    }
+
     pub async fn register_account(
         &self,
         contact_email: String,
@@ -63,18 +270,28 @@ impl Client {
         &self,
         config: RegisterAccountConfig,
     ) -> AcmeResult<Account> {
+        if config.external_account_binding.is_none() {
+            self.check_external_account_required()?;
+        }
         let req = &NewAccountResource {
-            contact: config.contacts.into_iter().map(Contact::uri).collect(),
+            contact: config
+                .contacts
+                .into_iter()
+                .map(Contact::validated_uri)
+                .collect::<AcmeResult<_>>()?,
             terms_of_service_agreed: config.terms_of_service_agreed,
             external_account_binding: config.external_account_binding,
             ..Default::default()
         };
         let account_key = config
             .account_key
-            .unwrap_or_else(|| Box::new(generate_account_key()));
+            .unwrap_or_else(|| generate_account_key_with(config.key_algorithm));
         self.get_account(account_key, req).await
     }
 
+    /// Looks up the existing account for `account_key` (which the server
+    /// identifies by its public key). Fails with
+    /// `AcmeError::AccountDoesNotExist` if there is none.
     pub async fn find_account(
         &self,
         account_key: impl AccountKey + 'static,
@@ -86,6 +303,120 @@ impl Client {
         self.get_account(account_key, req).await
     }
 
+    /// Like `find_account`, but `None` if no account exists for
+    /// `account_key`, e.g. to decide whether to register one. Other errors,
+    /// such as network failures, are still errors.
+    pub async fn account_exists(
+        &self,
+        account_key: impl AccountKey + 'static,
+    ) -> AcmeResult<Option<Account>> {
+        match self.find_account(account_key).await {
+            Ok(account) => Ok(Some(account)),
+            Err(err) if matches!(err.without_context(), AcmeError::AccountDoesNotExist(_)) => {
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Finds the account for `account_key`, registering one with `contacts`
+    /// if there is none, and agrees to the terms of service as `agree_tos`
+    /// says. Returns the account and whether it was newly registered.
+    ///
+    /// Noticing changed terms on an existing account takes an extra request
+    /// (a `Account::refresh`) with `AgreeTos::IfRequired`.
+    pub async fn get_or_register_account(
+        &self,
+        account_key: impl AccountKey + 'static,
+        contacts: Vec<Contact>,
+        agree_tos: AgreeTos,
+    ) -> AcmeResult<(Account, bool)> {
+        let contact = contacts
+            .into_iter()
+            .map(Contact::validated_uri)
+            .collect::<AcmeResult<_>>()?;
+        let public_jwk = account_key.public_jwk().map_err(AcmeError::CryptoError)?;
+        let public_jwk_json = RawValue::from_string(public_jwk)?;
+        let client = self.acme_client();
+
+        let lookup = &NewAccountResource {
+            only_return_existing: true,
+            ..Default::default()
+        };
+        match client
+            .new_account(&account_key, &public_jwk_json, lookup)
+            .await
+        {
+            Ok(resource) => {
+                let mut account = Account::from_resource(client, account_key, resource)?;
+                match agree_tos {
+                    AgreeTos::Always => account.agree_to_terms().await?,
+                    AgreeTos::IfRequired => match account.refresh().await {
+                        Ok(_) => {}
+                        Err(err)
+                            if matches!(
+                                err.without_context(),
+                                AcmeError::TermsOfServiceChanged { .. }
+                            ) =>
+                        {
+                            account.agree_to_terms().await?
+                        }
+                        Err(err) => return Err(err),
+                    },
+                    AgreeTos::Never => {}
+                }
+                return Ok((account, false));
+            }
+            Err(err) if matches!(err.without_context(), AcmeError::AccountDoesNotExist(_)) => {}
+            Err(err) => return Err(err),
+        }
+
+        self.check_external_account_required()?;
+        let register = &NewAccountResource {
+            contact,
+            terms_of_service_agreed: match agree_tos {
+                AgreeTos::Always => true,
+                AgreeTos::IfRequired => self.terms_of_service_uri().is_some(),
+                AgreeTos::Never => false,
+            },
+            ..Default::default()
+        };
+        let resource = client
+            .new_account(&account_key, &public_jwk_json, register)
+            .await?;
+        Ok((Account::from_resource(client, account_key, resource)?, true))
+    }
+
+    /// Rebuilds an account from saved credentials without contacting the
+    /// server. The account resource is not fetched; see `Account::refresh`.
+    pub fn load_account(&self, credentials: &AccountCredentials) -> AcmeResult<Account> {
+        if let (Some(saved), Some(current)) = (&credentials.directory_url, &self.directory_url) {
+            if saved != current {
+                return Err(AcmeError::InvalidState(format!(
+                    "account credentials are for directory {:?}, not {:?}",
+                    saved, current
+                )));
+            }
+        }
+        let account_key = account_key_from_jwk(&credentials.private_jwk)?;
+        let resource = AccountResource {
+            location: Some(credentials.account_url.clone()),
+            ..Default::default()
+        };
+        Account::from_resource(self.acme_client(), account_key, resource)
+    }
+
+    /// Fails without a request if registering needs an external account
+    /// binding, rather than letting the server refuse.
+    fn check_external_account_required(&self) -> AcmeResult<()> {
+        if self.requires_external_account_binding() {
+            return Err(AcmeError::ExternalAccountRequired {
+                website: self.metadata().website.clone(),
+            });
+        }
+        Ok(())
+    }
+
     async fn get_account(
         &self,
         account_key: impl AccountKey + 'static,
@@ -93,18 +424,620 @@ impl Client {
     ) -> AcmeResult<Account> {
         let public_jwk = account_key.public_jwk().map_err(AcmeError::CryptoError)?;
         let public_jwk_json = RawValue::from_string(public_jwk)?;
-        let client = AcmeClient::new(self.http.clone(), self.directory.clone());
+        let client = self.acme_client();
         let resource = client
             .new_account(&account_key, &public_jwk_json, req)
             .await?;
         Account::from_resource(client, account_key, resource)
     }
+
+    pub(crate) fn acme_client(&self) -> AcmeClient {
+        self.shared
+            .clone()
+            .with_retry_policy(self.retry_policy)
+            .with_timeouts(self.timeouts)
+            .with_body_limits(self.body_limits)
+            .with_directory_expires(self.directory_expires)
+            .with_directory_auto_refresh(self.auto_refresh_directory)
+            .with_directory_url(self.directory_url.clone())
+            .with_sleeper(self.sleeper.clone())
+            .with_clock(self.clock.clone())
+            .with_poll_interval(self.poll_interval)
+            .with_transcript(self.transcript.clone())
+            .with_quirks(self.quirks.clone())
+            .with_strict(self.strict)
+            .with_nonce_source(self.nonce_source.clone())
+    }
+}
+
+/// Fetches the directory within the limits a client was configured with.
+async fn fetch_directory(
+    http: &dyn HttpClient,
+    directory_url: &str,
+    body_limits: &BodyLimits,
+    timeouts: &Timeouts,
+    sleeper: &dyn Sleeper,
+    clock: &dyn Clock,
+) -> AcmeResult<(DirectoryResource, Option<DateTime<Utc>>)> {
+    let fetch = AcmeClient::fetch_directory_with_limits(http, directory_url, body_limits, clock);
+    match timeouts.total {
+        Some(total) => sleep::timeout(sleeper, total, fetch)
+            .await
+            .ok_or(AcmeError::Timeout {
+                operation: AcmeOp::GetDirectory,
+            })?,
+        None => fetch.await,
+    }
+}
+
+/// Configuration for a `Client`, from `Client::builder`. Only the HTTP
+/// client and the directory (or its URL) are required; everything else
+/// defaults as for `Client::new`, except that requests carry a User-Agent
+/// (`transport::DEFAULT_USER_AGENT` unless set).
+#[derive(Default)]
+pub struct ClientBuilder {
+    http: Option<Arc<dyn HttpClient>>,
+    directory_url: Option<String>,
+    directory: Option<DirectoryResource>,
+    user_agent: Option<String>,
+    retry_policy: RetryPolicy,
+    timeouts: Timeouts,
+    body_limits: BodyLimits,
+    sleeper: Option<Arc<dyn Sleeper>>,
+    clock: Option<Arc<dyn Clock>>,
+    poll_interval: Option<Duration>,
+    transcript: Option<Arc<Transcript>>,
+    quirks: Option<Quirks>,
+    strict: bool,
+    auto_refresh_directory: bool,
+    nonce_source: Option<Arc<dyn NonceSource>>,
+    nonce_prefetch: usize,
+    #[cfg(feature = "reqwest-rustls")]
+    transport: Option<TransportConfig>,
+}
+
+impl ClientBuilder {
+    pub fn http(mut self, http: impl Into<Arc<dyn HttpClient>>) -> Self {
+        self.http = Some(http.into());
+        self
+    }
+
+    /// Instead of `http`: a `ReqwestClient::with_config(&config)`, built in
+    /// `build`.
+    #[cfg(feature = "reqwest-rustls")]
+    pub fn transport(mut self, config: TransportConfig) -> Self {
+        self.transport = Some(config);
+        self
+    }
+
+    /// Instead of `http`: sends requests through `proxy`, with its Basic
+    /// credentials if any. Sets the proxy of the `transport` config (the
+    /// default one unless given first), so it goes after `transport`.
+    #[cfg(feature = "reqwest-rustls")]
+    pub fn proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.transport.get_or_insert_with(Default::default).proxy = Some(proxy);
+        self
+    }
+
+    /// The directory to fetch in `build`, and to refresh from later.
+    pub fn directory_url(mut self, directory_url: impl Into<String>) -> Self {
+        self.directory_url = Some(directory_url.into());
+        self
+    }
+
+    /// A directory already at hand, used instead of fetching one.
+    pub fn directory(mut self, directory: DirectoryResource) -> Self {
+        self.directory = Some(directory);
+        self
+    }
+
+    /// Sent as the User-Agent of requests that don't already have one.
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// See `Client::set_retry_policy`.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// See `Client::set_timeouts`; the total limit also applies to the
+    /// directory fetch in `build`.
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// See `Client::set_body_limits`.
+    pub fn body_limits(mut self, body_limits: BodyLimits) -> Self {
+        self.body_limits = body_limits;
+        self
+    }
+
+    /// See `Client::set_sleeper`.
+    pub fn sleeper(mut self, sleeper: impl Sleeper + 'static) -> Self {
+        self.sleeper = Some(Arc::new(sleeper));
+        self
+    }
+
+    /// See `Client::set_clock`.
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Some(Arc::new(clock));
+        self
+    }
+
+    /// See `Client::set_poll_interval`.
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = Some(poll_interval);
+        self
+    }
+
+    /// See `Client::set_transcript`.
+    pub fn transcript(mut self, transcript: Arc<Transcript>) -> Self {
+        self.transcript = Some(transcript);
+        self
+    }
+
+    /// See `Client::set_quirks`; by default they are detected from the
+    /// directory.
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = Some(quirks);
+        self
+    }
+
+    /// See `Client::set_strict`.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// See `Client::set_directory_auto_refresh`.
+    pub fn directory_auto_refresh(mut self, auto_refresh: bool) -> Self {
+        self.auto_refresh_directory = auto_refresh;
+        self
+    }
+
+    /// See `Client::set_nonce_source`.
+    pub fn nonce_source(mut self, nonce_source: Arc<dyn NonceSource>) -> Self {
+        self.nonce_source = Some(nonce_source);
+        self
+    }
+
+    /// How many nonces `build` fetches ahead of the first requests (default
+    /// none); see `AcmeClient::prefetch_nonces`.
+    pub fn nonce_prefetch(mut self, count: usize) -> Self {
+        self.nonce_prefetch = count;
+        self
+    }
+
+    /// Fetches the directory unless one was given, and any nonces to
+    /// prefetch.
+    pub async fn build(self) -> AcmeResult<Client> {
+        let http = self.http;
+        #[cfg(feature = "reqwest-rustls")]
+        let http =
+            match (http, &self.transport) {
+                (http, None) => http,
+                (None, Some(config)) => Some(ReqwestClient::with_config(config)?.into()),
+                (Some(_), Some(_)) => return Err(AcmeError::InvalidState(
+                    "Client::builder takes an HttpClient or a transport config and proxy, not both"
+                        .to_string(),
+                )),
+            };
+        let http = http.ok_or_else(|| {
+            AcmeError::InvalidState("Client::builder needs an HttpClient".to_string())
+        })?;
+        let user_agent = self
+            .user_agent
+            .unwrap_or_else(|| DEFAULT_USER_AGENT.to_string());
+        let http: Arc<dyn HttpClient> = Arc::new(UserAgent::new(http, user_agent));
+        let sleeper = self.sleeper.unwrap_or_else(default_sleeper);
+        let clock = self.clock.unwrap_or_else(system_clock);
+        let (directory, expires) = match (self.directory, &self.directory_url) {
+            (Some(directory), _) => (directory, None),
+            (None, Some(directory_url)) => {
+                fetch_directory(
+                    http.as_ref(),
+                    directory_url,
+                    &self.body_limits,
+                    &self.timeouts,
+                    sleeper.as_ref(),
+                    clock.as_ref(),
+                )
+                .await?
+            }
+            (None, None) => {
+                return Err(AcmeError::InvalidState(
+                    "Client::builder needs a directory or directory URL".to_string(),
+                ))
+            }
+        };
+
+        let mut client = Client::new(http, directory);
+        client.directory_url = self.directory_url;
+        client.directory_expires = expires;
+        client.auto_refresh_directory = self.auto_refresh_directory;
+        client.retry_policy = self.retry_policy;
+        client.timeouts = self.timeouts;
+        client.body_limits = self.body_limits;
+        client.sleeper = sleeper;
+        client.clock = clock;
+        if let Some(poll_interval) = self.poll_interval {
+            client.poll_interval = poll_interval;
+        }
+        client.transcript = self.transcript;
+        if let Some(quirks) = self.quirks {
+            client.quirks = quirks;
+        }
+        client.strict = self.strict;
+        client.nonce_source = self.nonce_source;
+        if self.nonce_prefetch > 0 {
+            client
+                .acme_client()
+                .prefetch_nonces(self.nonce_prefetch)
+                .await?;
+        }
+        Ok(client)
+    }
+}
+
+/// When `Client::get_or_register_account` agrees to the terms of service.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AgreeTos {
+    /// When registering, and to the current terms for an existing account.
+    Always,
+
+    /// When registering with a server that has terms of service, and when an
+    /// existing account's terms have changed.
+    IfRequired,
+
+    /// Never, e.g. so that a human can review them first; a server with
+    /// terms of service will then refuse to register the account.
+    Never,
 }
 
 #[derive(Default)]
 pub struct RegisterAccountConfig {
     pub account_key: Option<Box<dyn AccountKey>>,
+
+    /// The algorithm of the key generated if `account_key` is None.
+    pub key_algorithm: KeyAlgorithm,
+
     pub contacts: Vec<Contact>,
     pub terms_of_service_agreed: bool,
     pub external_account_binding: Option<Value>,
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::transport::Request;
+
+    use crate::crypto::{generate_account_key, jws::JwsSigner};
+    use crate::sleep::NoSleep;
+    use crate::test_support::mock_server::{Endpoint, Fault, MockAcmeServer, MOCK_DIRECTORY_URL};
+    use crate::transport::FnHttpClient;
+
+    use super::*;
+
+    #[async_std::test]
+    async fn builder() {
+        let server = MockAcmeServer::new();
+        let user_agents = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = user_agents.clone();
+        let mock = server.clone();
+        let http = FnHttpClient::new(move |req: Request| {
+            let user_agent = req.header("User-Agent").map(|v| v.as_str().to_string());
+            seen.lock().unwrap().push(user_agent);
+            let mock = mock.clone();
+            async move { mock.send(req).await }
+        });
+
+        let client = Client::builder()
+            .directory_url(MOCK_DIRECTORY_URL)
+            .http(Arc::new(http) as Arc<dyn HttpClient>)
+            .user_agent("example-agent/1.0")
+            .retry_policy(RetryPolicy::default())
+            .nonce_prefetch(4)
+            .sleeper(NoSleep)
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(server.request_count(Endpoint::NewNonce), 4);
+        assert_eq!(client.directory_url(), Some(MOCK_DIRECTORY_URL));
+
+        // Served from the prefetched nonces
+        client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+        assert_eq!(server.request_count(Endpoint::NewNonce), 4);
+        let user_agents = user_agents.lock().unwrap().clone();
+        assert!(user_agents.len() > 5);
+        assert!(user_agents
+            .iter()
+            .all(|user_agent| user_agent.as_deref() == Some("example-agent/1.0")));
+
+        let no_directory = Client::builder().http(server.http()).build().await;
+        assert!(matches!(no_directory, Err(AcmeError::InvalidState(_))));
+        let no_http = Client::builder()
+            .directory_url(MOCK_DIRECTORY_URL)
+            .build()
+            .await;
+        assert!(matches!(no_http, Err(AcmeError::InvalidState(_))));
+    }
+
+    #[async_std::test]
+    async fn external_account_required() {
+        let server = MockAcmeServer::new();
+        server.set_external_account_required(true);
+        let client = server.client().await.unwrap();
+        assert!(client.requires_external_account_binding());
+
+        let err = client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            &err,
+            AcmeError::ExternalAccountRequired { website: Some(website) }
+                if website == "https://acme.test/eab"
+        ));
+        let err = client
+            .get_or_register_account(generate_account_key(), vec![], AgreeTos::Always)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, AcmeError::ExternalAccountRequired { .. }));
+        assert_eq!(server.request_count(Endpoint::NewAccount), 1);
+    }
+
+    #[async_std::test]
+    async fn refresh_directory() {
+        let server = MockAcmeServer::new();
+        server.set_directory_cache_control("public, max-age=600");
+        let mut client = server.client().await.unwrap();
+        assert!(client.directory_expires().unwrap() > Utc::now());
+
+        server.set_terms_of_service("https://acme.test/terms/v2");
+        client.refresh_directory().await.unwrap();
+        assert_eq!(
+            client.terms_of_service_uri(),
+            Some("https://acme.test/terms/v2")
+        );
+    }
+
+    #[async_std::test]
+    async fn refresh_directory_keeps_nonces() {
+        let server = MockAcmeServer::new();
+        let mut client = server.client().await.unwrap();
+        let account = client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+        assert_eq!(server.request_count(Endpoint::NewNonce), 1);
+
+        server.set_terms_of_service("https://acme.test/terms/v2");
+        client.refresh_directory().await.unwrap();
+        // The nonce from the registration response is still pooled, for new
+        // accounts and existing ones alike
+        client
+            .register_account("other@example.com".to_string(), true)
+            .await
+            .unwrap();
+        account.new_dns_order("example.com").await.unwrap();
+        assert_eq!(server.request_count(Endpoint::NewNonce), 1);
+        assert_eq!(
+            client.terms_of_service_uri(),
+            Some("https://acme.test/terms/v2")
+        );
+    }
+
+    #[async_std::test]
+    async fn auto_refresh_stale_directory() {
+        let server = MockAcmeServer::new();
+        server.set_directory_cache_control("no-cache");
+        let mut client = server.client().await.unwrap();
+        client.set_directory_auto_refresh(true);
+        let account = client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+        let fetches = server.request_count(Endpoint::Directory);
+
+        server.set_terms_of_service("https://acme.test/terms/v2");
+        account.new_dns_order("example.com").await.unwrap();
+        assert_eq!(server.request_count(Endpoint::Directory), fetches + 1);
+        // The nonce from newAccount was still used
+        assert_eq!(server.request_count(Endpoint::NewNonce), 1);
+        assert_eq!(
+            account
+                .client()
+                .directory()
+                .meta
+                .terms_of_service
+                .as_deref(),
+            Some("https://acme.test/terms/v2")
+        );
+    }
+
+    #[async_std::test]
+    async fn register_account_key_algorithm() {
+        let server = MockAcmeServer::new();
+        let client = server.client().await.unwrap();
+        for key_algorithm in [
+            KeyAlgorithm::Es384,
+            KeyAlgorithm::Ed25519,
+            KeyAlgorithm::Rsa2048,
+        ] {
+            let account = client
+                .register_account_config(RegisterAccountConfig {
+                    contacts: vec![Contact::Email("admin@example.com".to_string())],
+                    terms_of_service_agreed: true,
+                    key_algorithm,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            assert_eq!(account.key().jws_alg(), key_algorithm.jws_alg());
+        }
+    }
+
+    #[async_std::test]
+    async fn account_exists() {
+        let server = MockAcmeServer::new();
+        let client = server.client().await.unwrap();
+        assert!(client
+            .account_exists(generate_account_key())
+            .await
+            .unwrap()
+            .is_none());
+        match client
+            .find_account(generate_account_key())
+            .await
+            .map_err(AcmeError::into_without_context)
+        {
+            Err(AcmeError::AccountDoesNotExist(problem)) => assert_eq!(problem.status, Some(400)),
+            res => panic!("expected AccountDoesNotExist, got {:?}", res.err()),
+        }
+
+        let account = client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+        let account_key = || account_key_from_jwk(&account.credentials().unwrap().private_jwk);
+        let found = client
+            .account_exists(account_key().unwrap())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.url(), account.url());
+
+        server.inject(Endpoint::NewAccount, Fault::MalformedProblem);
+        assert!(client.account_exists(account_key().unwrap()).await.is_err());
+    }
+
+    #[async_std::test]
+    async fn get_or_register_account() {
+        let server = MockAcmeServer::new();
+        server.set_terms_of_service("https://acme.test/terms/v1");
+        let client = server.client().await.unwrap();
+        let contacts = || vec![Contact::Email("admin@example.com".to_string())];
+
+        let (account, created) = client
+            .get_or_register_account(generate_account_key(), contacts(), AgreeTos::IfRequired)
+            .await
+            .unwrap();
+        assert!(created);
+        assert_eq!(account.resource().terms_of_service_agreed, Some(true));
+        let account_key = || account_key_from_jwk(&account.credentials().unwrap().private_jwk);
+
+        let (found, created) = client
+            .get_or_register_account(account_key().unwrap(), contacts(), AgreeTos::Never)
+            .await
+            .unwrap();
+        assert!(!created);
+        assert_eq!(found.url(), account.url());
+
+        server.change_terms_of_service("https://acme.test/terms/v2");
+        let (mut found, _) = client
+            .get_or_register_account(account_key().unwrap(), contacts(), AgreeTos::Never)
+            .await
+            .unwrap();
+        assert!(matches!(
+            found
+                .refresh()
+                .await
+                .map_err(AcmeError::into_without_context),
+            Err(AcmeError::TermsOfServiceChanged { .. })
+        ));
+        let (mut found, created) = client
+            .get_or_register_account(account_key().unwrap(), contacts(), AgreeTos::IfRequired)
+            .await
+            .unwrap();
+        assert!(!created);
+        found.refresh().await.unwrap();
+    }
+
+    #[async_std::test]
+    async fn timeouts() {
+        let server = MockAcmeServer::new();
+        let mut client = server.client().await.unwrap();
+        client.set_timeouts(Timeouts {
+            request: Some(Duration::from_secs(5)),
+            read: Some(Duration::from_secs(5)),
+            total: Some(Duration::from_secs(30)),
+        });
+        let account = client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+
+        server.inject(Endpoint::NewOrder, Fault::Hang);
+        match account
+            .new_dns_order("example.com")
+            .await
+            .map_err(AcmeError::into_without_context)
+        {
+            Err(AcmeError::Timeout { operation }) => assert_eq!(operation, AcmeOp::NewOrder),
+            res => panic!("expected timeout, got {:?}", res.err()),
+        }
+        account.new_dns_order("example.com").await.unwrap();
+
+        server.inject(Endpoint::Directory, Fault::Hang);
+        match client.refresh_directory().await {
+            Err(AcmeError::Timeout { operation }) => {
+                assert_eq!(operation, AcmeOp::GetDirectory)
+            }
+            res => panic!("expected timeout, got {:?}", res),
+        }
+    }
+
+    #[async_std::test]
+    async fn body_limits() {
+        let server = MockAcmeServer::new();
+        let mut client = server.client().await.unwrap();
+        let account = client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+
+        server.inject(Endpoint::NewOrder, Fault::Oversized { len: 4 << 20 });
+        match account
+            .new_dns_order("example.com")
+            .await
+            .map_err(AcmeError::into_without_context)
+        {
+            Err(AcmeError::ResponseTooLarge { limit }) => assert_eq!(limit, 1 << 20),
+            res => panic!("expected ResponseTooLarge, got {:?}", res.err()),
+        }
+        account.new_dns_order("example.com").await.unwrap();
+
+        client.set_body_limits(BodyLimits {
+            resource: Some(256),
+            ..Default::default()
+        });
+        let account = client
+            .load_account(&account.credentials().unwrap())
+            .unwrap();
+        server.inject(Endpoint::NewOrder, Fault::Oversized { len: 257 });
+        assert!(matches!(
+            account
+                .new_dns_order("example.com")
+                .await
+                .map_err(AcmeError::into_without_context),
+            Err(AcmeError::ResponseTooLarge { limit: 256 })
+        ));
+
+        client.set_body_limits(BodyLimits {
+            directory: Some(16),
+            ..Default::default()
+        });
+        assert!(matches!(
+            client.refresh_directory().await,
+            Err(AcmeError::ResponseTooLarge { limit: 16 })
+        ));
+    }
+}
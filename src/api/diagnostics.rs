@@ -0,0 +1,150 @@
+use std::collections::BTreeMap;
+
+use crate::error::AcmeResult;
+use crate::wire::directory::{AutoRenewalMetadata, DirectoryResource};
+use crate::wire::quirks::ServerSoftware;
+
+use super::client::Client;
+
+/// The optional features an ACME server advertises, as found by
+/// `Client::probe_capabilities`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerCapabilities {
+    /// The server implementation, if recognized; see `Quirks`.
+    pub software: ServerSoftware,
+
+    /// Pre-authorization via newAuthz.
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-7.4.1
+    pub new_authz: bool,
+
+    /// newAccount requests need an external account binding.
+    pub external_account_required: bool,
+
+    /// Certificate profile names, mapped to their descriptions.
+    pub profiles: BTreeMap<String, String>,
+
+    /// ACME Renewal Information (ARI).
+    pub renewal_info: bool,
+
+    /// Whether the server offers alternate certificate chains. The directory
+    /// doesn't say, so this is None until a certificate has been downloaded;
+    /// see `OrderStateValid::get_certificate_chain_with_alternates`.
+    pub alternate_chains: Option<bool>,
+
+    /// Authorizations covering subdomains (RFC 9444).
+    pub subdomain_auth_allowed: bool,
+
+    /// STAR certificate limits (RFC 8739), if supported.
+    pub auto_renewal: Option<AutoRenewalMetadata>,
+
+    /// Delegated certificates (RFC 9115).
+    pub delegation_enabled: bool,
+
+    pub terms_of_service: Option<String>,
+
+    pub caa_identities: Vec<String>,
+
+    /// Deviations from RFC 8555 noticed while probing that didn't stop the
+    /// probe, e.g. endpoints served over plain HTTP.
+    pub warnings: Vec<String>,
+}
+
+impl ServerCapabilities {
+    fn from_directory(directory: &DirectoryResource) -> Self {
+        let meta = &directory.meta;
+        let mut warnings = Vec::new();
+        let mut endpoints = vec![
+            ("newNonce", directory.new_nonce.as_str()),
+            ("newAccount", &directory.new_account),
+            ("newOrder", &directory.new_order),
+            ("revokeCert", &directory.revoke_cert),
+            ("keyChange", &directory.key_change),
+        ];
+        endpoints.extend(directory.new_authz.as_deref().map(|url| ("newAuthz", url)));
+        endpoints.extend(
+            directory
+                .renewal_info
+                .as_deref()
+                .map(|url| ("renewalInfo", url)),
+        );
+        for (name, url) in endpoints {
+            if !url.starts_with("https://") {
+                warnings.push(format!("{} isn't an https URL: {}", name, url));
+            }
+        }
+
+        Self {
+            software: ServerSoftware::detect(directory),
+            new_authz: directory.new_authz.is_some(),
+            external_account_required: meta.external_account_required.unwrap_or(false),
+            profiles: meta.profiles.clone(),
+            renewal_info: directory.renewal_info.is_some(),
+            alternate_chains: None,
+            subdomain_auth_allowed: meta.subdomain_auth_allowed(),
+            auto_renewal: meta.auto_renewal(),
+            delegation_enabled: meta.delegation_enabled(),
+            terms_of_service: meta.terms_of_service.clone(),
+            caa_identities: meta.caa_identities.clone(),
+            warnings,
+        }
+    }
+}
+
+impl Client {
+    /// Checks that the server answers the basic unauthenticated requests and
+    /// reports which optional features it supports, for tooling that adapts
+    /// per CA.
+    ///
+    /// The directory is re-fetched if its URL is known, and a nonce is taken
+    /// from the newNonce endpoint; failures of either are returned as errors.
+    pub async fn probe_capabilities(&self) -> AcmeResult<ServerCapabilities> {
+        let client = self.acme_client();
+        let directory = match client.directory_url() {
+            Some(_) => client.refresh_directory().await?,
+            None => client.directory(),
+        };
+        let mut capabilities = ServerCapabilities::from_directory(&directory);
+
+        let nonce = client.new_nonce().await?;
+        if nonce.is_empty()
+            || !nonce
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        {
+            capabilities
+                .warnings
+                .push(format!("newNonce returned a malformed nonce {:?}", nonce));
+        }
+        Ok(capabilities)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::mock_server::{Endpoint, MockAcmeServer};
+    use crate::wire::quirks::ServerSoftware;
+
+    #[async_std::test]
+    async fn probe_mock_server() {
+        let server = MockAcmeServer::new();
+        server.set_subdomain_auth_allowed(true);
+        let client = server.client().await.unwrap();
+
+        let capabilities = client.probe_capabilities().await.unwrap();
+        assert_eq!(capabilities.software, ServerSoftware::Unknown);
+        assert!(capabilities.new_authz);
+        assert!(capabilities.subdomain_auth_allowed);
+        assert!(!capabilities.external_account_required);
+        assert!(!capabilities.renewal_info);
+        assert!(capabilities.profiles.is_empty());
+        assert_eq!(capabilities.alternate_chains, None);
+        assert_eq!(
+            capabilities.terms_of_service.as_deref(),
+            Some("https://acme.test/terms")
+        );
+        assert!(capabilities.warnings.is_empty());
+
+        assert_eq!(server.request_count(Endpoint::Directory), 2);
+        assert_eq!(server.request_count(Endpoint::NewNonce), 1);
+    }
+}
@@ -0,0 +1,702 @@
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use hickory_resolver::{
+    config::{NameServerConfigGroup, ResolverConfig, ResolverOpts},
+    name_server::{ConnectionProvider, TokioConnectionProvider},
+    proto::rr::{RData, RecordType},
+    Name, ResolveError, Resolver, TokioResolver,
+};
+use serde::Deserialize;
+
+use crate::transport::{http_types::Url, HttpClient, Request};
+use crate::{
+    clock::{system_clock, Clock},
+    sleep::{default_sleeper, Sleeper},
+    wire::challenge::ChallengeType,
+};
+
+use super::{
+    dns_provider::CnameResolver,
+    solver::{ChallengeParams, ChallengeSolver},
+};
+
+/// Looks up TXT records.
+#[async_trait]
+pub trait TxtResolver: Send + Sync {
+    /// Returns the TXT values at `name`, with multi-string records joined.
+    async fn lookup_txt(&self, name: &str) -> anyhow::Result<Vec<String>>;
+}
+
+/// Follows at most this many CNAMEs from a record name.
+const MAX_CNAME_HOPS: usize = 8;
+
+/// Looks up TXT records at the authoritative nameservers of the name's zone,
+/// so that a caching resolver's negative answer from before the record was
+/// added can't hide it until its TTL expires. CNAMEs (e.g. to an acme-dns
+/// zone) and the zone's nameservers are looked up with a recursive
+/// resolver; the TXT lookups aren't cached.
+///
+/// Uses hickory-resolver, which with `TokioConnectionProvider` needs a
+/// Tokio runtime.
+pub struct AuthoritativeTxtResolver<P: ConnectionProvider = TokioConnectionProvider> {
+    recursive: Resolver<P>,
+    provider: P,
+    port: u16,
+}
+
+impl AuthoritativeTxtResolver {
+    /// Uses the system's resolver configuration (e.g. /etc/resolv.conf) to
+    /// find the nameservers.
+    pub fn from_system_conf() -> anyhow::Result<Self> {
+        Ok(Self::new(
+            TokioResolver::builder_tokio()?.build(),
+            TokioConnectionProvider::default(),
+        ))
+    }
+}
+
+impl<P: ConnectionProvider> AuthoritativeTxtResolver<P> {
+    /// Finds nameservers with `recursive` and queries them with connections
+    /// from `provider`.
+    pub fn new(recursive: Resolver<P>, provider: P) -> Self {
+        Self {
+            recursive,
+            provider,
+            port: 53,
+        }
+    }
+
+    /// The port nameservers are queried on (default 53).
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// The name `name` is an alias of, after following CNAMEs.
+    async fn canonical_name(&self, name: &str) -> anyhow::Result<Name> {
+        let mut name = fqdn(name)?;
+        for _ in 0..MAX_CNAME_HOPS {
+            match self.recursive_cname(&name).await? {
+                Some(target) => name = target,
+                None => return Ok(name),
+            }
+        }
+        bail!("more than {} CNAMEs from {}", MAX_CNAME_HOPS, name)
+    }
+
+    async fn recursive_cname(&self, name: &Name) -> anyhow::Result<Option<Name>> {
+        let lookup = match self.recursive.lookup(name.clone(), RecordType::CNAME).await {
+            Ok(lookup) => lookup,
+            Err(err) if is_no_records(&err) => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(lookup.record_iter().find_map(|record| match record.data() {
+            RData::CNAME(cname) if record.name() == name => Some(cname.0.clone()),
+            _ => None,
+        }))
+    }
+
+    /// The addresses of the nameservers of the zone `name` is in: those of
+    /// the closest enclosing name with NS records.
+    async fn nameservers(&self, name: &Name) -> anyhow::Result<Vec<IpAddr>> {
+        let mut zone = name.clone();
+        loop {
+            match self.recursive.ns_lookup(zone.clone()).await {
+                Ok(nameservers) => {
+                    let mut addrs = Vec::new();
+                    for nameserver in nameservers.iter() {
+                        match self.recursive.lookup_ip(nameserver.0.clone()).await {
+                            Ok(ips) => addrs.extend(ips.iter()),
+                            Err(err) => {
+                                log::debug!("nameserver {} not resolved: {}", nameserver.0, err)
+                            }
+                        }
+                    }
+                    if addrs.is_empty() {
+                        bail!("no nameserver of {} could be resolved", zone);
+                    }
+                    return Ok(addrs);
+                }
+                Err(err) if is_no_records(&err) && !zone.is_root() => zone = zone.base_name(),
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<P: ConnectionProvider> TxtResolver for AuthoritativeTxtResolver<P> {
+    async fn lookup_txt(&self, name: &str) -> anyhow::Result<Vec<String>> {
+        let name = self.canonical_name(name).await?;
+        let nameservers = self.nameservers(&name).await?;
+        let mut options = ResolverOpts::default();
+        options.recursion_desired = false;
+        options.cache_size = 0;
+        let config = ResolverConfig::from_parts(
+            None,
+            Vec::new(),
+            NameServerConfigGroup::from_ips_clear(&nameservers, self.port, true),
+        );
+        let authoritative = Resolver::builder_with_config(config, self.provider.clone())
+            .with_options(options)
+            .build();
+        let lookup = match authoritative.txt_lookup(name).await {
+            Ok(lookup) => lookup,
+            Err(err) if is_no_records(&err) => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(lookup
+            .iter()
+            .map(|txt| {
+                let value: Vec<u8> = txt
+                    .txt_data()
+                    .iter()
+                    .flat_map(|s| s.iter())
+                    .copied()
+                    .collect();
+                String::from_utf8_lossy(&value).into_owned()
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl<P: ConnectionProvider> CnameResolver for AuthoritativeTxtResolver<P> {
+    async fn lookup_cname(&self, name: &str) -> anyhow::Result<Option<String>> {
+        Ok(self
+            .recursive_cname(&fqdn(name)?)
+            .await?
+            .map(|target| target.to_ascii().trim_end_matches('.').to_string()))
+    }
+}
+
+fn fqdn(name: &str) -> anyhow::Result<Name> {
+    Ok(Name::from_ascii(format!(
+        "{}.",
+        name.trim_end_matches('.')
+    ))?)
+}
+
+/// NXDOMAIN, or a name without records of the type asked for.
+fn is_no_records(err: &ResolveError) -> bool {
+    err.is_no_records_found() || err.is_nx_domain()
+}
+
+pub static CLOUDFLARE_DOH_URL: &str = "https://cloudflare-dns.com/dns-query";
+pub static GOOGLE_DOH_URL: &str = "https://dns.google/resolve";
+
+/// Resolves TXT (and CNAME) records with a DNS-over-HTTPS JSON API, as
+/// served by e.g. Cloudflare and Google.
+///
+/// Public resolvers cache negative answers, so a lookup made before the
+/// record was added can keep it hidden for the zone's negative TTL; prefer
+/// `AuthoritativeTxtResolver` for propagation checks.
+pub struct DohTxtResolver {
+    http: Arc<dyn HttpClient>,
+    url: String,
+}
+
+impl DohTxtResolver {
+    pub fn new(http: impl Into<Arc<dyn HttpClient>>, url: impl Into<String>) -> Self {
+        Self {
+            http: http.into(),
+            url: url.into(),
+        }
+    }
+
+    pub fn cloudflare(http: impl Into<Arc<dyn HttpClient>>) -> Self {
+        Self::new(http, CLOUDFLARE_DOH_URL)
+    }
+
+    async fn query(&self, name: &str, rr_type: &str) -> anyhow::Result<String> {
+        let mut url = Url::parse(&self.url)?;
+        url.query_pairs_mut()
+            .append_pair("name", name)
+            .append_pair("type", rr_type);
+        let mut req = Request::get(url);
+        req.insert_header("Accept", "application/dns-json");
+        let mut resp = self.http.send(req).await.map_err(|err| err.into_inner())?;
+        if !resp.status().is_success() {
+            bail!("DNS-over-HTTPS query failed with status {}", resp.status());
+        }
+        resp.body_string().await.map_err(|err| err.into_inner())
+    }
+}
+
+#[async_trait]
+impl TxtResolver for DohTxtResolver {
+    async fn lookup_txt(&self, name: &str) -> anyhow::Result<Vec<String>> {
+        let body = self.query(name, "TXT").await?;
+        parse_doh_txt(&body)
+    }
+}
+
+#[async_trait]
+impl CnameResolver for DohTxtResolver {
+    async fn lookup_cname(&self, name: &str) -> anyhow::Result<Option<String>> {
+        let body = self.query(name, "CNAME").await?;
+        Ok(parse_doh_answers(&body, DNS_TYPE_CNAME)?
+            .into_iter()
+            .next()
+            .map(|target| target.trim_end_matches('.').to_string()))
+    }
+}
+
+#[derive(Deserialize)]
+struct DohResponse {
+    #[serde(rename = "Status")]
+    status: u16,
+    #[serde(rename = "Answer", default)]
+    answer: Vec<DohAnswer>,
+}
+
+#[derive(Deserialize)]
+struct DohAnswer {
+    #[serde(rename = "type")]
+    type_: u16,
+    data: String,
+}
+
+const DNS_TYPE_CNAME: u16 = 5;
+const DNS_TYPE_TXT: u16 = 16;
+const DNS_RCODE_NXDOMAIN: u16 = 3;
+
+/// Returns the data of the answers of type `rr_type`.
+fn parse_doh_answers(body: &str, rr_type: u16) -> anyhow::Result<Vec<String>> {
+    let resp: DohResponse = serde_json::from_str(body)?;
+    match resp.status {
+        0 | DNS_RCODE_NXDOMAIN => {}
+        rcode => bail!("DNS query failed with rcode {}", rcode),
+    }
+    Ok(resp
+        .answer
+        .into_iter()
+        .filter(|answer| answer.type_ == rr_type)
+        .map(|answer| answer.data)
+        .collect())
+}
+
+fn parse_doh_txt(body: &str) -> anyhow::Result<Vec<String>> {
+    Ok(parse_doh_answers(body, DNS_TYPE_TXT)?
+        .iter()
+        .map(|data| join_txt_strings(data))
+        .collect())
+}
+
+/// Joins the quoted character-strings of a TXT record's presentation format,
+/// e.g. `"abc" "def"` -> `abcdef`.
+fn join_txt_strings(data: &str) -> String {
+    if !data.starts_with('"') {
+        return data.to_string();
+    }
+    let mut value = String::new();
+    let mut quoted = false;
+    let mut chars = data.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '\\' if quoted => value.extend(chars.next()),
+            c if quoted => value.push(c),
+            _ => {}
+        }
+    }
+    value
+}
+
+/// Waits for dns-01 TXT records to become visible before the ACME server is
+/// asked to validate them, since a failed validation can't be retried.
+#[derive(Clone)]
+pub struct DnsPropagationChecker {
+    resolver: Arc<dyn TxtResolver>,
+    sleeper: Arc<dyn Sleeper>,
+    clock: Arc<dyn Clock>,
+    timeout: Duration,
+    poll_interval: Duration,
+}
+
+impl DnsPropagationChecker {
+    pub fn new(resolver: impl TxtResolver + 'static) -> Self {
+        Self {
+            resolver: Arc::new(resolver),
+            sleeper: default_sleeper(),
+            clock: system_clock(),
+            timeout: Duration::from_secs(120),
+            poll_interval: Duration::from_secs(5),
+        }
+    }
+
+    /// Sets the timer used between lookups (default
+    /// `sleep::default_sleeper()`).
+    pub fn with_sleeper(mut self, sleeper: impl Sleeper + 'static) -> Self {
+        self.sleeper = Arc::new(sleeper);
+        self
+    }
+
+    /// Sets the clock the timeout is measured by (default the system time),
+    /// e.g. the client's: `with_clock(client.clock().clone())`.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// How long to wait for a record before giving up (default 2 minutes).
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How long to wait between lookups (default 5 seconds).
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Polls until `name` has a TXT record with `value`. Lookup errors are
+    /// treated like a missing record until the timeout.
+    pub async fn wait_for_txt(&self, name: &str, value: &str) -> anyhow::Result<()> {
+        let deadline = self.clock.now() + chrono::Duration::from_std(self.timeout)?;
+        loop {
+            match self.resolver.lookup_txt(name).await {
+                Ok(values) if values.iter().any(|v| v == value) => return Ok(()),
+                Ok(_) => log::debug!("TXT record {} not yet visible", name),
+                Err(err) => log::debug!("TXT lookup for {} failed: {}", name, err),
+            }
+            if self.clock.now() >= deadline {
+                return Err(anyhow!(
+                    "TXT record {} {:?} not visible after {:?}",
+                    name,
+                    value,
+                    self.timeout
+                ));
+            }
+            self.sleeper.sleep(self.poll_interval).await;
+        }
+    }
+}
+
+/// Wraps a dns-01 capable solver so that presented TXT records are checked
+/// with a `DnsPropagationChecker` before the challenge is responded to.
+pub struct DnsCheckingSolver<S> {
+    inner: S,
+    checker: DnsPropagationChecker,
+}
+
+impl<S: ChallengeSolver> DnsCheckingSolver<S> {
+    pub fn new(inner: S, checker: DnsPropagationChecker) -> Self {
+        Self { inner, checker }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+}
+
+#[async_trait]
+impl<S: ChallengeSolver> ChallengeSolver for DnsCheckingSolver<S> {
+    fn supported_types(&self) -> Vec<ChallengeType> {
+        self.inner.supported_types()
+    }
+
+    async fn present(&self, params: &ChallengeParams) -> anyhow::Result<()> {
+        self.inner.present(params).await
+    }
+
+    async fn wait_until_ready(&self, params: &ChallengeParams) -> anyhow::Result<()> {
+        self.inner.wait_until_ready(params).await?;
+        if params.challenge_type == ChallengeType::Dns01 {
+            self.checker
+                .wait_for_txt(&params.dns01_txt_name(), &params.dns01_txt_value())
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn cleanup(&self, params: &ChallengeParams) -> anyhow::Result<()> {
+        self.inner.cleanup(params).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{Ipv4Addr, SocketAddr},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+    };
+
+    use chrono::Utc;
+    use hickory_resolver::proto::{
+        op::{Message, MessageType, ResponseCode},
+        rr::{
+            rdata::{A, CNAME, NS, SOA, TXT},
+            Record,
+        },
+    };
+
+    use crate::{
+        clock::ManualClock,
+        sleep::{FnSleeper, NoSleep},
+    };
+
+    use super::*;
+
+    /// Serves example.com on localhost, with ns1.example.com as its
+    /// nameserver, and also answers recursive queries for it.
+    #[derive(Default)]
+    struct TestNameserver {
+        txt: Mutex<Vec<String>>,
+        txt_queries: AtomicUsize,
+        recursive_txt_queries: AtomicUsize,
+    }
+
+    impl TestNameserver {
+        async fn start(self: &Arc<Self>) -> SocketAddr {
+            let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let addr = socket.local_addr().unwrap();
+            let nameserver = self.clone();
+            tokio::spawn(async move {
+                let mut buf = [0; 512];
+                loop {
+                    let (len, peer) = socket.recv_from(&mut buf).await.unwrap();
+                    let request = Message::from_vec(&buf[..len]).unwrap();
+                    let response = nameserver.answer(&request).to_vec().unwrap();
+                    socket.send_to(&response, peer).await.unwrap();
+                }
+            });
+            addr
+        }
+
+        fn answer(&self, request: &Message) -> Message {
+            let query = request.queries()[0].clone();
+            let name = query.name().to_ascii().to_ascii_lowercase();
+            let mut response = Message::new();
+            response
+                .set_id(request.id())
+                .set_message_type(MessageType::Response)
+                .set_op_code(request.op_code())
+                .set_recursion_desired(request.recursion_desired())
+                .set_authoritative(true)
+                .add_query(query.clone());
+            let record = |rdata| Record::from_rdata(query.name().clone(), 3600, rdata);
+            match (name.as_str(), query.query_type()) {
+                ("example.com.", RecordType::NS) => {
+                    let ns = Name::from_ascii("ns1.example.com.").unwrap();
+                    response.add_answer(record(RData::NS(NS(ns))));
+                }
+                ("ns1.example.com.", RecordType::A) => {
+                    response.add_answer(record(RData::A(A(Ipv4Addr::LOCALHOST))));
+                }
+                ("_acme-challenge.alias.example.com.", RecordType::CNAME) => {
+                    let target = Name::from_ascii("_acme-challenge.example.com.").unwrap();
+                    response.add_answer(record(RData::CNAME(CNAME(target))));
+                }
+                ("_acme-challenge.example.com.", RecordType::TXT) => {
+                    let counter = match request.recursion_desired() {
+                        true => &self.recursive_txt_queries,
+                        false => &self.txt_queries,
+                    };
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    for value in self.txt.lock().unwrap().iter() {
+                        response.add_answer(record(RData::TXT(TXT::new(vec![value.clone()]))));
+                    }
+                    if response.answers().is_empty() {
+                        // Cacheable for an hour (the SOA minimum)
+                        let zone = Name::from_ascii("example.com.").unwrap();
+                        let soa = SOA::new(zone.clone(), zone.clone(), 1, 3600, 600, 86400, 3600);
+                        response
+                            .set_response_code(ResponseCode::NXDomain)
+                            .add_name_server(Record::from_rdata(zone, 3600, RData::SOA(soa)));
+                    }
+                }
+                (
+                    "example.com."
+                    | "ns1.example.com."
+                    | "_acme-challenge.alias.example.com."
+                    | "_acme-challenge.example.com.",
+                    _,
+                ) => {}
+                _ => {
+                    response.set_response_code(ResponseCode::NXDomain);
+                }
+            }
+            response
+        }
+    }
+
+    #[tokio::test]
+    async fn authoritative_lookup_isnt_cached() {
+        let nameserver = Arc::new(TestNameserver::default());
+        let addr = nameserver.start().await;
+        let config = ResolverConfig::from_parts(
+            None,
+            Vec::new(),
+            NameServerConfigGroup::from_ips_clear(&[addr.ip()], addr.port(), true),
+        );
+        let recursive = || {
+            Resolver::builder_with_config(config.clone(), TokioConnectionProvider::default())
+                .build()
+        };
+        let resolver =
+            AuthoritativeTxtResolver::new(recursive(), TokioConnectionProvider::default())
+                .with_port(addr.port());
+
+        // A caching resolver remembers that the record didn't exist
+        let caching = recursive();
+        assert!(caching
+            .txt_lookup("_acme-challenge.example.com.")
+            .await
+            .is_err());
+        assert!(resolver
+            .lookup_txt("_acme-challenge.example.com")
+            .await
+            .unwrap()
+            .is_empty());
+
+        nameserver.txt.lock().unwrap().push("abc".to_string());
+        assert!(caching
+            .txt_lookup("_acme-challenge.example.com.")
+            .await
+            .is_err());
+        assert_eq!(
+            resolver
+                .lookup_txt("_acme-challenge.example.com")
+                .await
+                .unwrap(),
+            ["abc"]
+        );
+        // Through a CNAME
+        assert_eq!(
+            resolver
+                .lookup_txt("_acme-challenge.alias.example.com")
+                .await
+                .unwrap(),
+            ["abc"]
+        );
+        assert_eq!(
+            resolver
+                .lookup_cname("_acme-challenge.alias.example.com")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("_acme-challenge.example.com")
+        );
+        assert_eq!(nameserver.txt_queries.load(Ordering::SeqCst), 3);
+        assert_eq!(nameserver.recursive_txt_queries.load(Ordering::SeqCst), 1);
+    }
+
+    #[async_std::test]
+    async fn doh_query_is_encoded() {
+        let urls = Arc::new(Mutex::new(Vec::new()));
+        let seen = urls.clone();
+        let http = crate::transport::FnHttpClient::new(move |req: Request| {
+            seen.lock().unwrap().push(req.url().to_string());
+            async {
+                let mut resp = crate::transport::Response::new(200);
+                resp.set_body(r#"{"Status":3}"#);
+                Ok(resp)
+            }
+        });
+        let http: Arc<dyn HttpClient> = Arc::new(http);
+        let resolver = DohTxtResolver::new(http, "https://dns.example/resolve?ct=json");
+        assert!(resolver
+            .lookup_txt("_acme-challenge.example.com&type=A#")
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            *urls.lock().unwrap(),
+            ["https://dns.example/resolve?ct=json&name=_acme-challenge.example.com%26type%3DA%23&type=TXT"]
+        );
+    }
+
+    /// Returns `value` from the given lookup onwards.
+    struct DelayedResolver {
+        value: String,
+        lookups: Mutex<usize>,
+        visible_after: usize,
+    }
+
+    #[async_trait]
+    impl TxtResolver for DelayedResolver {
+        async fn lookup_txt(&self, _name: &str) -> anyhow::Result<Vec<String>> {
+            let mut lookups = self.lookups.lock().unwrap();
+            *lookups += 1;
+            if *lookups > self.visible_after {
+                Ok(vec![self.value.clone()])
+            } else {
+                Ok(vec![])
+            }
+        }
+    }
+
+    fn resolver(visible_after: usize) -> DelayedResolver {
+        DelayedResolver {
+            value: "abc".to_string(),
+            lookups: Mutex::new(0),
+            visible_after,
+        }
+    }
+
+    #[test]
+    fn parse_doh_response() {
+        let body = r#"{"Status":0,"Answer":[
+            {"name":"_acme-challenge.example.com","type":5,"TTL":60,"data":"other.example.com."},
+            {"name":"_acme-challenge.example.com","type":16,"TTL":60,"data":"\"abc\" \"d\\\"ef\""}
+        ]}"#;
+        assert_eq!(parse_doh_txt(body).unwrap(), vec!["abcd\"ef"]);
+        assert!(parse_doh_txt(r#"{"Status":3}"#).unwrap().is_empty());
+        assert!(parse_doh_txt(r#"{"Status":2}"#).is_err());
+    }
+
+    #[async_std::test]
+    async fn waits_for_record() {
+        let checker = DnsPropagationChecker::new(resolver(2)).with_sleeper(NoSleep);
+        checker
+            .wait_for_txt("_acme-challenge.example.com", "abc")
+            .await
+            .unwrap();
+    }
+
+    #[async_std::test]
+    async fn times_out_by_clock() {
+        let clock = Arc::new(ManualClock::new(Utc::now()));
+        let start = clock.now();
+        let sleeps = Arc::new(AtomicUsize::new(0));
+        let sleeper = {
+            let (clock, sleeps) = (clock.clone(), sleeps.clone());
+            FnSleeper(move |duration| {
+                clock.advance(chrono::Duration::from_std(duration).unwrap());
+                sleeps.fetch_add(1, Ordering::SeqCst);
+                async {}
+            })
+        };
+        let checker = DnsPropagationChecker::new(resolver(usize::MAX))
+            .with_sleeper(sleeper)
+            .with_clock(clock.clone())
+            .with_timeout(Duration::from_secs(60))
+            .with_poll_interval(Duration::from_secs(5));
+        assert!(checker
+            .wait_for_txt("_acme-challenge.example.com", "abc")
+            .await
+            .is_err());
+        // Lookups at 0, 5, ..., 60 seconds
+        assert_eq!(sleeps.load(Ordering::SeqCst), 12);
+        assert_eq!(clock.now() - start, chrono::Duration::seconds(60));
+    }
+
+    #[async_std::test]
+    async fn times_out() {
+        let checker = DnsPropagationChecker::new(resolver(usize::MAX))
+            .with_sleeper(FnSleeper(async_std::task::sleep))
+            .with_timeout(Duration::from_millis(20))
+            .with_poll_interval(Duration::from_millis(5));
+        assert!(checker
+            .wait_for_txt("_acme-challenge.example.com", "abc")
+            .await
+            .is_err());
+    }
+}
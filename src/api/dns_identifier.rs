@@ -1,6 +1,15 @@
-use crate::wire::identifier::AcmeIdentifier;
+use std::net::IpAddr;
 
-#[derive(Debug)]
+use icu_properties::{props::GeneralCategory, CodePointMapData};
+use idna::uts46::{AsciiDenyList, DnsLength, Hyphens, Uts46};
+use sha3::{Digest, Sha3_256};
+
+use crate::{
+    error::{AcmeError, AcmeResult},
+    wire::identifier::AcmeIdentifier,
+};
+
+#[derive(Clone, Debug)]
 pub struct DnsIdentifier(String);
 
 impl DnsIdentifier {
@@ -47,3 +56,312 @@ impl From<DnsIdentifier> for String {
         ident.0
     }
 }
+
+/// Checks that `name` is a syntactically valid (optionally wildcard) DNS name
+/// in ASCII form.
+pub fn validate_dns_name(name: &str) -> AcmeResult<()> {
+    if !name.is_ascii() {
+        return Err(AcmeError::InvalidIdentifier(name.to_string()));
+    }
+    IdentifierValidator::default()
+        .normalize_dns_name(name)
+        .map(drop)
+}
+
+/// Checks and normalizes DNS names before they're sent to a CA, which would
+/// otherwise reject them with an opaque rejectedIdentifier problem.
+///
+/// Names are converted to lowercase A-labels (so "Bücher.example" becomes
+/// "xn--bcher-kva.example") and a trailing dot is removed.
+#[derive(Clone, Debug)]
+pub struct IdentifierValidator {
+    allow_wildcard: bool,
+    allow_underscore: bool,
+}
+
+impl Default for IdentifierValidator {
+    fn default() -> Self {
+        Self {
+            allow_wildcard: true,
+            allow_underscore: false,
+        }
+    }
+}
+
+impl IdentifierValidator {
+    /// Whether a leading "*." label is accepted. Defaults to true.
+    pub fn allow_wildcard(mut self, allow: bool) -> Self {
+        self.allow_wildcard = allow;
+        self
+    }
+
+    /// Whether labels may contain underscores. Publicly-trusted CAs may not
+    /// issue for such names, so this defaults to false.
+    pub fn allow_underscore(mut self, allow: bool) -> Self {
+        self.allow_underscore = allow;
+        self
+    }
+
+    /// Returns the normalized form of `name`, or `InvalidIdentifier`.
+    pub fn normalize_dns_name(&self, name: &str) -> AcmeResult<String> {
+        let invalid = || AcmeError::InvalidIdentifier(name.to_string());
+        let policy = |reason: &str| AcmeError::InvalidIdentifier(format!("{}: {}", name, reason));
+        let base = name.strip_suffix('.').unwrap_or(name);
+        let (wildcard, base) = match base.strip_prefix("*.") {
+            Some(_) if !self.allow_wildcard => return Err(policy("wildcards are not allowed")),
+            Some(base) => (true, base),
+            None => (false, base),
+        };
+        if base.is_empty() {
+            return Err(invalid());
+        }
+        // Wildcard policy (CA/B Baseline Requirements section 3.2.2.6)
+        if base.contains('*') {
+            return Err(policy("a wildcard must be the entire leftmost label"));
+        }
+        if wildcard && !base.contains('.') {
+            return Err(policy("wildcard of a top-level domain"));
+        }
+        if wildcard && base.parse::<IpAddr>().is_ok() {
+            return Err(policy("IP addresses can't be wildcards"));
+        }
+        // UTS 46 processing (non-transitional): maps case and compatibility
+        // forms, rejects disallowed code points and bad punycode. ASCII
+        // rules are left to `valid_label`, IDNA 2008's to `idna2008_label`.
+        let ascii = Uts46::new()
+            .to_ascii(
+                base.as_bytes(),
+                AsciiDenyList::EMPTY,
+                Hyphens::Check,
+                DnsLength::Ignore,
+            )
+            .map_err(|_| invalid())?
+            .into_owned();
+        if ascii.len() > 253
+            || !ascii
+                .split('.')
+                .all(|label| self.valid_label(label) && idna2008_label(label))
+        {
+            return Err(invalid());
+        }
+        // Special-use (RFC 7686); CAs only issue for v3 onion services
+        if ascii.rsplit('.').next() == Some("onion") && onion_v3_public_key(&ascii).is_none() {
+            return Err(invalid());
+        }
+        Ok(if wildcard {
+            format!("*.{}", ascii)
+        } else {
+            ascii
+        })
+    }
+
+    fn valid_label(&self, label: &str) -> bool {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            // Reserved for A-labels (RFC 5891 section 4.2.3.1)
+            && (label.get(2..4) != Some("--") || label.starts_with("xn--"))
+            && label.bytes().all(|b| {
+                b.is_ascii_alphanumeric() || b == b'-' || (b == b'_' && self.allow_underscore)
+            })
+    }
+}
+
+/// Whether an A-label only has code points IDNA 2008 allows (RFC 5892
+/// section 2.1, LetterDigits, plus the CONTEXTJ joiners UTS 46 has already
+/// checked). UTS 46 alone also lets through symbols such as "☃".
+fn idna2008_label(label: &str) -> bool {
+    use icu_properties::props::GeneralCategory::*;
+
+    let Some(encoded) = label.strip_prefix("xn--") else {
+        return true;
+    };
+    let Some(unicode) = idna::punycode::decode_to_string(encoded) else {
+        return false;
+    };
+    let categories = CodePointMapData::<GeneralCategory>::new();
+    unicode.chars().all(|c| {
+        c.is_ascii()
+            || matches!(c, '\u{200c}' | '\u{200d}')
+            || matches!(
+                categories.get(c),
+                LowercaseLetter
+                    | UppercaseLetter
+                    | ModifierLetter
+                    | OtherLetter
+                    | NonspacingMark
+                    | SpacingMark
+                    | DecimalNumber
+            )
+    })
+}
+
+/// The Ed25519 public key encoded in a v3 onion service name such as
+/// "pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd.onion" (or a
+/// subdomain of one), if `name` is one.
+/// https://spec.torproject.org/rend-spec/encoding-onion-addresses.html
+pub fn onion_v3_public_key(name: &str) -> Option<[u8; 32]> {
+    let name = name.strip_prefix("*.").unwrap_or(name);
+    let name = name.strip_suffix('.').unwrap_or(name);
+    let mut labels = name.rsplit('.');
+    if !labels.next()?.eq_ignore_ascii_case("onion") {
+        return None;
+    }
+    let address = labels.next()?;
+    if address.len() != 56 {
+        return None;
+    }
+    // base32(PUBKEY | CHECKSUM[..2] | VERSION), 56 * 5 bits = 35 bytes
+    let mut decoded = Vec::with_capacity(35);
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in address.bytes() {
+        let value = match c.to_ascii_lowercase() {
+            c @ b'a'..=b'z' => c - b'a',
+            c @ b'2'..=b'7' => c - b'2' + 26,
+            _ => return None,
+        };
+        buffer = (buffer << 5) | u32::from(value);
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    let (public_key, checksum, version) = (&decoded[..32], &decoded[32..34], decoded[34]);
+    if version != 3 {
+        return None;
+    }
+    // CHECKSUM = SHA3-256(".onion checksum" | PUBKEY | VERSION)
+    let expected = Sha3_256::new()
+        .chain(b".onion checksum")
+        .chain(public_key)
+        .chain([version])
+        .finalize();
+    if checksum != &expected[..2] {
+        return None;
+    }
+    let public_key: [u8; 32] = public_key.try_into().unwrap();
+    ed25519_dalek::PublicKey::from_bytes(&public_key).ok()?;
+    Some(public_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::challenge::Challenge;
+
+    use super::*;
+
+    #[test]
+    fn dns01_record_names() {
+        for (name, wildcard) in [
+            ("example.com", false),
+            ("example.com", true),
+            ("Example.COM.", true),
+        ] {
+            let identifier =
+                DnsIdentifier::from_acme_identifier(&AcmeIdentifier::dns(name), wildcard).unwrap();
+            assert_eq!(
+                Challenge::dns01_record_name(&identifier),
+                "_acme-challenge.example.com"
+            );
+        }
+    }
+
+    #[test]
+    fn valid_dns_names() {
+        for name in [
+            "example.com",
+            "*.example.com",
+            "xn--bcher-kva.example",
+            "a-b.c",
+            "pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd.onion",
+            "*.www.pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd.onion",
+        ] {
+            validate_dns_name(name).unwrap();
+        }
+    }
+
+    #[test]
+    fn invalid_dns_names() {
+        for name in [
+            "",
+            "*.",
+            "a..b",
+            "-a.b",
+            "a-.b",
+            "a_b.c",
+            "*.*.example.com",
+            "ex ample.com",
+            "onion",
+            // v2 onion service
+            "expyuzz4wqqyqhjn.onion",
+            // Version byte 2
+            "pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryc.onion",
+            // Corrupted checksum
+            "pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscsyd.onion",
+            // Mistyped key, which is still a valid Ed25519 point
+            "pq6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd.onion",
+        ] {
+            validate_dns_name(name).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn normalize_dns_names() {
+        let validator = IdentifierValidator::default();
+        for (name, normalized) in [
+            ("Example.COM.", "example.com"),
+            ("*.example.com", "*.example.com"),
+            ("Bücher.example", "xn--bcher-kva.example"),
+            ("*.bücher.example.", "*.xn--bcher-kva.example"),
+            ("XN--BCHER-KVA.example", "xn--bcher-kva.example"),
+            // Not transitional (IDNA 2003) processing
+            ("faß.de", "xn--fa-hia.de"),
+        ] {
+            assert_eq!(validator.normalize_dns_name(name).unwrap(), normalized);
+        }
+
+        for name in [
+            "a_b.example.com",
+            "xn--bcher-kv.example",
+            "ab--c.example",
+            "☃.example",
+            "example.com..",
+            ".",
+        ] {
+            validator.normalize_dns_name(name).unwrap_err();
+        }
+        validate_dns_name("bücher.example").unwrap_err();
+
+        for (name, reason) in [
+            (
+                "*.*.example.com",
+                "a wildcard must be the entire leftmost label",
+            ),
+            (
+                "www.*.example.com",
+                "a wildcard must be the entire leftmost label",
+            ),
+            ("*.com", "wildcard of a top-level domain"),
+            ("*.192.0.2.1", "IP addresses can't be wildcards"),
+        ] {
+            match validator.normalize_dns_name(name) {
+                Err(AcmeError::InvalidIdentifier(message)) => {
+                    assert_eq!(message, format!("{}: {}", name, reason))
+                }
+                other => panic!("{:?}", other),
+            }
+        }
+
+        let validator = IdentifierValidator::default()
+            .allow_wildcard(false)
+            .allow_underscore(true);
+        validator.normalize_dns_name("*.example.com").unwrap_err();
+        assert_eq!(
+            validator.normalize_dns_name("_a.example.com").unwrap(),
+            "_a.example.com"
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use std::sync::Arc;
+
+use anyhow::bail;
+use async_trait::async_trait;
+
+use crate::wire::challenge::ChallengeType;
+
+use super::solver::{ChallengeParams, ChallengeSolver};
+
+/// Access to a DNS zone's TXT records, as needed for dns-01 challenges.
+///
+/// Names are fully qualified, without a trailing dot, e.g.
+/// "_acme-challenge.www.example.com".
+#[async_trait]
+pub trait DnsProvider: Send + Sync {
+    /// Returns the zone that `name` belongs to, e.g. "example.com".
+    async fn find_zone(&self, name: &str) -> anyhow::Result<String>;
+
+    /// Adds a TXT record with `value` at `name`, keeping any other values.
+    async fn create_txt(&self, zone: &str, name: &str, value: &str) -> anyhow::Result<()>;
+
+    /// Removes only the TXT record with `value` at `name`.
+    async fn delete_txt(&self, zone: &str, name: &str, value: &str) -> anyhow::Result<()>;
+}
+
+#[async_trait]
+impl<P: DnsProvider + ?Sized> DnsProvider for Box<P> {
+    async fn find_zone(&self, name: &str) -> anyhow::Result<String> {
+        (**self).find_zone(name).await
+    }
+
+    async fn create_txt(&self, zone: &str, name: &str, value: &str) -> anyhow::Result<()> {
+        (**self).create_txt(zone, name, value).await
+    }
+
+    async fn delete_txt(&self, zone: &str, name: &str, value: &str) -> anyhow::Result<()> {
+        (**self).delete_txt(zone, name, value).await
+    }
+}
+
+/// Looks up CNAME records, to find where a delegated TXT record lives.
+#[async_trait]
+pub trait CnameResolver: Send + Sync {
+    /// Returns the CNAME target of `name`, without a trailing dot, if any.
+    async fn lookup_cname(&self, name: &str) -> anyhow::Result<Option<String>>;
+}
+
+/// CNAME chains longer than this are treated as a loop.
+const MAX_CNAME_HOPS: usize = 8;
+
+/// A dns-01 solver that writes TXT records through a `DnsProvider`.
+pub struct Dns01Solver<P> {
+    provider: P,
+    cname_resolver: Option<Arc<dyn CnameResolver>>,
+}
+
+impl<P: DnsProvider> Dns01Solver<P> {
+    pub fn new(provider: P) -> Self {
+        Self {
+            provider,
+            cname_resolver: None,
+        }
+    }
+
+    /// Follows CNAMEs from `_acme-challenge.<domain>` and writes the TXT
+    /// record at the end of the chain, as for acme-dns style delegation.
+    pub fn with_cname_resolver(mut self, resolver: impl CnameResolver + 'static) -> Self {
+        self.cname_resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    pub fn provider(&self) -> &P {
+        &self.provider
+    }
+
+    /// The name the TXT record for `params` must be written at.
+    pub async fn txt_name(&self, params: &ChallengeParams) -> anyhow::Result<String> {
+        let mut name = params.dns01_txt_name();
+        let resolver = match &self.cname_resolver {
+            Some(resolver) => resolver,
+            None => return Ok(name),
+        };
+        for _ in 0..MAX_CNAME_HOPS {
+            match resolver.lookup_cname(&name).await? {
+                Some(target) => {
+                    log::debug!("following CNAME {} -> {}", name, target);
+                    name = target.trim_end_matches('.').to_string();
+                }
+                None => return Ok(name),
+            }
+        }
+        bail!("too many CNAMEs from {}", params.dns01_txt_name())
+    }
+}
+
+#[async_trait]
+impl<P: DnsProvider> ChallengeSolver for Dns01Solver<P> {
+    fn supported_types(&self) -> Vec<ChallengeType> {
+        vec![ChallengeType::Dns01]
+    }
+
+    async fn present(&self, params: &ChallengeParams) -> anyhow::Result<()> {
+        let name = self.txt_name(params).await?;
+        let zone = self.provider.find_zone(&name).await?;
+        self.provider
+            .create_txt(&zone, &name, &params.dns01_txt_value())
+            .await
+    }
+
+    async fn cleanup(&self, params: &ChallengeParams) -> anyhow::Result<()> {
+        let name = self.txt_name(params).await?;
+        let zone = self.provider.find_zone(&name).await?;
+        self.provider
+            .delete_txt(&zone, &name, &params.dns01_txt_value())
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct MemoryDnsProvider {
+        records: Mutex<Vec<(String, String, String)>>,
+    }
+
+    #[async_trait]
+    impl DnsProvider for MemoryDnsProvider {
+        async fn find_zone(&self, name: &str) -> anyhow::Result<String> {
+            let labels: Vec<_> = name.rsplitn(3, '.').collect();
+            Ok(format!("{}.{}", labels[1], labels[0]))
+        }
+
+        async fn create_txt(&self, zone: &str, name: &str, value: &str) -> anyhow::Result<()> {
+            let record = (zone.to_string(), name.to_string(), value.to_string());
+            self.records.lock().unwrap().push(record);
+            Ok(())
+        }
+
+        async fn delete_txt(&self, _zone: &str, name: &str, value: &str) -> anyhow::Result<()> {
+            self.records
+                .lock()
+                .unwrap()
+                .retain(|record| record.1 != name || record.2 != value);
+            Ok(())
+        }
+    }
+
+    #[async_std::test]
+    async fn solver_writes_through_provider() {
+        let solver = Dns01Solver::new(MemoryDnsProvider::default());
+        let params = ChallengeParams {
+            domain: "www.example.com".to_string(),
+            wildcard: false,
+            challenge_type: ChallengeType::Dns01,
+            token: "tok".to_string(),
+            key_authorization: "tok.thumbprint".to_string(),
+        };
+
+        solver.present(&params).await.unwrap();
+        assert_eq!(
+            *solver.provider().records.lock().unwrap(),
+            vec![(
+                "example.com".to_string(),
+                "_acme-challenge.www.example.com".to_string(),
+                params.dns01_txt_value()
+            )]
+        );
+        solver.cleanup(&params).await.unwrap();
+        assert!(solver.provider().records.lock().unwrap().is_empty());
+    }
+
+    struct Cnames(Vec<(&'static str, &'static str)>);
+
+    #[async_trait]
+    impl CnameResolver for Cnames {
+        async fn lookup_cname(&self, name: &str) -> anyhow::Result<Option<String>> {
+            Ok(self
+                .0
+                .iter()
+                .find(|(from, _)| *from == name)
+                .map(|(_, to)| to.to_string()))
+        }
+    }
+
+    #[async_std::test]
+    async fn follows_cname_delegation() {
+        let solver =
+            Dns01Solver::new(MemoryDnsProvider::default()).with_cname_resolver(Cnames(vec![
+                ("_acme-challenge.example.com", "alias.example.net."),
+                ("alias.example.net", "d420c923.auth.example.org."),
+            ]));
+        let params = ChallengeParams {
+            domain: "example.com".to_string(),
+            wildcard: true,
+            challenge_type: ChallengeType::Dns01,
+            token: "tok".to_string(),
+            key_authorization: "tok.thumbprint".to_string(),
+        };
+        assert_eq!(
+            solver.txt_name(&params).await.unwrap(),
+            "d420c923.auth.example.org"
+        );
+
+        let looping = Dns01Solver::new(MemoryDnsProvider::default()).with_cname_resolver(Cnames(
+            vec![("_acme-challenge.example.com", "_acme-challenge.example.com")],
+        ));
+        assert!(looping.txt_name(&params).await.is_err());
+    }
+}
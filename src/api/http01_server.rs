@@ -0,0 +1,265 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_std::{
+    io::{
+        prelude::{BufReadExt, ReadExt},
+        BufReader, WriteExt,
+    },
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    task,
+};
+use async_trait::async_trait;
+use futures_util::future::{AbortHandle, Abortable};
+
+use crate::{error::AcmeResult, wire::challenge::ChallengeType};
+
+use super::{
+    challenge::Challenge,
+    solver::{ChallengeParams, ChallengeSolver},
+};
+
+/// Response bodies by request path.
+type Responses = Arc<Mutex<HashMap<String, String>>>;
+
+/// Longest request head that will be read before giving up on a connection.
+const MAX_REQUEST_HEAD: u64 = 8 * 1024;
+
+/// How long a connection may take to send its request and read the response.
+pub const DEFAULT_CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A minimal standalone HTTP server answering http-01 challenges, for hosts
+/// with no web server of their own (the ACME server connects on port 80).
+///
+/// Only `GET` and `HEAD` requests for presented tokens are answered; anything
+/// else gets a 404. The listener stops when the server is dropped.
+pub struct Http01Server {
+    responses: Responses,
+    local_addr: SocketAddr,
+    abort_handle: AbortHandle,
+}
+
+impl Http01Server {
+    /// Binds to `addr`, e.g. "0.0.0.0:80", and starts serving.
+    pub async fn bind(addr: impl ToSocketAddrs) -> std::io::Result<Self> {
+        Self::bind_with_timeout(addr, DEFAULT_CONNECTION_TIMEOUT).await
+    }
+
+    /// Like `bind`, dropping connections that take longer than
+    /// `connection_timeout` instead of `DEFAULT_CONNECTION_TIMEOUT`.
+    pub async fn bind_with_timeout(
+        addr: impl ToSocketAddrs,
+        connection_timeout: Duration,
+    ) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        let responses = Responses::default();
+
+        let (abort_handle, abort_registration) = AbortHandle::new_pair();
+        let accept_responses = responses.clone();
+        task::spawn(Abortable::new(
+            async move {
+                loop {
+                    match listener.accept().await {
+                        Ok((stream, _)) => {
+                            task::spawn(serve(
+                                stream,
+                                accept_responses.clone(),
+                                connection_timeout,
+                            ));
+                        }
+                        Err(err) => log::warn!("http-01 server accept failed: {}", err),
+                    }
+                }
+            },
+            abort_registration,
+        ));
+
+        Ok(Self {
+            responses,
+            local_addr,
+            abort_handle,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Serves the response for an http-01 challenge until `remove_challenge`.
+    pub fn add_challenge(&self, challenge: &Challenge) -> AcmeResult<()> {
+        self.responses
+            .lock()
+            .unwrap()
+            .insert(challenge.http01_path()?, challenge.http01_body()?);
+        Ok(())
+    }
+
+    pub fn remove_challenge(&self, challenge: &Challenge) -> AcmeResult<()> {
+        self.responses
+            .lock()
+            .unwrap()
+            .remove(&challenge.http01_path()?);
+        Ok(())
+    }
+}
+
+impl Drop for Http01Server {
+    fn drop(&mut self) {
+        self.abort_handle.abort();
+    }
+}
+
+#[async_trait]
+impl ChallengeSolver for Http01Server {
+    fn supported_types(&self) -> Vec<ChallengeType> {
+        vec![ChallengeType::Http01]
+    }
+
+    async fn present(&self, params: &ChallengeParams) -> anyhow::Result<()> {
+        self.responses
+            .lock()
+            .unwrap()
+            .insert(params.http01_path(), params.key_authorization.clone());
+        Ok(())
+    }
+
+    async fn cleanup(&self, params: &ChallengeParams) -> anyhow::Result<()> {
+        self.responses.lock().unwrap().remove(&params.http01_path());
+        Ok(())
+    }
+}
+
+async fn serve(stream: TcpStream, responses: Responses, connection_timeout: Duration) {
+    let served = async_std::io::timeout(connection_timeout, serve_connection(stream, &responses));
+    if let Err(err) = served.await {
+        log::debug!("http-01 server connection failed: {}", err);
+    }
+}
+
+async fn serve_connection(
+    mut stream: TcpStream,
+    responses: &Mutex<HashMap<String, String>>,
+) -> std::io::Result<()> {
+    // Bounded so that a client can't grow a line without limit
+    let mut reader = BufReader::new((&stream).take(MAX_REQUEST_HEAD));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    // Drain the headers; none of them matter here
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "request head too long or truncated",
+            ));
+        }
+        if line == "\r\n" || line == "\n" {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let body = match method {
+        "GET" | "HEAD" => responses.lock().unwrap().get(path).cloned(),
+        _ => None,
+    };
+    log::debug!(
+        "http-01 server: {} {} -> {}",
+        method,
+        path,
+        if body.is_some() { 200 } else { 404 }
+    );
+
+    let (status, body) = match body {
+        Some(body) => ("200 OK", body),
+        None => ("404 Not Found", String::new()),
+    };
+    let mut response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        body.len()
+    );
+    if method != "HEAD" {
+        response.push_str(&body);
+    }
+    stream.write_all(response.as_bytes()).await?;
+    stream.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use async_std::io::ReadExt;
+
+    use super::*;
+
+    async fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!("GET {} HTTP/1.1\r\nHost: example.com\r\n\r\n", path);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[async_std::test]
+    async fn serves_presented_tokens() {
+        let server = Http01Server::bind("127.0.0.1:0").await.unwrap();
+        let params = ChallengeParams {
+            domain: "example.com".to_string(),
+            wildcard: false,
+            challenge_type: ChallengeType::Http01,
+            token: "tok".to_string(),
+            key_authorization: "tok.thumbprint".to_string(),
+        };
+        server.present(&params).await.unwrap();
+
+        let response = get(server.local_addr(), "/.well-known/acme-challenge/tok").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.ends_with("\r\n\r\ntok.thumbprint"), "{}", response);
+
+        let response = get(server.local_addr(), "/.well-known/acme-challenge/other").await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+        server.cleanup(&params).await.unwrap();
+        let response = get(server.local_addr(), "/.well-known/acme-challenge/tok").await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[async_std::test]
+    async fn drops_oversized_request_line() {
+        let server = Http01Server::bind("127.0.0.1:0").await.unwrap();
+        let mut stream = TcpStream::connect(server.local_addr()).await.unwrap();
+        // An endless request line: the server has to hang up for this to end
+        let writing = async {
+            let chunk = [b'A'; 1024];
+            loop {
+                if stream.write_all(&chunk).await.is_err() {
+                    return;
+                }
+            }
+        };
+        async_std::future::timeout(Duration::from_secs(5), writing)
+            .await
+            .expect("server kept reading an oversized request line");
+    }
+
+    #[async_std::test]
+    async fn drops_idle_connection() {
+        let server = Http01Server::bind_with_timeout("127.0.0.1:0", Duration::from_millis(100))
+            .await
+            .unwrap();
+        let mut stream = TcpStream::connect(server.local_addr()).await.unwrap();
+        let mut response = Vec::new();
+        let read =
+            async_std::future::timeout(Duration::from_secs(5), stream.read_to_end(&mut response))
+                .await
+                .expect("server kept an idle connection open");
+        assert!(read.is_err() || response.is_empty(), "{:?}", read);
+    }
+}
@@ -0,0 +1,1156 @@
+use serde::Serialize;
+
+use crate::{
+    cancel::CancellationToken,
+    error::{AcmeError, AcmeResult, AuthorizationFailure},
+    wire::{
+        authorization::AuthorizationStatus,
+        challenge::ChallengeType,
+        identifier::AcmeIdentifier,
+        order::{NewOrderResource, OrderStatus},
+        problem::AcmeProblem,
+    },
+    x509::{CsrBuilder, KeyType},
+};
+
+use super::{
+    account::Account,
+    authorization::Authorization,
+    new_order::check_wildcards_solvable,
+    observer::{IssuanceObserver, NoopObserver},
+    order::{Order, OrderState},
+    policy::IssuancePolicy,
+    solver::ChallengeSolver,
+    store::{CertificateBundle, CertificateMetadata},
+};
+
+/// Runs a complete issuance: creates the order, solves every pending
+/// authorization with `solver`, finalizes with a generated key and downloads
+/// the certificate chain, sleeping between status polls as configured on the
+/// account's client. The account's `IssuancePolicy` applies unless
+/// `IssuanceOptions::policy` replaces it.
+///
+/// Every challenge is responded to even if some fail validation, and the
+/// failures are returned together as `AcmeError::AuthorizationsFailed`; see
+/// `issue_with_report` for the outcome of each.
+///
+/// All challenge responses are presented before any is submitted for
+/// validation, so that DNS changes can propagate together. In particular the
+/// apex and wildcard authorizations of e.g. `example.com` and
+/// `*.example.com` share one dns-01 TXT name, which then holds both values.
+///
+/// Identifiers may name an ancestor domain (RFC 9444; see
+/// `NewOrderBuilder::dns_under`), whose authorization is then solved once for
+/// all of its subdomains, or reused while valid. If the CA doesn't allow
+/// subdomain authorization the ancestor domains are dropped.
+pub async fn issue(
+    account: &Account,
+    new_order: &NewOrderResource,
+    solver: &(impl ChallengeSolver + ?Sized),
+) -> AcmeResult<CertificateBundle> {
+    issue_with_options(account, new_order, solver, &IssuanceOptions::default()).await
+}
+
+/// Like `issue`, reporting progress to `observer`.
+pub async fn issue_with_observer(
+    account: &Account,
+    new_order: &NewOrderResource,
+    solver: &(impl ChallengeSolver + ?Sized),
+    observer: &dyn IssuanceObserver,
+) -> AcmeResult<CertificateBundle> {
+    let options = IssuanceOptions::default().observer(observer);
+    issue_with_options(account, new_order, solver, &options).await
+}
+
+/// Like `issue`, with the given options.
+pub async fn issue_with_options(
+    account: &Account,
+    new_order: &NewOrderResource,
+    solver: &(impl ChallengeSolver + ?Sized),
+    options: &IssuanceOptions<'_>,
+) -> AcmeResult<CertificateBundle> {
+    let mut report = IssuanceReport::default();
+    Ok(
+        issue_order(account, new_order, solver, options, &mut report)
+            .await?
+            .0,
+    )
+}
+
+/// Like `issue_with_options`, also returning what became of each
+/// authorization, even if the issuance failed.
+pub async fn issue_with_report(
+    account: &Account,
+    new_order: &NewOrderResource,
+    solver: &(impl ChallengeSolver + ?Sized),
+    options: &IssuanceOptions<'_>,
+) -> (AcmeResult<CertificateBundle>, IssuanceReport) {
+    let mut report = IssuanceReport::default();
+    let res = issue_order(account, new_order, solver, options, &mut report).await;
+    (res.map(|(bundle, _)| bundle), report)
+}
+
+/// The per-authorization outcome of an issuance, e.g. to retry without the
+/// identifiers that failed validation.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct IssuanceReport {
+    /// The order, once it was created.
+    pub order_url: Option<String>,
+
+    /// The order's authorizations, in order; empty if the issuance failed
+    /// before they were fetched.
+    pub authorizations: Vec<AuthorizationReport>,
+}
+
+impl IssuanceReport {
+    pub fn valid(&self) -> impl Iterator<Item = &AuthorizationReport> {
+        self.authorizations
+            .iter()
+            .filter(|authorization| matches!(authorization.outcome, AuthorizationOutcome::Valid))
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = &AuthorizationReport> {
+        self.authorizations.iter().filter(|authorization| {
+            matches!(authorization.outcome, AuthorizationOutcome::Failed { .. })
+        })
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct AuthorizationReport {
+    pub url: String,
+
+    /// The identifier authorized, without any wildcard prefix.
+    pub identifier: AcmeIdentifier,
+
+    pub wildcard: bool,
+
+    pub outcome: AuthorizationOutcome,
+}
+
+impl AuthorizationReport {
+    /// The name as it appears in the order, e.g. "*.example.com".
+    pub fn name(&self) -> String {
+        if self.wildcard {
+            format!("*.{}", self.identifier.value)
+        } else {
+            self.identifier.value.clone()
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum AuthorizationOutcome {
+    /// Validated, or already valid.
+    Valid,
+
+    /// Validation failed or couldn't be attempted, e.g. because the solver
+    /// failed to present the response.
+    Failed {
+        error: String,
+        problem: Option<Box<AcmeProblem>>,
+    },
+
+    /// Not attempted, because the issuance stopped first.
+    Skipped,
+}
+
+impl AuthorizationOutcome {
+    fn failed(err: &AcmeError) -> Self {
+        AuthorizationOutcome::Failed {
+            error: err.to_string(),
+            problem: err.problem().cloned().map(Box::new),
+        }
+    }
+}
+
+/// Optional settings for `issue_with_options`.
+#[derive(Clone)]
+pub struct IssuanceOptions<'a> {
+    observer: &'a dyn IssuanceObserver,
+    policy: Option<IssuancePolicy>,
+    cancellation: CancellationToken,
+    key_type: Option<KeyType>,
+    #[cfg(feature = "x509")]
+    preferred_chain: Option<String>,
+}
+
+impl Default for IssuanceOptions<'_> {
+    fn default() -> Self {
+        Self {
+            observer: &NoopObserver,
+            policy: None,
+            cancellation: CancellationToken::new(),
+            key_type: None,
+            #[cfg(feature = "x509")]
+            preferred_chain: None,
+        }
+    }
+}
+
+impl<'a> IssuanceOptions<'a> {
+    pub fn observer(mut self, observer: &'a dyn IssuanceObserver) -> Self {
+        self.observer = observer;
+        self
+    }
+
+    /// Uses `policy` in place of the account's `IssuancePolicy`.
+    pub fn policy(mut self, policy: IssuancePolicy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Stops the issuance with `AcmeError::Cancelled` once `token` is
+    /// cancelled, removing any presented challenge responses. The error holds
+    /// the order, which can be resumed with `Account::resume_order` and
+    /// completed by hand. Once the order is finalized the issuance completes
+    /// regardless, since the new private key would otherwise be lost.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// The type of certificate key to generate, which the policy must allow.
+    /// Defaults to the policy's first allowed key type, or else P-256.
+    pub fn key_type(mut self, key_type: KeyType) -> Self {
+        self.key_type = Some(key_type);
+        self
+    }
+
+    /// Prefers the chain whose topmost certificate was issued by `issuer`,
+    /// e.g. "ISRG Root X1", over the policy's preferred chain; see
+    /// `OrderStateValid::get_preferred_certificate_chain`.
+    #[cfg(feature = "x509")]
+    pub fn preferred_chain(mut self, issuer: impl Into<String>) -> Self {
+        self.preferred_chain = Some(issuer.into());
+        self
+    }
+
+    /// The policy in effect for issuance with `account`.
+    pub(crate) fn policy_for<'b>(&'b self, account: &'b Account) -> &'b IssuancePolicy {
+        self.policy
+            .as_ref()
+            .unwrap_or_else(|| account.issuance_policy())
+    }
+
+    pub(crate) fn policy_override(&self) -> Option<&IssuancePolicy> {
+        self.policy.as_ref()
+    }
+
+    pub(crate) fn cancellation_token(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+}
+
+/// Like `issue`, then checks the downloaded chain with
+/// `x509::verify_certificate` so that a certificate corrupted by the CA or a
+/// proxy is caught before it is deployed.
+#[cfg(feature = "x509")]
+pub async fn issue_verified(
+    account: &Account,
+    new_order: &NewOrderResource,
+    solver: &(impl ChallengeSolver + ?Sized),
+) -> AcmeResult<(CertificateBundle, crate::x509::IssuedCertificate)> {
+    let options = IssuanceOptions::default();
+    let mut report = IssuanceReport::default();
+    let (bundle, csr_der) = issue_order(account, new_order, solver, &options, &mut report).await?;
+    let issued = crate::x509::verify_certificate_at(
+        &bundle.fullchain_pem,
+        &bundle.metadata.identifiers,
+        csr_der,
+        account.client().clock().now(),
+    )?;
+    Ok((bundle, issued))
+}
+
+/// Runs the issuance, returning the bundle and the DER-encoded CSR.
+async fn issue_order(
+    account: &Account,
+    new_order: &NewOrderResource,
+    solver: &(impl ChallengeSolver + ?Sized),
+    options: &IssuanceOptions<'_>,
+    report: &mut IssuanceReport,
+) -> AcmeResult<(CertificateBundle, Vec<u8>)> {
+    let res = issue_order_inner(account, new_order, solver, options, report).await;
+    if let Err(err) = &res {
+        options.observer.on_error(err);
+    }
+    res
+}
+
+async fn issue_order_inner(
+    account: &Account,
+    new_order: &NewOrderResource,
+    solver: &(impl ChallengeSolver + ?Sized),
+    options: &IssuanceOptions<'_>,
+    report: &mut IssuanceReport,
+) -> AcmeResult<(CertificateBundle, Vec<u8>)> {
+    let observer = options.observer;
+    let policy = options.policy_for(account);
+    let key_type = options
+        .key_type
+        .or_else(|| policy.allowed_key_types.first().copied())
+        .unwrap_or_default();
+    if !policy.allows_key_type(key_type) {
+        return Err(AcmeError::InvalidState(format!(
+            "key type {:?} is not allowed by the issuance policy",
+            key_type
+        )));
+    }
+    check_wildcards_solvable(&new_order.identifiers, &solver.supported_types())?;
+    options.cancellation.check()?;
+    let subdomain_auth_allowed = account.client().directory().meta.subdomain_auth_allowed();
+    let has_ancestors = new_order
+        .identifiers
+        .iter()
+        .any(|identifier| identifier.ancestor_domain.is_some());
+    let mut order = if has_ancestors && !subdomain_auth_allowed {
+        let mut new_order = new_order.clone();
+        for identifier in &mut new_order.identifiers {
+            identifier.ancestor_domain = None;
+        }
+        account.new_order(&new_order).await?
+    } else {
+        account.new_order(new_order).await?
+    };
+    observer.on_order_created(&order);
+    report.order_url = Some(order.url().to_string());
+
+    authorize_order(account, &mut order, solver, policy, options, report)
+        .await
+        .map_err(|err| order.attach_checkpoint(err))?;
+    let (private_key_pem, csr_der) = CsrBuilder::new()
+        .key_type(key_type)
+        .identifiers(&order.resource().identifiers)?
+        .build()?;
+    match order.state_result()? {
+        OrderState::Ready(mut ready) => ready.finalize_observed(&csr_der, observer).await?,
+        _ => return Err(unexpected_order_status(&order)),
+    };
+    observer.on_finalized(&order);
+
+    // The new key exists only here, so there's no stopping from now on
+    let uncancellable = CancellationToken::new();
+    poll_order_while(account, &mut order, OrderStatus::Processing, &uncancellable).await?;
+    let fullchain_pem = match order.state_result()? {
+        #[cfg(feature = "x509")]
+        OrderState::Valid(valid)
+            if options.preferred_chain.is_some() || policy.preferred_chain.is_some() =>
+        {
+            let issuer = options
+                .preferred_chain
+                .as_deref()
+                .or(policy.preferred_chain.as_deref())
+                .unwrap_or_default();
+            valid.get_preferred_certificate_chain(issuer).await?
+        }
+        OrderState::Valid(valid) => valid.get_certificate_chain().await?,
+        _ => return Err(unexpected_order_status(&order)),
+    };
+
+    let bundle = CertificateBundle {
+        private_key_pem,
+        fullchain_pem,
+        metadata: CertificateMetadata {
+            identifiers: order.resource().identifiers.clone(),
+            order_url: order.url().to_string(),
+            issued_at: account.client().clock().now().into(),
+        },
+    };
+    observer.on_certificate_downloaded(&bundle);
+    Ok((bundle, csr_der))
+}
+
+/// Solves the order's authorizations and waits until it is ready.
+async fn authorize_order(
+    account: &Account,
+    order: &mut Order,
+    solver: &(impl ChallengeSolver + ?Sized),
+    policy: &IssuancePolicy,
+    options: &IssuanceOptions<'_>,
+    report: &mut IssuanceReport,
+) -> AcmeResult<()> {
+    // Subdomains under one ancestor may share its authorization
+    let mut authorizations: Vec<Authorization> = Vec::new();
+    for authorization in order.get_authorizations() {
+        let authorization = authorization.await?;
+        if !authorizations
+            .iter()
+            .any(|a| a.url() == authorization.url())
+        {
+            authorizations.push(authorization);
+        }
+    }
+    solve_batched(
+        account,
+        &mut authorizations,
+        solver,
+        policy,
+        options,
+        report,
+    )
+    .await?;
+    poll_order_while(account, order, OrderStatus::Pending, &options.cancellation).await
+}
+
+/// Presents the responses for every pending authorization, then responds to
+/// and waits for each in turn, then cleans them all up. Every challenge is
+/// responded to even if some fail validation; failures are collected into
+/// `AcmeError::AuthorizationsFailed`. Outcomes are recorded in `report`.
+async fn solve_batched(
+    account: &Account,
+    authorizations: &mut [Authorization],
+    solver: &(impl ChallengeSolver + ?Sized),
+    policy: &IssuancePolicy,
+    options: &IssuanceOptions<'_>,
+    report: &mut IssuanceReport,
+) -> AcmeResult<()> {
+    let mut outcomes: Vec<AuthorizationOutcome> = authorizations
+        .iter()
+        .map(|authorization| match authorization.status_result() {
+            Ok(AuthorizationStatus::Pending) => AuthorizationOutcome::Skipped,
+            Ok(_) => AuthorizationOutcome::Valid,
+            Err(err) => AuthorizationOutcome::failed(&err),
+        })
+        .collect();
+    let res = solve_pending(
+        account,
+        authorizations,
+        solver,
+        policy,
+        options,
+        &mut outcomes,
+    )
+    .await;
+    report.authorizations = authorizations
+        .iter()
+        .zip(outcomes)
+        .map(|(authorization, outcome)| AuthorizationReport {
+            url: authorization.url().to_string(),
+            identifier: authorization.identifier().clone(),
+            wildcard: authorization.resource().wildcard,
+            outcome,
+        })
+        .collect();
+    res?;
+    for authorization in authorizations.iter() {
+        authorization.status_result()?;
+    }
+    Ok(())
+}
+
+async fn solve_pending(
+    account: &Account,
+    authorizations: &mut [Authorization],
+    solver: &(impl ChallengeSolver + ?Sized),
+    policy: &IssuancePolicy,
+    options: &IssuanceOptions<'_>,
+    outcomes: &mut [AuthorizationOutcome],
+) -> AcmeResult<()> {
+    let (observer, cancel) = (options.observer, &options.cancellation);
+    cancel.check()?;
+    let mut challenges = Vec::new();
+    let mut params = Vec::new();
+    for (idx, authorization) in authorizations.iter().enumerate() {
+        if authorization.status() == AuthorizationStatus::Pending {
+            let (challenge, challenge_params) = authorization
+                .solvable_challenge(solver, policy)
+                .inspect_err(|err| outcomes[idx] = AuthorizationOutcome::failed(err))?;
+            challenges.push((idx, challenge));
+            params.push(challenge_params);
+        }
+    }
+
+    let mut presented = 0;
+    let mut res = Ok(());
+    for (challenge_params, (idx, _)) in params.iter().zip(&challenges) {
+        if let Err(err) = cancel.check() {
+            res = Err(err);
+            break;
+        }
+        if let Err(err) = solver.present(challenge_params).await {
+            let err = AcmeError::SolverError(err);
+            outcomes[*idx] = AuthorizationOutcome::failed(&err);
+            res = Err(err);
+            break;
+        }
+        presented += 1;
+        observer.on_challenge_presented(challenge_params);
+    }
+    if res.is_ok() {
+        for (challenge_params, (idx, _)) in params.iter().zip(&challenges) {
+            if let Err(err) = solver.wait_until_ready(challenge_params).await {
+                let err = AcmeError::SolverError(err);
+                outcomes[*idx] = AuthorizationOutcome::failed(&err);
+                res = Err(err);
+                break;
+            }
+        }
+    }
+    if let (true, Some(wait)) = (
+        res.is_ok()
+            && params
+                .iter()
+                .any(|p| p.challenge_type == ChallengeType::Dns01),
+        policy.dns_propagation_wait,
+    ) {
+        res = cancel.run(account.client().sleeper().sleep(wait)).await;
+    }
+    let mut failures = Vec::new();
+    if res.is_ok() {
+        for (idx, challenge) in challenges {
+            let authorization = &mut authorizations[idx];
+            match authorization
+                .respond_and_wait(challenge, policy.max_validation_wait, cancel)
+                .await
+            {
+                Ok(()) => {
+                    outcomes[idx] = AuthorizationOutcome::Valid;
+                    observer.on_challenge_valid(authorization);
+                }
+                Err(err) if matches!(err.without_context(), AcmeError::Cancelled { .. }) => {
+                    res = Err(err);
+                    break;
+                }
+                Err(err) => {
+                    outcomes[idx] = AuthorizationOutcome::failed(&err);
+                    failures.push(AuthorizationFailure {
+                        authorization_url: authorization.url().to_string(),
+                        identifier: Some(authorization.identifier().clone()),
+                        error: Box::new(err),
+                    });
+                }
+            }
+        }
+    }
+
+    for challenge_params in &params[..presented] {
+        if let Err(err) = solver.cleanup(challenge_params).await {
+            log::warn!(
+                "challenge cleanup for {} failed: {}",
+                challenge_params.domain,
+                err
+            );
+        }
+    }
+    res?;
+    if !failures.is_empty() {
+        return Err(AcmeError::AuthorizationsFailed(failures));
+    }
+    Ok(())
+}
+
+async fn poll_order_while(
+    account: &Account,
+    order: &mut Order,
+    status: OrderStatus,
+    cancel: &CancellationToken,
+) -> AcmeResult<()> {
+    while order.status() == status {
+        order.check_expiry()?;
+        cancel.run(account.client().poll_sleep()).await?;
+        order.refresh().await?;
+    }
+    Ok(())
+}
+
+fn unexpected_order_status(order: &Order) -> AcmeError {
+    AcmeError::InvalidState(format!("unexpected order status {:?}", order.status()))
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+
+    use crate::{
+        api::solver::{ChallengeParams, LoggingDns01Solver, MemoryHttp01Solver},
+        clock::{Clock, ManualClock},
+        test_support::mock_server::{Endpoint, Fault, MockAcmeServer},
+        wire::{
+            challenge::ChallengeType,
+            identifier::AcmeIdentifier,
+            problem::{AcmeProblem, AcmeProblemType},
+        },
+    };
+
+    use super::*;
+
+    const CHAIN: &str = "-----BEGIN CERTIFICATE-----\nMA==\n-----END CERTIFICATE-----\n";
+
+    async fn account(server: &MockAcmeServer) -> Account {
+        let client = server.client().await.unwrap();
+        client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap()
+    }
+
+    fn new_order(names: &[&str]) -> NewOrderResource {
+        NewOrderResource {
+            identifiers: names
+                .iter()
+                .map(|name| AcmeIdentifier::dns(*name))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[async_std::test]
+    async fn issue_with_mock_server() {
+        let server = MockAcmeServer::new();
+        server.set_processing_polls(1);
+        server.set_certificate_chain(CHAIN);
+        let account = account(&server).await;
+        let solver = MemoryHttp01Solver::new();
+
+        let bundle = issue(
+            &account,
+            &new_order(&["example.com", "www.example.com"]),
+            &solver,
+        )
+        .await
+        .unwrap();
+        assert_eq!(bundle.fullchain_pem, CHAIN);
+        assert!(bundle.private_key_pem.contains("PRIVATE KEY"));
+        assert_eq!(bundle.metadata.identifiers.len(), 2);
+    }
+
+    /// A self-signed certificate, so also its own topmost issuer.
+    #[cfg(feature = "x509")]
+    fn chain_issued_by(issuer: &str) -> String {
+        use openssl::{
+            asn1::Asn1Time,
+            ec::{EcGroup, EcKey},
+            hash::MessageDigest,
+            nid::Nid,
+            pkey::PKey,
+            x509::{X509Builder, X509NameBuilder},
+        };
+
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, issuer).unwrap();
+        let name = name.build();
+        let mut cert = X509Builder::new().unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(&key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        cert.sign(&key, MessageDigest::sha256()).unwrap();
+        String::from_utf8(cert.build().to_pem().unwrap()).unwrap()
+    }
+
+    #[cfg(feature = "x509")]
+    #[async_std::test]
+    async fn issue_with_preferred_chain() {
+        let server = MockAcmeServer::new();
+        let account = account(&server).await;
+        let default_chain = chain_issued_by("Root A");
+        let alternates = vec![chain_issued_by("Root B"), chain_issued_by("Root C")];
+        server.set_certificate_chain(default_chain.clone());
+        server.set_alternate_certificate_chains(alternates.clone());
+
+        for (preferred, expected) in [
+            ("Root C", &alternates[1]),
+            ("Root A", &default_chain),
+            ("Root D", &default_chain),
+        ] {
+            let options = IssuanceOptions::default().preferred_chain(preferred);
+            let bundle = issue_with_options(
+                &account,
+                &new_order(&["example.com"]),
+                &MemoryHttp01Solver::new(),
+                &options,
+            )
+            .await
+            .unwrap();
+            assert_eq!(&bundle.fullchain_pem, expected, "{}", preferred);
+        }
+        // Alternates are only fetched until one matches
+        assert_eq!(server.request_count(Endpoint::Certificate), 3 + 1 + 3);
+    }
+
+    #[derive(Default)]
+    struct RecordingObserver {
+        events: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl RecordingObserver {
+        fn record(&self, event: String) {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    impl IssuanceObserver for RecordingObserver {
+        fn on_order_created(&self, order: &Order) {
+            self.record(format!("order {:?}", order.status()));
+        }
+
+        fn on_challenge_presented(&self, params: &ChallengeParams) {
+            self.record(format!("presented {}", params.domain));
+        }
+
+        fn on_challenge_valid(&self, authorization: &Authorization) {
+            self.record(format!("valid {}", authorization.identifier().value));
+        }
+
+        fn on_finalized(&self, _order: &Order) {
+            self.record("finalized".to_string());
+        }
+
+        fn on_certificate_downloaded(&self, _bundle: &CertificateBundle) {
+            self.record("downloaded".to_string());
+        }
+
+        fn on_retry(&self, operation: &str, _error: &AcmeError) {
+            self.record(format!("retry {}", operation));
+        }
+
+        fn on_error(&self, error: &AcmeError) {
+            self.record(format!("error {}", error));
+        }
+    }
+
+    #[async_std::test]
+    async fn issue_reports_progress() {
+        let server = MockAcmeServer::new();
+        server.set_certificate_chain(CHAIN);
+        let account = account(&server).await;
+        server.inject(
+            Endpoint::Finalize,
+            Fault::Problem {
+                status: 403,
+                problem: Box::new(AcmeProblem {
+                    type_: Some(AcmeProblemType::OrderNotReady),
+                    ..Default::default()
+                }),
+            },
+        );
+
+        let observer = RecordingObserver::default();
+        issue_with_observer(
+            &account,
+            &new_order(&["example.com"]),
+            &MemoryHttp01Solver::new(),
+            &observer,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            *observer.events.lock().unwrap(),
+            [
+                "order Pending",
+                "presented example.com",
+                "valid example.com",
+                "retry finalize",
+                "finalized",
+                "downloaded"
+            ]
+        );
+
+        server.fail_validation("example.org");
+        let observer = RecordingObserver::default();
+        issue_with_observer(
+            &account,
+            &new_order(&["example.org"]),
+            &MemoryHttp01Solver::new(),
+            &observer,
+        )
+        .await
+        .unwrap_err();
+        let events = observer.events.lock().unwrap();
+        assert_eq!(events.len(), 3);
+        assert!(events[2].starts_with("error "), "{:?}", events);
+    }
+
+    #[async_std::test]
+    async fn issue_rejects_unsolvable_wildcard() {
+        let server = MockAcmeServer::new();
+        let account = account(&server).await;
+
+        let err = issue(
+            &account,
+            &new_order(&["example.com", "*.example.com"]),
+            &MemoryHttp01Solver::new(),
+        )
+        .await
+        .unwrap_err();
+        assert!(
+            matches!(&err, AcmeError::InvalidIdentifier(message) if message.starts_with("*.example.com:")),
+            "{:?}",
+            err
+        );
+    }
+
+    #[cfg(feature = "x509")]
+    #[async_std::test]
+    async fn issue_verified_rejects_corrupt_chain() {
+        let server = MockAcmeServer::new();
+        server.set_certificate_chain(CHAIN);
+        let account = account(&server).await;
+
+        let err = issue_verified(
+            &account,
+            &new_order(&["example.com"]),
+            &MemoryHttp01Solver::new(),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, AcmeError::InvalidCertificate(_)), "{:?}", err);
+    }
+
+    #[async_std::test]
+    async fn issue_reports_validation_error() {
+        let server = MockAcmeServer::new();
+        server.fail_validation("example.com");
+        let account = account(&server).await;
+
+        let err = issue(
+            &account,
+            &new_order(&["example.com"]),
+            &MemoryHttp01Solver::new(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            err.problem().unwrap().type_,
+            Some(AcmeProblemType::IncorrectResponse)
+        );
+    }
+
+    #[async_std::test]
+    async fn issue_with_ancestor_domain() {
+        let server = MockAcmeServer::new();
+        server.set_subdomain_auth_allowed(true);
+        server.set_certificate_chain(CHAIN);
+        let account = account(&server).await;
+        let solver = MemoryHttp01Solver::new();
+
+        let new_order = account
+            .order()
+            .dns_under("a.example.com", "example.com")
+            .dns_under("b.example.com", "example.com")
+            .build()
+            .unwrap();
+        issue(&account, &new_order, &solver).await.unwrap();
+        assert_eq!(server.request_count(Endpoint::Challenge), 1);
+
+        // The valid ancestor authorization is reused
+        let new_order = account
+            .order()
+            .dns_under("c.example.com", "example.com")
+            .build()
+            .unwrap();
+        assert_eq!(
+            new_order.identifiers[0].ancestor_domain.as_deref(),
+            Some("example.com")
+        );
+        issue(&account, &new_order, &solver).await.unwrap();
+        assert_eq!(server.request_count(Endpoint::Challenge), 1);
+    }
+
+    #[async_std::test]
+    async fn issue_drops_unsupported_ancestor_domain() {
+        let server = MockAcmeServer::new();
+        server.set_certificate_chain(CHAIN);
+        let account = account(&server).await;
+
+        let new_order = NewOrderResource {
+            identifiers: vec![
+                AcmeIdentifier::dns("a.example.com").with_ancestor_domain("example.com")
+            ],
+            ..Default::default()
+        };
+        let bundle = issue(&account, &new_order, &MemoryHttp01Solver::new())
+            .await
+            .unwrap();
+        assert_eq!(
+            bundle.metadata.identifiers,
+            [AcmeIdentifier::dns("a.example.com")]
+        );
+        assert!(account
+            .order()
+            .dns_under("a.example.com", "example.com")
+            .build()
+            .unwrap()
+            .identifiers[0]
+            .ancestor_domain
+            .is_none());
+        account
+            .order()
+            .dns_under("a.example.org", "example.com")
+            .build()
+            .unwrap_err();
+    }
+
+    /// Tracks the most TXT records presented at any one time.
+    #[derive(Default)]
+    struct SnapshotDns01Solver {
+        inner: LoggingDns01Solver,
+        max_records: std::sync::Mutex<usize>,
+    }
+
+    #[async_trait]
+    impl ChallengeSolver for SnapshotDns01Solver {
+        fn supported_types(&self) -> Vec<ChallengeType> {
+            self.inner.supported_types()
+        }
+
+        async fn present(&self, params: &ChallengeParams) -> anyhow::Result<()> {
+            self.inner.present(params).await?;
+            let mut max_records = self.max_records.lock().unwrap();
+            *max_records = (*max_records).max(self.inner.records().len());
+            Ok(())
+        }
+
+        async fn cleanup(&self, params: &ChallengeParams) -> anyhow::Result<()> {
+            self.inner.cleanup(params).await
+        }
+    }
+
+    #[async_std::test]
+    async fn issue_presents_wildcard_and_apex_records_together() {
+        let server = MockAcmeServer::new();
+        server.set_certificate_chain(CHAIN);
+        let account = account(&server).await;
+        let solver = SnapshotDns01Solver::default();
+
+        let bundle = issue(
+            &account,
+            &new_order(&["*.example.com", "example.com"]),
+            &solver,
+        )
+        .await
+        .unwrap();
+        assert_eq!(bundle.fullchain_pem, CHAIN);
+        assert_eq!(*solver.max_records.lock().unwrap(), 2);
+        assert!(solver.inner.records().is_empty());
+    }
+
+    /// Records the types of the challenges it presents.
+    #[derive(Default)]
+    struct HttpAndDnsSolver {
+        presented: std::sync::Mutex<Vec<ChallengeType>>,
+    }
+
+    #[async_trait]
+    impl ChallengeSolver for HttpAndDnsSolver {
+        fn supported_types(&self) -> Vec<ChallengeType> {
+            vec![ChallengeType::Http01, ChallengeType::Dns01]
+        }
+
+        async fn present(&self, params: &ChallengeParams) -> anyhow::Result<()> {
+            let challenge_type = params.challenge_type.clone();
+            self.presented.lock().unwrap().push(challenge_type);
+            Ok(())
+        }
+
+        async fn cleanup(&self, _params: &ChallengeParams) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A client whose clock is moved along by its sleeps.
+    async fn account_with_clock(server: &MockAcmeServer) -> (Account, std::sync::Arc<ManualClock>) {
+        let clock = std::sync::Arc::new(ManualClock::new(chrono::Utc::now()));
+        let sleeper_clock = clock.clone();
+        let mut client = server.client().await.unwrap();
+        client.set_clock(clock.clone());
+        client.set_sleeper(crate::sleep::FnSleeper(move |duration| {
+            sleeper_clock.advance(chrono::Duration::from_std(duration).unwrap());
+            futures_util::future::ready(())
+        }));
+        let account = client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+        (account, clock)
+    }
+
+    #[async_std::test]
+    async fn issue_with_policy() {
+        let server = MockAcmeServer::new();
+        server.set_certificate_chain(CHAIN);
+        let (mut account, clock) = account_with_clock(&server).await;
+        let solver = HttpAndDnsSolver::default();
+
+        account.set_issuance_policy(IssuancePolicy {
+            preferred_challenge_types: vec![ChallengeType::Dns01],
+            allowed_key_types: vec![KeyType::P384],
+            dns_propagation_wait: Some(std::time::Duration::from_secs(120)),
+            ..Default::default()
+        });
+        let start = clock.now();
+        let bundle = issue(&account, &new_order(&["example.com"]), &solver)
+            .await
+            .unwrap();
+        assert_eq!(bundle.fullchain_pem, CHAIN);
+        assert_eq!(*solver.presented.lock().unwrap(), [ChallengeType::Dns01]);
+        assert!(clock.now() - start >= chrono::Duration::seconds(120));
+
+        // Options may pick another allowed key type, or replace the policy
+        let new_orders = server.request_count(Endpoint::NewOrder);
+        let options = IssuanceOptions::default().key_type(KeyType::P256);
+        let err = issue_with_options(&account, &new_order(&["example.com"]), &solver, &options)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AcmeError::InvalidState(_)), "{:?}", err);
+        assert_eq!(server.request_count(Endpoint::NewOrder), new_orders);
+        let options = options.policy(IssuancePolicy::default());
+        issue_with_options(&account, &new_order(&["example.com"]), &solver, &options)
+            .await
+            .unwrap();
+        assert_eq!(solver.presented.lock().unwrap()[1], ChallengeType::Http01);
+    }
+
+    #[async_std::test]
+    async fn issue_times_out_validation() {
+        let server = MockAcmeServer::new();
+        server.set_processing_polls(100);
+        let (mut account, _clock) = account_with_clock(&server).await;
+        account.set_issuance_policy(IssuancePolicy {
+            max_validation_wait: Some(std::time::Duration::from_secs(30)),
+            ..Default::default()
+        });
+
+        let solver = MemoryHttp01Solver::new();
+        let err = issue(&account, &new_order(&["example.com"]), &solver)
+            .await
+            .unwrap_err();
+        match err {
+            AcmeError::AuthorizationsFailed(failures) => {
+                assert!(matches!(*failures[0].error, AcmeError::Timeout { .. }))
+            }
+            err => panic!("{:?}", err),
+        }
+        assert!(server.request_count(Endpoint::Authorization) < 100);
+    }
+
+    #[async_std::test]
+    async fn issue_cancelled_during_validation() {
+        let server = MockAcmeServer::new();
+        server.set_processing_polls(100);
+        let cancel = CancellationToken::new();
+        let sleeper_cancel = cancel.clone();
+        let mut client = server.client().await.unwrap();
+        client.set_sleeper(crate::sleep::FnSleeper(move |_| {
+            sleeper_cancel.cancel();
+            futures_util::future::ready(())
+        }));
+        let account = client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+        let solver = LoggingDns01Solver::default();
+
+        let options = IssuanceOptions::default().cancellation(cancel);
+        let err = issue_with_options(&account, &new_order(&["example.com"]), &solver, &options)
+            .await
+            .unwrap_err();
+        let saved = match err {
+            AcmeError::Cancelled { order: Some(saved) } => saved,
+            err => panic!("{:?}", err),
+        };
+        assert!(solver.records().is_empty());
+        let order = account.resume_order(*saved).await.unwrap();
+        assert_eq!(order.status(), OrderStatus::Pending);
+
+        let err = issue_with_options(&account, &new_order(&["example.com"]), &solver, &options)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, AcmeError::Cancelled { order: None }),
+            "{:?}",
+            err
+        );
+    }
+
+    #[async_std::test]
+    async fn issue_reports_each_authorization() {
+        let server = MockAcmeServer::new();
+        server.fail_validation("b.example.com");
+        server.fail_validation("d.example.com");
+        let account = account(&server).await;
+        let names = [
+            "a.example.com",
+            "b.example.com",
+            "c.example.com",
+            "d.example.com",
+        ];
+
+        let (res, report) = issue_with_report(
+            &account,
+            &new_order(&names),
+            &LoggingDns01Solver::new(),
+            &IssuanceOptions::default(),
+        )
+        .await;
+        match res.unwrap_err() {
+            AcmeError::AuthorizationsFailed(failures) => assert_eq!(failures.len(), 2),
+            err => panic!("{:?}", err),
+        }
+        assert!(report.order_url.is_some());
+        let valid: Vec<_> = report.valid().map(AuthorizationReport::name).collect();
+        assert_eq!(valid, ["a.example.com", "c.example.com"]);
+        let failed: Vec<_> = report.failed().collect();
+        assert_eq!(failed[0].name(), "b.example.com");
+        assert!(matches!(
+            &failed[1].outcome,
+            AuthorizationOutcome::Failed { problem: Some(problem), .. }
+                if problem.has_type(AcmeProblemType::IncorrectResponse)
+        ));
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["authorizations"][0]["outcome"]["status"], "valid");
+
+        // A solver failure stops the issuance before any response is sent
+        let solver = FailingSolver {
+            fail_on: "b.example.com",
+        };
+        let (res, report) = issue_with_report(
+            &account,
+            &new_order(&names),
+            &solver,
+            &IssuanceOptions::default(),
+        )
+        .await;
+        assert!(matches!(res, Err(AcmeError::SolverError(_))));
+        let outcomes: Vec<_> = report
+            .authorizations
+            .iter()
+            .map(|authorization| {
+                serde_json::to_value(&authorization.outcome).unwrap()["status"].clone()
+            })
+            .collect();
+        assert_eq!(outcomes, ["skipped", "failed", "skipped", "skipped"]);
+    }
+
+    /// Fails to present the response for one domain.
+    struct FailingSolver {
+        fail_on: &'static str,
+    }
+
+    #[async_trait]
+    impl ChallengeSolver for FailingSolver {
+        fn supported_types(&self) -> Vec<ChallengeType> {
+            vec![ChallengeType::Http01]
+        }
+
+        async fn present(&self, params: &ChallengeParams) -> anyhow::Result<()> {
+            anyhow::ensure!(
+                params.domain != self.fail_on,
+                "no server for {}",
+                params.domain
+            );
+            Ok(())
+        }
+
+        async fn cleanup(&self, _params: &ChallengeParams) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+}
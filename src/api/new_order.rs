@@ -0,0 +1,226 @@
+use std::net::IpAddr;
+
+use chrono::{DateTime, Duration, FixedOffset};
+
+use crate::{
+    error::{AcmeError, AcmeResult},
+    wire::{challenge::ChallengeType, identifier::AcmeIdentifier, order::NewOrderResource},
+};
+
+use super::{account::Account, dns_identifier::IdentifierValidator, order::Order};
+
+/// Builds and submits a newOrder request; see `Account::order`.
+pub struct NewOrderBuilder<'a> {
+    account: &'a Account,
+    resource: NewOrderResource,
+    validator: IdentifierValidator,
+    challenge_types: Option<Vec<ChallengeType>>,
+    // Identifier values and the names they were added as
+    requested_names: Vec<(String, String)>,
+    error: Option<AcmeError>,
+}
+
+impl<'a> NewOrderBuilder<'a> {
+    pub(crate) fn new(account: &'a Account) -> Self {
+        Self {
+            account,
+            resource: Default::default(),
+            validator: Default::default(),
+            challenge_types: None,
+            requested_names: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// Sets how names passed to `dns` and `dns_under` are checked; only
+    /// affects names added after this call.
+    pub fn identifier_validator(mut self, validator: IdentifierValidator) -> Self {
+        self.validator = validator;
+        self
+    }
+
+    /// Adds a DNS identifier, which may be a wildcard ("*.example.com").
+    /// Unicode names are converted to A-labels.
+    ///
+    /// Like every identifier, names are normalized (case, trailing dot) and
+    /// duplicates dropped, keeping the first; `Order::requested_names` maps
+    /// authorizations back to the names as added.
+    pub fn dns(mut self, name: impl Into<String>) -> Self {
+        let requested = name.into();
+        let name = self.normalize(requested.clone());
+        self.add(AcmeIdentifier::dns(name), requested)
+    }
+
+    fn normalize(&mut self, name: String) -> String {
+        match self.validator.normalize_dns_name(&name) {
+            Ok(normalized) => normalized,
+            Err(err) => {
+                self.error.get_or_insert(err);
+                name
+            }
+        }
+    }
+
+    /// Adds a DNS identifier to be covered by an authorization of its
+    /// `ancestor` domain (RFC 9444), so that e.g. many subdomains of one
+    /// customer domain need only one validation. The ancestor is only sent
+    /// if the server advertises `subdomainAuthAllowed`; otherwise `name` is
+    /// authorized by itself.
+    pub fn dns_under(mut self, name: impl Into<String>, ancestor: impl Into<String>) -> Self {
+        let requested = name.into();
+        let name = self.normalize(requested.clone());
+        let ancestor = self.normalize(ancestor.into());
+        let is_subdomain = name
+            .strip_suffix(ancestor.as_str())
+            .is_some_and(|prefix| prefix.ends_with('.'));
+        if !is_subdomain {
+            self.error
+                .get_or_insert(AcmeError::InvalidIdentifier(format!(
+                    "{} is not a subdomain of {}",
+                    name, ancestor
+                )));
+        }
+        let allowed = self
+            .account
+            .client()
+            .directory()
+            .meta
+            .subdomain_auth_allowed();
+        let mut identifier = AcmeIdentifier::dns(name);
+        if allowed {
+            identifier.ancestor_domain = Some(ancestor);
+        }
+        self.add(identifier, requested)
+    }
+
+    /// Adds an IP address identifier (RFC 8738).
+    pub fn ip(self, addr: IpAddr) -> Self {
+        self.add(AcmeIdentifier::ip(addr), addr.to_string())
+    }
+
+    /// Adds an identifier of any type. DNS names are normalized as for `dns`
+    /// and IP addresses put in their canonical form.
+    pub fn identifier(mut self, mut identifier: AcmeIdentifier) -> Self {
+        let requested = identifier.value.clone();
+        if identifier.dns_name().is_some() {
+            identifier.value = self.normalize(identifier.value);
+        } else if identifier.is_ip() {
+            match identifier.value.parse::<IpAddr>() {
+                Ok(addr) => identifier.value = addr.to_string(),
+                Err(_) => {
+                    self.error
+                        .get_or_insert(AcmeError::InvalidIdentifier(identifier.value.clone()));
+                }
+            }
+        }
+        self.add(identifier, requested)
+    }
+
+    fn add(mut self, identifier: AcmeIdentifier, requested: String) -> Self {
+        let duplicate = self.resource.identifiers.iter().any(|existing| {
+            existing.type_ == identifier.type_ && existing.value == identifier.value
+        });
+        if !duplicate {
+            self.requested_names
+                .push((identifier.value.clone(), requested));
+            self.resource.identifiers.push(identifier);
+        }
+        self
+    }
+
+    pub fn not_before(mut self, not_before: impl Into<DateTime<FixedOffset>>) -> Self {
+        self.resource.not_before = Some(not_before.into());
+        self
+    }
+
+    pub fn not_after(mut self, not_after: impl Into<DateTime<FixedOffset>>) -> Self {
+        self.resource.not_after = Some(not_after.into());
+        self
+    }
+
+    /// Requests a notAfter of `duration` from now, by the client's clock.
+    pub fn not_after_in(self, duration: Duration) -> Self {
+        let now = self.account.client().clock().now();
+        self.not_after(now + duration)
+    }
+
+    /// Selects a certificate profile; see `Client::supported_profiles`.
+    pub fn profile(mut self, profile: impl Into<String>) -> Self {
+        self.resource.profile = Some(profile.into());
+        self
+    }
+
+    /// Marks the order as replacing the certificate with the given ARI CertID.
+    pub fn replaces(mut self, cert_id: impl Into<String>) -> Self {
+        self.resource.replaces = Some(cert_id.into());
+        self
+    }
+
+    /// Requests a certificate under a delegation from the Identifier Owner;
+    /// see `Account::get_delegation` and `CsrBuilder::from_template`.
+    pub fn delegation(mut self, delegation_url: impl Into<String>) -> Self {
+        self.resource.delegation = Some(delegation_url.into());
+        self
+    }
+
+    /// Allows the certificate to be fetched without authentication; see
+    /// `AcmeClient::get_certificate_chain_unauthenticated`.
+    pub fn allow_certificate_get(mut self, allow: bool) -> Self {
+        self.resource.allow_certificate_get = Some(allow);
+        self
+    }
+
+    /// The challenge types that will be used to authorize the order (e.g.
+    /// from `ChallengeSolver::supported_types`), so that `build` can reject
+    /// identifiers none of them can validate.
+    pub fn challenge_types(mut self, types: impl IntoIterator<Item = ChallengeType>) -> Self {
+        self.challenge_types = Some(types.into_iter().collect());
+        self
+    }
+
+    /// Validates the request and returns the wire resource without sending it.
+    pub fn build(self) -> AcmeResult<NewOrderResource> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        if self.resource.identifiers.is_empty() {
+            return Err(AcmeError::InvalidState(
+                "new order has no identifiers".to_string(),
+            ));
+        }
+        if let Some(types) = &self.challenge_types {
+            check_wildcards_solvable(&self.resource.identifiers, types)?;
+        }
+        Ok(self.resource)
+    }
+
+    pub async fn send(mut self) -> AcmeResult<Order> {
+        let account = self.account;
+        let requested_names = std::mem::take(&mut self.requested_names);
+        let order = account.new_order(&self.build()?).await?;
+        Ok(order.with_requested_names(requested_names))
+    }
+}
+
+/// Wildcard authorizations can't be validated with http-01 (RFC 8555 section
+/// 8.3) or tls-alpn-01 (RFC 8737 section 3), so fails if `types` has neither
+/// dns-01 nor an extension type that might work.
+pub(crate) fn check_wildcards_solvable(
+    identifiers: &[AcmeIdentifier],
+    types: &[ChallengeType],
+) -> AcmeResult<()> {
+    let solvable = types
+        .iter()
+        .any(|type_| matches!(type_, ChallengeType::Dns01 | ChallengeType::Other(_)));
+    match identifiers
+        .iter()
+        .filter_map(AcmeIdentifier::dns_name)
+        .find(|name| name.starts_with("*."))
+    {
+        Some(name) if !solvable => Err(AcmeError::InvalidIdentifier(format!(
+            "{}: wildcards can only be validated with dns-01",
+            name
+        ))),
+        _ => Ok(()),
+    }
+}
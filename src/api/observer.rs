@@ -0,0 +1,39 @@
+use crate::error::AcmeError;
+
+use super::{
+    authorization::Authorization, order::Order, solver::ChallengeParams, store::CertificateBundle,
+};
+
+/// Receives progress events from `issuance::issue_with_observer` and
+/// `IssuanceScheduler`, e.g. to print progress or record metrics. Every
+/// method defaults to doing nothing.
+///
+/// Callbacks run inline in the issuance flow, so they should return quickly.
+pub trait IssuanceObserver: Send + Sync {
+    /// The newOrder request succeeded.
+    fn on_order_created(&self, _order: &Order) {}
+
+    /// A challenge response was put in place by the solver.
+    fn on_challenge_presented(&self, _params: &ChallengeParams) {}
+
+    /// The server validated an authorization's challenge.
+    fn on_challenge_valid(&self, _authorization: &Authorization) {}
+
+    /// The finalize request was accepted.
+    fn on_finalized(&self, _order: &Order) {}
+
+    fn on_certificate_downloaded(&self, _bundle: &CertificateBundle) {}
+
+    /// A failed `operation` (such as "finalize" after orderNotReady) is
+    /// about to be retried.
+    fn on_retry(&self, _operation: &str, _error: &AcmeError) {}
+
+    /// The issuance failed with `error`.
+    fn on_error(&self, _error: &AcmeError) {}
+}
+
+/// An `IssuanceObserver` that ignores every event.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopObserver;
+
+impl IssuanceObserver for NoopObserver {}
@@ -0,0 +1,88 @@
+use std::time::Duration;
+
+use crate::wire::challenge::ChallengeType;
+#[cfg(any(feature = "x509", feature = "x509-rcgen"))]
+use crate::x509::KeyType;
+
+/// How certificates are issued, in one place: set on an `Account` with
+/// `Account::set_issuance_policy`, or for a single issuance with
+/// `IssuanceOptions::policy`. The defaults leave every choice to the solver,
+/// the options and the client.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IssuancePolicy {
+    /// Challenge types to use when offered, most preferred first. Other
+    /// types the solver supports are used after these, in its own order.
+    pub preferred_challenge_types: Vec<ChallengeType>,
+
+    /// Certificate key types that may be generated; the first is used unless
+    /// `IssuanceOptions::key_type` picks another. Empty allows any.
+    #[cfg(any(feature = "x509", feature = "x509-rcgen"))]
+    pub allowed_key_types: Vec<KeyType>,
+
+    /// The issuer of the preferred chain's topmost certificate; see
+    /// `IssuanceOptions::preferred_chain`.
+    #[cfg(feature = "x509")]
+    pub preferred_chain: Option<String>,
+
+    /// How long to wait for each authorization to be validated after its
+    /// challenge is responded to, as measured by the client's `Clock`.
+    pub max_validation_wait: Option<Duration>,
+
+    /// A fixed delay between presenting dns-01 responses and responding to
+    /// the challenges, for DNS providers that are slow to publish changes.
+    /// It comes after any `ChallengeSolver::wait_until_ready`.
+    pub dns_propagation_wait: Option<Duration>,
+
+    /// How many issuances an `IssuanceScheduler` may run at once.
+    pub max_concurrent: Option<usize>,
+}
+
+impl IssuancePolicy {
+    /// Orders the challenge types `supported` by a solver by preference.
+    pub fn challenge_preference(&self, supported: &[ChallengeType]) -> Vec<ChallengeType> {
+        let preferred = self
+            .preferred_challenge_types
+            .iter()
+            .filter(|challenge_type| supported.contains(challenge_type));
+        let rest = supported
+            .iter()
+            .filter(|challenge_type| !self.preferred_challenge_types.contains(challenge_type));
+        preferred.chain(rest).cloned().collect()
+    }
+
+    /// Whether certificates may be issued for keys of `key_type`.
+    #[cfg(any(feature = "x509", feature = "x509-rcgen"))]
+    pub fn allows_key_type(&self, key_type: KeyType) -> bool {
+        self.allowed_key_types.is_empty() || self.allowed_key_types.contains(&key_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn challenge_preference() {
+        let supported = [
+            ChallengeType::Http01,
+            ChallengeType::Dns01,
+            ChallengeType::TlsAlpn01,
+        ];
+        assert_eq!(
+            IssuancePolicy::default().challenge_preference(&supported),
+            supported
+        );
+        let policy = IssuancePolicy {
+            preferred_challenge_types: vec![ChallengeType::Dns01, ChallengeType::OnionCsr01],
+            ..Default::default()
+        };
+        assert_eq!(
+            policy.challenge_preference(&supported),
+            [
+                ChallengeType::Dns01,
+                ChallengeType::Http01,
+                ChallengeType::TlsAlpn01
+            ]
+        );
+    }
+}
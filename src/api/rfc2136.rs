@@ -0,0 +1,787 @@
+use std::{ops::Range, time::Duration};
+
+use anyhow::{anyhow, bail};
+use async_std::{
+    io::{ReadExt, WriteExt},
+    net::TcpStream,
+};
+use async_trait::async_trait;
+use chrono::Utc;
+use hmac::{Hmac, Mac, NewMac};
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Sha256, Sha512};
+use zeroize::Zeroizing;
+
+use super::dns_provider::DnsProvider;
+
+const TYPE_SOA: u16 = 6;
+const TYPE_TXT: u16 = 16;
+const TYPE_TSIG: u16 = 250;
+
+const CLASS_IN: u16 = 1;
+const CLASS_NONE: u16 = 254;
+const CLASS_ANY: u16 = 255;
+
+const FLAG_QR: u16 = 1 << 15;
+const OPCODE_UPDATE: u16 = 5 << 11;
+
+const RCODE_NXDOMAIN: u16 = 3;
+
+/// Allowed clock skew for TSIG signatures, in seconds.
+const TSIG_FUDGE: u16 = 300;
+
+/// https://www.rfc-editor.org/rfc/rfc8945.html#section-6
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TsigAlgorithm {
+    HmacSha256,
+    HmacSha512,
+}
+
+impl TsigAlgorithm {
+    fn name(self) -> &'static str {
+        match self {
+            Self::HmacSha256 => "hmac-sha256",
+            Self::HmacSha512 => "hmac-sha512",
+        }
+    }
+
+    fn sign(self, secret: &[u8], data: &[u8]) -> Vec<u8> {
+        match self {
+            Self::HmacSha256 => hmac::<Sha256>(secret, data)
+                .finalize()
+                .into_bytes()
+                .to_vec(),
+            Self::HmacSha512 => hmac::<Sha512>(secret, data)
+                .finalize()
+                .into_bytes()
+                .to_vec(),
+        }
+    }
+
+    fn verify(self, secret: &[u8], data: &[u8], mac: &[u8]) -> bool {
+        match self {
+            Self::HmacSha256 => hmac::<Sha256>(secret, data).verify(mac).is_ok(),
+            Self::HmacSha512 => hmac::<Sha512>(secret, data).verify(mac).is_ok(),
+        }
+    }
+}
+
+fn hmac<D>(secret: &[u8], data: &[u8]) -> Hmac<D>
+where
+    D: sha2::digest::Update
+        + sha2::digest::BlockInput
+        + sha2::digest::FixedOutput
+        + sha2::digest::Reset
+        + Default
+        + Clone,
+{
+    let mut mac = Hmac::<D>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac
+}
+
+/// A shared secret for signing updates, as configured on the DNS server
+/// (e.g. BIND's `key` statement).
+#[derive(Clone)]
+pub struct TsigKey {
+    name: String,
+    algorithm: TsigAlgorithm,
+    secret: Zeroizing<Vec<u8>>,
+}
+
+impl TsigKey {
+    pub fn new(name: impl Into<String>, algorithm: TsigAlgorithm, secret: Vec<u8>) -> Self {
+        Self {
+            name: name.into(),
+            algorithm,
+            secret: Zeroizing::new(secret),
+        }
+    }
+
+    /// Takes the secret base64-encoded, as output by e.g. `tsig-keygen`.
+    pub fn from_base64(
+        name: impl Into<String>,
+        algorithm: TsigAlgorithm,
+        secret: &str,
+    ) -> anyhow::Result<Self> {
+        Ok(Self::new(name, algorithm, base64::decode(secret.trim())?))
+    }
+}
+
+impl std::fmt::Debug for TsigKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TsigKey")
+            .field("name", &self.name)
+            .field("algorithm", &self.algorithm)
+            .field("secret", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Writes TXT records with DNS UPDATE messages sent over TCP to the zone's
+/// primary server.
+/// https://www.rfc-editor.org/rfc/rfc2136.html
+#[derive(Clone, Debug)]
+pub struct Rfc2136Provider {
+    server: String,
+    key: Option<TsigKey>,
+    zone: Option<String>,
+    ttl: u32,
+    timeout: Duration,
+}
+
+impl Rfc2136Provider {
+    /// `server` is a "host:port" address, e.g. "ns1.example.com:53".
+    pub fn new(server: impl Into<String>) -> Self {
+        Self {
+            server: server.into(),
+            key: None,
+            zone: None,
+            ttl: 60,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Signs updates with TSIG; almost all servers require this.
+    pub fn with_tsig_key(mut self, key: TsigKey) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// Uses `zone` for every name instead of asking the server for its SOA.
+    pub fn with_zone(mut self, zone: impl Into<String>) -> Self {
+        self.zone = Some(zone.into());
+        self
+    }
+
+    /// The TTL of created records (default 60 seconds).
+    pub fn with_ttl(mut self, ttl: u32) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// How long to wait for each server response (default 10 seconds).
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    async fn update(&self, zone: &str, name: &str, class: u16, value: &str) -> anyhow::Result<()> {
+        let id = OsRng.next_u32() as u16;
+        let ttl = if class == CLASS_IN { self.ttl } else { 0 };
+        let mut msg = update_message(id, zone, name, class, ttl, value)?;
+        let request_mac = match &self.key {
+            Some(key) => Some(sign(&mut msg, key, Utc::now().timestamp() as u64, None)?),
+            None => None,
+        };
+
+        let resp = self.exchange(&msg).await?;
+        let parsed = parse_message(&resp)?;
+        parsed.check_response_to(id)?;
+        let verified = match (&self.key, &request_mac) {
+            (Some(key), Some(mac)) => verify(
+                &resp,
+                &parsed,
+                key,
+                Some(mac),
+                Utc::now().timestamp() as u64,
+            ),
+            _ => Ok(()),
+        };
+        match (parsed.rcode(), verified) {
+            (0, verified) => verified,
+            (rcode, Ok(())) => bail!("DNS update for {} failed: {}", name, rcode_name(rcode)),
+            (rcode, Err(err)) => bail!(
+                "DNS update for {} failed: {} ({})",
+                name,
+                rcode_name(rcode),
+                err
+            ),
+        }
+    }
+
+    async fn exchange(&self, msg: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let len = u16::try_from(msg.len()).map_err(|_| anyhow!("DNS message too long"))?;
+        let exchange = async {
+            let mut stream = TcpStream::connect(self.server.as_str()).await?;
+            let mut framed = len.to_be_bytes().to_vec();
+            framed.extend_from_slice(msg);
+            stream.write_all(&framed).await?;
+            let mut len = [0; 2];
+            stream.read_exact(&mut len).await?;
+            let mut resp = vec![0; u16::from_be_bytes(len) as usize];
+            stream.read_exact(&mut resp).await?;
+            Ok::<_, std::io::Error>(resp)
+        };
+        async_std::future::timeout(self.timeout, exchange)
+            .await
+            .map_err(|_| anyhow!("DNS server {} timed out", self.server))?
+            .map_err(Into::into)
+    }
+}
+
+#[async_trait]
+impl DnsProvider for Rfc2136Provider {
+    /// Asks the server for the SOA of `name`; an authoritative server answers
+    /// with the enclosing zone's SOA record.
+    async fn find_zone(&self, name: &str) -> anyhow::Result<String> {
+        if let Some(zone) = &self.zone {
+            return Ok(zone.clone());
+        }
+        let id = OsRng.next_u32() as u16;
+        let resp = self.exchange(&soa_query(id, name)?).await?;
+        let parsed = parse_message(&resp)?;
+        parsed.check_response_to(id)?;
+        match parsed.rcode() {
+            0 | RCODE_NXDOMAIN => {}
+            rcode => bail!("SOA query for {} failed: {}", name, rcode_name(rcode)),
+        }
+        parsed
+            .answers
+            .iter()
+            .chain(&parsed.authority)
+            .find(|record| record.type_ == TYPE_SOA)
+            .map(|record| record.name.clone())
+            .ok_or_else(|| anyhow!("no zone found for {}", name))
+    }
+
+    async fn create_txt(&self, zone: &str, name: &str, value: &str) -> anyhow::Result<()> {
+        self.update(zone, name, CLASS_IN, value).await
+    }
+
+    async fn delete_txt(&self, zone: &str, name: &str, value: &str) -> anyhow::Result<()> {
+        self.update(zone, name, CLASS_NONE, value).await
+    }
+}
+
+fn rcode_name(rcode: u16) -> String {
+    let name = match rcode {
+        1 => "FORMERR",
+        2 => "SERVFAIL",
+        3 => "NXDOMAIN",
+        4 => "NOTIMP",
+        5 => "REFUSED",
+        6 => "YXDOMAIN",
+        7 => "YXRRSET",
+        8 => "NXRRSET",
+        9 => "NOTAUTH",
+        10 => "NOTZONE",
+        16 => "BADSIG",
+        17 => "BADKEY",
+        18 => "BADTIME",
+        22 => "BADTRUNC",
+        _ => return format!("rcode {}", rcode),
+    };
+    name.to_string()
+}
+
+fn push_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+fn push_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+fn push_u48(buf: &mut Vec<u8>, value: u64) {
+    buf.extend_from_slice(&value.to_be_bytes()[2..]);
+}
+
+fn push_name(buf: &mut Vec<u8>, name: &str) -> anyhow::Result<()> {
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        if label.len() > 63 {
+            bail!("DNS label too long in {:?}", name);
+        }
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label.as_bytes());
+    }
+    buf.push(0);
+    Ok(())
+}
+
+fn header(id: u16, flags: u16, counts: [u16; 4]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(512);
+    push_u16(&mut buf, id);
+    push_u16(&mut buf, flags);
+    for count in counts {
+        push_u16(&mut buf, count);
+    }
+    buf
+}
+
+/// An UPDATE adding (class IN) or deleting (class NONE) one TXT record.
+/// https://www.rfc-editor.org/rfc/rfc2136.html#section-2.5
+fn update_message(
+    id: u16,
+    zone: &str,
+    name: &str,
+    class: u16,
+    ttl: u32,
+    value: &str,
+) -> anyhow::Result<Vec<u8>> {
+    let mut msg = header(id, OPCODE_UPDATE, [1, 0, 1, 0]);
+    push_name(&mut msg, zone)?;
+    push_u16(&mut msg, TYPE_SOA);
+    push_u16(&mut msg, CLASS_IN);
+
+    let mut rdata = Vec::new();
+    for chunk in value.as_bytes().chunks(255) {
+        rdata.push(chunk.len() as u8);
+        rdata.extend_from_slice(chunk);
+    }
+    if rdata.is_empty() {
+        rdata.push(0);
+    }
+    push_name(&mut msg, name)?;
+    push_u16(&mut msg, TYPE_TXT);
+    push_u16(&mut msg, class);
+    push_u32(&mut msg, ttl);
+    push_u16(&mut msg, rdata.len() as u16);
+    msg.extend_from_slice(&rdata);
+    Ok(msg)
+}
+
+fn soa_query(id: u16, name: &str) -> anyhow::Result<Vec<u8>> {
+    let mut msg = header(id, 0, [1, 0, 0, 0]);
+    push_name(&mut msg, name)?;
+    push_u16(&mut msg, TYPE_SOA);
+    push_u16(&mut msg, CLASS_IN);
+    Ok(msg)
+}
+
+/// https://www.rfc-editor.org/rfc/rfc8945.html#section-4.3.3
+fn tsig_variables(
+    key: &TsigKey,
+    time_signed: u64,
+    fudge: u16,
+    error: u16,
+    other: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    push_name(&mut buf, &key.name.to_ascii_lowercase())?;
+    push_u16(&mut buf, CLASS_ANY);
+    push_u32(&mut buf, 0);
+    push_name(&mut buf, key.algorithm.name())?;
+    push_u48(&mut buf, time_signed);
+    push_u16(&mut buf, fudge);
+    push_u16(&mut buf, error);
+    push_u16(&mut buf, other.len() as u16);
+    buf.extend_from_slice(other);
+    Ok(buf)
+}
+
+/// Appends a TSIG record to `msg` and returns its MAC. Responses are signed
+/// over the request's MAC too.
+fn sign(
+    msg: &mut Vec<u8>,
+    key: &TsigKey,
+    time_signed: u64,
+    request_mac: Option<&[u8]>,
+) -> anyhow::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    if let Some(request_mac) = request_mac {
+        push_u16(&mut data, request_mac.len() as u16);
+        data.extend_from_slice(request_mac);
+    }
+    data.extend_from_slice(msg);
+    data.extend(tsig_variables(key, time_signed, TSIG_FUDGE, 0, &[])?);
+    let mac = key.algorithm.sign(&key.secret, &data);
+
+    let mut rdata = Vec::new();
+    push_name(&mut rdata, key.algorithm.name())?;
+    push_u48(&mut rdata, time_signed);
+    push_u16(&mut rdata, TSIG_FUDGE);
+    push_u16(&mut rdata, mac.len() as u16);
+    rdata.extend_from_slice(&mac);
+    rdata.extend_from_slice(&msg[0..2]);
+    push_u16(&mut rdata, 0);
+    push_u16(&mut rdata, 0);
+
+    push_name(msg, &key.name)?;
+    push_u16(msg, TYPE_TSIG);
+    push_u16(msg, CLASS_ANY);
+    push_u32(msg, 0);
+    push_u16(msg, rdata.len() as u16);
+    msg.extend_from_slice(&rdata);
+    let arcount = u16::from_be_bytes([msg[10], msg[11]]) + 1;
+    msg[10..12].copy_from_slice(&arcount.to_be_bytes());
+    Ok(mac)
+}
+
+/// Checks the TSIG record that must end `buf`, at Unix time `now`.
+fn verify(
+    buf: &[u8],
+    msg: &Message,
+    key: &TsigKey,
+    request_mac: Option<&[u8]>,
+    now: u64,
+) -> anyhow::Result<()> {
+    let tsig = msg
+        .additional
+        .last()
+        .filter(|record| record.type_ == TYPE_TSIG)
+        .ok_or_else(|| anyhow!("DNS message is not signed"))?;
+    if !same_name(&tsig.name, &key.name) {
+        bail!("DNS message signed with unexpected key {:?}", tsig.name);
+    }
+
+    let mut pos = tsig.rdata.start;
+    let algorithm = read_name(buf, &mut pos)?;
+    let time_signed = u64::from(read_u16(buf, &mut pos)?) << 32
+        | u64::from(read_u16(buf, &mut pos)?) << 16
+        | u64::from(read_u16(buf, &mut pos)?);
+    let fudge = read_u16(buf, &mut pos)?;
+    let mac_len = read_u16(buf, &mut pos)? as usize;
+    let mac = read_bytes(buf, &mut pos, mac_len)?;
+    let original_id = read_bytes(buf, &mut pos, 2)?;
+    let error = read_u16(buf, &mut pos)?;
+    let other_len = read_u16(buf, &mut pos)? as usize;
+    let other = read_bytes(buf, &mut pos, other_len)?;
+
+    if error != 0 {
+        bail!("TSIG error {}", rcode_name(error));
+    }
+    if !same_name(&algorithm, key.algorithm.name()) {
+        bail!(
+            "DNS message signed with unexpected algorithm {:?}",
+            algorithm
+        );
+    }
+
+    let mut data = Vec::new();
+    if let Some(request_mac) = request_mac {
+        push_u16(&mut data, request_mac.len() as u16);
+        data.extend_from_slice(request_mac);
+    }
+    let unsigned_start = data.len();
+    data.extend_from_slice(&buf[..tsig.start]);
+    data[unsigned_start..unsigned_start + 2].copy_from_slice(original_id);
+    let arcount = u16::from_be_bytes([buf[10], buf[11]]) - 1;
+    data[unsigned_start + 10..unsigned_start + 12].copy_from_slice(&arcount.to_be_bytes());
+    data.extend(tsig_variables(key, time_signed, fudge, error, other)?);
+    if !key.algorithm.verify(&key.secret, &data, mac) {
+        bail!("TSIG signature mismatch");
+    }
+
+    if now.abs_diff(time_signed) > u64::from(fudge) {
+        bail!("TSIG time outside allowed clock skew");
+    }
+    Ok(())
+}
+
+fn same_name(a: &str, b: &str) -> bool {
+    a.trim_end_matches('.')
+        .eq_ignore_ascii_case(b.trim_end_matches('.'))
+}
+
+struct Record {
+    name: String,
+    type_: u16,
+    /// Offset of the record in the message.
+    start: usize,
+    rdata: Range<usize>,
+}
+
+struct Message {
+    id: u16,
+    flags: u16,
+    answers: Vec<Record>,
+    authority: Vec<Record>,
+    additional: Vec<Record>,
+}
+
+impl Message {
+    fn rcode(&self) -> u16 {
+        self.flags & 0xf
+    }
+
+    fn check_response_to(&self, id: u16) -> anyhow::Result<()> {
+        if self.flags & FLAG_QR == 0 || self.id != id {
+            bail!("unexpected DNS response");
+        }
+        Ok(())
+    }
+}
+
+fn read_u16(buf: &[u8], pos: &mut usize) -> anyhow::Result<u16> {
+    let bytes = read_bytes(buf, pos, 2)?;
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_bytes<'a>(buf: &'a [u8], pos: &mut usize, len: usize) -> anyhow::Result<&'a [u8]> {
+    let bytes = buf
+        .get(*pos..*pos + len)
+        .ok_or_else(|| anyhow!("truncated DNS message"))?;
+    *pos += len;
+    Ok(bytes)
+}
+
+/// Reads a possibly compressed name, without the trailing dot.
+fn read_name(buf: &[u8], pos: &mut usize) -> anyhow::Result<String> {
+    let mut labels = Vec::new();
+    let mut cursor = *pos;
+    let mut jumped = false;
+    // Each pointer must go backwards, so this bounds the loop
+    let mut limit = cursor;
+    loop {
+        let len = *buf
+            .get(cursor)
+            .ok_or_else(|| anyhow!("truncated DNS message"))? as usize;
+        if len & 0xc0 == 0xc0 {
+            let target = (read_u16(buf, &mut cursor)? & 0x3fff) as usize;
+            if !jumped {
+                *pos = cursor;
+                jumped = true;
+            }
+            if target >= limit {
+                bail!("invalid DNS name compression");
+            }
+            limit = target;
+            cursor = target;
+        } else if len == 0 {
+            cursor += 1;
+            break;
+        } else {
+            cursor += 1;
+            let label = read_bytes(buf, &mut cursor, len)?;
+            labels.push(String::from_utf8_lossy(label).into_owned());
+        }
+    }
+    if !jumped {
+        *pos = cursor;
+    }
+    Ok(labels.join("."))
+}
+
+fn parse_message(buf: &[u8]) -> anyhow::Result<Message> {
+    let mut pos = 0;
+    let id = read_u16(buf, &mut pos)?;
+    let flags = read_u16(buf, &mut pos)?;
+    let mut counts = [0; 4];
+    for count in &mut counts {
+        *count = read_u16(buf, &mut pos)?;
+    }
+    for _ in 0..counts[0] {
+        read_name(buf, &mut pos)?;
+        read_bytes(buf, &mut pos, 4)?;
+    }
+    let mut sections = [Vec::new(), Vec::new(), Vec::new()];
+    for (section, count) in sections.iter_mut().zip(&counts[1..]) {
+        for _ in 0..*count {
+            let start = pos;
+            let name = read_name(buf, &mut pos)?;
+            let type_ = read_u16(buf, &mut pos)?;
+            // class and ttl
+            read_bytes(buf, &mut pos, 6)?;
+            let rdata_len = read_u16(buf, &mut pos)? as usize;
+            let rdata_start = pos;
+            read_bytes(buf, &mut pos, rdata_len)?;
+            section.push(Record {
+                name,
+                type_,
+                start,
+                rdata: rdata_start..pos,
+            });
+        }
+    }
+    let [answers, authority, additional] = sections;
+    Ok(Message {
+        id,
+        flags,
+        answers,
+        authority,
+        additional,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use async_std::net::TcpListener;
+
+    use super::*;
+
+    fn key() -> TsigKey {
+        TsigKey::from_base64("acme-key.", TsigAlgorithm::HmacSha256, "c2VjcmV0").unwrap()
+    }
+
+    #[test]
+    fn encode_update() {
+        let msg =
+            update_message(0x1234, "example.com", "a.example.com", CLASS_IN, 60, "v").unwrap();
+        let mut expected = vec![0x12, 0x34, 0x28, 0, 0, 1, 0, 0, 0, 1, 0, 0];
+        expected.extend(b"\x07example\x03com\x00\x00\x06\x00\x01");
+        expected
+            .extend(b"\x01a\x07example\x03com\x00\x00\x10\x00\x01\x00\x00\x00\x3c\x00\x02\x01v");
+        assert_eq!(msg, expected);
+    }
+
+    #[test]
+    fn read_compressed_name() {
+        let buf = b"\x07example\x03com\x00\x01a\xc0\x00";
+        let mut pos = 13;
+        assert_eq!(read_name(buf, &mut pos).unwrap(), "a.example.com");
+        assert_eq!(pos, buf.len());
+
+        let looping = b"\x01a\xc0\x00";
+        assert!(read_name(looping, &mut 0).is_err());
+    }
+
+    fn unhex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// A TSIG-signed exchange produced by another implementation
+    /// (hickory-proto 0.25's `TSigner`), so that a mistake made the same way
+    /// in `sign` and `verify` doesn't go unnoticed.
+    struct KnownAnswer {
+        algorithm: TsigAlgorithm,
+        /// An UPDATE adding a TXT record for _acme-challenge.example.com,
+        /// id 0x2f1a, signed at 1700000000.
+        request: &'static str,
+        /// The NOERROR response to it, signed at 1700000001.
+        response: &'static str,
+    }
+
+    const SECRET: &str = "2kS2Vdw8t3ZbN8sMZ0xr1cFvJ8cM0vQ5Lr0C5p3c0Qk=";
+
+    const KNOWN_ANSWERS: [KnownAnswer; 2] = [
+        KnownAnswer {
+            algorithm: TsigAlgorithm::HmacSha256,
+            request: "2f1a28000001000000010001076578616d706c6503636f6d00000600010f5f61636d652d6368616c6c656e6765c00c001000010000003c002c2b4c6f71586359563871354f4e624a5178626d52375343544e6f337469415844666f77796a78416a457558300861636d652d6b65790000fa00ff00000000003d0b686d61632d7368613235360000006553f100012c00203576b0a578807b22a84c7c6113ef19ec8ac16be71678c2ffab335c463ad95c632f1a00000000",
+            response: "2f1aa80000000000000000010861636d652d6b65790000fa00ff00000000003d0b686d61632d7368613235360000006553f101012c00206a2b9cc1f98e71a4606fcc87528988e79fff8b0a4d61fb887ff19e98db63d65e2f1a00000000",
+        },
+        KnownAnswer {
+            algorithm: TsigAlgorithm::HmacSha512,
+            request: "2f1a28000001000000010001076578616d706c6503636f6d00000600010f5f61636d652d6368616c6c656e6765c00c001000010000003c002c2b4c6f71586359563871354f4e624a5178626d52375343544e6f337469415844666f77796a78416a457558300861636d652d6b65790000fa00ff00000000005d0b686d61632d7368613531320000006553f100012c0040c27c92c9272d8258ff52ade652101a58071f4876d6b3c71d21bf1386ffa8d5647e20c337c2c440296111dca35ebf66503653dde5f7ea3f98948c99cbb48e38e22f1a00000000",
+            response: "2f1aa80000000000000000010861636d652d6b65790000fa00ff00000000005d0b686d61632d7368613531320000006553f101012c004015824e4961bc56792d9dcca4f57d53022784bc13f73f27ab905cc2926f480c62fd385ec9b2ac3e296867ad2f757e1532a7913af18f0b8d4112663c34f02bae052f1a00000000",
+        },
+    ];
+
+    /// Length of the TSIG record's RDATA after the MAC.
+    const TSIG_TRAILER: usize = 6;
+
+    #[test]
+    fn sign_known_answers() {
+        for known in KNOWN_ANSWERS {
+            let key = TsigKey::from_base64("acme-key.", known.algorithm, SECRET).unwrap();
+            let request = unhex(known.request);
+            // Strip the TSIG record (its name is the key's, at the first
+            // occurrence of it) and restore the additional count
+            let tsig_start = request
+                .windows(10)
+                .position(|w| w == b"\x08acme-key\x00")
+                .unwrap();
+            let mut msg = request[..tsig_start].to_vec();
+            msg[11] -= 1;
+
+            let mac = sign(&mut msg, &key, 1_700_000_000, None).unwrap();
+            assert_eq!(msg, request, "{:?}", known.algorithm);
+            assert_eq!(
+                mac,
+                request[request.len() - TSIG_TRAILER - mac.len()..request.len() - TSIG_TRAILER]
+            );
+        }
+    }
+
+    #[test]
+    fn verify_known_answers() {
+        for known in KNOWN_ANSWERS {
+            let key = TsigKey::from_base64("acme-key.", known.algorithm, SECRET).unwrap();
+            let request = unhex(known.request);
+            let response = unhex(known.response);
+            let mac_len = match known.algorithm {
+                TsigAlgorithm::HmacSha256 => 32,
+                TsigAlgorithm::HmacSha512 => 64,
+            };
+            let request_mac =
+                &request[request.len() - TSIG_TRAILER - mac_len..request.len() - TSIG_TRAILER];
+
+            let parsed = parse_message(&request).unwrap();
+            verify(&request, &parsed, &key, None, 1_700_000_000).unwrap();
+            let parsed = parse_message(&response).unwrap();
+            verify(&response, &parsed, &key, Some(request_mac), 1_700_000_000).unwrap();
+
+            // Outside the fudge
+            verify(&response, &parsed, &key, Some(request_mac), 1_700_000_302).unwrap_err();
+            // Not a response to this request
+            verify(&response, &parsed, &key, Some(&[0; 32]), 1_700_000_000).unwrap_err();
+            // Wrong secret
+            let other = TsigKey::new("acme-key.", known.algorithm, b"other".to_vec());
+            verify(&response, &parsed, &other, Some(request_mac), 1_700_000_000).unwrap_err();
+            // Any changed byte of the signed request after the ID, which is
+            // covered by the TSIG record's original ID instead
+            let parsed = parse_message(&request).unwrap();
+            for i in 2..parsed.additional.last().unwrap().start {
+                let mut tampered = request.clone();
+                tampered[i] ^= 1;
+                let verified = parse_message(&tampered)
+                    .and_then(|parsed| verify(&tampered, &parsed, &key, None, 1_700_000_000));
+                assert!(verified.is_err(), "byte {} of {:?}", i, known.algorithm);
+            }
+        }
+    }
+
+    /// Accepts one update and answers it with `rcode`, signed.
+    async fn serve_update(listener: TcpListener, rcode: u16) -> Vec<u8> {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut len = [0; 2];
+        stream.read_exact(&mut len).await.unwrap();
+        let mut req = vec![0; u16::from_be_bytes(len) as usize];
+        stream.read_exact(&mut req).await.unwrap();
+
+        let parsed = parse_message(&req).unwrap();
+        verify(&req, &parsed, &key(), None, Utc::now().timestamp() as u64).unwrap();
+        // The MAC is followed by original id, error and other length
+        let request_mac = req[req.len() - 6 - 32..req.len() - 6].to_vec();
+
+        let mut resp = header(
+            u16::from_be_bytes([req[0], req[1]]),
+            FLAG_QR | OPCODE_UPDATE | rcode,
+            [0, 0, 0, 0],
+        );
+        sign(
+            &mut resp,
+            &key(),
+            Utc::now().timestamp() as u64,
+            Some(&request_mac),
+        )
+        .unwrap();
+        let mut framed = (resp.len() as u16).to_be_bytes().to_vec();
+        framed.extend(resp);
+        stream.write_all(&framed).await.unwrap();
+        req
+    }
+
+    #[async_std::test]
+    async fn signed_update() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let provider =
+            Rfc2136Provider::new(listener.local_addr().unwrap().to_string()).with_tsig_key(key());
+        let server = async_std::task::spawn(serve_update(listener, 0));
+
+        provider
+            .create_txt("example.com", "_acme-challenge.example.com", "value")
+            .await
+            .unwrap();
+        let req = server.await;
+        assert!(req.windows(6).any(|w| w == b"\x05value"));
+    }
+
+    #[async_std::test]
+    async fn refused_update() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let provider =
+            Rfc2136Provider::new(listener.local_addr().unwrap().to_string()).with_tsig_key(key());
+        let server = async_std::task::spawn(serve_update(listener, 5));
+
+        let err = provider
+            .delete_txt("example.com", "_acme-challenge.example.com", "value")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("REFUSED"), "{}", err);
+        server.await;
+    }
+}
@@ -0,0 +1,751 @@
+//! Bulk issuance that stays within a CA's rate limits.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use futures_util::{
+    future::{select, Either},
+    stream::{FuturesUnordered, StreamExt},
+};
+
+use crate::{
+    cancel::CancellationToken,
+    error::{AcmeError, AcmeResult},
+    wire::{identifier::AcmeIdentifier, order::NewOrderResource},
+};
+
+use super::{
+    account::Account,
+    account_pool::AccountPool,
+    issuance::{issue_with_options, IssuanceOptions},
+    observer::IssuanceObserver,
+    solver::ChallengeSolver,
+    store::CertificateBundle,
+};
+
+const DEFAULT_MAX_CONCURRENT: usize = 4;
+
+/// At most `count` (at least 1) events in any sliding `period`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Budget {
+    pub count: usize,
+    pub period: Duration,
+}
+
+impl Budget {
+    pub const fn new(count: usize, period: Duration) -> Self {
+        Self { count, period }
+    }
+}
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+const WEEK: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// The rate limits to budget for; None disables a limit. Defaults to Let's
+/// Encrypt's: https://letsencrypt.org/docs/rate-limits/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimits {
+    /// New orders per account (Let's Encrypt: 300 per 3 hours).
+    pub new_orders_per_account: Option<Budget>,
+
+    /// Orders including a name under a registered domain (Let's Encrypt: 50
+    /// certificates per week).
+    pub certificates_per_registered_domain: Option<Budget>,
+
+    /// Orders for exactly the same identifiers (Let's Encrypt: 5 per week).
+    pub duplicate_certificates: Option<Budget>,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            new_orders_per_account: Some(Budget::new(300, 3 * HOUR)),
+            certificates_per_registered_domain: Some(Budget::new(50, WEEK)),
+            duplicate_certificates: Some(Budget::new(5, WEEK)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Bucket {
+    Account(String),
+    RegisteredDomain(String),
+    Identifiers(Vec<String>),
+}
+
+/// Counts orders against `RateLimits` and works out when the next one may be
+/// placed.
+///
+/// Orders are counted when they are scheduled, whether or not they go on to
+/// be issued, which errs on the side of staying under the limits. Keep one
+/// tracker across scheduler runs, seeded with `record` from earlier
+/// issuance, for the budget to be accurate.
+#[derive(Debug)]
+pub struct RateLimitTracker {
+    limits: RateLimits,
+    registered_domain: fn(&str) -> String,
+    events: HashMap<Bucket, VecDeque<DateTime<Utc>>>,
+    blocked_until: HashMap<String, DateTime<Utc>>,
+}
+
+impl RateLimitTracker {
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            limits,
+            registered_domain: last_two_labels,
+            events: HashMap::new(),
+            blocked_until: HashMap::new(),
+        }
+    }
+
+    /// Sets how an identifier maps to its registered domain. The default
+    /// takes the last two labels, which is wrong under public suffixes like
+    /// "co.uk"; use a public suffix list if those are issued for.
+    pub fn with_registered_domain(mut self, registered_domain: fn(&str) -> String) -> Self {
+        self.registered_domain = registered_domain;
+        self
+    }
+
+    pub fn limits(&self) -> &RateLimits {
+        &self.limits
+    }
+
+    /// The earliest time, no earlier than `now`, that `account_url` may
+    /// order a certificate for `identifiers`.
+    pub fn next_slot(
+        &self,
+        account_url: &str,
+        identifiers: &[AcmeIdentifier],
+        now: DateTime<Utc>,
+    ) -> DateTime<Utc> {
+        let mut slot = now;
+        if let Some(blocked_until) = self.blocked_until.get(account_url) {
+            slot = slot.max(*blocked_until);
+        }
+        for (bucket, budget) in self.buckets(account_url, identifiers) {
+            let events = match self.events.get(&bucket) {
+                Some(events) => events,
+                None => continue,
+            };
+            // Slots are handed out in order within each bucket
+            if let Some(last) = events.back() {
+                slot = slot.max(*last);
+            }
+            let count = budget.count.max(1);
+            if events.len() >= count {
+                let oldest_in_window = events[events.len() - count];
+                let refill = oldest_in_window
+                    .checked_add_signed(to_chrono(budget.period))
+                    .unwrap_or(chrono::MAX_DATETIME);
+                slot = slot.max(refill);
+            }
+        }
+        slot
+    }
+
+    /// Counts an order placed (or scheduled) at `at`.
+    pub fn record(&mut self, account_url: &str, identifiers: &[AcmeIdentifier], at: DateTime<Utc>) {
+        for (bucket, budget) in self.buckets(account_url, identifiers) {
+            let events = self.events.entry(bucket).or_default();
+            let idx = events.partition_point(|event| *event <= at);
+            events.insert(idx, at);
+            // Only the most recent `count` events can matter
+            while events.len() > budget.count.max(1) {
+                events.pop_front();
+            }
+        }
+    }
+
+    /// Holds back orders from `account_url` until `until`, e.g. after a
+    /// rateLimited error with a Retry-After time.
+    pub fn block_until(&mut self, account_url: &str, until: DateTime<Utc>) {
+        let blocked_until = self
+            .blocked_until
+            .entry(account_url.to_string())
+            .or_insert(until);
+        *blocked_until = (*blocked_until).max(until);
+    }
+
+    fn buckets(&self, account_url: &str, identifiers: &[AcmeIdentifier]) -> Vec<(Bucket, Budget)> {
+        let mut buckets = Vec::new();
+        if let Some(budget) = self.limits.new_orders_per_account {
+            buckets.push((Bucket::Account(account_url.to_string()), budget));
+        }
+        if let Some(budget) = self.limits.certificates_per_registered_domain {
+            // IP addresses have no registered domain
+            let mut domains: Vec<_> = identifiers
+                .iter()
+                .filter(|identifier| !identifier.is_ip())
+                .map(|identifier| (self.registered_domain)(&identifier.value))
+                .collect();
+            domains.sort();
+            domains.dedup();
+            buckets.extend(
+                domains
+                    .into_iter()
+                    .map(|domain| (Bucket::RegisteredDomain(domain), budget)),
+            );
+        }
+        if let Some(budget) = self.limits.duplicate_certificates {
+            let mut values: Vec<_> = identifiers
+                .iter()
+                .map(|identifier| identifier.value.to_ascii_lowercase())
+                .collect();
+            values.sort();
+            values.dedup();
+            buckets.push((Bucket::Identifiers(values), budget));
+        }
+        buckets
+    }
+}
+
+impl Default for RateLimitTracker {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+fn last_two_labels(name: &str) -> String {
+    let name = name.trim_start_matches("*.").trim_end_matches('.');
+    let labels: Vec<_> = name.rsplitn(3, '.').collect();
+    match labels.as_slice() {
+        [tld, domain, _] => format!("{}.{}", domain, tld).to_ascii_lowercase(),
+        _ => name.to_ascii_lowercase(),
+    }
+}
+
+fn to_chrono(duration: Duration) -> chrono::Duration {
+    chrono::Duration::from_std(duration).unwrap_or(chrono::Duration::max_value())
+}
+
+/// One finished issuance from `IssuanceScheduler::run`.
+#[derive(Debug)]
+pub struct ScheduledIssuance {
+    pub new_order: NewOrderResource,
+
+    /// The account the order was placed with.
+    pub account_url: String,
+
+    /// When the order was scheduled to be placed.
+    pub started_at: DateTime<Utc>,
+
+    pub result: AcmeResult<CertificateBundle>,
+}
+
+/// Queues issuance requests for an account or `AccountPool` and runs them
+/// with `issuance::issue`, spacing orders to fit the `RateLimitTracker`'s
+/// budget and running at most `max_concurrent` at once (4 unless set here
+/// or by the `IssuancePolicy`).
+///
+/// Requests are started in the order their rate-limit slots come up, so one
+/// exhausted registered domain doesn't hold up the rest of the queue;
+/// requests waiting for their slot don't count against `max_concurrent`. A
+/// round-robin pool's next account is used unless another has an earlier
+/// slot. Waiting uses the account client's `Sleeper`.
+pub struct IssuanceScheduler<'a, S: ChallengeSolver + ?Sized> {
+    accounts: Accounts<'a>,
+    solver: &'a S,
+    options: IssuanceOptions<'a>,
+    tracker: RateLimitTracker,
+    max_concurrent: Option<usize>,
+    queue: VecDeque<NewOrderResource>,
+}
+
+#[derive(Clone, Copy)]
+enum Accounts<'a> {
+    One(&'a Account),
+    Pool(&'a AccountPool),
+}
+
+impl<'a, S: ChallengeSolver + ?Sized> IssuanceScheduler<'a, S> {
+    pub fn new(account: &'a Account, solver: &'a S) -> Self {
+        Self::with_accounts(Accounts::One(account), solver)
+    }
+
+    pub fn for_pool(pool: &'a AccountPool, solver: &'a S) -> Self {
+        Self::with_accounts(Accounts::Pool(pool), solver)
+    }
+
+    fn with_accounts(accounts: Accounts<'a>, solver: &'a S) -> Self {
+        Self {
+            accounts,
+            solver,
+            options: Default::default(),
+            tracker: Default::default(),
+            max_concurrent: None,
+            queue: VecDeque::new(),
+        }
+    }
+
+    pub fn with_tracker(mut self, tracker: RateLimitTracker) -> Self {
+        self.tracker = tracker;
+        self
+    }
+
+    /// Reports the progress of every issuance to `observer`.
+    pub fn with_observer(mut self, observer: &'a dyn IssuanceObserver) -> Self {
+        self.options = self.options.observer(observer);
+        self
+    }
+
+    /// Stops the run once `token` is cancelled; see `run`.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.options = self.options.cancellation(token);
+        self
+    }
+
+    /// Issues with `options`, e.g. to set a preferred chain.
+    pub fn with_options(mut self, options: IssuanceOptions<'a>) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// How many issuances may run at once, overriding the policy's
+    /// `max_concurrent`.
+    pub fn with_max_concurrent(mut self, max_concurrent: usize) -> Self {
+        self.max_concurrent = Some(max_concurrent);
+        self
+    }
+
+    /// This scheduler's limit, else the policy's. A pool's accounts may
+    /// have different policies, of which the strictest limit applies.
+    fn max_concurrent(&self) -> usize {
+        let policy_limit = match (self.options.policy_override(), self.accounts) {
+            (Some(policy), _) => policy.max_concurrent,
+            (None, Accounts::One(account)) => account.issuance_policy().max_concurrent,
+            (None, Accounts::Pool(pool)) => pool
+                .accounts()
+                .iter()
+                .filter_map(|account| account.issuance_policy().max_concurrent)
+                .min(),
+        };
+        self.max_concurrent
+            .or(policy_limit)
+            .unwrap_or(DEFAULT_MAX_CONCURRENT)
+            .max(1)
+    }
+
+    pub fn tracker(&self) -> &RateLimitTracker {
+        &self.tracker
+    }
+
+    pub fn into_tracker(self) -> RateLimitTracker {
+        self.tracker
+    }
+
+    pub fn enqueue(&mut self, new_order: NewOrderResource) {
+        self.queue.push_back(new_order);
+    }
+
+    /// How many requests are waiting to be started.
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Runs every queued request, returning the results in the order they
+    /// finish. A rateLimited error with a Retry-After time holds back the
+    /// account's remaining orders until then.
+    ///
+    /// If the run is cancelled, issuances in progress stop as described for
+    /// `IssuanceOptions::cancellation` and those waiting for their slot fail
+    /// with `AcmeError::Cancelled`; requests not yet started stay queued.
+    pub async fn run(&mut self) -> Vec<ScheduledIssuance> {
+        let (solver, options) = (self.solver, self.options.clone());
+        let options = &options;
+        let max_concurrent = self.max_concurrent();
+        let cancel = options.cancellation_token();
+        let mut in_flight = FuturesUnordered::new();
+        // Requests waiting for their slot, earliest first. They don't count
+        // against `max_concurrent` until they start.
+        let mut deferred: Vec<Scheduled<'a>> = Vec::new();
+        let mut finished = Vec::new();
+        loop {
+            while in_flight.len() < max_concurrent && !cancel.is_cancelled() {
+                let scheduled = match deferred.first() {
+                    Some(first) if first.is_due() => deferred.remove(0),
+                    _ => match self.next_scheduled() {
+                        Some(next) => next,
+                        None => break,
+                    },
+                };
+                if !scheduled.is_due() {
+                    let idx = deferred.partition_point(|other| other.slot <= scheduled.slot);
+                    deferred.insert(idx, scheduled);
+                    continue;
+                }
+                in_flight.push(async move {
+                    let result = issue_with_options(
+                        scheduled.account,
+                        &scheduled.new_order,
+                        solver,
+                        options,
+                    )
+                    .await;
+                    scheduled.finish(result)
+                });
+            }
+            if cancel.is_cancelled() {
+                finished.extend(
+                    deferred.drain(..).map(|scheduled| {
+                        scheduled.finish(Err(AcmeError::Cancelled { order: None }))
+                    }),
+                );
+            }
+
+            // Only wake for the next slot if there's room to start it
+            let next_slot = deferred
+                .first_mut()
+                .filter(|_| in_flight.len() < max_concurrent);
+            let issuance = match next_slot {
+                Some(next) => {
+                    let sleep = cancel.run(next.account.client().sleeper().sleep(next.wait()));
+                    let sleep = std::pin::pin!(sleep);
+                    let slept = match select(sleep, in_flight.next()).await {
+                        Either::Left((slept, _)) => slept,
+                        Either::Right((Some(issuance), _)) => {
+                            self.finished(&issuance);
+                            finished.push(issuance);
+                            continue;
+                        }
+                        Either::Right((None, sleep)) => sleep.await,
+                    };
+                    // Sleepers may return early (e.g. `NoSleep`); the slot
+                    // has come up as far as the scheduler is concerned
+                    if slept.is_ok() {
+                        next.due = true;
+                    }
+                    continue;
+                }
+                None => match in_flight.next().await {
+                    Some(issuance) => issuance,
+                    None => return finished,
+                },
+            };
+            self.finished(&issuance);
+            finished.push(issuance);
+        }
+    }
+
+    /// Holds back the account after a rateLimited error with a Retry-After
+    /// time.
+    fn finished(&mut self, issuance: &ScheduledIssuance) {
+        if let Some(AcmeError::RateLimited(err)) = issuance
+            .result
+            .as_ref()
+            .err()
+            .map(AcmeError::without_context)
+        {
+            if let Some(retry_after) = err.retry_after {
+                self.tracker
+                    .block_until(&issuance.account_url, retry_after.with_timezone(&Utc));
+            }
+        }
+    }
+
+    /// Takes the queued request with the earliest slot, picks its account
+    /// and records it.
+    fn next_scheduled(&mut self) -> Option<Scheduled<'a>> {
+        let accounts = self.accounts;
+        let mut earliest: Option<(usize, usize, &'a Account, DateTime<Utc>)> = None;
+        for (idx, new_order) in self.queue.iter().enumerate() {
+            let candidates = match accounts {
+                Accounts::One(account) => vec![(0, account)],
+                Accounts::Pool(pool) => pool.candidates(&new_order.identifiers),
+            };
+            for (account_idx, account) in candidates {
+                let now = account.client().clock().now();
+                let slot = self
+                    .tracker
+                    .next_slot(account.url(), &new_order.identifiers, now);
+                if earliest.is_none_or(|(_, _, _, earliest)| slot < earliest) {
+                    earliest = Some((idx, account_idx, account, slot));
+                }
+            }
+        }
+        let (idx, account_idx, account, slot) = earliest?;
+        if let Accounts::Pool(pool) = accounts {
+            pool.selected(account_idx);
+        }
+        let new_order = self.queue.remove(idx)?;
+        self.tracker
+            .record(account.url(), &new_order.identifiers, slot);
+        Some(Scheduled {
+            new_order,
+            account,
+            slot,
+            due: false,
+        })
+    }
+}
+
+/// A request taken from the queue, with the account and slot it was given.
+struct Scheduled<'a> {
+    new_order: NewOrderResource,
+    account: &'a Account,
+    slot: DateTime<Utc>,
+
+    /// Whether the scheduler has already waited for the slot.
+    due: bool,
+}
+
+impl Scheduled<'_> {
+    fn is_due(&self) -> bool {
+        self.due || self.slot <= self.account.client().clock().now()
+    }
+
+    fn wait(&self) -> Duration {
+        (self.slot - self.account.client().clock().now())
+            .to_std()
+            .unwrap_or_default()
+    }
+
+    fn finish(self, result: AcmeResult<CertificateBundle>) -> ScheduledIssuance {
+        ScheduledIssuance {
+            new_order: self.new_order,
+            account_url: self.account.url().to_string(),
+            started_at: self.slot,
+            result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        api::{account_pool::AccountSelection, solver::MemoryHttp01Solver},
+        test_support::mock_server::{Endpoint, Fault, MockAcmeServer},
+    };
+
+    use super::*;
+
+    fn identifiers(names: &[&str]) -> Vec<AcmeIdentifier> {
+        names
+            .iter()
+            .map(|name| AcmeIdentifier::dns(*name))
+            .collect()
+    }
+
+    fn new_order(names: &[&str]) -> NewOrderResource {
+        NewOrderResource {
+            identifiers: identifiers(names),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn tracker_slots() {
+        let mut tracker = RateLimitTracker::new(RateLimits {
+            new_orders_per_account: Some(Budget::new(3, HOUR)),
+            certificates_per_registered_domain: Some(Budget::new(2, WEEK)),
+            duplicate_certificates: None,
+        });
+        let now = Utc::now();
+        let hour = to_chrono(HOUR);
+        let example = identifiers(&["a.example.com"]);
+        assert_eq!(tracker.next_slot("acct", &example, now), now);
+
+        tracker.record("acct", &example, now - hour * 2);
+        tracker.record("acct", &identifiers(&["www.example.com"]), now);
+        // The registered domain's budget is used up for a week
+        assert_eq!(
+            tracker.next_slot("acct", &example, now),
+            now - hour * 2 + to_chrono(WEEK)
+        );
+        // The account's budget has room for one more this hour
+        let other = identifiers(&["example.org"]);
+        assert_eq!(tracker.next_slot("acct", &other, now), now);
+        tracker.record("acct", &other, now);
+        tracker.record("acct", &identifiers(&["example.net"]), now);
+        assert_eq!(tracker.next_slot("acct", &other, now), now + hour);
+        assert_eq!(tracker.next_slot("other-acct", &other, now), now);
+
+        tracker.block_until("other-acct", now + hour * 5);
+        assert_eq!(tracker.next_slot("other-acct", &other, now), now + hour * 5);
+    }
+
+    #[test]
+    fn registered_domains() {
+        assert_eq!(last_two_labels("*.WWW.Example.com."), "example.com");
+        assert_eq!(last_two_labels("example.com"), "example.com");
+        assert_eq!(last_two_labels("localhost"), "localhost");
+    }
+
+    #[test]
+    fn ip_identifiers_have_no_registered_domain() {
+        let mut tracker = RateLimitTracker::new(RateLimits {
+            new_orders_per_account: None,
+            certificates_per_registered_domain: Some(Budget::new(1, WEEK)),
+            duplicate_certificates: None,
+        });
+        let now = Utc::now();
+        let ip = |addr: &str| vec![AcmeIdentifier::ip(addr.parse().unwrap())];
+        tracker.record("acct", &ip("192.0.2.1"), now);
+        // Would both be "2.1" if bucketed by their last two labels
+        assert_eq!(tracker.next_slot("acct", &ip("198.51.2.1"), now), now);
+        assert_eq!(tracker.next_slot("acct", &ip("192.0.2.1"), now), now);
+    }
+
+    #[async_std::test]
+    async fn run_spaces_duplicates() {
+        let server = MockAcmeServer::new();
+        let client = server.client().await.unwrap();
+        let account = client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+        let solver = MemoryHttp01Solver::new();
+        let tracker = RateLimitTracker::new(RateLimits {
+            duplicate_certificates: Some(Budget::new(1, WEEK)),
+            ..Default::default()
+        });
+        let mut scheduler = IssuanceScheduler::new(&account, &solver)
+            .with_tracker(tracker)
+            .with_max_concurrent(2);
+        scheduler.enqueue(new_order(&["example.com"]));
+        scheduler.enqueue(new_order(&["example.com"]));
+        scheduler.enqueue(new_order(&["example.org"]));
+        server.inject(Endpoint::NewOrder, Fault::BadNonce);
+
+        let finished = scheduler.run().await;
+        assert_eq!(finished.len(), 3);
+        assert_eq!(scheduler.queued(), 0);
+        let mut started: Vec<_> = finished
+            .iter()
+            .map(|issuance| {
+                issuance.result.as_ref().unwrap();
+                (
+                    issuance.started_at,
+                    &issuance.new_order.identifiers[0].value,
+                )
+            })
+            .collect();
+        started.sort();
+        // The duplicate waits a week; example.org doesn't wait for it
+        assert_eq!(started[0].1, "example.com");
+        assert_eq!(started[1].1, "example.org");
+        assert_eq!(started[2].1, "example.com");
+        assert!(started[2].0 - started[0].0 >= to_chrono(WEEK));
+    }
+
+    /// Sleeps of a day or more never finish; shorter ones return at once.
+    struct LongSleepsHang;
+
+    #[async_trait::async_trait]
+    impl crate::sleep::Sleeper for LongSleepsHang {
+        async fn sleep(&self, duration: Duration) {
+            if duration >= Duration::from_secs(24 * 60 * 60) {
+                futures_util::future::pending().await
+            }
+        }
+    }
+
+    #[async_std::test]
+    async fn exhausted_domain_does_not_hold_up_others() {
+        let server = MockAcmeServer::new();
+        let mut client = server.client().await.unwrap();
+        client.set_sleeper(LongSleepsHang);
+        let account = client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+        let solver = MemoryHttp01Solver::new();
+        let mut tracker = RateLimitTracker::new(RateLimits {
+            certificates_per_registered_domain: Some(Budget::new(1, WEEK)),
+            ..Default::default()
+        });
+        tracker.record(account.url(), &identifiers(&["example.com"]), Utc::now());
+        let cancel = CancellationToken::new();
+        let mut scheduler = IssuanceScheduler::new(&account, &solver)
+            .with_tracker(tracker)
+            .with_cancellation(cancel.clone())
+            .with_max_concurrent(2);
+        // More requests waiting a week than can run at once, ahead of the
+        // ones that can go now
+        for name in ["a.example.com", "b.example.com", "c.example.com"] {
+            scheduler.enqueue(new_order(&[name]));
+        }
+        for name in ["example.org", "example.net", "example.edu"] {
+            scheduler.enqueue(new_order(&[name]));
+        }
+
+        let run = scheduler.run();
+        let issued = async {
+            while server.request_count(Endpoint::Certificate) < 3 {
+                async_std::task::sleep(Duration::from_millis(1)).await;
+            }
+            cancel.cancel();
+        };
+        let (finished, ()) = futures_util::future::join(run, issued).await;
+        assert_eq!(finished.len(), 6);
+        for issuance in &finished[..3] {
+            issuance.result.as_ref().unwrap();
+            assert!(!issuance.new_order.identifiers[0]
+                .value
+                .ends_with(".example.com"));
+        }
+        for issuance in &finished[3..] {
+            assert!(matches!(issuance.result, Err(AcmeError::Cancelled { .. })));
+        }
+    }
+
+    #[async_std::test]
+    async fn run_across_pool() {
+        let server = MockAcmeServer::new();
+        let client = server.client().await.unwrap();
+        let mut accounts = Vec::new();
+        for email in ["a@example.com", "b@example.com"] {
+            accounts.push(
+                client
+                    .register_account(email.to_string(), true)
+                    .await
+                    .unwrap(),
+            );
+        }
+        let pool = AccountPool::new(accounts, AccountSelection::RoundRobin).unwrap();
+        let solver = MemoryHttp01Solver::new();
+        let tracker = RateLimitTracker::new(RateLimits {
+            new_orders_per_account: Some(Budget::new(1, HOUR)),
+            ..Default::default()
+        });
+        let mut scheduler = IssuanceScheduler::for_pool(&pool, &solver).with_tracker(tracker);
+        for name in ["example.com", "example.org", "example.net"] {
+            scheduler.enqueue(new_order(&[name]));
+        }
+
+        let mut finished = scheduler.run().await;
+        finished.sort_by_key(|issuance| issuance.started_at);
+        // One order per account per hour: the third waits for the first
+        // account's budget
+        assert_ne!(finished[0].account_url, finished[1].account_url);
+        assert_eq!(finished[2].account_url, finished[0].account_url);
+        assert_eq!(
+            finished[2].started_at - finished[0].started_at,
+            to_chrono(HOUR)
+        );
+        assert!(finished.iter().all(|issuance| issuance.result.is_ok()));
+    }
+
+    #[async_std::test]
+    async fn run_cancelled() {
+        let server = MockAcmeServer::new();
+        let client = server.client().await.unwrap();
+        let account = client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+        let solver = MemoryHttp01Solver::new();
+        let cancel = CancellationToken::new();
+        let mut scheduler =
+            IssuanceScheduler::new(&account, &solver).with_cancellation(cancel.clone());
+        scheduler.enqueue(new_order(&["example.com"]));
+        scheduler.enqueue(new_order(&["example.org"]));
+
+        cancel.cancel();
+        assert!(scheduler.run().await.is_empty());
+        assert_eq!(scheduler.queued(), 2);
+        assert_eq!(server.request_count(Endpoint::NewOrder), 0);
+    }
+}
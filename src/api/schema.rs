@@ -0,0 +1,194 @@
+//! Versioned envelopes for data that outlives a process: account
+//! credentials, order checkpoints and certificate metadata.
+//!
+//! `to_envelope` wraps a value as `{"kind": ..., "version": ..., "data": ...}`
+//! and `from_envelope` unwraps it, first upgrading data written by older
+//! versions of this crate one version at a time with `Versioned::migrate`.
+//! JSON written before envelopes existed is loaded as version 0.
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+
+use crate::error::{AcmeError, AcmeResult};
+
+use super::{
+    account::AccountCredentials, authorization::SavedAuthorization, challenge::SavedChallenge,
+    order::SavedOrder, store::CertificateMetadata,
+};
+
+/// A type persisted in a versioned envelope.
+pub trait Versioned: Serialize + DeserializeOwned {
+    /// Identifies the type in envelopes, so that e.g. an order isn't loaded
+    /// as account credentials.
+    const KIND: &'static str;
+
+    /// The version the type serializes as. Bump it when the serialized form
+    /// changes in a way serde defaults don't cover, and handle the previous
+    /// version in `migrate`.
+    const VERSION: u32;
+
+    /// Upgrades `data` written as `version` to `version + 1`. The default
+    /// leaves it as is, for versions that only added optional fields.
+    fn migrate(version: u32, data: Value) -> AcmeResult<Value> {
+        let _ = version;
+        Ok(data)
+    }
+}
+
+/// `value` in an envelope of the current version.
+pub fn to_envelope<T: Versioned>(value: &T) -> AcmeResult<Value> {
+    let mut envelope = Map::new();
+    envelope.insert("kind".to_string(), T::KIND.into());
+    envelope.insert("version".to_string(), T::VERSION.into());
+    envelope.insert("data".to_string(), serde_json::to_value(value)?);
+    Ok(Value::Object(envelope))
+}
+
+/// Loads a `T` from an envelope written by this or an older version of the
+/// crate, or from bare JSON written before envelopes. Envelopes of another
+/// kind or of a newer version are an `UnsupportedSchema` error.
+pub fn from_envelope<T: Versioned>(value: Value) -> AcmeResult<T> {
+    let (mut version, mut data) = match value {
+        Value::Object(mut envelope) if is_envelope(&envelope) => {
+            let kind = envelope["kind"].as_str().unwrap_or_default().to_string();
+            let version = envelope["version"]
+                .as_u64()
+                .and_then(|version| u32::try_from(version).ok())
+                .unwrap_or(u32::MAX);
+            if kind != T::KIND || version > T::VERSION {
+                return Err(AcmeError::UnsupportedSchema { kind, version });
+            }
+            (version, envelope.remove("data").unwrap_or_default())
+        }
+        bare => (0, bare),
+    };
+    while version < T::VERSION {
+        data = T::migrate(version, data)?;
+        version += 1;
+    }
+    Ok(serde_json::from_value(data)?)
+}
+
+/// `to_envelope`, as pretty-printed JSON.
+pub fn to_vec<T: Versioned>(value: &T) -> AcmeResult<Vec<u8>> {
+    Ok(serde_json::to_vec_pretty(&to_envelope(value)?)?)
+}
+
+/// `from_envelope`, from JSON.
+pub fn from_slice<T: Versioned>(json: &[u8]) -> AcmeResult<T> {
+    from_envelope(serde_json::from_slice(json)?)
+}
+
+fn is_envelope(object: &Map<String, Value>) -> bool {
+    object.len() == 3
+        && object.get("kind").is_some_and(Value::is_string)
+        && object.get("version").is_some_and(Value::is_u64)
+        && object.contains_key("data")
+}
+
+impl Versioned for AccountCredentials {
+    const KIND: &'static str = "accountCredentials";
+    const VERSION: u32 = 1;
+}
+
+impl Versioned for SavedOrder {
+    const KIND: &'static str = "order";
+    const VERSION: u32 = 1;
+}
+
+impl Versioned for SavedAuthorization {
+    const KIND: &'static str = "authorization";
+    const VERSION: u32 = 1;
+}
+
+impl Versioned for SavedChallenge {
+    const KIND: &'static str = "challenge";
+    const VERSION: u32 = 1;
+}
+
+impl Versioned for CertificateMetadata {
+    const KIND: &'static str = "certificateMetadata";
+    const VERSION: u32 = 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn credentials_round_trip() {
+        let credentials = AccountCredentials {
+            directory_url: None,
+            account_url: "https://acme.test/acct/1".to_string(),
+            private_jwk: "{}".to_string(),
+        };
+        let json = to_vec(&credentials).unwrap();
+        let envelope: Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(envelope["kind"], "accountCredentials");
+        assert_eq!(envelope["version"], 1);
+        let loaded: AccountCredentials = from_slice(&json).unwrap();
+        assert_eq!(loaded.account_url, credentials.account_url);
+
+        // As written before envelopes
+        let bare = serde_json::to_vec(&credentials).unwrap();
+        let loaded: AccountCredentials = from_slice(&bare).unwrap();
+        assert_eq!(loaded.account_url, credentials.account_url);
+
+        let newer = json!({"kind": "accountCredentials", "version": 2, "data": {}});
+        match from_envelope::<AccountCredentials>(newer) {
+            Err(AcmeError::UnsupportedSchema { kind, version }) => {
+                assert_eq!((kind.as_str(), version), ("accountCredentials", 2))
+            }
+            res => panic!("expected unsupported schema, got {:?}", res),
+        }
+        let order = json!({"kind": "order", "version": 1, "data": {}});
+        assert!(matches!(
+            from_envelope::<AccountCredentials>(order),
+            Err(AcmeError::UnsupportedSchema { .. })
+        ));
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Renamed {
+        name: String,
+        port: u16,
+    }
+
+    impl Versioned for Renamed {
+        const KIND: &'static str = "renamed";
+        const VERSION: u32 = 3;
+
+        fn migrate(version: u32, mut data: Value) -> AcmeResult<Value> {
+            match version {
+                // Version 1 renamed "host" to "name"
+                0 => {
+                    let host = data["host"].take();
+                    data["name"] = host;
+                }
+                // Version 3 added "port"; earlier data meant 443
+                2 => data["port"] = 443.into(),
+                _ => {}
+            }
+            Ok(data)
+        }
+    }
+
+    #[test]
+    fn migrations() {
+        let expected = Renamed {
+            name: "example.com".to_string(),
+            port: 443,
+        };
+        assert_eq!(
+            from_envelope::<Renamed>(json!({"host": "example.com"})).unwrap(),
+            expected
+        );
+        let v2 = json!({"kind": "renamed", "version": 2, "data": {"name": "example.com"}});
+        assert_eq!(from_envelope::<Renamed>(v2).unwrap(), expected);
+        let current = to_envelope(&expected).unwrap();
+        assert_eq!(from_envelope::<Renamed>(current).unwrap(), expected);
+    }
+}
@@ -0,0 +1,206 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use async_trait::async_trait;
+
+use crate::wire::challenge::ChallengeType;
+
+use super::challenge::{dns01_record_name, dns01_txt_value, http01_path};
+
+/// Everything a solver needs to provision a single challenge response.
+#[derive(Clone, Debug)]
+pub struct ChallengeParams {
+    /// The identifier being authorized, without any wildcard prefix.
+    pub domain: String,
+
+    /// Whether the authorization is for the wildcard of `domain`.
+    pub wildcard: bool,
+
+    pub challenge_type: ChallengeType,
+
+    pub token: String,
+
+    pub key_authorization: String,
+}
+
+impl ChallengeParams {
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-8.4
+    pub fn dns01_txt_name(&self) -> String {
+        dns01_record_name(&self.domain)
+    }
+
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-8.4
+    pub fn dns01_txt_value(&self) -> String {
+        dns01_txt_value(&self.key_authorization)
+    }
+
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-8.3
+    pub fn http01_path(&self) -> String {
+        http01_path(&self.token)
+    }
+}
+
+/// Provisions (and later removes) challenge responses, e.g. by writing DNS
+/// records or serving files over HTTP.
+#[async_trait]
+pub trait ChallengeSolver: Send + Sync {
+    /// The challenge types this solver can present, in order of preference.
+    fn supported_types(&self) -> Vec<ChallengeType>;
+
+    /// Makes the challenge response available to the ACME server.
+    ///
+    /// A dns-01 TXT name may be presented several times at once with
+    /// different values (e.g. for `example.com` and `*.example.com`); each
+    /// record must be added alongside any existing ones rather than replace
+    /// them.
+    async fn present(&self, params: &ChallengeParams) -> anyhow::Result<()>;
+
+    /// Called after `present` and before the challenge is responded to; a
+    /// place to wait for e.g. DNS propagation (see `DnsCheckingSolver` with
+    /// the "dns-check" feature). An error skips the response.
+    async fn wait_until_ready(&self, _params: &ChallengeParams) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Removes a response previously set up by `present`.
+    async fn cleanup(&self, params: &ChallengeParams) -> anyhow::Result<()>;
+
+    fn supports(&self, challenge_type: &ChallengeType) -> bool {
+        self.supported_types().contains(challenge_type)
+    }
+}
+
+/// An http-01 solver that keeps responses in memory, for embedding into an
+/// existing HTTP server.
+#[derive(Debug, Default)]
+pub struct MemoryHttp01Solver {
+    responses: Mutex<HashMap<String, String>>,
+}
+
+impl MemoryHttp01Solver {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the response body for a request path, if one is presented.
+    pub fn response_for_path(&self, path: &str) -> Option<String> {
+        let token = path.strip_prefix(&http01_path(""))?;
+        self.response_for_token(token)
+    }
+
+    pub fn response_for_token(&self, token: &str) -> Option<String> {
+        self.responses.lock().unwrap().get(token).cloned()
+    }
+}
+
+#[async_trait]
+impl ChallengeSolver for MemoryHttp01Solver {
+    fn supported_types(&self) -> Vec<ChallengeType> {
+        vec![ChallengeType::Http01]
+    }
+
+    async fn present(&self, params: &ChallengeParams) -> anyhow::Result<()> {
+        self.responses
+            .lock()
+            .unwrap()
+            .insert(params.token.clone(), params.key_authorization.clone());
+        Ok(())
+    }
+
+    async fn cleanup(&self, params: &ChallengeParams) -> anyhow::Result<()> {
+        self.responses.lock().unwrap().remove(&params.token);
+        Ok(())
+    }
+}
+
+/// A dns-01 solver that only logs the TXT records that need to be created,
+/// e.g. for manual DNS setup. Presented records are also kept for inspection.
+#[derive(Debug, Default)]
+pub struct LoggingDns01Solver {
+    records: Mutex<Vec<(String, String)>>,
+}
+
+impl LoggingDns01Solver {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Currently presented (name, value) TXT records.
+    pub fn records(&self) -> Vec<(String, String)> {
+        self.records.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl ChallengeSolver for LoggingDns01Solver {
+    fn supported_types(&self) -> Vec<ChallengeType> {
+        vec![ChallengeType::Dns01]
+    }
+
+    async fn present(&self, params: &ChallengeParams) -> anyhow::Result<()> {
+        let record = (params.dns01_txt_name(), params.dns01_txt_value());
+        log::info!("present TXT record {} {:?}", record.0, record.1);
+        self.records.lock().unwrap().push(record);
+        Ok(())
+    }
+
+    async fn cleanup(&self, params: &ChallengeParams) -> anyhow::Result<()> {
+        let record = (params.dns01_txt_name(), params.dns01_txt_value());
+        log::info!("remove TXT record {} {:?}", record.0, record.1);
+        self.records.lock().unwrap().retain(|r| *r != record);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(challenge_type: ChallengeType) -> ChallengeParams {
+        ChallengeParams {
+            domain: "example.org".to_string(),
+            wildcard: false,
+            challenge_type,
+            token: "DGyRejmCefe7v4NfDGDKfA".to_string(),
+            key_authorization: "DGyRejmCefe7v4NfDGDKfA.kPrK_qmxVWaYVA9wwBF6Iuo3vVzz7TxHCTwXBygrS4k"
+                .to_string(),
+        }
+    }
+
+    #[async_std::test]
+    async fn memory_http01_solver() {
+        let solver = MemoryHttp01Solver::new();
+        let params = params(ChallengeType::Http01);
+        assert!(solver.supports(&ChallengeType::Http01));
+        assert!(!solver.supports(&ChallengeType::Dns01));
+
+        solver.present(&params).await.unwrap();
+        assert_eq!(
+            solver
+                .response_for_path("/.well-known/acme-challenge/DGyRejmCefe7v4NfDGDKfA")
+                .unwrap(),
+            params.key_authorization
+        );
+
+        solver.cleanup(&params).await.unwrap();
+        assert!(solver.response_for_token(&params.token).is_none());
+    }
+
+    #[async_std::test]
+    async fn logging_dns01_solver() {
+        let solver = LoggingDns01Solver::new();
+        let params = params(ChallengeType::Dns01);
+        assert!(solver.supports(&ChallengeType::Dns01));
+
+        solver.present(&params).await.unwrap();
+        assert_eq!(
+            solver.records(),
+            [(
+                "_acme-challenge.example.org".to_string(),
+                params.dns01_txt_value()
+            )]
+        );
+
+        solver.cleanup(&params).await.unwrap();
+        assert!(solver.records().is_empty());
+    }
+}
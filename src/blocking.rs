@@ -0,0 +1,529 @@
+//! A synchronous facade over the async API, for CLI tools and build scripts
+//! that don't otherwise need an async runtime.
+//!
+//! Each call runs the async core to completion on the calling thread with a
+//! minimal executor. The `HttpClient` (and `Sleeper`, see
+//! `Client::set_sleeper`) must therefore not depend on a runtime that isn't
+//! running; with the "async-std" feature the default sleeper is fine since
+//! async-std starts its own runtime on demand. The "tokio" feature's default
+//! needs a tokio runtime, so without one set e.g. `sleep::ThreadSleeper`.
+
+use std::{
+    future::Future,
+    pin::pin,
+    sync::Arc,
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+    time::Duration,
+};
+
+use chrono::{DateTime, FixedOffset, Utc};
+
+use crate::transport::HttpClient;
+use crate::{
+    api::{
+        self,
+        account::{AccountCredentials, Contact},
+        authorization::SavedAuthorization,
+        client::{AgreeTos, RegisterAccountConfig},
+        diagnostics::ServerCapabilities,
+        order::{OrderState, SavedOrder},
+        policy::IssuancePolicy,
+        solver::ChallengeSolver,
+    },
+    clock::Clock,
+    crypto::account_key::AccountKey,
+    error::{AcmeError, AcmeResult},
+    sleep::Sleeper,
+    wire::{
+        account::{AccountResource, AccountStatus},
+        authorization::{AuthorizationResource, AuthorizationStatus},
+        common::SignedResponse,
+        directory::{DirectoryMetadata, DirectoryResource},
+        order::{NewOrderResource, OrderResource, OrderStatus},
+        quirks::Quirks,
+    },
+};
+
+#[cfg(any(feature = "x509", feature = "x509-rcgen"))]
+use crate::api::store::{CertificateBundle, CertificateStore};
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Runs `future` to completion on the current thread, parking it while the
+/// future is pending.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+/// Blocking version of `api::client::Client`.
+pub struct Client {
+    inner: api::client::Client,
+}
+
+impl Client {
+    pub fn new(http: impl Into<Arc<dyn HttpClient>>, directory: DirectoryResource) -> Self {
+        api::client::Client::new(http, directory).into()
+    }
+
+    pub fn for_directory_url(
+        http: impl Into<Arc<dyn HttpClient>>,
+        directory_url: impl AsRef<str>,
+    ) -> AcmeResult<Self> {
+        block_on(api::client::Client::for_directory_url(http, directory_url)).map(Self::from)
+    }
+
+    /// Builds a client configured with `api::client::Client::builder`.
+    pub fn build(builder: api::client::ClientBuilder) -> AcmeResult<Self> {
+        block_on(builder.build()).map(Self::from)
+    }
+
+    pub fn set_sleeper(&mut self, sleeper: impl Sleeper + 'static) {
+        self.inner.set_sleeper(sleeper)
+    }
+
+    pub fn set_poll_interval(&mut self, poll_interval: Duration) {
+        self.inner.set_poll_interval(poll_interval)
+    }
+
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) {
+        self.inner.set_clock(clock)
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.inner.set_quirks(quirks)
+    }
+
+    pub fn refresh_directory(&mut self) -> AcmeResult<()> {
+        block_on(self.inner.refresh_directory())
+    }
+
+    pub fn metadata(&self) -> &DirectoryMetadata {
+        self.inner.metadata()
+    }
+
+    pub fn terms_of_service_uri(&self) -> Option<&str> {
+        self.inner.terms_of_service_uri()
+    }
+
+    pub fn requires_external_account_binding(&self) -> bool {
+        self.inner.requires_external_account_binding()
+    }
+
+    pub fn probe_capabilities(&self) -> AcmeResult<ServerCapabilities> {
+        block_on(self.inner.probe_capabilities())
+    }
+
+    pub fn register_account(
+        &self,
+        contact_email: String,
+        terms_of_service_agreed: bool,
+    ) -> AcmeResult<Account> {
+        block_on(
+            self.inner
+                .register_account(contact_email, terms_of_service_agreed),
+        )
+        .map(Account::from)
+    }
+
+    pub fn register_account_config(&self, config: RegisterAccountConfig) -> AcmeResult<Account> {
+        block_on(self.inner.register_account_config(config)).map(Account::from)
+    }
+
+    pub fn find_account(&self, account_key: impl AccountKey + 'static) -> AcmeResult<Account> {
+        block_on(self.inner.find_account(account_key)).map(Account::from)
+    }
+
+    pub fn account_exists(
+        &self,
+        account_key: impl AccountKey + 'static,
+    ) -> AcmeResult<Option<Account>> {
+        block_on(self.inner.account_exists(account_key)).map(|account| account.map(Account::from))
+    }
+
+    pub fn get_or_register_account(
+        &self,
+        account_key: impl AccountKey + 'static,
+        contacts: Vec<Contact>,
+        agree_tos: AgreeTos,
+    ) -> AcmeResult<(Account, bool)> {
+        block_on(
+            self.inner
+                .get_or_register_account(account_key, contacts, agree_tos),
+        )
+        .map(|(account, created)| (Account::from(account), created))
+    }
+
+    pub fn load_account(&self, credentials: &AccountCredentials) -> AcmeResult<Account> {
+        self.inner.load_account(credentials).map(Account::from)
+    }
+
+    pub fn inner(&self) -> &api::client::Client {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> api::client::Client {
+        self.inner
+    }
+}
+
+impl From<api::client::Client> for Client {
+    fn from(inner: api::client::Client) -> Self {
+        Self { inner }
+    }
+}
+
+/// Blocking version of `api::account::Account`.
+#[derive(Clone)]
+pub struct Account {
+    inner: api::account::Account,
+}
+
+impl Account {
+    pub fn resource(&self) -> &AccountResource {
+        self.inner.resource()
+    }
+
+    pub fn url(&self) -> &str {
+        self.inner.url()
+    }
+
+    pub fn status(&self) -> AccountStatus {
+        self.inner.status()
+    }
+
+    pub fn credentials(&self) -> AcmeResult<AccountCredentials> {
+        self.inner.credentials()
+    }
+
+    pub fn set_issuance_policy(&mut self, policy: IssuancePolicy) {
+        self.inner.set_issuance_policy(policy)
+    }
+
+    pub fn refresh(&mut self) -> AcmeResult<AccountStatus> {
+        block_on(self.inner.refresh())
+    }
+
+    pub fn new_order(&self, new_order: &NewOrderResource) -> AcmeResult<Order> {
+        block_on(self.inner.new_order(new_order)).map(Order::from)
+    }
+
+    pub fn new_dns_order(&self, dns_name: impl Into<String>) -> AcmeResult<Order> {
+        block_on(self.inner.new_dns_order(dns_name)).map(Order::from)
+    }
+
+    pub fn new_wildcard_order(&self, domain: impl Into<String>) -> AcmeResult<Order> {
+        block_on(self.inner.new_wildcard_order(domain)).map(Order::from)
+    }
+
+    pub fn get_order(&self, order_url: impl AsRef<str>) -> AcmeResult<Order> {
+        block_on(self.inner.get_order(order_url)).map(Order::from)
+    }
+
+    pub fn resume_order(&self, saved: SavedOrder) -> AcmeResult<Order> {
+        block_on(self.inner.resume_order(saved)).map(Order::from)
+    }
+
+    pub fn get_authorization(&self, saved: SavedAuthorization) -> AcmeResult<Authorization> {
+        block_on(self.inner.resume_authorization(saved)).map(Authorization::from)
+    }
+
+    pub fn post_as_get(&self, url: impl AsRef<str>) -> AcmeResult<SignedResponse> {
+        block_on(self.inner.post_as_get(url))
+    }
+
+    /// Runs a complete issuance for `new_order`; see `issuance::issue`.
+    #[cfg(any(feature = "x509", feature = "x509-rcgen"))]
+    pub fn issue(
+        &self,
+        new_order: &NewOrderResource,
+        solver: &(impl ChallengeSolver + ?Sized),
+    ) -> AcmeResult<CertificateBundle> {
+        block_on(self.inner.issue(new_order, solver))
+    }
+
+    #[cfg(any(feature = "x509", feature = "x509-rcgen"))]
+    pub fn issue_and_store(
+        &self,
+        new_order: &NewOrderResource,
+        solver: &(impl ChallengeSolver + ?Sized),
+        store: &(impl CertificateStore + ?Sized),
+    ) -> AcmeResult<CertificateBundle> {
+        block_on(self.inner.issue_and_store(new_order, solver, store))
+    }
+
+    pub fn agree_to_terms(&mut self) -> AcmeResult<()> {
+        block_on(self.inner.agree_to_terms())
+    }
+
+    pub fn deactivate(&mut self) -> AcmeResult<()> {
+        block_on(self.inner.deactivate())
+    }
+
+    pub fn inner(&self) -> &api::account::Account {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> api::account::Account {
+        self.inner
+    }
+}
+
+impl From<api::account::Account> for Account {
+    fn from(inner: api::account::Account) -> Self {
+        Self { inner }
+    }
+}
+
+/// Blocking version of `api::order::Order`. The typestate methods of the
+/// async `Order` are flattened into `finalize` and `get_certificate_chain`,
+/// which fail with `AcmeError::InvalidState` if the order isn't ready or
+/// valid respectively.
+#[derive(Clone)]
+pub struct Order {
+    inner: api::order::Order,
+}
+
+impl Order {
+    pub fn save(&self) -> SavedOrder {
+        self.inner.save()
+    }
+
+    pub fn resource(&self) -> &OrderResource {
+        self.inner.resource()
+    }
+
+    pub fn url(&self) -> &str {
+        self.inner.url()
+    }
+
+    pub fn status(&self) -> OrderStatus {
+        self.inner.status()
+    }
+
+    pub fn status_result(&self) -> AcmeResult<OrderStatus> {
+        self.inner.status_result()
+    }
+
+    pub fn expires_at(&self) -> Option<DateTime<FixedOffset>> {
+        self.inner.expires_at()
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.inner.is_expired(now)
+    }
+
+    pub fn authorization_urls(&self) -> std::slice::Iter<'_, String> {
+        self.inner.authorization_urls()
+    }
+
+    pub fn get_authorizations(&self) -> AcmeResult<Vec<Authorization>> {
+        self.inner
+            .get_authorizations()
+            .map(|authorization| block_on(authorization).map(Authorization::from))
+            .collect()
+    }
+
+    pub fn refresh(&mut self) -> AcmeResult<OrderStatus> {
+        block_on(self.inner.refresh())
+    }
+
+    pub fn solve_all_authorizations(
+        &mut self,
+        solver: &(impl ChallengeSolver + ?Sized),
+        concurrency_limit: usize,
+    ) -> AcmeResult<()> {
+        block_on(
+            self.inner
+                .solve_all_authorizations(solver, concurrency_limit),
+        )
+    }
+
+    pub fn status_changed(&mut self) -> AcmeResult<OrderStatus> {
+        block_on(self.inner.status_changed())
+    }
+
+    pub fn abandon(&mut self) -> AcmeResult<Vec<String>> {
+        block_on(self.inner.abandon())
+    }
+
+    pub fn finalize(&mut self, csr_der: impl AsRef<[u8]>) -> AcmeResult<OrderStatus> {
+        match self.inner.state_result()? {
+            OrderState::Ready(mut ready) => block_on(ready.finalize(csr_der))?,
+            _ => return Err(self.unexpected_status("ready")),
+        };
+        Ok(self.status())
+    }
+
+    // Returns PEM-encoded private key
+    #[cfg(any(feature = "x509", feature = "x509-rcgen"))]
+    #[deprecated(note = "use finalize_with_new_key(KeyType::P256)")]
+    pub fn finalize_with_generated_key(&mut self) -> AcmeResult<zeroize::Zeroizing<String>> {
+        self.finalize_with_new_key(crate::x509::KeyType::P256)
+    }
+
+    // Returns PEM-encoded private key
+    #[cfg(any(feature = "x509", feature = "x509-rcgen"))]
+    pub fn finalize_with_new_key(
+        &mut self,
+        key_type: crate::x509::KeyType,
+    ) -> AcmeResult<zeroize::Zeroizing<String>> {
+        match self.inner.state_result()? {
+            OrderState::Ready(mut ready) => block_on(ready.finalize_with_new_key(key_type)),
+            _ => Err(self.unexpected_status("ready")),
+        }
+    }
+
+    pub fn get_certificate_chain(&mut self) -> AcmeResult<String> {
+        match self.inner.state_result()? {
+            OrderState::Valid(valid) => block_on(valid.get_certificate_chain()),
+            _ => Err(self.unexpected_status("valid")),
+        }
+    }
+
+    pub fn download_certificate(
+        &mut self,
+        format: api::order::DownloadFormat<'_>,
+    ) -> AcmeResult<api::order::DownloadedCertificate> {
+        match self.inner.state_result()? {
+            OrderState::Valid(valid) => block_on(valid.download_certificate(format)),
+            _ => Err(self.unexpected_status("valid")),
+        }
+    }
+
+    fn unexpected_status(&self, expected: &str) -> AcmeError {
+        AcmeError::InvalidState(format!(
+            "expected order to be {}; got {:?}",
+            expected,
+            self.status()
+        ))
+    }
+
+    pub fn inner(&self) -> &api::order::Order {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> api::order::Order {
+        self.inner
+    }
+}
+
+impl From<api::order::Order> for Order {
+    fn from(inner: api::order::Order) -> Self {
+        Self { inner }
+    }
+}
+
+/// Blocking version of `api::authorization::Authorization`.
+#[derive(Clone)]
+pub struct Authorization {
+    inner: api::authorization::Authorization,
+}
+
+impl Authorization {
+    pub fn save(&self) -> SavedAuthorization {
+        self.inner.save()
+    }
+
+    pub fn resource(&self) -> &AuthorizationResource {
+        self.inner.resource()
+    }
+
+    pub fn url(&self) -> &str {
+        self.inner.url()
+    }
+
+    pub fn status(&self) -> AuthorizationStatus {
+        self.inner.status()
+    }
+
+    pub fn expires_at(&self) -> Option<DateTime<FixedOffset>> {
+        self.inner.expires_at()
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.inner.is_expired(now)
+    }
+
+    pub fn refresh(&mut self) -> AcmeResult<AuthorizationStatus> {
+        block_on(self.inner.refresh())
+    }
+
+    pub fn deactivate(&mut self) -> AcmeResult<AuthorizationStatus> {
+        block_on(self.inner.deactivate())
+    }
+
+    pub fn solve(&mut self, solver: &(impl ChallengeSolver + ?Sized)) -> AcmeResult<()> {
+        block_on(self.inner.solve(solver))
+    }
+
+    pub fn inner(&self) -> &api::authorization::Authorization {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> api::authorization::Authorization {
+        self.inner
+    }
+}
+
+impl From<api::authorization::Authorization> for Authorization {
+    fn from(inner: api::authorization::Authorization) -> Self {
+        Self { inner }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        api::solver::LoggingDns01Solver,
+        sleep::{NoSleep, ThreadSleeper},
+        test_support::mock_server::{MockAcmeServer, MOCK_DIRECTORY_URL},
+    };
+
+    use super::*;
+
+    #[test]
+    fn block_on_waits_for_wakeup() {
+        let start = std::time::Instant::now();
+        block_on(ThreadSleeper.sleep(Duration::from_millis(20)));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn solve_order_without_runtime() {
+        let server = MockAcmeServer::new();
+        let mut client =
+            Client::for_directory_url(Arc::new(server) as Arc<dyn HttpClient>, MOCK_DIRECTORY_URL)
+                .unwrap();
+        client.set_sleeper(NoSleep);
+        let account = client
+            .register_account("admin@example.com".to_string(), true)
+            .unwrap();
+        let mut order = account.new_wildcard_order("example.com").unwrap();
+        assert_eq!(order.get_authorizations().unwrap().len(), 2);
+
+        order
+            .solve_all_authorizations(&LoggingDns01Solver::new(), 2)
+            .unwrap();
+        assert_eq!(order.status(), OrderStatus::Ready);
+        assert!(order.get_certificate_chain().is_err());
+    }
+}
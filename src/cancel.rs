@@ -0,0 +1,118 @@
+//! Cancellation of long-running operations (polling, scheduled issuance)
+//! that doesn't depend on a particular async runtime.
+//!
+//! Every future in this crate may also simply be dropped. Resources only
+//! change after a request succeeds, so an `Order` or `Authorization` that was
+//! being polled still holds the last state the server reported and can be
+//! saved and resumed. What dropping skips is cleanup: challenge responses
+//! presented during `issuance::issue` stay in place, whereas cancelling with
+//! a token removes them.
+
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Poll, Waker},
+};
+
+use futures_util::future::Either;
+
+use crate::error::{AcmeError, AcmeResult};
+
+/// A flag that operations given a clone of it check between requests and
+/// while waiting, failing with `AcmeError::Cancelled` once it is set.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<Inner>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Cancels every operation using this token (or a clone of it), now and
+    /// in future.
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        for waker in self.0.wakers.lock().unwrap().drain(..) {
+            waker.wake();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Completes once the token is cancelled.
+    pub async fn cancelled(&self) {
+        futures_util::future::poll_fn(|cx| {
+            if self.is_cancelled() {
+                return Poll::Ready(());
+            }
+            let mut wakers = self.0.wakers.lock().unwrap();
+            // Checked again under the lock, which `cancel` takes after setting
+            // the flag, so that no wake-up is missed
+            if self.is_cancelled() {
+                return Poll::Ready(());
+            }
+            if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                wakers.push(cx.waker().clone());
+            }
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// Fails with `AcmeError::Cancelled` if the token is cancelled.
+    pub fn check(&self) -> AcmeResult<()> {
+        if self.is_cancelled() {
+            Err(AcmeError::Cancelled { order: None })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Runs `future` unless the token is cancelled first, in which case the
+    /// future is dropped.
+    pub async fn run<T>(&self, future: impl Future<Output = T>) -> AcmeResult<T> {
+        self.check()?;
+        let future = std::pin::pin!(future);
+        let cancelled = std::pin::pin!(self.cancelled());
+        match futures_util::future::select(future, cancelled).await {
+            Either::Left((output, _)) => Ok(output),
+            Either::Right(_) => Err(AcmeError::Cancelled { order: None }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::sleep::default_sleeper;
+
+    use super::*;
+
+    #[async_std::test]
+    async fn cancel_running_future() {
+        let token = CancellationToken::new();
+        assert!(token.check().is_ok());
+        let sleeper = default_sleeper();
+        let cancel = async {
+            sleeper.sleep(Duration::from_millis(10)).await;
+            token.clone().cancel();
+        };
+        let sleep = token.run(sleeper.sleep(Duration::from_secs(60)));
+        let (result, ()) = futures_util::future::join(sleep, cancel).await;
+        assert!(matches!(result, Err(AcmeError::Cancelled { order: None })));
+        assert!(token.is_cancelled());
+        assert!(token.run(async {}).await.is_err());
+    }
+}
@@ -1,12 +1,22 @@
 pub mod account_key;
 pub mod ed25519;
+#[cfg(feature = "encryption")]
+pub mod encrypted;
 pub mod es256;
+pub mod es384;
 pub mod jws;
+pub mod public_jwk;
+pub mod rs256;
 
 pub(crate) mod jwk;
 
 use account_key::{AccountKey, GenerateAccountKey};
+use ed25519::Ed25519AccountKey;
 use es256::Es256AccountKey;
+use es384::Es384AccountKey;
+use jws::Algorithm;
+use pkcs8::{DecodePrivateKey, PrivateKeyDocument};
+use rs256::Rs256AccountKey;
 
 use crate::{AcmeError, AcmeResult};
 
@@ -14,12 +24,49 @@ pub fn generate_account_key() -> impl AccountKey {
     Es256AccountKey::generate()
 }
 
+/// Account key algorithms that `generate_account_key_with` can generate.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyAlgorithm {
+    #[default]
+    Es256,
+    Es384,
+    Ed25519,
+    /// RSA with a 2048-bit modulus, signing with RS256. Generating one takes
+    /// a while.
+    Rsa2048,
+}
+
+impl KeyAlgorithm {
+    /// The JWS "alg" of keys of this algorithm.
+    pub fn jws_alg(self) -> Algorithm {
+        match self {
+            KeyAlgorithm::Es256 => Algorithm::Es256,
+            KeyAlgorithm::Es384 => Algorithm::Es384,
+            KeyAlgorithm::Ed25519 => Algorithm::EdDsa,
+            KeyAlgorithm::Rsa2048 => Algorithm::Rs256,
+        }
+    }
+}
+
+pub fn generate_account_key_with(algorithm: KeyAlgorithm) -> Box<dyn AccountKey> {
+    match algorithm {
+        KeyAlgorithm::Es256 => Box::new(Es256AccountKey::generate()),
+        KeyAlgorithm::Es384 => Box::new(Es384AccountKey::generate()),
+        KeyAlgorithm::Ed25519 => Box::new(Ed25519AccountKey::generate()),
+        KeyAlgorithm::Rsa2048 => Box::new(Rs256AccountKey::generate()),
+    }
+}
+
 pub fn account_key_from_jwk(jwk: impl AsRef<str>) -> AcmeResult<Box<dyn AccountKey>> {
     let jwk = jwk.as_ref();
     if let Ok(key) = es256::from_jwk(jwk) {
         Ok(Box::new(key))
+    } else if let Ok(key) = es384::from_jwk(jwk) {
+        Ok(Box::new(key))
     } else if let Ok(key) = ed25519::from_jwk(jwk) {
         Ok(Box::new(key))
+    } else if let Ok(key) = rs256::from_jwk(jwk) {
+        Ok(Box::new(key))
     } else {
         Err(AcmeError::CryptoError(anyhow::anyhow!(
             "couldn't decode account key from JWK"
@@ -27,6 +74,29 @@ pub fn account_key_from_jwk(jwk: impl AsRef<str>) -> AcmeResult<Box<dyn AccountK
     }
 }
 
+pub fn account_key_from_pkcs8_der(der: impl AsRef<[u8]>) -> AcmeResult<Box<dyn AccountKey>> {
+    let der = der.as_ref();
+    if let Ok(key) = es256::from_pkcs8_der(der) {
+        Ok(Box::new(key))
+    } else if let Ok(key) = es384::from_pkcs8_der(der) {
+        Ok(Box::new(key))
+    } else if let Ok(key) = ed25519::from_pkcs8_der(der) {
+        Ok(Box::new(key))
+    } else if let Ok(key) = rs256::from_pkcs8_der(der) {
+        Ok(Box::new(key))
+    } else {
+        Err(AcmeError::CryptoError(anyhow::anyhow!(
+            "couldn't decode account key from PKCS#8"
+        )))
+    }
+}
+
+pub fn account_key_from_pkcs8_pem(pem: impl AsRef<str>) -> AcmeResult<Box<dyn AccountKey>> {
+    let doc = PrivateKeyDocument::from_pkcs8_pem(pem.as_ref())
+        .map_err(|err| AcmeError::CryptoError(err.into()))?;
+    account_key_from_pkcs8_der(doc)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -34,13 +104,95 @@ mod tests {
     #[test]
     fn account_key_from_jwk_es256() {
         let key = account_key_from_jwk(es256::tests::JWK).unwrap();
-        assert_eq!(key.jws_alg(), "ES256");
+        assert_eq!(key.jws_alg(), Algorithm::Es256);
     }
 
     #[test]
     fn account_key_from_jwk_ed25519() {
         let key = account_key_from_jwk(ed25519::tests::JWK).unwrap();
-        assert_eq!(key.jws_alg(), "EdDSA");
+        assert_eq!(key.jws_alg(), Algorithm::EdDsa);
+    }
+
+    #[test]
+    fn account_key_from_jwk_es384() {
+        let key = account_key_from_jwk(es384::tests::JWK).unwrap();
+        assert_eq!(key.jws_alg(), Algorithm::Es384);
+    }
+
+    #[test]
+    fn account_key_from_jwk_rs256() {
+        let key = account_key_from_jwk(rs256::tests::JWK).unwrap();
+        assert_eq!(key.jws_alg(), Algorithm::Rs256);
+    }
+
+    #[test]
+    fn account_key_from_pkcs8_pem_ed25519() {
+        let key = account_key_from_pkcs8_pem(ed25519::tests::PKCS8_PEM).unwrap();
+        assert_eq!(key.jws_alg(), Algorithm::EdDsa);
+        assert_eq!(*key.to_pkcs8_pem().unwrap(), ed25519::tests::PKCS8_PEM);
+    }
+
+    #[test]
+    fn account_key_from_pkcs8_es256() {
+        let der = es256::from_jwk(es256::tests::JWK)
+            .unwrap()
+            .to_pkcs8_der()
+            .unwrap();
+        let key = account_key_from_pkcs8_der(&*der).unwrap();
+        assert_eq!(key.jws_alg(), Algorithm::Es256);
+    }
+
+    #[test]
+    fn account_key_from_pkcs8_es384_rs256() {
+        let es384 = es384::from_jwk(es384::tests::JWK).unwrap();
+        let key = account_key_from_pkcs8_der(&*es384.to_pkcs8_der().unwrap()).unwrap();
+        assert_eq!(key.jws_alg(), Algorithm::Es384);
+
+        let rs256 = rs256::from_jwk(rs256::tests::JWK).unwrap();
+        let key = account_key_from_pkcs8_pem(&*rs256.to_pkcs8_pem().unwrap()).unwrap();
+        assert_eq!(key.jws_alg(), Algorithm::Rs256);
+    }
+
+    #[test]
+    fn generate_account_key_algorithms() {
+        for algorithm in [
+            KeyAlgorithm::Es256,
+            KeyAlgorithm::Es384,
+            KeyAlgorithm::Ed25519,
+            KeyAlgorithm::Rsa2048,
+        ] {
+            let key = generate_account_key_with(algorithm);
+            assert_eq!(key.jws_alg(), algorithm.jws_alg());
+            let reloaded = account_key_from_jwk(&*key.private_jwk().unwrap()).unwrap();
+            assert_eq!(reloaded.thumbprint().unwrap(), key.thumbprint().unwrap());
+        }
+    }
+
+    #[test]
+    fn debug_redacts_private_keys() {
+        let keys = [
+            (
+                es256::tests::JWK,
+                format!("{:?}", es256::from_jwk(es256::tests::JWK).unwrap()),
+            ),
+            (
+                es384::tests::JWK,
+                format!("{:?}", es384::from_jwk(es384::tests::JWK).unwrap()),
+            ),
+            (
+                ed25519::tests::JWK,
+                format!("{:?}", ed25519::from_jwk(ed25519::tests::JWK).unwrap()),
+            ),
+            (
+                rs256::tests::JWK,
+                format!("{:?}", rs256::from_jwk(rs256::tests::JWK).unwrap()),
+            ),
+        ];
+        for (jwk, debug) in keys {
+            let jwk: serde_json::Value = serde_json::from_str(jwk).unwrap();
+            assert!(debug.contains("<redacted>"), "{}", debug);
+            assert!(!debug.contains(jwk["d"].as_str().unwrap()), "{}", debug);
+        }
     }
 
     #[test]
@@ -1,12 +1,33 @@
+use pkcs8::{DecodePrivateKey, EncodePrivateKey, LineEnding, PrivateKeyDocument};
 use rand::{CryptoRng, RngCore};
 use signature::rand_core::OsRng;
 use zeroize::Zeroizing;
 
-use super::jws::JwsSigner;
+use super::{
+    jwk,
+    jws::{Algorithm, JwsSigner},
+};
 
 pub trait AccountKey: JwsSigner + Send + Sync + std::fmt::Debug {
     fn private_jwk(&self) -> anyhow::Result<Zeroizing<String>>;
     fn public_jwk(&self) -> anyhow::Result<String>;
+
+    /// DER-encoded PKCS#8 private key. Keys that can't be exported, e.g.
+    /// ones held in an HSM, can leave the default, which fails.
+    fn to_pkcs8_der(&self) -> anyhow::Result<Zeroizing<Vec<u8>>> {
+        anyhow::bail!("{:?} doesn't support PKCS#8 export", self.jws_alg())
+    }
+
+    /// PEM-encoded PKCS#8 private key ("BEGIN PRIVATE KEY")
+    fn to_pkcs8_pem(&self) -> anyhow::Result<Zeroizing<String>> {
+        let doc = PrivateKeyDocument::from_pkcs8_der(&self.to_pkcs8_der()?)?;
+        Ok(doc.to_pkcs8_pem(LineEnding::LF)?)
+    }
+
+    /// RFC 7638 SHA-256 thumbprint of the public JWK, base64url-encoded.
+    fn thumbprint(&self) -> anyhow::Result<String> {
+        jwk::thumbprint(self.public_jwk()?)
+    }
 }
 
 pub trait GenerateAccountKey: AccountKey + Sized {
@@ -18,7 +39,7 @@ pub trait GenerateAccountKey: AccountKey + Sized {
 }
 
 impl JwsSigner for Box<dyn AccountKey> {
-    fn jws_alg(&self) -> &str {
+    fn jws_alg(&self) -> Algorithm {
         self.as_ref().jws_alg()
     }
 
@@ -35,4 +56,55 @@ impl AccountKey for Box<dyn AccountKey> {
     fn public_jwk(&self) -> anyhow::Result<String> {
         self.as_ref().public_jwk()
     }
+
+    fn to_pkcs8_der(&self) -> anyhow::Result<Zeroizing<Vec<u8>>> {
+        self.as_ref().to_pkcs8_der()
+    }
+
+    fn to_pkcs8_pem(&self) -> anyhow::Result<Zeroizing<String>> {
+        self.as_ref().to_pkcs8_pem()
+    }
+
+    fn thumbprint(&self) -> anyhow::Result<String> {
+        self.as_ref().thumbprint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crypto::es256::Es256AccountKey;
+
+    use super::*;
+
+    /// An implementor from before `to_pkcs8_der` was added.
+    #[derive(Debug)]
+    struct JwkOnly(Es256AccountKey);
+
+    impl JwsSigner for JwkOnly {
+        fn jws_alg(&self) -> Algorithm {
+            self.0.jws_alg()
+        }
+
+        fn jws_sign(&self, input: &[u8]) -> Vec<u8> {
+            self.0.jws_sign(input)
+        }
+    }
+
+    impl AccountKey for JwkOnly {
+        fn private_jwk(&self) -> anyhow::Result<Zeroizing<String>> {
+            self.0.private_jwk()
+        }
+
+        fn public_jwk(&self) -> anyhow::Result<String> {
+            self.0.public_jwk()
+        }
+    }
+
+    #[test]
+    fn pkcs8_export_unsupported_by_default() {
+        let key: Box<dyn AccountKey> = Box::new(JwkOnly(Es256AccountKey::generate()));
+        key.to_pkcs8_der().unwrap_err();
+        key.to_pkcs8_pem().unwrap_err();
+        key.thumbprint().unwrap();
+    }
 }
@@ -0,0 +1,244 @@
+//! Passphrase encryption of private keys at rest: AES-256-GCM with a key
+//! derived by Argon2id, serialized as JSON with everything but the
+//! passphrase needed to decrypt.
+
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Nonce,
+};
+use argon2::{Algorithm, Argon2, Params, Version};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use signature::rand_core::OsRng;
+use zeroize::Zeroizing;
+
+use crate::base64url;
+
+use super::account_key::AccountKey;
+
+/// The most memory (in KiB) `decrypt` will have Argon2 use, so that a
+/// tampered file can't exhaust it: 1 GiB.
+pub const MAX_M_COST: u32 = 1 << 20;
+
+/// The most passes `decrypt` will have Argon2 make, so that a tampered file
+/// can't make loading it take hours.
+pub const MAX_T_COST: u32 = 16;
+
+/// The most lanes `decrypt` will have Argon2 use.
+pub const MAX_P_COST: u32 = 16;
+
+const KDF_ARGON2ID: &str = "argon2id";
+const CIPHER_A256GCM: &str = "A256GCM";
+
+/// Argon2id costs, as in RFC 9106.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KdfParams {
+    /// Memory in KiB, at least 8 per lane.
+    pub m_cost: u32,
+    /// Passes over the memory.
+    pub t_cost: u32,
+    /// Lanes.
+    pub p_cost: u32,
+}
+
+/// OWASP's recommendation for Argon2id, as of 2023: 19 MiB, 2 passes, 1
+/// lane.
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            m_cost: 19 * 1024,
+            t_cost: 2,
+            p_cost: 1,
+        }
+    }
+}
+
+impl KdfParams {
+    fn derive_key(&self, passphrase: &str, salt: &[u8]) -> anyhow::Result<Zeroizing<[u8; 32]>> {
+        anyhow::ensure!(
+            self.m_cost <= MAX_M_COST && self.t_cost <= MAX_T_COST && self.p_cost <= MAX_P_COST,
+            "Argon2 costs m={}, t={}, p={} are more than the m={}, t={}, p={} allowed",
+            self.m_cost,
+            self.t_cost,
+            self.p_cost,
+            MAX_M_COST,
+            MAX_T_COST,
+            MAX_P_COST
+        );
+        let params = Params::new(self.m_cost, self.t_cost, self.p_cost, Some(32))
+            .map_err(|err| anyhow::anyhow!("invalid Argon2 costs: {}", err))?;
+        let mut key = Zeroizing::new([0; 32]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), salt, &mut *key)
+            .map_err(|err| anyhow::anyhow!("key derivation failed: {}", err))?;
+        Ok(key)
+    }
+}
+
+/// Data encrypted with a passphrase.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Encrypted {
+    pub kdf: String,
+    #[serde(flatten)]
+    pub kdf_params: KdfParams,
+    /// base64url
+    pub salt: String,
+    pub cipher: String,
+    /// base64url
+    pub nonce: String,
+    /// base64url, with the authentication tag
+    pub ciphertext: String,
+}
+
+impl Encrypted {
+    pub fn encrypt(plaintext: &[u8], passphrase: &str) -> anyhow::Result<Self> {
+        Self::encrypt_with_params(plaintext, passphrase, KdfParams::default())
+    }
+
+    /// Like `encrypt`, with Argon2 costs other than the defaults, up to
+    /// `MAX_M_COST`, `MAX_T_COST` and `MAX_P_COST`.
+    pub fn encrypt_with_params(
+        plaintext: &[u8],
+        passphrase: &str,
+        kdf_params: KdfParams,
+    ) -> anyhow::Result<Self> {
+        let mut salt = [0; 16];
+        OsRng.fill_bytes(&mut salt);
+        let mut nonce = [0; 12];
+        OsRng.fill_bytes(&mut nonce);
+        let key = kdf_params.derive_key(passphrase, &salt)?;
+        let ciphertext = cipher(&key)?
+            .encrypt(&Nonce::from(nonce), plaintext)
+            .map_err(|_| anyhow::anyhow!("encryption failed"))?;
+        Ok(Self {
+            kdf: KDF_ARGON2ID.to_string(),
+            kdf_params,
+            salt: base64url::encode(salt),
+            cipher: CIPHER_A256GCM.to_string(),
+            nonce: base64url::encode(nonce),
+            ciphertext: base64url::encode(ciphertext),
+        })
+    }
+
+    /// Fails if `passphrase` is wrong or the data was tampered with, which
+    /// can't be told apart.
+    pub fn decrypt(&self, passphrase: &str) -> anyhow::Result<Zeroizing<Vec<u8>>> {
+        anyhow::ensure!(
+            self.kdf == KDF_ARGON2ID && self.cipher == CIPHER_A256GCM,
+            "unsupported encryption {}/{}",
+            self.kdf,
+            self.cipher
+        );
+        let nonce = <[u8; 12]>::try_from(base64url::decode(&self.nonce)?)
+            .map_err(|_| anyhow::anyhow!("invalid AES-GCM nonce"))?;
+        let key = self
+            .kdf_params
+            .derive_key(passphrase, &base64url::decode(&self.salt)?)?;
+        let plaintext = cipher(&key)?
+            .decrypt(
+                &Nonce::from(nonce),
+                base64url::decode(&self.ciphertext)?.as_slice(),
+            )
+            .map_err(|_| anyhow::anyhow!("wrong passphrase or corrupted data"))?;
+        Ok(Zeroizing::new(plaintext))
+    }
+}
+
+fn cipher(key: &[u8; 32]) -> anyhow::Result<Aes256Gcm> {
+    Aes256Gcm::new_from_slice(key).map_err(|_| anyhow::anyhow!("invalid AES-256 key"))
+}
+
+/// A private JWK (see `AccountKey::private_jwk`) encrypted with a
+/// passphrase, for keeping account keys on disk.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct EncryptedJwk(pub Encrypted);
+
+impl EncryptedJwk {
+    pub fn encrypt(key: &impl AccountKey, passphrase: &str) -> anyhow::Result<Self> {
+        Ok(Self(Encrypted::encrypt(
+            key.private_jwk()?.as_bytes(),
+            passphrase,
+        )?))
+    }
+
+    /// The private JWK, e.g. for `crypto::account_key_from_jwk`.
+    pub fn decrypt(&self, passphrase: &str) -> anyhow::Result<Zeroizing<String>> {
+        let jwk = self.0.decrypt(passphrase)?;
+        Ok(Zeroizing::new(String::from_utf8(jwk.to_vec())?))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::crypto::{account_key_from_jwk, es256};
+
+    use super::*;
+
+    /// Argon2's minimum costs, to keep tests fast.
+    pub const CHEAP: KdfParams = KdfParams {
+        m_cost: 8,
+        t_cost: 1,
+        p_cost: 1,
+    };
+
+    #[test]
+    fn encrypted_jwk() {
+        let key = es256::from_jwk(es256::tests::JWK).unwrap();
+        let encrypted = EncryptedJwk(
+            Encrypted::encrypt_with_params(
+                key.private_jwk().unwrap().as_bytes(),
+                "correct horse",
+                CHEAP,
+            )
+            .unwrap(),
+        );
+        let json = serde_json::to_string(&encrypted).unwrap();
+        assert!(!json.contains(key.private_jwk().unwrap().as_str()));
+        let header: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(header["kdf"], "argon2id");
+        assert_eq!(
+            (&header["m_cost"], &header["t_cost"], &header["p_cost"]),
+            (&8.into(), &1.into(), &1.into())
+        );
+
+        let decrypted = serde_json::from_str::<EncryptedJwk>(&json)
+            .unwrap()
+            .decrypt("correct horse")
+            .unwrap();
+        let reloaded = account_key_from_jwk(&*decrypted).unwrap();
+        assert_eq!(reloaded.thumbprint().unwrap(), key.thumbprint().unwrap());
+
+        encrypted.decrypt("battery staple").unwrap_err();
+        let mut tampered = encrypted.clone();
+        tampered.0.kdf_params.t_cost += 1;
+        tampered.decrypt("correct horse").unwrap_err();
+    }
+
+    #[test]
+    fn cost_limits() {
+        let mut encrypted = Encrypted::encrypt_with_params(b"secret", "pw", CHEAP).unwrap();
+        // fails before deriving a key, which would take all the memory
+        encrypted.kdf_params.m_cost = u32::MAX;
+        let err = encrypted.decrypt("pw").unwrap_err();
+        assert!(err.to_string().contains("allowed"), "{}", err);
+        encrypted.kdf_params = KdfParams { t_cost: 0, ..CHEAP };
+        encrypted.decrypt("pw").unwrap_err();
+
+        let too_slow = KdfParams {
+            t_cost: MAX_T_COST + 1,
+            ..CHEAP
+        };
+        Encrypted::encrypt_with_params(b"secret", "pw", too_slow).unwrap_err();
+        let too_little_memory = KdfParams { m_cost: 7, ..CHEAP };
+        Encrypted::encrypt_with_params(b"secret", "pw", too_little_memory).unwrap_err();
+    }
+
+    #[test]
+    fn rejects_other_kdfs() {
+        let mut encrypted = Encrypted::encrypt_with_params(b"secret", "pw", CHEAP).unwrap();
+        encrypted.kdf = "PBKDF2-SHA256".to_string();
+        let err = encrypted.decrypt("pw").unwrap_err();
+        assert!(err.to_string().contains("unsupported"), "{}", err);
+    }
+}
@@ -1,29 +1,44 @@
-use p256::{ecdsa::SigningKey, SecretKey};
+use p256::{
+    ecdsa::{Signature, SigningKey, VerifyingKey},
+    SecretKey,
+};
+use pkcs8::{DecodePrivateKey, EncodePrivateKey};
 use rand::{CryptoRng, RngCore};
-use signature::Signer;
+use signature::{Signer, Verifier};
 use zeroize::Zeroizing;
 
 use super::{
     account_key::{AccountKey, GenerateAccountKey},
-    jws::JwsSigner,
+    jws::{Algorithm, JwsSigner, JwsVerifier},
 };
 
-#[derive(Debug)]
 pub struct Es256AccountKey(SecretKey);
 
 pub fn from_jwk(jwk: impl AsRef<str>) -> anyhow::Result<Es256AccountKey> {
     Ok(SecretKey::from_jwk_str(jwk.as_ref())?.into())
 }
 
+pub fn from_pkcs8_der(der: impl AsRef<[u8]>) -> anyhow::Result<Es256AccountKey> {
+    Ok(SecretKey::from_pkcs8_der(der.as_ref())?.into())
+}
+
 impl GenerateAccountKey for Es256AccountKey {
     fn generate_rng(rng: impl CryptoRng + RngCore) -> Self {
         SecretKey::random(rng).into()
     }
 }
 
+impl std::fmt::Debug for Es256AccountKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Es256AccountKey")
+            .field("secret", &"<redacted>")
+            .finish()
+    }
+}
+
 impl JwsSigner for Es256AccountKey {
-    fn jws_alg(&self) -> &str {
-        "ES256"
+    fn jws_alg(&self) -> Algorithm {
+        Algorithm::Es256
     }
 
     fn jws_sign(&self, input: &[u8]) -> Vec<u8> {
@@ -31,6 +46,18 @@ impl JwsSigner for Es256AccountKey {
     }
 }
 
+impl JwsVerifier for Es256AccountKey {
+    fn jws_alg(&self) -> Algorithm {
+        Algorithm::Es256
+    }
+
+    fn jws_verify(&self, input: &[u8], signature: &[u8]) -> bool {
+        let verifying_key = VerifyingKey::from(&SigningKey::from(&self.0));
+        Signature::try_from(signature)
+            .is_ok_and(|signature| verifying_key.verify(input, &signature).is_ok())
+    }
+}
+
 impl AccountKey for Es256AccountKey {
     fn private_jwk(&self) -> anyhow::Result<Zeroizing<String>> {
         Ok(self.0.to_jwk_string())
@@ -39,6 +66,10 @@ impl AccountKey for Es256AccountKey {
     fn public_jwk(&self) -> anyhow::Result<String> {
         Ok(self.0.public_key().to_jwk_string())
     }
+
+    fn to_pkcs8_der(&self) -> anyhow::Result<Zeroizing<Vec<u8>>> {
+        Ok(Zeroizing::new(self.0.to_pkcs8_der()?.as_ref().to_vec()))
+    }
 }
 
 impl From<SecretKey> for Es256AccountKey {
@@ -54,6 +85,11 @@ impl From<Es256AccountKey> for SecretKey {
 }
 
 #[cfg(test)]
+#[allow(
+    clippy::redundant_static_lifetimes,
+    clippy::declare_interior_mutable_const,
+    clippy::borrow_interior_mutable_const
+)]
 pub mod tests {
     use once_cell::sync::Lazy;
 
@@ -76,6 +112,13 @@ pub mod tests {
         assert_eq!(*jwk, expect);
     }
 
+    #[test]
+    fn round_trip_pkcs8() {
+        let der = KEY.to_pkcs8_der().unwrap();
+        let key = from_pkcs8_der(&*der).unwrap();
+        assert_eq!(key.private_jwk().unwrap(), KEY.private_jwk().unwrap());
+    }
+
     #[test]
     fn generate_smoke_test() {
         Es256AccountKey::generate();
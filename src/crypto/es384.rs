@@ -0,0 +1,137 @@
+use p384::{
+    ecdsa::{
+        signature::{Signer, Verifier},
+        Signature, SigningKey, VerifyingKey,
+    },
+    pkcs8::{DecodePrivateKey, EncodePrivateKey},
+    SecretKey,
+};
+use rand::{CryptoRng, RngCore};
+use zeroize::Zeroizing;
+
+use super::{
+    account_key::{AccountKey, GenerateAccountKey},
+    jws::{Algorithm, JwsSigner, JwsVerifier},
+};
+
+pub struct Es384AccountKey(SecretKey);
+
+pub fn from_jwk(jwk: impl AsRef<str>) -> anyhow::Result<Es384AccountKey> {
+    Ok(SecretKey::from_jwk_str(jwk.as_ref())?.into())
+}
+
+pub fn from_pkcs8_der(der: impl AsRef<[u8]>) -> anyhow::Result<Es384AccountKey> {
+    Ok(SecretKey::from_pkcs8_der(der.as_ref())?.into())
+}
+
+impl GenerateAccountKey for Es384AccountKey {
+    fn generate_rng(mut rng: impl CryptoRng + RngCore) -> Self {
+        SecretKey::random(&mut rng).into()
+    }
+}
+
+impl std::fmt::Debug for Es384AccountKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Es384AccountKey")
+            .field("secret", &"<redacted>")
+            .finish()
+    }
+}
+
+impl JwsSigner for Es384AccountKey {
+    fn jws_alg(&self) -> Algorithm {
+        Algorithm::Es384
+    }
+
+    fn jws_sign(&self, input: &[u8]) -> Vec<u8> {
+        let signature: Signature = SigningKey::from(&self.0).sign(input);
+        signature.to_vec()
+    }
+}
+
+impl JwsVerifier for Es384AccountKey {
+    fn jws_alg(&self) -> Algorithm {
+        Algorithm::Es384
+    }
+
+    fn jws_verify(&self, input: &[u8], signature: &[u8]) -> bool {
+        let verifying_key = VerifyingKey::from(&SigningKey::from(&self.0));
+        Signature::from_slice(signature)
+            .is_ok_and(|signature| verifying_key.verify(input, &signature).is_ok())
+    }
+}
+
+impl AccountKey for Es384AccountKey {
+    fn private_jwk(&self) -> anyhow::Result<Zeroizing<String>> {
+        Ok(self.0.to_jwk_string())
+    }
+
+    fn public_jwk(&self) -> anyhow::Result<String> {
+        Ok(self.0.public_key().to_jwk_string())
+    }
+
+    fn to_pkcs8_der(&self) -> anyhow::Result<Zeroizing<Vec<u8>>> {
+        Ok(Zeroizing::new(self.0.to_pkcs8_der()?.as_bytes().to_vec()))
+    }
+}
+
+impl From<SecretKey> for Es384AccountKey {
+    fn from(secret: SecretKey) -> Self {
+        Self(secret)
+    }
+}
+
+impl From<Es384AccountKey> for SecretKey {
+    fn from(key: Es384AccountKey) -> Self {
+        key.0
+    }
+}
+
+#[cfg(test)]
+#[allow(
+    clippy::redundant_static_lifetimes,
+    clippy::declare_interior_mutable_const,
+    clippy::borrow_interior_mutable_const
+)]
+pub mod tests {
+    use once_cell::sync::Lazy;
+
+    use super::*;
+
+    pub const JWK: &'static str = r#"{
+        "kty":"EC", "crv":"P-384",
+        "x":"SKdRvDvhrauypFv9hSU2Qct6t6oZ9RXPiHl5Tbs6var_Sewy9-Ri4-_nhF8-FLjy",
+        "y":"kagyye81Oi6TqIOH_8sGkIk_2kmnMG2cJSLyl8KRMiC9Jo3gZXhPX-lMiLU7pV6F",
+        "d":"63T8KTThqtp37P9PBR_-TCR7FYVY0uflf_30LE4wHwDGVNweWqNYb7d_L9peP2r-"
+    }"#;
+
+    const KEY: Lazy<Es384AccountKey> = Lazy::new(|| from_jwk(JWK).unwrap());
+
+    #[test]
+    fn round_trip_jwk() {
+        let jwk = KEY.private_jwk().unwrap();
+        let expect: String = JWK.split_whitespace().collect();
+        assert_eq!(*jwk, expect);
+    }
+
+    #[test]
+    fn round_trip_pkcs8() {
+        let der = KEY.to_pkcs8_der().unwrap();
+        let key = from_pkcs8_der(&*der).unwrap();
+        assert_eq!(key.private_jwk().unwrap(), KEY.private_jwk().unwrap());
+    }
+
+    #[test]
+    fn generate_smoke_test() {
+        Es384AccountKey::generate();
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let signature = KEY.jws_sign(b"test");
+        // r || s, as JWS wants
+        assert_eq!(signature.len(), 96);
+        assert!(KEY.jws_verify(b"test", &signature));
+        assert!(!KEY.jws_verify(b"tested", &signature));
+    }
+}
@@ -1,4 +1,8 @@
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::base64url;
 
 #[derive(Serialize, Deserialize)]
 pub struct Jwk<'a> {
@@ -12,3 +16,75 @@ pub struct Jwk<'a> {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub d: Option<&'a str>,
 }
+
+/// JWK SHA-256 thumbprint
+/// https://datatracker.ietf.org/doc/html/rfc7638
+pub fn thumbprint(jwk: impl AsRef<str>) -> anyhow::Result<String> {
+    let jwk: Map<String, Value> = serde_json::from_str(jwk.as_ref())?;
+    // Required members, in lexicographic order
+    let members: &[&str] = match jwk.get("kty").and_then(Value::as_str) {
+        Some("EC") => &["crv", "kty", "x", "y"],
+        Some("OKP") => &["crv", "kty", "x"],
+        Some("RSA") => &["e", "kty", "n"],
+        Some(kty) => anyhow::bail!("unsupported JWK key type {:?}", kty),
+        None => anyhow::bail!("JWK missing \"kty\""),
+    };
+    let mut canonical = Vec::new();
+    for name in members {
+        let value = jwk
+            .get(*name)
+            .ok_or_else(|| anyhow::anyhow!("JWK missing {:?}", name))?;
+        canonical.push(format!(
+            "{}:{}",
+            serde_json::to_string(name)?,
+            serde_json::to_string(value)?
+        ));
+    }
+    let canonical = format!("{{{}}}", canonical.join(","));
+    Ok(base64url::encode(Sha256::digest(canonical.as_bytes())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfc7638_rsa_thumbprint() {
+        // https://datatracker.ietf.org/doc/html/rfc7638#section-3.1
+        let jwk = r#"{
+            "kty": "RSA",
+            "n": "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw",
+            "e": "AQAB",
+            "alg": "RS256",
+            "kid": "2011-04-29"
+        }"#;
+        assert_eq!(
+            thumbprint(jwk).unwrap(),
+            "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs"
+        );
+    }
+
+    #[test]
+    fn rfc8037_ed25519_thumbprint() {
+        // https://datatracker.ietf.org/doc/html/rfc8037#appendix-A.3
+        let jwk =
+            r#"{"kty":"OKP","crv":"Ed25519","x":"11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo"}"#;
+        assert_eq!(
+            thumbprint(jwk).unwrap(),
+            "kPrK_qmxVWaYVA9wwBF6Iuo3vVzz7TxHCTwXBygrS4k"
+        );
+    }
+
+    #[test]
+    fn thumbprint_ignores_private_members() {
+        let public =
+            r#"{"kty":"OKP","crv":"Ed25519","x":"11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo"}"#;
+        let private = r#"{"x":"11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo","kty":"OKP","crv":"Ed25519","d":"nWGxne_9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A"}"#;
+        assert_eq!(thumbprint(public).unwrap(), thumbprint(private).unwrap());
+    }
+
+    #[test]
+    fn thumbprint_missing_member() {
+        thumbprint(r#"{"kty":"EC","crv":"P-256","x":"abc"}"#).unwrap_err();
+    }
+}
@@ -1,43 +1,236 @@
-use serde::Serialize;
+//! Flattened JWS (RFC 7515) signing and verification, as ACME uses it:
+//! protected headers only, with an attached, empty or detached payload.
+
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
 
 use crate::base64url;
 
 pub static CONTENT_TYPE: &str = "application/jose+json";
 
+/// JWS "alg" values (RFC 7518 and RFC 8037) that ACME servers accept.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Algorithm {
+    /// ECDSA with P-256 and SHA-256, the one RFC 8555 requires servers to
+    /// support.
+    Es256,
+    Es384,
+    Es512,
+    /// Ed25519 (or Ed448), per the key.
+    EdDsa,
+    Rs256,
+    /// HMAC with SHA-256, for external account bindings (RFC 8555 section
+    /// 7.3.4) only.
+    Hs256,
+}
+
+impl Algorithm {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Es256 => "ES256",
+            Self::Es384 => "ES384",
+            Self::Es512 => "ES512",
+            Self::EdDsa => "EdDSA",
+            Self::Rs256 => "RS256",
+            Self::Hs256 => "HS256",
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            Self::Es256,
+            Self::Es384,
+            Self::Es512,
+            Self::EdDsa,
+            Self::Rs256,
+            Self::Hs256,
+        ]
+        .into_iter()
+        .find(|alg| alg.as_str() == s)
+        .ok_or_else(|| anyhow!("unsupported JWS alg {:?}", s))
+    }
+}
+
+impl Serialize for Algorithm {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Algorithm {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
 pub trait JwsSigner {
-    fn jws_alg(&self) -> &str;
+    fn jws_alg(&self) -> Algorithm;
     fn jws_sign(&self, input: &[u8]) -> Vec<u8>;
 }
 
-pub fn jws_flattened(
+/// Checks signatures made by a `JwsSigner`.
+pub trait JwsVerifier {
+    fn jws_alg(&self) -> Algorithm;
+    fn jws_verify(&self, input: &[u8], signature: &[u8]) -> bool;
+}
+
+/// What a JWS signs, and whether it carries it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Payload<'a> {
+    /// The empty payload of a POST-as-GET request (RFC 8555 section 6.3).
+    Empty,
+    Attached(&'a [u8]),
+    /// Signed but left out of the JWS (RFC 7515 appendix F), for the
+    /// recipient to supply, e.g. a document sent alongside it.
+    Detached(&'a [u8]),
+}
+
+impl Payload<'_> {
+    fn signed(&self) -> &[u8] {
+        match self {
+            Self::Empty => &[],
+            Self::Attached(payload) | Self::Detached(payload) => payload,
+        }
+    }
+}
+
+/// Signs `payload` with `signer`, whatever its algorithm; the header's
+/// "alg" must be the signer's.
+pub fn sign(
     signer: &impl JwsSigner,
     header: &JwsHeader<impl Serialize>,
-    payload: &[u8],
+    payload: Payload<'_>,
 ) -> anyhow::Result<Jws> {
-    // https://tools.ietf.org/id/draft-ietf-jose-json-web-signature-01.html#rfc.section.5
-    let header_json = serde_json::to_vec(header)?;
-    let header_b64 = base64url::encode(header_json);
-    let payload_b64 = base64url::encode(payload);
+    if header.alg != signer.jws_alg() {
+        bail!(
+            "JWS header alg {} doesn't match the signer's {}",
+            header.alg,
+            signer.jws_alg()
+        );
+    }
+    // https://www.rfc-editor.org/rfc/rfc7515.html#section-7.2.2
+    let header_b64 = base64url::encode(serde_json::to_vec(header)?);
+    let payload_b64 = base64url::encode(payload.signed());
     let input = format!("{}.{}", header_b64, payload_b64);
-    let signature = signer.jws_sign(input.as_bytes());
-    let signature_b64 = base64url::encode(signature);
+    let signature_b64 = base64url::encode(signer.jws_sign(input.as_bytes()));
     Ok(Jws {
         protected: header_b64,
-        payload: payload_b64,
+        payload: match payload {
+            Payload::Detached(_) => String::new(),
+            _ => payload_b64,
+        },
         signature: signature_b64,
     })
 }
 
-#[derive(Serialize)]
+/// `sign` with an attached (possibly empty) payload.
+pub fn jws_flattened(
+    signer: &impl JwsSigner,
+    header: &JwsHeader<impl Serialize>,
+    payload: &[u8],
+) -> anyhow::Result<Jws> {
+    sign(signer, header, Payload::Attached(payload))
+}
+
+/// The decoded contents of a JWS whose signature checked out.
+#[derive(Debug)]
+pub struct VerifiedJws {
+    pub header: Map<String, Value>,
+    pub payload: Vec<u8>,
+}
+
+/// Checks the signature of `jws` with `verifier`, which must be for the
+/// header's "alg".
+pub fn jws_verify(jws: &Jws, verifier: &impl JwsVerifier) -> anyhow::Result<VerifiedJws> {
+    let payload = base64url::decode(&jws.payload)?;
+    verify_input(jws, &jws.payload, payload, verifier)
+}
+
+/// Like `jws_verify`, for a JWS signed with `Payload::Detached(payload)`.
+pub fn jws_verify_detached(
+    jws: &Jws,
+    payload: &[u8],
+    verifier: &impl JwsVerifier,
+) -> anyhow::Result<VerifiedJws> {
+    if !jws.payload.is_empty() {
+        bail!("JWS payload isn't detached");
+    }
+    verify_input(jws, &base64url::encode(payload), payload.to_vec(), verifier)
+}
+
+fn verify_input(
+    jws: &Jws,
+    payload_b64: &str,
+    payload: Vec<u8>,
+    verifier: &impl JwsVerifier,
+) -> anyhow::Result<VerifiedJws> {
+    let header: Map<String, Value> = serde_json::from_slice(&base64url::decode(&jws.protected)?)?;
+    let alg = header.get("alg").and_then(Value::as_str);
+    if alg != Some(verifier.jws_alg().as_str()) {
+        bail!("JWS alg {:?} doesn't match {}", alg, verifier.jws_alg());
+    }
+    let input = format!("{}.{}", jws.protected, payload_b64);
+    let signature = base64url::decode(&jws.signature)?;
+    if !verifier.jws_verify(input.as_bytes(), &signature) {
+        return Err(anyhow!("JWS signature doesn't verify"));
+    }
+    Ok(VerifiedJws { header, payload })
+}
+
+/// Serializes `value` as JSON with object keys sorted at every level, so that
+/// equal values always produce the same bytes (and signatures).
+pub fn canonical_json(value: &impl Serialize) -> serde_json::Result<Vec<u8>> {
+    fn sort_keys(value: Value) -> Value {
+        match value {
+            Value::Object(map) => {
+                let mut entries: Vec<_> = map.into_iter().collect();
+                entries.sort_by(|a, b| a.0.cmp(&b.0));
+                Value::Object(
+                    entries
+                        .into_iter()
+                        .map(|(k, v)| (k, sort_keys(v)))
+                        .collect(),
+                )
+            }
+            Value::Array(values) => Value::Array(values.into_iter().map(sort_keys).collect()),
+            value => value,
+        }
+    }
+    serde_json::to_vec(&sort_keys(serde_json::to_value(value)?))
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct Jws {
     pub protected: String,
     pub payload: String,
     pub signature: String,
 }
 
+impl Jws {
+    /// Checks the signature, e.g. with a `PublicJwk`; see `jws_verify`.
+    pub fn verify(&self, verifier: &impl JwsVerifier) -> anyhow::Result<VerifiedJws> {
+        jws_verify(self, verifier)
+    }
+}
+
 #[derive(Serialize)]
 pub struct JwsHeader<'a, JwkT: Serialize> {
-    pub alg: &'a str,
+    pub alg: Algorithm,
     pub nonce: &'a str,
     pub url: &'a str,
 
@@ -47,3 +240,85 @@ pub struct JwsHeader<'a, JwkT: Serialize> {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub kid: Option<&'a str>,
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::crypto::{
+        account_key::GenerateAccountKey, ed25519::Ed25519AccountKey, es256::Es256AccountKey,
+    };
+
+    use super::*;
+
+    fn header<'a>(signer: &'a impl JwsSigner) -> JwsHeader<'a, ()> {
+        JwsHeader {
+            alg: signer.jws_alg(),
+            nonce: "nonce",
+            url: "https://example.com/acme/new-order",
+            jwk: None,
+            kid: Some("https://example.com/acme/acct/1"),
+        }
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let es256 = Es256AccountKey::generate();
+        let jws = jws_flattened(&es256, &header(&es256), b"{}").unwrap();
+        let verified = jws_verify(&jws, &es256).unwrap();
+        assert_eq!(verified.header["nonce"], "nonce");
+        assert_eq!(verified.payload, b"{}");
+
+        let ed25519 = Ed25519AccountKey::generate();
+        let jws = jws_flattened(&ed25519, &header(&ed25519), b"").unwrap();
+        jws_verify(&jws, &ed25519).unwrap();
+        // Wrong alg, then wrong key
+        assert!(jws_verify(&jws, &es256).is_err());
+        assert!(jws_verify(&jws, &Ed25519AccountKey::generate()).is_err());
+
+        // Signatures are deterministic
+        let again = jws_flattened(&ed25519, &header(&ed25519), b"").unwrap();
+        assert_eq!(jws.signature, again.signature);
+    }
+
+    #[test]
+    fn detached_and_empty_payloads() {
+        let key = Es256AccountKey::generate();
+        let jws = sign(&key, &header(&key), Payload::Detached(b"{\"a\":1}")).unwrap();
+        assert_eq!(jws.payload, "");
+        let verified = jws_verify_detached(&jws, b"{\"a\":1}", &key).unwrap();
+        assert_eq!(verified.payload, b"{\"a\":1}");
+        assert!(jws_verify_detached(&jws, b"{\"a\":2}", &key).is_err());
+
+        let jws = sign(&key, &header(&key), Payload::Empty).unwrap();
+        assert_eq!(jws.payload, "");
+        assert!(jws_verify(&jws, &key).unwrap().payload.is_empty());
+        assert!(jws_verify_detached(&jws, b"", &key).is_ok());
+
+        let attached = sign(&key, &header(&key), Payload::Attached(b"{}")).unwrap();
+        assert!(jws_verify_detached(&attached, b"{}", &key).is_err());
+    }
+
+    #[test]
+    fn algorithm_must_match_signer() {
+        let key = Ed25519AccountKey::generate();
+        let header = JwsHeader::<()> {
+            alg: Algorithm::Es256,
+            ..header(&key)
+        };
+        assert!(sign(&key, &header, Payload::Empty).is_err());
+
+        assert_eq!("EdDSA".parse::<Algorithm>().unwrap(), Algorithm::EdDsa);
+        assert!("none".parse::<Algorithm>().is_err());
+        assert_eq!(serde_json::to_value(Algorithm::Hs256).unwrap(), "HS256");
+    }
+
+    #[test]
+    fn canonical_json_sorts_keys() {
+        let value = json!({"b": [{"d": 1, "c": 2}], "a": null});
+        assert_eq!(
+            canonical_json(&value).unwrap(),
+            br#"{"a":null,"b":[{"c":2,"d":1}]}"#
+        );
+    }
+}
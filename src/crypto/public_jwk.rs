@@ -0,0 +1,172 @@
+use anyhow::{anyhow, bail};
+use rsa::{pkcs1v15, sha2::Sha256, BigUint, RsaPublicKey};
+use serde_json::{Map, Value};
+use signature::Verifier as _;
+
+use super::jws::{Algorithm, JwsVerifier};
+use crate::base64url;
+
+/// A public JWK that can verify JWS signatures, e.g. an account's key as
+/// returned by the server or a key from an ACME extension.
+#[derive(Debug)]
+pub enum PublicJwk {
+    Es256(p256::PublicKey),
+    Es384(p384::PublicKey),
+    Ed25519(ed25519_dalek::PublicKey),
+    Rs256(RsaPublicKey),
+}
+
+impl PublicJwk {
+    pub fn from_jwk(jwk: impl AsRef<str>) -> anyhow::Result<Self> {
+        let jwk = jwk.as_ref();
+        let members: Map<String, Value> = serde_json::from_str(jwk)?;
+        let member = |name: &str| {
+            members
+                .get(name)
+                .and_then(Value::as_str)
+                .ok_or_else(|| anyhow!("JWK missing {:?}", name))
+        };
+        match (member("kty")?, members.get("crv").and_then(Value::as_str)) {
+            ("EC", Some("P-256")) => Ok(Self::Es256(p256::PublicKey::from_jwk_str(jwk)?)),
+            ("EC", Some("P-384")) => Ok(Self::Es384(p384::PublicKey::from_jwk_str(jwk)?)),
+            ("OKP", Some("Ed25519")) => Ok(Self::Ed25519(ed25519_dalek::PublicKey::from_bytes(
+                &base64url::decode(member("x")?)?,
+            )?)),
+            ("RSA", _) => {
+                let n = BigUint::from_bytes_be(&base64url::decode(member("n")?)?);
+                let e = BigUint::from_bytes_be(&base64url::decode(member("e")?)?);
+                Ok(Self::Rs256(RsaPublicKey::new(n, e)?))
+            }
+            (kty, crv) => bail!("unsupported JWK key type {:?} (crv {:?})", kty, crv),
+        }
+    }
+}
+
+impl JwsVerifier for PublicJwk {
+    fn jws_alg(&self) -> Algorithm {
+        match self {
+            Self::Es256(_) => Algorithm::Es256,
+            Self::Es384(_) => Algorithm::Es384,
+            Self::Ed25519(_) => Algorithm::EdDsa,
+            Self::Rs256(_) => Algorithm::Rs256,
+        }
+    }
+
+    fn jws_verify(&self, input: &[u8], signature: &[u8]) -> bool {
+        match self {
+            Self::Es256(key) => p256::ecdsa::Signature::try_from(signature).is_ok_and(|sig| {
+                p256::ecdsa::VerifyingKey::from(key)
+                    .verify(input, &sig)
+                    .is_ok()
+            }),
+            Self::Es384(key) => p384::ecdsa::Signature::from_slice(signature).is_ok_and(|sig| {
+                use p384::ecdsa::signature::Verifier;
+                p384::ecdsa::VerifyingKey::from(key)
+                    .verify(input, &sig)
+                    .is_ok()
+            }),
+            Self::Ed25519(key) => ed25519_dalek::Signature::try_from(signature)
+                .is_ok_and(|sig| key.verify(input, &sig).is_ok()),
+            Self::Rs256(key) => pkcs1v15::Signature::try_from(signature).is_ok_and(|sig| {
+                use rsa::signature::Verifier;
+                pkcs1v15::VerifyingKey::<Sha256>::new(key.clone())
+                    .verify(input, &sig)
+                    .is_ok()
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crypto::{
+        account_key::{AccountKey, GenerateAccountKey},
+        ed25519::Ed25519AccountKey,
+        es256::Es256AccountKey,
+        es384,
+        jws::{jws_flattened, JwsHeader, JwsSigner},
+        rs256,
+    };
+
+    use super::*;
+
+    fn sign(signer: &impl JwsSigner) -> crate::crypto::jws::Jws {
+        let header = JwsHeader::<()> {
+            alg: signer.jws_alg(),
+            nonce: "nonce",
+            url: "https://example.com/acme/new-order",
+            jwk: None,
+            kid: Some("https://example.com/acme/acct/1"),
+        };
+        jws_flattened(signer, &header, b"{}").unwrap()
+    }
+
+    #[test]
+    fn verify_with_public_jwk() {
+        let es256 = Es256AccountKey::generate();
+        let ed25519 = Ed25519AccountKey::generate();
+        let es256_jwk = PublicJwk::from_jwk(es256.public_jwk().unwrap()).unwrap();
+        let ed25519_jwk = PublicJwk::from_jwk(ed25519.public_jwk().unwrap()).unwrap();
+
+        assert_eq!(sign(&es256).verify(&es256_jwk).unwrap().payload, b"{}");
+        sign(&ed25519).verify(&ed25519_jwk).unwrap();
+        sign(&es256).verify(&ed25519_jwk).unwrap_err();
+
+        let mut tampered = sign(&ed25519);
+        tampered.payload = base64url::encode("[]");
+        tampered.verify(&ed25519_jwk).unwrap_err();
+    }
+
+    #[test]
+    fn verify_es384_rs256() {
+        let es384 = es384::from_jwk(es384::tests::JWK).unwrap();
+        let rs256 = rs256::from_jwk(rs256::tests::JWK).unwrap();
+        let es384_jwk = PublicJwk::from_jwk(es384.public_jwk().unwrap()).unwrap();
+        let rs256_jwk = PublicJwk::from_jwk(rs256.public_jwk().unwrap()).unwrap();
+
+        sign(&es384).verify(&es384_jwk).unwrap();
+        sign(&rs256).verify(&rs256_jwk).unwrap();
+        sign(&rs256).verify(&es384_jwk).unwrap_err();
+
+        let mut tampered = sign(&rs256);
+        tampered.payload = base64url::encode("[]");
+        tampered.verify(&rs256_jwk).unwrap_err();
+    }
+
+    /// RS256 as OpenSSL signs it.
+    #[cfg(feature = "x509")]
+    #[test]
+    fn verify_rs256() {
+        use openssl::{hash::MessageDigest, pkey::PKey, rsa::Rsa, sign::Signer};
+
+        struct RsaSigner(PKey<openssl::pkey::Private>);
+
+        impl JwsSigner for RsaSigner {
+            fn jws_alg(&self) -> Algorithm {
+                Algorithm::Rs256
+            }
+
+            fn jws_sign(&self, input: &[u8]) -> Vec<u8> {
+                let mut signer = Signer::new(MessageDigest::sha256(), &self.0).unwrap();
+                signer.sign_oneshot_to_vec(input).unwrap()
+            }
+        }
+
+        let rsa = Rsa::generate(2048).unwrap();
+        let jwk = serde_json::json!({
+            "kty": "RSA",
+            "n": base64url::encode(rsa.n().to_vec()),
+            "e": base64url::encode(rsa.e().to_vec()),
+        });
+        let jwk = PublicJwk::from_jwk(jwk.to_string()).unwrap();
+        sign(&RsaSigner(PKey::from_rsa(rsa).unwrap()))
+            .verify(&jwk)
+            .unwrap();
+    }
+
+    #[test]
+    fn unsupported_jwk() {
+        PublicJwk::from_jwk(r#"{"kty":"EC","crv":"P-521","x":"","y":""}"#).unwrap_err();
+        PublicJwk::from_jwk("{}").unwrap_err();
+    }
+}
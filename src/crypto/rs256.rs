@@ -0,0 +1,215 @@
+use rand::{CryptoRng, RngCore};
+use rsa::{
+    pkcs1v15::{Signature, SigningKey, VerifyingKey},
+    pkcs8::{DecodePrivateKey, EncodePrivateKey},
+    sha2::Sha256,
+    signature::{SignatureEncoding, Signer, Verifier},
+    traits::{PrivateKeyParts, PublicKeyParts},
+    BigUint, RsaPrivateKey,
+};
+use serde::Deserialize;
+use serde_json::json;
+use zeroize::Zeroizing;
+
+use super::{
+    account_key::{AccountKey, GenerateAccountKey},
+    jws::{Algorithm, JwsSigner, JwsVerifier},
+};
+use crate::base64url;
+
+/// The modulus size of generated keys, and the smallest accepted.
+pub const BITS: usize = 2048;
+
+pub struct Rs256AccountKey(RsaPrivateKey);
+
+#[derive(Deserialize)]
+struct RsaJwk<'a> {
+    kty: &'a str,
+    n: &'a str,
+    e: &'a str,
+    d: &'a str,
+    p: Option<&'a str>,
+    q: Option<&'a str>,
+}
+
+pub fn from_jwk(jwk: impl AsRef<str>) -> anyhow::Result<Rs256AccountKey> {
+    let jwk: RsaJwk = serde_json::from_str(jwk.as_ref())?;
+    anyhow::ensure!(jwk.kty == "RSA", "invalid JWK for RSA private key");
+    let uint = |value: &str| -> anyhow::Result<BigUint> {
+        Ok(BigUint::from_bytes_be(&Zeroizing::new(base64url::decode(
+            value,
+        )?)))
+    };
+    let primes = match (jwk.p, jwk.q) {
+        (Some(p), Some(q)) => vec![uint(p)?, uint(q)?],
+        _ => Vec::new(),
+    };
+    let key = RsaPrivateKey::from_components(uint(jwk.n)?, uint(jwk.e)?, uint(jwk.d)?, primes)?;
+    Rs256AccountKey::try_from(key)
+}
+
+pub fn from_pkcs8_der(der: impl AsRef<[u8]>) -> anyhow::Result<Rs256AccountKey> {
+    Rs256AccountKey::try_from(RsaPrivateKey::from_pkcs8_der(der.as_ref())?)
+}
+
+impl GenerateAccountKey for Rs256AccountKey {
+    fn generate_rng(mut rng: impl CryptoRng + RngCore) -> Self {
+        Self(RsaPrivateKey::new(&mut rng, BITS).expect("RSA key generation failed"))
+    }
+}
+
+impl std::fmt::Debug for Rs256AccountKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Rs256AccountKey")
+            .field("bits", &(self.0.size() * 8))
+            .field("secret", &"<redacted>")
+            .finish()
+    }
+}
+
+impl JwsSigner for Rs256AccountKey {
+    fn jws_alg(&self) -> Algorithm {
+        Algorithm::Rs256
+    }
+
+    fn jws_sign(&self, input: &[u8]) -> Vec<u8> {
+        SigningKey::<Sha256>::new(self.0.clone())
+            .sign(input)
+            .to_vec()
+    }
+}
+
+impl JwsVerifier for Rs256AccountKey {
+    fn jws_alg(&self) -> Algorithm {
+        Algorithm::Rs256
+    }
+
+    fn jws_verify(&self, input: &[u8], signature: &[u8]) -> bool {
+        let verifying_key = VerifyingKey::<Sha256>::new(self.0.to_public_key());
+        Signature::try_from(signature)
+            .is_ok_and(|signature| verifying_key.verify(input, &signature).is_ok())
+    }
+}
+
+impl AccountKey for Rs256AccountKey {
+    fn private_jwk(&self) -> anyhow::Result<Zeroizing<String>> {
+        let uint = |value: &BigUint| base64url::encode(Zeroizing::new(value.to_bytes_be()));
+        let [p, q] = self.0.primes() else {
+            anyhow::bail!("RSA keys with more than two primes aren't supported");
+        };
+        let (dp, dq, qi) = match (self.0.dp(), self.0.dq(), self.0.crt_coefficient()) {
+            (Some(dp), Some(dq), Some(qi)) => (dp, dq, qi),
+            _ => anyhow::bail!("RSA key is missing its CRT parameters"),
+        };
+        let jwk = json!({
+            "kty": "RSA",
+            "n": uint(self.0.n()),
+            "e": uint(self.0.e()),
+            "d": uint(self.0.d()),
+            "p": uint(p),
+            "q": uint(q),
+            "dp": uint(dp),
+            "dq": uint(dq),
+            "qi": uint(&qi),
+        });
+        Ok(Zeroizing::new(jwk.to_string()))
+    }
+
+    fn public_jwk(&self) -> anyhow::Result<String> {
+        Ok(json!({
+            "kty": "RSA",
+            "n": base64url::encode(self.0.n().to_bytes_be()),
+            "e": base64url::encode(self.0.e().to_bytes_be()),
+        })
+        .to_string())
+    }
+
+    fn to_pkcs8_der(&self) -> anyhow::Result<Zeroizing<Vec<u8>>> {
+        Ok(Zeroizing::new(self.0.to_pkcs8_der()?.as_bytes().to_vec()))
+    }
+}
+
+impl TryFrom<RsaPrivateKey> for Rs256AccountKey {
+    type Error = anyhow::Error;
+
+    fn try_from(key: RsaPrivateKey) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            key.size() * 8 >= BITS,
+            "RSA account keys must have at least {} bits",
+            BITS
+        );
+        Ok(Self(key))
+    }
+}
+
+impl From<Rs256AccountKey> for RsaPrivateKey {
+    fn from(key: Rs256AccountKey) -> Self {
+        key.0
+    }
+}
+
+#[cfg(test)]
+#[allow(
+    clippy::redundant_static_lifetimes,
+    clippy::declare_interior_mutable_const,
+    clippy::borrow_interior_mutable_const
+)]
+pub mod tests {
+    use once_cell::sync::Lazy;
+
+    use super::*;
+
+    pub const JWK: &'static str = r#"{
+        "d":"YdMOOL8OqIGNRQ4aAQw67_VVs-hu-uMfp3I62M1SZh9gNeVVURrlO3S6g7B6XybdyvtZbFHDX5GUl26qenHYDbj2UzJvaBS3nI0z2O7QzBvDNR4Yr4K0Mr8D8BiaJcPwONyA3RLqXveT_PE10khCc_b0Qes2jpXZwsNpSe8BWkSxkGHVFT-xTOmaQRiFWwQRC4sTFGzfI18vNlEAzFfkUBaV_0uHjjgVv-K07QDaXDtf1oHEzHN-H4E8bzxcAqpaNIyJ3Fh6Er-7IBl-VzuAtBasn-kcGjg0llXNdDUAJ-XF9z4SpeIwuTWSdCSyrt72RsKVi8Ierf2aJ0VfoD3PfQ",
+        "dp":"XO-FCmC-rYmeNw7c63upXkM52REAIdqkoOng35RnCB_5McDjLDrnVZfL1_ZRRJ8UO_-wqq8VChbWZOPHSw1XQLWIj2JBMY34sPKQ9ujhsIlIm--plLzixDDhGpoawhQseieLhe5_Us5-K1QUcvx7RQaqwO7pEGThHm5r7H0iHo8",
+        "dq":"vKyBqXd3HmJ1o1VxY2lx0N36LhGtW6TgjHYJ3TMX5DSk-i-NHJkFsJCJ63Wo7jpHTwZuw7E645mtiaTR0DbpAxUhfHQb9PQ8SAtn5WqiaaIk4Ohs5ygoJFUkYwDDAqWJFODcxFQ0SYtOdeeNj-vVsqfUTC21q_7tF9SG1HZeHok",
+        "e":"AQAB",
+        "kty":"RSA",
+        "n":"thZoviDCGZSX1a3RW8Rv2z2PWq0NmU6YQZFeXI8zgpNtMiTUGCt_LWRJcFW5VslsqXpKq15SnwzGrTODcY0unEIi25I6k-8ZqwnelYXgZJqS3QeCZnEtHJrrCBit2WC1sLWNNFdkTP-FEWSzJodnSMjLPd0tDlzo4DaIKf-pDLlCqtKJS4GHtD7egWlVoboPa7-KjaB2WwSoadyErUpDMZNBs3eQDfTHL9fsB9_cOfF5JdUWKe9PIdf37PlAnm1emuoLzzELNd_H83HbURUej-Nje4nwOKoBls9jBFjhrH56JMU0ZKBlbdov_Uf01TFdtzRagF5qwdaPHXrPB6bxDQ",
+        "p":"1zJxkVwh9Rgsy_35Rg8BGABvZozzHl76BGslFqtRQXxVzFX4ys6jOuuP8tmdfZe4GihK125WUVlzPVXm3MsryYUpn84Tsr-TKTq1zJ-FzA3wi0uMX2R-h-gYx07W2yZv6E1nbLGVFQKDqDYyC-S9oe6BSQj_7i5OTiBZGexlX5M",
+        "q":"2JzYuTzeLSPpteRcK_ZD0VvpmFU5Hz2sYHd_pZORGefTAUs_rqiYl4AcHA3L42J0CCt5E4j0M0C1jWI6ibLxGiDGsZSDtYUy3_FKSXOTq7WoAezeJfHTUURd5ADQ9nD1FhdKoSC0YRTKbFbo1s00m1YL0k1AWlvZkfj_e_XSkN8",
+        "qi":"tlqYOGPrrwUxh8SE7ddoF-w-siakAlm7AgfuaPVTXbsYMCvzY95ipWPvYUd3S4OqxpEuzJCvAlmVbqELwJZVooqQfMj0Os_IF54Nq7igOfbE-VlkrIaBMcPt0Liu_qLP65A8IbX7xVZ_NWV5eiXGvlTGfLPBKSSCmkr-Xa-chZw"
+    }"#;
+
+    const KEY: Lazy<Rs256AccountKey> = Lazy::new(|| from_jwk(JWK).unwrap());
+
+    #[test]
+    fn round_trip_jwk() {
+        let jwk = KEY.private_jwk().unwrap();
+        let expect: String = JWK.split_whitespace().collect();
+        assert_eq!(*jwk, expect);
+    }
+
+    #[test]
+    fn jwk_without_primes() {
+        let mut jwk: serde_json::Value = serde_json::from_str(JWK).unwrap();
+        for member in ["p", "q", "dp", "dq", "qi"] {
+            jwk.as_object_mut().unwrap().remove(member);
+        }
+        let key = from_jwk(jwk.to_string()).unwrap();
+        // PKCS#1 v1.5 signatures are deterministic
+        assert_eq!(key.jws_sign(b"test"), KEY.jws_sign(b"test"));
+    }
+
+    #[test]
+    fn round_trip_pkcs8() {
+        let der = KEY.to_pkcs8_der().unwrap();
+        let key = from_pkcs8_der(&*der).unwrap();
+        assert_eq!(key.private_jwk().unwrap(), KEY.private_jwk().unwrap());
+    }
+
+    #[test]
+    fn rejects_short_keys() {
+        let key = RsaPrivateKey::new(&mut rand::rngs::OsRng, 1024).unwrap();
+        let err = Rs256AccountKey::try_from(key).unwrap_err();
+        assert!(err.to_string().contains("2048 bits"), "{}", err);
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let signature = KEY.jws_sign(b"test");
+        assert_eq!(signature.len(), BITS / 8);
+        assert!(KEY.jws_verify(b"test", &signature));
+        assert!(!KEY.jws_verify(b"tested", &signature));
+    }
+}
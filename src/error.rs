@@ -1,19 +1,47 @@
+use chrono::{DateTime, FixedOffset};
 use thiserror::Error;
 
-use super::wire::problem::AcmeProblem;
+use super::api::order::SavedOrder;
+use super::wire::{
+    account::AccountStatus,
+    challenge::ChallengeType,
+    identifier::AcmeIdentifier,
+    problem::{AcmeProblem, AcmeProblemType},
+};
 
 pub type AcmeResult<T> = Result<T, AcmeError>;
 
 #[derive(Error, Debug)]
 pub enum AcmeError {
     #[error("{0}")]
-    AcmeProblem(AcmeProblem),
+    AcmeProblem(Box<AcmeProblem>),
+
+    #[error("rate limited: {}", .0.problem)]
+    RateLimited(Box<RateLimitedError>),
+
+    /// The server's terms of service changed and must be agreed to before the
+    /// account can be used again; see `Account::agree_to_terms`.
+    #[error("terms of service changed: {terms_url}")]
+    TermsOfServiceChanged {
+        terms_url: String,
+        instance_url: Option<String>,
+        problem: Box<AcmeProblem>,
+    },
+
+    /// No account exists for the key; see `Client::account_exists`.
+    #[error("no account exists for this key")]
+    AccountDoesNotExist(Box<AcmeProblem>),
+
+    /// The account was deactivated, or revoked by the server, so every
+    /// request with it is refused; a new account must be registered.
+    #[error("account is no longer usable: {0:?}")]
+    AccountUnusable(AccountStatus),
 
     #[error(transparent)]
     CryptoError(anyhow::Error),
 
     #[error("http: [{}] {0}", .0.status())]
-    HttpError(http_client::Error),
+    HttpError(crate::transport::Error),
 
     #[error("json: {0}")]
     JsonError(#[from] serde_json::Error),
@@ -24,15 +52,290 @@ pub enum AcmeError {
     #[error("missing expected header {0}")]
     MissingExpectedHeader(&'static str),
 
+    /// A response broke requirements of RFC 8555 that only a strict client
+    /// (`Client::set_strict`) enforces.
+    #[error("response breaks RFC 8555: {}", .violations.join("; "))]
+    NonConformant { violations: Vec<String> },
+
+    /// The directory says the server only registers accounts bound to an
+    /// account with the CA (RFC 8555 section 7.3.4), and no binding was
+    /// given. `website` is the CA's, from the directory, if it names one.
+    #[error(
+        "the server requires an external account binding{}",
+        .website.as_deref().map(|website| format!("; see {}", website)).unwrap_or_default()
+    )]
+    ExternalAccountRequired { website: Option<String> },
+
+    /// An operation took longer than allowed by the client's `Timeouts`.
+    #[error("{operation} timed out")]
+    Timeout { operation: AcmeOp },
+
+    /// The operation was stopped with a `CancellationToken`. An unfinished
+    /// order from `issuance::issue` is included, for `Account::resume_order`.
+    #[error("cancelled")]
+    Cancelled { order: Option<Box<SavedOrder>> },
+
+    /// The order expired before it was finalized; a new order is needed.
+    #[error("order {url} expired at {expires}")]
+    OrderExpired {
+        url: String,
+        expires: DateTime<FixedOffset>,
+    },
+
+    /// A response body exceeded the client's `BodyLimits`; it was not
+    /// read any further.
+    #[error("response body larger than {limit} bytes")]
+    ResponseTooLarge { limit: usize },
+
+    #[error("unexpected content type {0:?}")]
+    UnexpectedContentType(String),
+
     #[error("account key missing key id")]
     NoKeyId,
 
     #[error("{0}")]
     InvalidState(String),
+
+    #[error("invalid identifier {0:?}")]
+    InvalidIdentifier(String),
+
+    #[error("invalid contact {0:?}")]
+    InvalidContact(String),
+
+    /// A challenge token that isn't plain base64url of a sensible length,
+    /// and so isn't safe to use in file paths, URLs or DNS names.
+    #[error("invalid challenge token: {0}")]
+    InvalidToken(String),
+
+    /// A downloaded certificate failed verification, or a server's chain
+    /// matched no pin; see `x509::verify_certificate` and
+    /// `transport::SpkiPins`.
+    #[error("invalid certificate: {0}")]
+    InvalidCertificate(String),
+
+    /// None of the challenge types the client can solve is offered for
+    /// `identifier`; `offered` are the types the server offered.
+    #[error(
+        "no supported challenge for {identifier:?}; the server offered {}",
+        display_challenge_types(.offered)
+    )]
+    NoSupportedChallenge {
+        identifier: String,
+        offered: Vec<ChallengeType>,
+    },
+
+    /// Persisted data of another kind, or written by a newer version of
+    /// this crate; see `api::schema`.
+    #[error("unsupported schema: {kind} version {version}")]
+    UnsupportedSchema { kind: String, version: u32 },
+
+    #[error("{} authorization(s) failed: {}", .0.len(), display_failures(.0))]
+    AuthorizationsFailed(Vec<AuthorizationFailure>),
+
+    #[error("challenge solver: {0}")]
+    SolverError(anyhow::Error),
+
+    #[error("certificate store: {0}")]
+    StoreError(anyhow::Error),
+
+    /// A request failed; `source` is the underlying error, which
+    /// `without_context` and `problem` see through.
+    #[error("{operation} at {url} failed: {source}")]
+    Contextual {
+        operation: AcmeOp,
+        url: String,
+        source: Box<AcmeError>,
+    },
 }
 
-impl From<http_client::Error> for AcmeError {
-    fn from(err: http_client::Error) -> Self {
+impl From<crate::transport::Error> for AcmeError {
+    fn from(err: crate::transport::Error) -> Self {
         AcmeError::HttpError(err)
     }
-}
\ No newline at end of file
+}
+
+impl AcmeError {
+    /// Wraps a problem document, distinguishing rateLimited problems, terms
+    /// of service changes and unknown accounts.
+    pub fn from_problem(problem: AcmeProblem) -> Self {
+        if problem.has_type(AcmeProblemType::AccountDoesNotExist) {
+            AcmeError::AccountDoesNotExist(Box::new(problem))
+        } else if problem.has_type(AcmeProblemType::RateLimited) {
+            AcmeError::RateLimited(Box::new(RateLimitedError {
+                retry_after: problem.retry_after(),
+                limit_name: problem.rate_limit_name().map(str::to_string),
+                problem,
+            }))
+        } else if let (true, Some(terms_url)) = (
+            problem.has_type(AcmeProblemType::UserActionRequired),
+            problem.terms_of_service.clone(),
+        ) {
+            AcmeError::TermsOfServiceChanged {
+                terms_url,
+                instance_url: problem.instance.clone(),
+                problem: Box::new(problem),
+            }
+        } else {
+            AcmeError::AcmeProblem(Box::new(problem))
+        }
+    }
+
+    /// Adds the operation and URL of the failed request, unless the error
+    /// already has them.
+    pub(crate) fn with_context(self, operation: AcmeOp, url: &str) -> Self {
+        match self {
+            AcmeError::Contextual { .. } => self,
+            source => AcmeError::Contextual {
+                operation,
+                url: url.to_string(),
+                source: Box::new(source),
+            },
+        }
+    }
+
+    /// The operation and URL of the request that failed, if known.
+    pub fn context(&self) -> Option<(AcmeOp, &str)> {
+        match self {
+            AcmeError::Contextual { operation, url, .. } => Some((*operation, url)),
+            _ => None,
+        }
+    }
+
+    /// The underlying error, e.g. for matching on its variant.
+    pub fn without_context(&self) -> &AcmeError {
+        match self {
+            AcmeError::Contextual { source, .. } => source.without_context(),
+            err => err,
+        }
+    }
+
+    pub fn into_without_context(self) -> AcmeError {
+        match self {
+            AcmeError::Contextual { source, .. } => source.into_without_context(),
+            err => err,
+        }
+    }
+
+    /// The problem document behind this error, if any.
+    pub fn problem(&self) -> Option<&AcmeProblem> {
+        match self.without_context() {
+            AcmeError::AcmeProblem(problem) | AcmeError::AccountDoesNotExist(problem) => {
+                Some(problem)
+            }
+            AcmeError::RateLimited(err) => Some(&err.problem),
+            AcmeError::TermsOfServiceChanged { problem, .. } => Some(problem),
+            AcmeError::AuthorizationsFailed(failures) => {
+                failures.iter().find_map(|failure| failure.error.problem())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The kind of request an `AcmeError::Contextual` came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AcmeOp {
+    GetDirectory,
+    NewNonce,
+    NewAccount,
+    GetAccount,
+    UpdateAccount,
+    DeactivateAccount,
+    NewOrder,
+    GetOrder,
+    NewAuthorization,
+    GetAuthorization,
+    DeactivateAuthorization,
+    GetChallenge,
+    RespondChallenge,
+    Finalize,
+    DownloadCertificate,
+    GetDelegation,
+    GetRenewalInfo,
+    /// A POST-as-GET of some other resource.
+    Fetch,
+    /// A POST with a payload to some other resource.
+    Post,
+    /// Waiting for an authorization to be validated.
+    Validation,
+}
+
+impl std::fmt::Display for AcmeOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            AcmeOp::GetDirectory => "directory fetch",
+            AcmeOp::NewNonce => "newNonce",
+            AcmeOp::NewAccount => "newAccount",
+            AcmeOp::GetAccount => "account fetch",
+            AcmeOp::UpdateAccount => "account update",
+            AcmeOp::DeactivateAccount => "account deactivation",
+            AcmeOp::NewOrder => "newOrder",
+            AcmeOp::GetOrder => "order fetch",
+            AcmeOp::NewAuthorization => "newAuthz",
+            AcmeOp::GetAuthorization => "authorization fetch",
+            AcmeOp::DeactivateAuthorization => "authorization deactivation",
+            AcmeOp::GetChallenge => "challenge fetch",
+            AcmeOp::RespondChallenge => "challenge response",
+            AcmeOp::Finalize => "finalize",
+            AcmeOp::DownloadCertificate => "certificate download",
+            AcmeOp::GetDelegation => "delegation fetch",
+            AcmeOp::GetRenewalInfo => "renewalInfo",
+            AcmeOp::Fetch => "fetch",
+            AcmeOp::Post => "POST",
+            AcmeOp::Validation => "validation",
+        })
+    }
+}
+
+/// https://www.rfc-editor.org/rfc/rfc8555.html#section-6.6
+#[derive(Debug, Clone)]
+pub struct RateLimitedError {
+    /// When the request may be retried, from the Retry-After header.
+    pub retry_after: Option<DateTime<FixedOffset>>,
+
+    /// The name of the exceeded limit, if the server identified it.
+    pub limit_name: Option<String>,
+
+    pub problem: AcmeProblem,
+}
+
+/// One authorization that couldn't be completed; see
+/// `Order::solve_all_authorizations`.
+#[derive(Debug)]
+pub struct AuthorizationFailure {
+    pub authorization_url: String,
+
+    /// The identifier being authorized, unless the authorization couldn't be
+    /// fetched.
+    pub identifier: Option<AcmeIdentifier>,
+
+    pub error: Box<AcmeError>,
+}
+
+impl std::fmt::Display for AuthorizationFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.identifier {
+            Some(identifier) => write!(f, "{}: {}", identifier.value, self.error),
+            None => write!(f, "{}: {}", self.authorization_url, self.error),
+        }
+    }
+}
+
+fn display_challenge_types(challenge_types: &[ChallengeType]) -> String {
+    if challenge_types.is_empty() {
+        return "none".to_string();
+    }
+    challenge_types
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn display_failures(failures: &[AuthorizationFailure]) -> String {
+    failures
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
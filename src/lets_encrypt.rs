@@ -0,0 +1,199 @@
+//! Let's Encrypt's environments, certificate profiles and rate limits, for
+//! developing against staging with the same settings as production.
+//!
+//! https://letsencrypt.org/docs/staging-environment/
+
+use std::sync::Arc;
+
+#[cfg(any(feature = "x509", feature = "x509-rcgen"))]
+use crate::api::scheduler::{Budget, RateLimits};
+use crate::transport::HttpClient;
+use crate::{
+    error::AcmeResult,
+    wire::{directory::DirectoryResource, identifier::AcmeIdentifier},
+    Client, LETS_ENCRYPT_DIRECTORY_URL, LETS_ENCRYPT_STAGING_DIRECTORY_URL,
+};
+
+/// The certificate profiles Let's Encrypt offers; the directory's
+/// `meta.profiles` is the authoritative list.
+/// https://letsencrypt.org/docs/profiles/
+pub mod profiles {
+    /// The default: 90-day certificates, as issued before profiles existed.
+    pub const CLASSIC: &str = "classic";
+
+    /// Like "classic", without the TLS client authentication usage or
+    /// subject common name.
+    pub const TLS_SERVER: &str = "tlsserver";
+
+    /// Certificates valid for about six days, for clients that renew often.
+    pub const SHORT_LIVED: &str = "shortlived";
+}
+
+/// A Let's Encrypt environment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LetsEncrypt {
+    Production,
+    Staging,
+}
+
+impl LetsEncrypt {
+    pub fn directory_url(self) -> &'static str {
+        match self {
+            Self::Production => LETS_ENCRYPT_DIRECTORY_URL,
+            Self::Staging => LETS_ENCRYPT_STAGING_DIRECTORY_URL,
+        }
+    }
+
+    fn host(self) -> &'static str {
+        match self {
+            Self::Production => "acme-v02.api.letsencrypt.org",
+            Self::Staging => "acme-staging-v02.api.letsencrypt.org",
+        }
+    }
+
+    /// The environment to test against before using this one: staging for
+    /// both, since staging behaves like production with higher limits and
+    /// an untrusted root.
+    pub fn staging_equivalent(self) -> Self {
+        Self::Staging
+    }
+
+    /// The environment `url` (a directory or any other ACME URL) belongs to.
+    pub fn from_url(url: &str) -> Option<Self> {
+        let host = url.strip_prefix("https://")?.split(['/', ':']).next()?;
+        [Self::Production, Self::Staging]
+            .into_iter()
+            .find(|environment| host.eq_ignore_ascii_case(environment.host()))
+    }
+
+    /// The environment a directory was fetched from, going by its newOrder
+    /// URL.
+    pub fn from_directory(directory: &DirectoryResource) -> Option<Self> {
+        Self::from_url(&directory.new_order)
+    }
+
+    /// The environment's documented rate limits, for an `IssuanceScheduler`.
+    /// https://letsencrypt.org/docs/rate-limits/
+    #[cfg(any(feature = "x509", feature = "x509-rcgen"))]
+    pub fn rate_limits(self) -> RateLimits {
+        const HOUR: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+        const WEEK: std::time::Duration = std::time::Duration::from_secs(7 * 24 * 60 * 60);
+        match self {
+            Self::Production => RateLimits::default(),
+            Self::Staging => RateLimits {
+                new_orders_per_account: Some(Budget::new(1500, 3 * HOUR)),
+                certificates_per_registered_domain: Some(Budget::new(30_000, WEEK)),
+                duplicate_certificates: Some(Budget::new(30_000, WEEK)),
+            },
+        }
+    }
+
+    pub async fn client(self, http: impl Into<Arc<dyn HttpClient>>) -> AcmeResult<Client> {
+        Client::for_directory_url(http, self.directory_url()).await
+    }
+}
+
+/// Whether `name` is reserved for testing or documentation (RFC 2606 and
+/// RFC 6761), e.g. "example.com" or "www.acme.test", or is mDNS-only
+/// (".local"). No public CA can validate such a name.
+pub fn is_test_domain(name: &str) -> bool {
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    let name = name.strip_prefix("*.").unwrap_or(&name);
+    let under = |domain: &str| {
+        name == domain
+            || name
+                .strip_suffix(domain)
+                .is_some_and(|prefix| prefix.ends_with('.'))
+    };
+    ["example.com", "example.net", "example.org"]
+        .into_iter()
+        .chain(["test", "example", "invalid", "localhost", "local"])
+        .any(under)
+}
+
+/// Logs a warning, and returns true, if `identifiers` are to be ordered from
+/// Let's Encrypt's production environment (per `directory`) and include a
+/// test domain. Such orders fail validation, and count against production
+/// rate limits while doing so; `Account::new_order` checks every order.
+pub fn warn_on_test_domains(directory: &DirectoryResource, identifiers: &[AcmeIdentifier]) -> bool {
+    if LetsEncrypt::from_directory(directory) != Some(LetsEncrypt::Production) {
+        return false;
+    }
+    let test_domains: Vec<&str> = identifiers
+        .iter()
+        .filter(|identifier| identifier.type_ == "dns" && is_test_domain(&identifier.value))
+        .map(|identifier| identifier.value.as_str())
+        .collect();
+    if test_domains.is_empty() {
+        return false;
+    }
+    log::warn!(
+        "ordering test domains ({}) from Let's Encrypt production; use {} instead",
+        test_domains.join(", "),
+        LetsEncrypt::Production.staging_equivalent().directory_url()
+    );
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directory(base: &str) -> DirectoryResource {
+        serde_json::from_value(serde_json::json!({
+            "newNonce": format!("{}/acme/new-nonce", base),
+            "newAccount": format!("{}/acme/new-acct", base),
+            "newOrder": format!("{}/acme/new-order", base),
+            "revokeCert": format!("{}/acme/revoke-cert", base),
+            "keyChange": format!("{}/acme/key-change", base),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn environments() {
+        assert_eq!(
+            LetsEncrypt::from_url(LETS_ENCRYPT_DIRECTORY_URL),
+            Some(LetsEncrypt::Production)
+        );
+        assert_eq!(
+            LetsEncrypt::from_url("https://ACME-staging-v02.api.letsencrypt.org:443/acme/acct/1"),
+            Some(LetsEncrypt::Staging)
+        );
+        assert_eq!(LetsEncrypt::from_url("https://acme.test/directory"), None);
+        assert_eq!(
+            LetsEncrypt::Production.staging_equivalent().directory_url(),
+            LETS_ENCRYPT_STAGING_DIRECTORY_URL
+        );
+    }
+
+    #[test]
+    fn test_domains() {
+        for name in [
+            "example.com",
+            "www.Example.ORG.",
+            "*.acme.test",
+            "printer.local",
+        ] {
+            assert!(is_test_domain(name), "{}", name);
+        }
+        for name in [
+            "myexample.com",
+            "example.co.uk",
+            "test.example.io",
+            "letsencrypt.org",
+        ] {
+            assert!(!is_test_domain(name), "{}", name);
+        }
+
+        let identifiers = [
+            AcmeIdentifier::dns("good.org"),
+            AcmeIdentifier::dns("www.example.com"),
+        ];
+        let production = directory("https://acme-v02.api.letsencrypt.org");
+        assert!(warn_on_test_domains(&production, &identifiers));
+        assert!(!warn_on_test_domains(&production, &identifiers[..1]));
+        let staging = directory("https://acme-staging-v02.api.letsencrypt.org");
+        assert!(!warn_on_test_domains(&staging, &identifiers));
+    }
+}
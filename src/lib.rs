@@ -1,10 +1,31 @@
+// getrandom (and so key generation) and chrono's clock need JS bindings on
+// wasm32-unknown-unknown; `cargo check-wasm` checks that build.
+#[cfg(all(target_arch = "wasm32", not(feature = "web")))]
+compile_error!("the \"web\" feature is required on wasm32");
+#[cfg(all(target_arch = "wasm32", feature = "x509"))]
+compile_error!("the \"x509\" feature needs OpenSSL; use \"x509-rcgen\" on wasm32");
+// JS values are kept in futures that must be Send, which is only sound
+// without threads.
+#[cfg(all(target_arch = "wasm32", target_feature = "atomics"))]
+compile_error!("wasm32 with the \"atomics\" target feature isn't supported");
+
 pub mod api;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod cancel;
+pub mod clock;
 pub mod crypto;
 pub mod error;
+pub mod lets_encrypt;
+pub mod sleep;
+pub mod transport;
 pub mod wire;
 
-#[cfg(feature = "x509")]
-mod x509;
+#[cfg(any(feature = "x509", feature = "x509-rcgen"))]
+pub mod x509;
+
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
 
 pub(crate) mod base64url;
 
@@ -15,7 +36,7 @@ pub use error::{AcmeError, AcmeResult};
 
 pub static LETS_ENCRYPT_DIRECTORY_URL: &str = "https://acme-v02.api.letsencrypt.org/directory";
 pub async fn lets_encrypt_client(
-    http: impl Into<Arc<dyn http_client::HttpClient>>,
+    http: impl Into<Arc<dyn transport::HttpClient>>,
 ) -> AcmeResult<Client> {
     Client::for_directory_url(http, LETS_ENCRYPT_DIRECTORY_URL).await
 }
@@ -23,7 +44,7 @@ pub async fn lets_encrypt_client(
 pub static LETS_ENCRYPT_STAGING_DIRECTORY_URL: &str =
     "https://acme-staging-v02.api.letsencrypt.org/directory";
 pub async fn lets_encrypt_staging_client(
-    http: impl Into<Arc<dyn http_client::HttpClient>>,
+    http: impl Into<Arc<dyn transport::HttpClient>>,
 ) -> AcmeResult<Client> {
     Client::for_directory_url(http, LETS_ENCRYPT_STAGING_DIRECTORY_URL).await
 }
@@ -0,0 +1,316 @@
+//! Timers for polling and retries that don't depend on a particular async
+//! runtime.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    collections::BTreeMap,
+    sync::{Condvar, Mutex, OnceLock},
+    task::{Poll, Waker},
+    time::Instant,
+};
+use std::{future::Future, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use futures_util::future::Either;
+
+#[async_trait]
+pub trait Sleeper: Send + Sync {
+    async fn sleep(&self, duration: Duration);
+}
+
+/// Adapts a runtime's sleep function, e.g. `FnSleeper(tokio::time::sleep)`.
+pub struct FnSleeper<F>(pub F);
+
+#[async_trait]
+impl<F, SleepFuture> Sleeper for FnSleeper<F>
+where
+    F: Fn(Duration) -> SleepFuture + Send + Sync,
+    SleepFuture: Future<Output = ()> + Send,
+{
+    async fn sleep(&self, duration: Duration) {
+        (self.0)(duration).await
+    }
+}
+
+#[cfg(feature = "async-std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AsyncStdSleeper;
+
+#[cfg(feature = "async-std")]
+#[async_trait]
+impl Sleeper for AsyncStdSleeper {
+    async fn sleep(&self, duration: Duration) {
+        async_std::task::sleep(duration).await
+    }
+}
+
+/// Sleeps with tokio's timer. Must be used within a tokio runtime with the
+/// time driver enabled.
+#[cfg(feature = "tokio")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioSleeper;
+
+#[cfg(feature = "tokio")]
+#[async_trait]
+impl Sleeper for TokioSleeper {
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+}
+
+/// Sleeps on a timer thread shared by all sleeps, which works under any
+/// executor (but not on wasm). Prefer the sleeper of the runtime in use.
+///
+/// Dropping a sleep cancels its timer, so a `timeout` that finishes early
+/// leaves nothing behind on the timer thread.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ThreadSleeper;
+
+#[cfg(not(target_arch = "wasm32"))]
+#[async_trait]
+impl Sleeper for ThreadSleeper {
+    async fn sleep(&self, duration: Duration) {
+        ThreadSleep {
+            deadline: Instant::now() + duration,
+            key: None,
+        }
+        .await
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+struct ThreadSleep {
+    deadline: Instant,
+    /// The entry in the timer queue, once registered.
+    key: Option<(Instant, u64)>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Future for ThreadSleep {
+    type Output = ();
+
+    fn poll(mut self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            return Poll::Ready(());
+        }
+        let timers = timer_thread();
+        let mut queue = timers.queue.lock().unwrap();
+        let deadline = self.deadline;
+        let key = *self.key.get_or_insert_with(|| {
+            queue.next_id += 1;
+            (deadline, queue.next_id)
+        });
+        let is_next = queue.wakers.keys().next().is_none_or(|next| key < *next);
+        queue.wakers.insert(key, cx.waker().clone());
+        if is_next {
+            timers.changed.notify_one();
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for ThreadSleep {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            // The timer thread only waits longer for a removed entry, and
+            // rechecks the queue when it wakes.
+            timer_thread().queue.lock().unwrap().wakers.remove(&key);
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct Timers {
+    queue: Mutex<TimerQueue>,
+    changed: Condvar,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct TimerQueue {
+    next_id: u64,
+    wakers: BTreeMap<(Instant, u64), Waker>,
+}
+
+/// The timers behind `ThreadSleeper`, started on first use.
+#[cfg(not(target_arch = "wasm32"))]
+fn timer_thread() -> &'static Timers {
+    static TIMERS: OnceLock<&'static Timers> = OnceLock::new();
+    TIMERS.get_or_init(|| {
+        let timers: &'static Timers = Box::leak(Box::default());
+        std::thread::Builder::new()
+            .name("acme-timer".to_string())
+            .spawn(move || {
+                let mut queue = timers.queue.lock().unwrap();
+                loop {
+                    let now = Instant::now();
+                    match queue.wakers.keys().next().copied() {
+                        Some(next) if next.0 <= now => {
+                            queue.wakers.remove(&next).unwrap().wake();
+                        }
+                        Some(next) => {
+                            queue = timers.changed.wait_timeout(queue, next.0 - now).unwrap().0;
+                        }
+                        None => queue = timers.changed.wait(queue).unwrap(),
+                    }
+                }
+            })
+            .expect("failed to start timer thread");
+        timers
+    })
+}
+
+/// Sleeps with the JavaScript `setTimeout`, for browsers and edge runtimes.
+#[cfg(all(feature = "web", target_arch = "wasm32"))]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WebSleeper;
+
+#[cfg(all(feature = "web", target_arch = "wasm32"))]
+#[async_trait]
+impl Sleeper for WebSleeper {
+    async fn sleep(&self, duration: Duration) {
+        let millis = duration.as_millis().try_into().unwrap_or(u32::MAX);
+        AssertSend(gloo_timers::future::TimeoutFuture::new(millis)).await
+    }
+}
+
+/// JS values can't be sent between threads, but wasm32-unknown-unknown only
+/// has the one.
+#[cfg(all(feature = "web", target_arch = "wasm32"))]
+struct AssertSend<F>(F);
+
+// SAFETY: Without the "atomics" target feature, wasm32-unknown-unknown has no
+// threads, so the future can only ever be polled and dropped on the thread
+// that created it. Builds with "atomics" (e.g. wasm-bindgen-rayon) can move
+// futures to web workers, so they don't get this impl.
+#[cfg(all(
+    feature = "web",
+    target_arch = "wasm32",
+    not(target_feature = "atomics")
+))]
+unsafe impl<F> Send for AssertSend<F> {}
+
+#[cfg(all(feature = "web", target_arch = "wasm32"))]
+impl<F: Future + Unpin> Future for AssertSend<F> {
+    type Output = F::Output;
+
+    fn poll(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Self::Output> {
+        std::pin::Pin::new(&mut self.0).poll(cx)
+    }
+}
+
+/// Runs `future` until it completes or `duration` passes on `sleeper`,
+/// whichever is first; None on timeout.
+pub async fn timeout<T>(
+    sleeper: &dyn Sleeper,
+    duration: Duration,
+    future: impl Future<Output = T>,
+) -> Option<T> {
+    let future = std::pin::pin!(future);
+    match futures_util::future::select(future, sleeper.sleep(duration)).await {
+        Either::Left((output, _)) => Some(output),
+        Either::Right(_) => None,
+    }
+}
+
+/// Returns immediately, e.g. for tests against a mock server.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoSleep;
+
+#[async_trait]
+impl Sleeper for NoSleep {
+    async fn sleep(&self, _duration: Duration) {}
+}
+
+/// `WebSleeper` on wasm, else `AsyncStdSleeper` with the "async-std"
+/// feature, else `TokioSleeper` with the "tokio" feature, else
+/// `ThreadSleeper`.
+pub fn default_sleeper() -> Arc<dyn Sleeper> {
+    #[cfg(target_arch = "wasm32")]
+    return Arc::new(WebSleeper);
+    #[cfg(all(feature = "async-std", not(target_arch = "wasm32")))]
+    return Arc::new(AsyncStdSleeper);
+    #[cfg(all(
+        feature = "tokio",
+        not(feature = "async-std"),
+        not(target_arch = "wasm32")
+    ))]
+    return Arc::new(TokioSleeper);
+    #[cfg(all(
+        not(feature = "async-std"),
+        not(feature = "tokio"),
+        not(target_arch = "wasm32")
+    ))]
+    {
+        log::debug!("no async runtime feature enabled, timing with a timer thread");
+        Arc::new(ThreadSleeper)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    #[async_std::test]
+    async fn thread_sleeper() {
+        let start = Instant::now();
+        ThreadSleeper.sleep(Duration::from_millis(20)).await;
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[async_std::test]
+    async fn thread_sleeper_concurrent_sleeps() {
+        let start = Instant::now();
+        let sleeps = (1..=50).map(|i| ThreadSleeper.sleep(Duration::from_millis(60 - i)));
+        futures_util::future::join_all(sleeps).await;
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(59), "{:?}", elapsed);
+    }
+
+    #[async_std::test]
+    async fn thread_sleeper_timeout_cancels_timer() {
+        let pending = || {
+            timer_thread()
+                .queue
+                .lock()
+                .unwrap()
+                .wakers
+                .keys()
+                .filter(|(deadline, _)| *deadline > Instant::now() + Duration::from_secs(3000))
+                .count()
+        };
+        let before = pending();
+        let output = timeout(&ThreadSleeper, Duration::from_secs(3600), async {
+            ThreadSleeper.sleep(Duration::from_millis(10)).await;
+            1
+        })
+        .await;
+        assert_eq!(output, Some(1));
+        assert_eq!(pending(), before);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(start_paused = true)]
+    async fn tokio_sleeper() {
+        let start = tokio::time::Instant::now();
+        TokioSleeper.sleep(Duration::from_secs(3600)).await;
+        assert!(start.elapsed() >= Duration::from_secs(3600));
+    }
+
+    #[async_std::test]
+    async fn fn_sleeper() {
+        let start = Instant::now();
+        FnSleeper(async_std::task::sleep)
+            .sleep(Duration::from_millis(20))
+            .await;
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+}
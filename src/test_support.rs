@@ -0,0 +1,261 @@
+//! Helpers for testing code built on this crate against
+//! [Pebble](https://github.com/letsencrypt/pebble), Let's Encrypt's ACME test
+//! server, and its companion `pebble-challtestsrv` mock DNS/HTTP server.
+//!
+//! Pebble serves its API over TLS with a throwaway CA, so the `HttpClient`
+//! given to `PebbleEnv` must be configured to trust Pebble's minica root.
+
+use std::{
+    path::Path,
+    process::{Child, Command, Stdio},
+    sync::Arc,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use serde_json::json;
+
+use crate::transport::{Body, HttpClient, Request};
+use crate::{
+    api::solver::{ChallengeParams, ChallengeSolver},
+    error::AcmeResult,
+    sleep::default_sleeper,
+    wire::challenge::ChallengeType,
+    Client,
+};
+
+pub mod fixtures;
+pub mod fuzz;
+#[cfg(all(test, feature = "reqwest"))]
+pub(crate) mod https_server;
+pub mod mock_server;
+#[cfg(all(test, feature = "reqwest-rustls"))]
+pub(crate) mod proxy;
+
+/// Pebble's default directory URL.
+pub static PEBBLE_DIRECTORY_URL: &str = "https://localhost:14000/dir";
+
+/// pebble-challtestsrv's default management API URL.
+pub static CHALLTESTSRV_URL: &str = "http://localhost:8055";
+
+/// A Pebble instance and its challenge test server.
+pub struct PebbleEnv {
+    http: Arc<dyn HttpClient>,
+    directory_url: String,
+    challtestsrv: ChallTestSrv,
+    _processes: Option<PebbleProcesses>,
+}
+
+impl PebbleEnv {
+    /// Talks to an already-running Pebble.
+    pub fn new(
+        http: impl Into<Arc<dyn HttpClient>>,
+        directory_url: impl Into<String>,
+        challtestsrv_url: impl Into<String>,
+    ) -> Self {
+        let http = http.into();
+        Self {
+            challtestsrv: ChallTestSrv::new(http.clone(), challtestsrv_url),
+            http,
+            directory_url: directory_url.into(),
+            _processes: None,
+        }
+    }
+
+    /// Talks to an already-running Pebble at the URLs given by the
+    /// `PEBBLE_DIRECTORY_URL` and `PEBBLE_CHALLTESTSRV_URL` environment
+    /// variables, falling back to the default local ports.
+    pub fn from_env(http: impl Into<Arc<dyn HttpClient>>) -> Self {
+        let directory_url =
+            std::env::var("PEBBLE_DIRECTORY_URL").unwrap_or_else(|_| PEBBLE_DIRECTORY_URL.into());
+        let challtestsrv_url =
+            std::env::var("PEBBLE_CHALLTESTSRV_URL").unwrap_or_else(|_| CHALLTESTSRV_URL.into());
+        Self::new(http, directory_url, challtestsrv_url)
+    }
+
+    /// Starts `pebble` and `pebble-challtestsrv` (which must be on the PATH)
+    /// with their default ports and waits for the directory to respond. The
+    /// processes are killed when the returned value is dropped.
+    ///
+    /// `pebble_config` is a Pebble JSON config file, e.g. Pebble's own
+    /// `test/config/pebble-config.json`.
+    pub async fn spawn(
+        http: impl Into<Arc<dyn HttpClient>>,
+        pebble_config: impl AsRef<Path>,
+    ) -> anyhow::Result<Self> {
+        let mut env = Self::new(http, PEBBLE_DIRECTORY_URL, CHALLTESTSRV_URL);
+        env._processes = Some(PebbleProcesses::spawn(pebble_config.as_ref())?);
+
+        let mut attempts = 50;
+        loop {
+            match env.client().await {
+                Ok(_) => return Ok(env),
+                Err(err) if attempts == 0 => return Err(err.into()),
+                Err(_) => attempts -= 1,
+            }
+            default_sleeper().sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    pub fn directory_url(&self) -> &str {
+        &self.directory_url
+    }
+
+    /// Fetches the directory and returns a new client for it.
+    pub async fn client(&self) -> AcmeResult<Client> {
+        Client::for_directory_url(self.http.clone(), &self.directory_url).await
+    }
+
+    /// The challenge test server, which is also a `ChallengeSolver`.
+    pub fn challtestsrv(&self) -> &ChallTestSrv {
+        &self.challtestsrv
+    }
+}
+
+/// Client for pebble-challtestsrv's management API.
+/// https://github.com/letsencrypt/pebble/tree/main/cmd/pebble-challtestsrv
+///
+/// As a `ChallengeSolver` it presents http-01 and dns-01 responses.
+#[derive(Clone)]
+pub struct ChallTestSrv {
+    http: Arc<dyn HttpClient>,
+    url: String,
+}
+
+impl ChallTestSrv {
+    pub fn new(http: impl Into<Arc<dyn HttpClient>>, url: impl Into<String>) -> Self {
+        Self {
+            http: http.into(),
+            url: url.into(),
+        }
+    }
+
+    /// Sets the IPv4 address returned for all A queries.
+    pub async fn set_default_ipv4(&self, ip: &str) -> anyhow::Result<()> {
+        self.post("set-default-ipv4", json!({ "ip": ip })).await
+    }
+
+    /// Adds a TXT record; `host` is a fully-qualified name with a trailing
+    /// dot.
+    pub async fn set_txt(&self, host: &str, value: &str) -> anyhow::Result<()> {
+        self.post("set-txt", json!({ "host": host, "value": value }))
+            .await
+    }
+
+    pub async fn clear_txt(&self, host: &str) -> anyhow::Result<()> {
+        self.post("clear-txt", json!({ "host": host })).await
+    }
+
+    pub async fn add_http01(&self, token: &str, content: &str) -> anyhow::Result<()> {
+        self.post("add-http01", json!({ "token": token, "content": content }))
+            .await
+    }
+
+    pub async fn del_http01(&self, token: &str) -> anyhow::Result<()> {
+        self.post("del-http01", json!({ "token": token })).await
+    }
+
+    async fn post(&self, path: &str, body: serde_json::Value) -> anyhow::Result<()> {
+        let mut req =
+            Request::post(format!("{}/{}", self.url.trim_end_matches('/'), path).as_str());
+        req.set_body(Body::from_json(&body).map_err(|err| err.into_inner())?);
+        let resp = self.http.send(req).await.map_err(|err| err.into_inner())?;
+        if !resp.status().is_success() {
+            anyhow::bail!("challtestsrv {} returned {}", path, resp.status());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ChallengeSolver for ChallTestSrv {
+    fn supported_types(&self) -> Vec<ChallengeType> {
+        vec![ChallengeType::Http01, ChallengeType::Dns01]
+    }
+
+    async fn present(&self, params: &ChallengeParams) -> anyhow::Result<()> {
+        match &params.challenge_type {
+            ChallengeType::Http01 => {
+                self.add_http01(&params.token, &params.key_authorization)
+                    .await
+            }
+            ChallengeType::Dns01 => {
+                self.set_txt(&dns01_host(params), &params.dns01_txt_value())
+                    .await
+            }
+            other => anyhow::bail!("unsupported challenge type {:?}", other.as_str()),
+        }
+    }
+
+    async fn cleanup(&self, params: &ChallengeParams) -> anyhow::Result<()> {
+        match &params.challenge_type {
+            ChallengeType::Http01 => self.del_http01(&params.token).await,
+            ChallengeType::Dns01 => self.clear_txt(&dns01_host(params)).await,
+            other => anyhow::bail!("unsupported challenge type {:?}", other.as_str()),
+        }
+    }
+}
+
+fn dns01_host(params: &ChallengeParams) -> String {
+    format!("{}.", params.dns01_txt_name())
+}
+
+/// Child processes started by `PebbleEnv::spawn`, killed on drop.
+struct PebbleProcesses {
+    pebble: Child,
+    challtestsrv: Child,
+}
+
+impl PebbleProcesses {
+    fn spawn(pebble_config: &Path) -> std::io::Result<Self> {
+        let challtestsrv = Command::new("pebble-challtestsrv")
+            .args(["-defaultIPv4", "127.0.0.1", "-defaultIPv6", ""])
+            .stdout(Stdio::null())
+            .spawn()?;
+        let pebble = Command::new("pebble")
+            .arg("-config")
+            .arg(pebble_config)
+            .args(["-dnsserver", "127.0.0.1:8053"])
+            .env("PEBBLE_VA_NOSLEEP", "1")
+            .stdout(Stdio::null())
+            .spawn();
+        Ok(Self {
+            pebble: match pebble {
+                Ok(pebble) => pebble,
+                Err(err) => {
+                    let mut challtestsrv = challtestsrv;
+                    let _ = challtestsrv.kill();
+                    let _ = challtestsrv.wait();
+                    return Err(err);
+                }
+            },
+            challtestsrv,
+        })
+    }
+}
+
+impl Drop for PebbleProcesses {
+    fn drop(&mut self) {
+        for child in [&mut self.pebble, &mut self.challtestsrv] {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dns01_host_is_fully_qualified() {
+        let params = ChallengeParams {
+            domain: "example.org".to_string(),
+            wildcard: true,
+            challenge_type: ChallengeType::Dns01,
+            token: "DGyRejmCefe7v4NfDGDKfA".to_string(),
+            key_authorization: String::new(),
+        };
+        assert_eq!(dns01_host(&params), "_acme-challenge.example.org.");
+    }
+}
@@ -0,0 +1,65 @@
+//! Canned directory documents for tests that don't talk to a server.
+
+use crate::wire::directory::DirectoryResource;
+
+/// The directory served by Pebble with its default config.
+pub const PEBBLE_DIRECTORY: &str = r#"{
+  "keyChange": "https://localhost:14000/rollover-account-key",
+  "meta": {
+    "externalAccountRequired": false,
+    "profiles": {
+      "default": "The profile you know and love",
+      "shortlived": "A short-lived cert profile, without actual enforcement"
+    },
+    "termsOfService": "data:text/plain,Do%20what%20thou%20wilt"
+  },
+  "newAccount": "https://localhost:14000/sign-me-up",
+  "newNonce": "https://localhost:14000/nonce-plz",
+  "newOrder": "https://localhost:14000/order-plz",
+  "renewalInfo": "https://localhost:14000/draft-ietf-acme-ari-03/renewalInfo",
+  "revokeCert": "https://localhost:14000/revoke-cert"
+}"#;
+
+/// The Let's Encrypt production directory.
+pub const LETS_ENCRYPT_DIRECTORY: &str = r#"{
+  "keyChange": "https://acme-v02.api.letsencrypt.org/acme/key-change",
+  "meta": {
+    "caaIdentities": ["letsencrypt.org"],
+    "profiles": {
+      "classic": "https://letsencrypt.org/docs/profiles#classic",
+      "shortlived": "https://letsencrypt.org/docs/profiles#shortlived",
+      "tlsserver": "https://letsencrypt.org/docs/profiles#tlsserver"
+    },
+    "termsOfService": "https://letsencrypt.org/documents/LE-SA-v1.5-February-24-2025.pdf",
+    "website": "https://letsencrypt.org"
+  },
+  "newAccount": "https://acme-v02.api.letsencrypt.org/acme/new-acct",
+  "newNonce": "https://acme-v02.api.letsencrypt.org/acme/new-nonce",
+  "newOrder": "https://acme-v02.api.letsencrypt.org/acme/new-order",
+  "renewalInfo": "https://acme-v02.api.letsencrypt.org/draft-ietf-acme-ari-03/renewalInfo",
+  "revokeCert": "https://acme-v02.api.letsencrypt.org/acme/revoke-cert"
+}"#;
+
+pub fn pebble_directory() -> DirectoryResource {
+    serde_json::from_str(PEBBLE_DIRECTORY).unwrap()
+}
+
+pub fn lets_encrypt_directory() -> DirectoryResource {
+    serde_json::from_str(LETS_ENCRYPT_DIRECTORY).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixtures_parse() {
+        let pebble = pebble_directory();
+        assert_eq!(pebble.new_nonce, "https://localhost:14000/nonce-plz");
+        assert!(pebble.meta.profiles.contains_key("shortlived"));
+
+        let lets_encrypt = lets_encrypt_directory();
+        assert!(lets_encrypt.renewal_info.is_some());
+        assert_eq!(lets_encrypt.meta.caa_identities, ["letsencrypt.org"]);
+    }
+}
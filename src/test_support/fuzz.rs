@@ -0,0 +1,395 @@
+//! Robustness checks for the wire types, shared by the cargo-fuzz targets in
+//! `fuzz/` and the generated cases run as unit tests.
+//!
+//! Whatever a server sends, deserializing a wire type must either fail or
+//! give a value whose serialization is stable: serializing it, deserializing
+//! that and serializing again gives the same JSON. Nothing may panic.
+//!
+//! `WireGen` generates documents that are mostly well-formed, with the kinds
+//! of deviations servers are known for: unknown fields, statuses and problem
+//! types, odd or malformed timestamps, and values of the wrong type.
+//!
+//! The fuzz targets need cargo-fuzz and a nightly toolchain, e.g.
+//! `cargo +nightly fuzz run order` in the repository root.
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::wire::{
+    authorization::AuthorizationResource, challenge::ChallengeResource,
+    directory::DirectoryResource, order::OrderResource, problem::AcmeProblem,
+};
+
+/// Checks that `data` either doesn't deserialize as `T` or round-trips,
+/// panicking otherwise. Returns whether it deserialized.
+pub fn check_round_trip<T: Serialize + DeserializeOwned>(data: &[u8]) -> bool {
+    let value: T = match serde_json::from_slice(data) {
+        Ok(value) => value,
+        Err(_) => return false,
+    };
+    let json = serde_json::to_value(&value).expect("deserialized value doesn't serialize");
+    let again: T = serde_json::from_value(json.clone())
+        .unwrap_or_else(|err| panic!("serialized value doesn't deserialize: {}: {}", err, json));
+    assert_eq!(
+        serde_json::to_value(&again).unwrap(),
+        json,
+        "serialization isn't stable"
+    );
+    true
+}
+
+/// `check_round_trip` for each of the wire types a server sends.
+pub fn check_wire_types(data: &[u8]) {
+    check_round_trip::<DirectoryResource>(data);
+    check_round_trip::<OrderResource>(data);
+    check_round_trip::<AuthorizationResource>(data);
+    check_round_trip::<ChallengeResource>(data);
+    check_round_trip::<AcmeProblem>(data);
+}
+
+/// A deterministic generator of wire documents; the same seed always gives
+/// the same documents.
+pub struct WireGen {
+    state: u64,
+}
+
+impl WireGen {
+    pub fn new(seed: u64) -> Self {
+        // xorshift gets stuck at zero
+        Self {
+            state: seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1,
+        }
+    }
+
+    pub fn directory(&mut self) -> Value {
+        let mut meta = Map::new();
+        meta.insert("termsOfService".into(), self.url());
+        meta.insert("website".into(), self.url());
+        meta.insert("caaIdentities".into(), json!([self.string()]));
+        meta.insert("externalAccountRequired".into(), self.boolean());
+        meta.insert("profiles".into(), json!({ "classic": self.string() }));
+        self.unknown_fields(&mut meta);
+        let mut directory = Map::new();
+        for name in [
+            "newNonce",
+            "newAccount",
+            "newOrder",
+            "newAuthz",
+            "revokeCert",
+            "keyChange",
+            "renewalInfo",
+        ] {
+            directory.insert(name.into(), self.url());
+        }
+        directory.insert("meta".into(), Value::Object(meta));
+        self.object(directory)
+    }
+
+    pub fn order(&mut self) -> Value {
+        let mut order = Map::new();
+        order.insert(
+            "status".into(),
+            self.status(&["pending", "ready", "processing", "valid", "invalid"]),
+        );
+        order.insert("expires".into(), self.timestamp());
+        order.insert("notBefore".into(), self.timestamp());
+        order.insert("notAfter".into(), self.timestamp());
+        let identifiers = (0..self.below(3)).map(|_| self.identifier()).collect();
+        order.insert("identifiers".into(), Value::Array(identifiers));
+        let authorizations = (0..self.below(3)).map(|_| self.url()).collect();
+        order.insert("authorizations".into(), Value::Array(authorizations));
+        order.insert("finalize".into(), self.url());
+        order.insert("certificate".into(), self.url());
+        order.insert("profile".into(), self.string().into());
+        order.insert("allow-certificate-get".into(), self.boolean());
+        if self.chance(4) {
+            order.insert("error".into(), self.problem());
+        }
+        self.object(order)
+    }
+
+    pub fn authorization(&mut self) -> Value {
+        let mut authorization = Map::new();
+        authorization.insert("identifier".into(), self.identifier());
+        authorization.insert(
+            "status".into(),
+            self.status(&[
+                "pending",
+                "valid",
+                "invalid",
+                "deactivated",
+                "expired",
+                "revoked",
+            ]),
+        );
+        authorization.insert("expires".into(), self.timestamp());
+        let challenges = (0..self.below(4)).map(|_| self.challenge()).collect();
+        authorization.insert("challenges".into(), Value::Array(challenges));
+        authorization.insert("wildcard".into(), self.boolean());
+        authorization.insert("subdomainAuthAllowed".into(), self.boolean());
+        self.object(authorization)
+    }
+
+    pub fn challenge(&mut self) -> Value {
+        let mut challenge = Map::new();
+        let type_ = self.pick(&[
+            "http-01",
+            "dns-01",
+            "tls-alpn-01",
+            "dns-account-01",
+            "onion-csr-01",
+            "device-attest-01",
+            "tkauth-01",
+            "email-reply-00",
+            "",
+        ]);
+        challenge.insert("type".into(), type_.into());
+        challenge.insert("url".into(), self.url());
+        challenge.insert(
+            "status".into(),
+            self.status(&["pending", "processing", "valid", "invalid"]),
+        );
+        challenge.insert("validated".into(), self.timestamp());
+        challenge.insert("token".into(), self.string().into());
+        if self.chance(2) {
+            challenge.insert("port".into(), self.pick(&[json!(443), json!("8443")]));
+        }
+        if self.chance(3) {
+            challenge.insert(
+                "validationRecord".into(),
+                json!([{
+                    "url": self.url(),
+                    "hostname": self.string(),
+                    "port": self.pick(&[json!("80"), json!(80), json!(-1)]),
+                    "addressesResolved": [self.string()],
+                    "addressUsed": self.string(),
+                }]),
+            );
+        }
+        if self.chance(4) {
+            challenge.insert("error".into(), self.problem());
+        }
+        self.object(challenge)
+    }
+
+    pub fn problem(&mut self) -> Value {
+        let mut problem = Map::new();
+        let type_ = self.pick(&[
+            "urn:ietf:params:acme:error:malformed",
+            "urn:ietf:params:acme:error:rateLimited",
+            "urn:ietf:params:acme:error:userActionRequired",
+            "urn:ietf:params:acme:error:futureError",
+            "about:blank",
+            "",
+        ]);
+        problem.insert("type".into(), type_.into());
+        problem.insert("detail".into(), self.string().into());
+        problem.insert("title".into(), self.string().into());
+        problem.insert("instance".into(), self.url());
+        problem.insert(
+            "status".into(),
+            self.pick(&[json!(400), json!(429), json!(70000), json!("400")]),
+        );
+        if self.chance(4) {
+            problem.insert("identifier".into(), self.identifier());
+        }
+        if self.chance(4) {
+            let subproblems = (0..self.below(3)).map(|_| self.problem()).collect();
+            problem.insert("subproblems".into(), Value::Array(subproblems));
+        }
+        self.object(problem)
+    }
+
+    /// Corrupts serialized bytes: flips, inserts or deletes a few bytes, or
+    /// truncates them.
+    pub fn mutate(&mut self, bytes: &mut Vec<u8>) {
+        for _ in 0..=self.below(3) {
+            let at = self.below(bytes.len() + 1);
+            match self.below(4) {
+                0 if at < bytes.len() => bytes[at] ^= 1 << self.below(8),
+                1 => bytes.insert(at, self.pick(b"{}[]\",:\\0-e\xff")),
+                2 if at < bytes.len() => {
+                    bytes.remove(at);
+                }
+                _ => bytes.truncate(at),
+            }
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        // xorshift64*
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// True one time in `n`.
+    fn chance(&mut self, n: usize) -> bool {
+        self.below(n) == 0
+    }
+
+    fn pick<T: Clone>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len())].clone()
+    }
+
+    /// Drops a field or adds unknown ones now and then, and rarely replaces
+    /// the whole document with some other value.
+    fn object(&mut self, mut map: Map<String, Value>) -> Value {
+        if self.chance(4) && !map.is_empty() {
+            let key = map.keys().nth(self.below(map.len())).unwrap().clone();
+            map.remove(&key);
+        }
+        self.unknown_fields(&mut map);
+        if self.chance(50) {
+            return self.scalar();
+        }
+        Value::Object(map)
+    }
+
+    fn unknown_fields(&mut self, map: &mut Map<String, Value>) {
+        if self.chance(3) {
+            let value = self.any(2);
+            map.insert(format!("x-extension-{}", self.below(3)), value);
+        }
+    }
+
+    fn status(&mut self, known: &[&str]) -> Value {
+        match self.below(10) {
+            0 => "unknown".into(),
+            1 => known[0].to_ascii_uppercase().into(),
+            2 => self.scalar(),
+            _ => self.pick(known).into(),
+        }
+    }
+
+    fn timestamp(&mut self) -> Value {
+        if self.chance(4) {
+            return self.pick(&[
+                json!("2016-01-20 14:09:07Z"),
+                json!("2016-13-45T25:61:61Z"),
+                json!("+275760-09-13T00:00:00Z"),
+                json!("yesterday"),
+                json!(""),
+                json!(1453298947),
+            ]);
+        }
+        self.pick(&[
+            json!("2016-01-20T14:09:07.99Z"),
+            json!("2016-01-20T14:09:07Z"),
+            json!("2016-01-20T14:09:07.123456789+14:00"),
+            json!("2016-12-31T23:59:60Z"),
+            Value::Null,
+        ])
+    }
+
+    fn identifier(&mut self) -> Value {
+        let type_ = self.pick(&["dns", "ip", "email", "permanent-identifier", "DNS"]);
+        let mut identifier = Map::new();
+        identifier.insert("type".into(), type_.into());
+        identifier.insert("value".into(), self.string().into());
+        self.object(identifier)
+    }
+
+    fn url(&mut self) -> Value {
+        if self.chance(10) {
+            return self.scalar();
+        }
+        Value::String(format!("https://acme.test/{}", self.string()))
+    }
+
+    fn string(&mut self) -> String {
+        self.pick(&[
+            "example.com",
+            "*.example.com",
+            "192.0.2.1",
+            "::1",
+            "LoqXcYV8q5ONbJQxbmR7SCTNo3tiAXDfowyjxAjEuX0",
+            "",
+            "\u{0}\u{7f}\u{feff}",
+            "üñî.example",
+            "\"quoted\\",
+            "🦀",
+        ])
+        .to_string()
+    }
+
+    fn boolean(&mut self) -> Value {
+        match self.below(8) {
+            0 => "true".into(),
+            1 => Value::Null,
+            n => Value::Bool(n % 2 == 0),
+        }
+    }
+
+    fn scalar(&mut self) -> Value {
+        match self.below(6) {
+            0 => Value::Null,
+            1 => Value::Bool(self.chance(2)),
+            2 => json!(self.next() as i64),
+            3 => json!(f64::from(self.next() as u32) / 7.0),
+            4 => json!(u64::MAX),
+            _ => self.string().into(),
+        }
+    }
+
+    fn any(&mut self, depth: usize) -> Value {
+        match self.below(if depth == 0 { 1 } else { 3 }) {
+            0 => self.scalar(),
+            1 => Value::Array((0..self.below(3)).map(|_| self.any(depth - 1)).collect()),
+            _ => {
+                let mut map = Map::new();
+                for _ in 0..self.below(3) {
+                    map.insert(self.string(), self.any(depth - 1));
+                }
+                Value::Object(map)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `check_round_trip::<T>` on `count` generated documents and
+    /// corrupted copies of them, returning how many documents deserialized.
+    fn check_generated<T: Serialize + DeserializeOwned>(
+        count: u64,
+        generate: impl Fn(&mut WireGen) -> Value,
+    ) -> usize {
+        let mut parsed = 0;
+        for seed in 0..count {
+            let mut gen = WireGen::new(seed);
+            let mut bytes = serde_json::to_vec(&generate(&mut gen)).unwrap();
+            if check_round_trip::<T>(&bytes) {
+                parsed += 1;
+            }
+            for _ in 0..4 {
+                gen.mutate(&mut bytes);
+                check_round_trip::<T>(&bytes);
+            }
+        }
+        parsed
+    }
+
+    #[test]
+    fn generated_wire_types() {
+        // Enough of each must deserialize for the round trip to be tested
+        assert!(check_generated::<DirectoryResource>(500, WireGen::directory) > 25);
+        assert!(check_generated::<OrderResource>(500, WireGen::order) > 25);
+        assert!(check_generated::<AuthorizationResource>(500, WireGen::authorization) > 25);
+        assert!(check_generated::<ChallengeResource>(500, WireGen::challenge) > 25);
+        assert!(check_generated::<AcmeProblem>(500, WireGen::problem) > 25);
+    }
+
+    #[test]
+    fn generator_is_deterministic() {
+        assert_eq!(WireGen::new(7).order(), WireGen::new(7).order());
+        assert_ne!(WireGen::new(7).order(), WireGen::new(8).order());
+    }
+}
@@ -0,0 +1,170 @@
+//! Serves a `MockAcmeServer` over HTTPS on a local port, for testing
+//! transports that make real connections.
+
+use std::{net::SocketAddr, sync::Arc};
+
+use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, KeyPair};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpListener,
+    task::JoinHandle,
+};
+use tokio_rustls::{
+    rustls::{
+        self,
+        pki_types::{CertificateDer, PrivatePkcs8KeyDer},
+    },
+    TlsAcceptor,
+};
+
+use super::mock_server::MockAcmeServer;
+use crate::transport::{http_types::Method, HttpClient, Request};
+
+/// A `MockAcmeServer` at https://localhost:<port>, with a certificate issued
+/// by a throwaway CA. Stops when dropped.
+pub(crate) struct MockHttpsServer {
+    pub server: MockAcmeServer,
+    addr: SocketAddr,
+    ca: CertificateDer<'static>,
+    accept_task: JoinHandle<()>,
+}
+
+impl MockHttpsServer {
+    /// Must be called within a tokio runtime.
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = MockAcmeServer::with_base_url(format!("https://localhost:{}", addr.port()));
+
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca_params
+            .distinguished_name
+            .push(DnType::CommonName, "Mock ACME CA");
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+        let leaf_key = KeyPair::generate().unwrap();
+        let leaf = CertificateParams::new(vec!["localhost".to_string()])
+            .unwrap()
+            .signed_by(&leaf_key, &ca, &ca_key)
+            .unwrap();
+
+        let config = rustls::ServerConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![leaf.der().clone(), ca.der().clone()],
+            PrivatePkcs8KeyDer::from(leaf_key.serialize_der()).into(),
+        )
+        .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+
+        let accept_server = server.clone();
+        let accept_task = tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else {
+                    continue;
+                };
+                let acceptor = acceptor.clone();
+                let server = accept_server.clone();
+                tokio::spawn(async move {
+                    if let Ok(stream) = acceptor.accept(stream).await {
+                        let _ = serve_connection(stream, &server).await;
+                    }
+                });
+            }
+        });
+
+        Self {
+            server,
+            addr,
+            ca: ca.der().clone(),
+            accept_task,
+        }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn directory_url(&self) -> String {
+        self.server.directory_url()
+    }
+
+    /// The DER certificate of the CA that issued the server's certificate.
+    pub fn ca_der(&self) -> &CertificateDer<'static> {
+        &self.ca
+    }
+}
+
+impl Drop for MockHttpsServer {
+    fn drop(&mut self) {
+        self.accept_task.abort();
+    }
+}
+
+/// Answers HTTP/1.1 requests on `stream` until the client closes it.
+pub(crate) async fn serve_connection(
+    stream: impl AsyncRead + AsyncWrite + Unpin,
+    server: &MockAcmeServer,
+) -> std::io::Result<()> {
+    let base_url = server
+        .directory_url()
+        .trim_end_matches("/directory")
+        .to_string();
+    let mut stream = BufReader::new(stream);
+    loop {
+        let mut request_line = String::new();
+        if stream.read_line(&mut request_line).await? == 0 {
+            return Ok(());
+        }
+        let mut parts = request_line.split_whitespace();
+        let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+        let method: Method = method.parse().unwrap();
+        let mut req = Request::new(method, format!("{}{}", base_url, path).as_str());
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            stream.read_line(&mut line).await?;
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line.split_once(':').unwrap();
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = value.trim().parse().unwrap();
+            } else {
+                req.append_header(name, value.trim());
+            }
+        }
+        let mut body = vec![0; content_length];
+        stream.read_exact(&mut body).await?;
+        req.set_body(body);
+
+        let mut resp = server.send(req).await.unwrap();
+        let body = resp.body_bytes().await.unwrap();
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\n",
+            resp.status(),
+            resp.status().canonical_reason()
+        );
+        for (name, values) in resp.iter() {
+            if name.as_str().eq_ignore_ascii_case("Content-Length") {
+                continue;
+            }
+            for value in values {
+                head.push_str(&format!("{}: {}\r\n", name, value));
+            }
+        }
+        head.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+        let stream = stream.get_mut();
+        stream.write_all(head.as_bytes()).await?;
+        if method != Method::Head {
+            stream.write_all(&body).await?;
+        }
+        stream.flush().await?;
+    }
+}
@@ -0,0 +1,1367 @@
+//! An in-process ACME server for deterministic tests of client flows.
+//!
+//! `MockAcmeServer` implements `HttpClient`, so it stands in for the network:
+//! requests are answered directly from in-memory state. JWS signatures,
+//! nonces and request URLs are checked like a real server would, but
+//! challenges are never actually validated; instead the outcome and timing of
+//! each state transition can be scripted, and faults can be injected into any
+//! endpoint.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{ready, Context, Poll},
+};
+
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use futures_io::{AsyncBufRead, AsyncRead};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::transport::{http_types::Method, Body, HttpClient, Request, Response};
+use crate::{
+    base64url,
+    crypto::{
+        jwk::thumbprint,
+        jws::{Jws, JwsVerifier},
+        public_jwk::PublicJwk,
+    },
+    error::AcmeResult,
+    sleep::NoSleep,
+    wire::{
+        account::{AccountStatus, NewAccountResource},
+        authorization::{AuthorizationResource, AuthorizationStatus, NewAuthorizationResource},
+        challenge::{ChallengeResource, ChallengeStatus, ChallengeType, ValidationRecord},
+        identifier::AcmeIdentifier,
+        order::{NewOrderResource, OrderResource, OrderStatus},
+        problem::{AcmeProblem, AcmeProblemType},
+    },
+    Client,
+};
+
+const BASE_URL: &str = "https://acme.test";
+
+/// The directory URL of every `MockAcmeServer`.
+pub static MOCK_DIRECTORY_URL: &str = "https://acme.test/directory";
+
+const DEFAULT_CERTIFICATE_CHAIN: &str = "-----BEGIN CERTIFICATE-----\nbW9jayBsZWFm\n-----END CERTIFICATE-----\n-----BEGIN CERTIFICATE-----\nbW9jayBpbnRlcm1lZGlhdGU=\n-----END CERTIFICATE-----\n";
+
+/// The endpoints served by `MockAcmeServer`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    Directory,
+    NewNonce,
+    NewAccount,
+    Account,
+    NewOrder,
+    NewAuthz,
+    Order,
+    Finalize,
+    Authorization,
+    Challenge,
+    Certificate,
+}
+
+/// A failure to return instead of handling a request normally.
+#[derive(Clone, Debug)]
+pub enum Fault {
+    /// A badNonce problem; the response still carries a fresh nonce.
+    BadNonce,
+
+    /// A 429 rateLimited problem with a Retry-After header.
+    RateLimited { retry_after_secs: u32 },
+
+    /// A 500 response claiming to be a problem document, with an unparseable
+    /// body.
+    MalformedProblem,
+
+    /// A 200 HTML page, as from a misconfigured proxy.
+    Html,
+
+    /// An arbitrary problem document.
+    Problem {
+        status: u16,
+        problem: Box<AcmeProblem>,
+    },
+
+    /// Never responds, e.g. to test timeouts.
+    Hang,
+
+    /// A 200 response of an empty JSON object padded with whitespace to
+    /// `len` bytes, sent without a Content-Length.
+    Oversized { len: usize },
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MockAcmeServer {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    next_id: u64,
+    nonces: HashSet<String>,
+    account_urls: HashMap<String, String>,
+    accounts: BTreeMap<String, MockAccount>,
+    orders: BTreeMap<String, MockOrder>,
+    authorizations: BTreeMap<String, MockAuthorization>,
+    faults: VecDeque<(Endpoint, Fault)>,
+    requests: Vec<Endpoint>,
+    last_accept: Option<String>,
+    processing_polls: u32,
+    failing_identifiers: HashSet<String>,
+    certificate_chain: Option<String>,
+    alternate_certificate_chains: Vec<String>,
+    certificate_content_type: Option<String>,
+    terms_of_service: Option<String>,
+    directory_cache_control: Option<String>,
+    subdomain_auth_allowed: bool,
+    external_account_required: bool,
+    refuse_unusable_account_fetch: bool,
+    base_url: Option<String>,
+}
+
+#[derive(Debug)]
+struct MockAccount {
+    jwk: String,
+    resource: Value,
+    must_agree_to_terms: bool,
+}
+
+#[derive(Debug)]
+struct MockOrder {
+    account_url: String,
+    resource: OrderResource,
+    polls_remaining: u32,
+}
+
+#[derive(Debug)]
+struct MockAuthorization {
+    account_url: String,
+    resource: AuthorizationResource,
+    // Set once a challenge has been responded to
+    polls_remaining: Option<u32>,
+}
+
+impl MockAcmeServer {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// A server whose resources are under `base_url` (with no trailing
+    /// slash) instead of "https://acme.test", e.g. for serving it on a real
+    /// port.
+    pub fn with_base_url(base_url: impl Into<String>) -> Self {
+        let server = Self::new();
+        server.state().base_url = Some(base_url.into());
+        server
+    }
+
+    /// The directory URL, `MOCK_DIRECTORY_URL` unless built
+    /// `with_base_url`.
+    pub fn directory_url(&self) -> String {
+        format!("{}/directory", self.state().base_url())
+    }
+
+    /// This server as an `HttpClient` for `Client::new` and friends.
+    pub fn http(&self) -> Arc<dyn HttpClient> {
+        Arc::new(self.clone())
+    }
+
+    /// A client for this server's directory.
+    /// A client for this server that doesn't sleep between polls.
+    pub async fn client(&self) -> AcmeResult<Client> {
+        let mut client = Client::for_directory_url(self.http(), self.directory_url()).await?;
+        client.set_sleeper(NoSleep);
+        Ok(client)
+    }
+
+    /// Sets how many times authorizations and orders are polled in the
+    /// "processing" state (after a challenge response or finalize) before
+    /// they move on. Defaults to 0, i.e. transitions happen immediately.
+    pub fn set_processing_polls(&self, polls: u32) {
+        self.state().processing_polls = polls;
+    }
+
+    /// Makes validation of `identifier` (a DNS name or IP address, without
+    /// wildcard prefix) fail with an incorrectResponse problem.
+    pub fn fail_validation(&self, identifier: impl Into<String>) {
+        self.state().failing_identifiers.insert(identifier.into());
+    }
+
+    /// Sets the PEM chain returned from certificate URLs.
+    pub fn set_certificate_chain(&self, pem: impl Into<String>) {
+        self.state().certificate_chain = Some(pem.into());
+    }
+
+    /// Offers `chains` as alternates (Link rel="alternate") of every
+    /// certificate.
+    pub fn set_alternate_certificate_chains(&self, chains: Vec<String>) {
+        self.state().alternate_certificate_chains = chains;
+    }
+
+    /// Sets the Content-Type of certificate chain responses.
+    pub fn set_certificate_content_type(&self, content_type: impl Into<String>) {
+        self.state().certificate_content_type = Some(content_type.into());
+    }
+
+    /// Changes the terms of service URL advertised in the directory.
+    pub fn set_terms_of_service(&self, url: impl Into<String>) {
+        self.state().terms_of_service = Some(url.into());
+    }
+
+    /// Revokes the account at `url`, as a CA might after abuse reports.
+    pub fn revoke_account(&self, url: &str) {
+        if let Some(account) = self.state().accounts.get_mut(url) {
+            account.resource["status"] = "revoked".into();
+        }
+    }
+
+    /// Publishes new terms of service that every existing account must agree
+    /// to: until it does, its requests fail with userActionRequired.
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-7.3.3
+    pub fn change_terms_of_service(&self, url: impl Into<String>) {
+        let mut state = self.state();
+        state.terms_of_service = Some(url.into());
+        for account in state.accounts.values_mut() {
+            account.must_agree_to_terms = true;
+        }
+    }
+
+    /// Sets the Cache-Control header sent with the directory.
+    pub fn set_directory_cache_control(&self, value: impl Into<String>) {
+        self.state().directory_cache_control = Some(value.into());
+    }
+
+    /// Advertises subdomain authorization (RFC 9444): newOrder identifiers
+    /// may then name an ancestor domain, whose authorization the account's
+    /// orders share. Without this, ancestorDomain is rejected as malformed.
+    pub fn set_subdomain_auth_allowed(&self, allowed: bool) {
+        self.state().subdomain_auth_allowed = allowed;
+    }
+
+    /// Advertises `meta.externalAccountRequired` (and a website). The server
+    /// doesn't check bindings; this is for testing client-side checks.
+    pub fn set_external_account_required(&self, required: bool) {
+        self.state().external_account_required = required;
+    }
+
+    /// Refuses a deactivated or revoked account even a POST-as-GET of its
+    /// own resource, as RFC 8555 section 7.3.6 allows. By default that's
+    /// answered, reporting the account's status.
+    pub fn set_refuse_unusable_account_fetch(&self, refuse: bool) {
+        self.state().refuse_unusable_account_fetch = refuse;
+    }
+
+    /// Answers the next request to `endpoint` with `fault`.
+    pub fn inject(&self, endpoint: Endpoint, fault: Fault) {
+        self.inject_times(endpoint, fault, 1);
+    }
+
+    /// Answers the next `times` requests to `endpoint` with `fault`.
+    pub fn inject_times(&self, endpoint: Endpoint, fault: Fault, times: usize) {
+        let mut state = self.state();
+        for _ in 0..times {
+            state.faults.push_back((endpoint, fault.clone()));
+        }
+    }
+
+    /// Forgets every issued nonce, as if the server had restarted.
+    pub fn invalidate_nonces(&self) {
+        self.state().nonces.clear();
+    }
+
+    /// Modifies a stored order, e.g. to force a status.
+    pub fn update_order(&self, url: &str, f: impl FnOnce(&mut OrderResource)) {
+        if let Some(order) = self.state().orders.get_mut(url) {
+            f(&mut order.resource);
+        }
+    }
+
+    /// Modifies a stored authorization, e.g. to force a status.
+    pub fn update_authorization(&self, url: &str, f: impl FnOnce(&mut AuthorizationResource)) {
+        if let Some(authorization) = self.state().authorizations.get_mut(url) {
+            f(&mut authorization.resource);
+        }
+    }
+
+    /// The Accept header of the last request, if it had one.
+    pub fn last_accept(&self) -> Option<String> {
+        self.state().last_accept.clone()
+    }
+
+    /// The number of requests received by `endpoint`, including faulted
+    /// ones.
+    pub fn request_count(&self, endpoint: Endpoint) -> usize {
+        let state = self.state();
+        state.requests.iter().filter(|e| **e == endpoint).count()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    /// None if the request should never be answered.
+    fn handle(
+        &self,
+        method: Method,
+        url: &str,
+        accept: Option<String>,
+        body: &[u8],
+    ) -> Option<Response> {
+        let mut state = self.state();
+        state.last_accept = accept;
+        let endpoint = match route(state.base_url(), url) {
+            Some(endpoint) => endpoint,
+            None => {
+                return Some(state.problem(404, AcmeProblemType::Malformed, "no such resource"))
+            }
+        };
+        state.requests.push(endpoint);
+
+        if let Some(idx) = state.faults.iter().position(|(e, _)| *e == endpoint) {
+            let (_, fault) = state.faults.remove(idx).unwrap();
+            return match fault {
+                Fault::Hang => None,
+                fault => Some(state.fault(fault)),
+            };
+        }
+
+        Some(match (endpoint, method) {
+            (Endpoint::Directory, Method::Get) => {
+                let mut resp = json_response(200, state.directory());
+                if let Some(cache_control) = &state.directory_cache_control {
+                    resp.insert_header("Cache-Control", cache_control.as_str());
+                }
+                resp
+            }
+            (Endpoint::NewNonce, Method::Head | Method::Get) => {
+                let mut resp = Response::new(if method == Method::Head { 200 } else { 204 });
+                resp.insert_header("Replay-Nonce", state.new_nonce());
+                resp
+            }
+            (_, Method::Post) => state.handle_post(endpoint, url, body),
+            _ => state.problem(405, AcmeProblemType::Malformed, "method not allowed"),
+        })
+    }
+}
+
+#[async_trait]
+impl HttpClient for MockAcmeServer {
+    async fn send(&self, mut req: Request) -> Result<Response, crate::transport::Error> {
+        let body = req.body_bytes().await?;
+        let accept = req
+            .header("Accept")
+            .map(|values| values.as_str().to_string());
+        match self.handle(req.method(), req.url().as_str(), accept, &body) {
+            Some(resp) => Ok(resp),
+            None => futures_util::future::pending().await,
+        }
+    }
+}
+
+/// A verified JWS request.
+struct Signed {
+    account_url: Option<String>,
+    jwk: String,
+    payload: Vec<u8>,
+}
+
+impl Signed {
+    fn is_post_as_get(&self) -> bool {
+        self.payload.is_empty()
+    }
+}
+
+impl State {
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn base_url(&self) -> &str {
+        self.base_url.as_deref().unwrap_or(BASE_URL)
+    }
+
+    fn new_nonce(&mut self) -> String {
+        let nonce = base64url::encode(format!("nonce-{}", self.next_id()));
+        self.nonces.insert(nonce.clone());
+        nonce
+    }
+
+    fn directory(&self) -> Value {
+        let terms_of_service = self
+            .terms_of_service
+            .clone()
+            .unwrap_or_else(|| format!("{}/terms", self.base_url()));
+        let mut directory = json!({
+            "newNonce": format!("{}/new-nonce", self.base_url()),
+            "newAccount": format!("{}/new-account", self.base_url()),
+            "newOrder": format!("{}/new-order", self.base_url()),
+            "newAuthz": format!("{}/new-authz", self.base_url()),
+            "revokeCert": format!("{}/revoke-cert", self.base_url()),
+            "keyChange": format!("{}/key-change", self.base_url()),
+            "meta": {
+                "termsOfService": terms_of_service,
+            },
+        });
+        if self.subdomain_auth_allowed {
+            directory["meta"]["subdomainAuthAllowed"] = true.into();
+        }
+        if self.external_account_required {
+            directory["meta"]["externalAccountRequired"] = true.into();
+            directory["meta"]["website"] = format!("{}/eab", self.base_url()).into();
+        }
+        directory
+    }
+
+    fn handle_post(&mut self, endpoint: Endpoint, url: &str, body: &[u8]) -> Response {
+        let signed = match self.verify(endpoint, url, body) {
+            Ok(signed) => signed,
+            Err(resp) => return resp,
+        };
+        if let Some(resp) = self.check_terms_agreement(endpoint, &signed) {
+            return resp;
+        }
+        let mut resp = match endpoint {
+            Endpoint::NewAccount => self.new_account(signed),
+            Endpoint::Account => self.account(url, signed),
+            Endpoint::NewOrder => self.new_order(signed),
+            Endpoint::NewAuthz => self.new_authz(signed),
+            Endpoint::Order => self.order(url, signed),
+            Endpoint::Finalize => self.finalize(url, signed),
+            Endpoint::Authorization => self.authorization(url, signed),
+            Endpoint::Challenge => self.challenge(url, signed),
+            Endpoint::Certificate => self.certificate(url, signed),
+            Endpoint::Directory | Endpoint::NewNonce => {
+                self.problem(405, AcmeProblemType::Malformed, "method not allowed")
+            }
+        };
+        if resp.header("Replay-Nonce").is_none() {
+            resp.insert_header("Replay-Nonce", self.new_nonce());
+        }
+        resp
+    }
+
+    // https://www.rfc-editor.org/rfc/rfc8555.html#section-6.2
+    // The error is the response to send back as is.
+    #[allow(clippy::result_large_err)]
+    fn verify(&mut self, endpoint: Endpoint, url: &str, body: &[u8]) -> Result<Signed, Response> {
+        let malformed = |state: &mut Self, detail: &str| {
+            Err(state.problem(400, AcmeProblemType::Malformed, detail))
+        };
+        let jws: Jws = match serde_json::from_slice(body) {
+            Ok(jws) => jws,
+            Err(_) => return malformed(self, "request body is not a flattened JWS"),
+        };
+        let header: Value = match base64url::decode(&jws.protected)
+            .ok()
+            .and_then(|header| serde_json::from_slice(&header).ok())
+        {
+            Some(header) => header,
+            None => return malformed(self, "invalid protected header"),
+        };
+        let payload = match base64url::decode(&jws.payload) {
+            Ok(payload) => payload,
+            Err(_) => return malformed(self, "invalid payload encoding"),
+        };
+
+        let nonce = header["nonce"].as_str().unwrap_or_default();
+        if !self.nonces.remove(nonce) {
+            return Err(self.problem(400, AcmeProblemType::BadNonce, "unknown or reused nonce"));
+        }
+        if header["url"].as_str() != Some(url) {
+            return Err(self.problem(
+                401,
+                AcmeProblemType::Unauthorized,
+                "protected header url does not match request URL",
+            ));
+        }
+
+        let (account_url, jwk) = match (endpoint, header.get("jwk"), header["kid"].as_str()) {
+            (Endpoint::NewAccount, Some(jwk), None) => (None, jwk.to_string()),
+            (Endpoint::NewAccount, _, _) => {
+                return malformed(self, "newAccount requests must use \"jwk\"")
+            }
+            (_, None, Some(kid)) => match self.accounts.get(kid) {
+                // Every other request is refused, as Boulder does
+                Some(account)
+                    if account.resource["status"] != "valid"
+                        && (self.refuse_unusable_account_fetch
+                            || url != kid
+                            || !payload.is_empty()) =>
+                {
+                    let detail = format!(
+                        "account is not valid, has status {}",
+                        account.resource["status"]
+                    );
+                    return Err(self.problem(401, AcmeProblemType::Unauthorized, &detail));
+                }
+                Some(account) => (Some(kid.to_string()), account.jwk.clone()),
+                None => {
+                    return Err(self.problem(
+                        400,
+                        AcmeProblemType::AccountDoesNotExist,
+                        "no account for kid",
+                    ))
+                }
+            },
+            _ => return malformed(self, "requests must use \"kid\""),
+        };
+
+        let alg = header["alg"].as_str().unwrap_or_default();
+        let input = format!("{}.{}", jws.protected, jws.payload);
+        let signature = base64url::decode(&jws.signature).unwrap_or_default();
+        if verify_signature(&jwk, alg, input.as_bytes(), &signature).is_none() {
+            return malformed(self, "JWS signature is invalid");
+        }
+
+        Ok(Signed {
+            account_url,
+            jwk,
+            payload,
+        })
+    }
+
+    fn new_account(&mut self, signed: Signed) -> Response {
+        let req: NewAccountResource = match serde_json::from_slice(&signed.payload) {
+            Ok(req) => req,
+            Err(err) => return self.problem(400, AcmeProblemType::Malformed, &err.to_string()),
+        };
+        let key_id = thumbprint(&signed.jwk).unwrap_or_default();
+        if let Some(url) = self.account_urls.get(&key_id).cloned() {
+            return located(
+                json_response(200, self.accounts[&url].resource.clone()),
+                &url,
+            );
+        }
+        if req.only_return_existing {
+            return self.problem(
+                400,
+                AcmeProblemType::AccountDoesNotExist,
+                "no account exists with the provided key",
+            );
+        }
+
+        let account_id = self.next_id();
+        let url = format!("{}/account/{}", self.base_url(), account_id);
+        let resource = json!({
+            "status": AccountStatus::Valid,
+            "contact": req.contact,
+            "termsOfServiceAgreed": req.terms_of_service_agreed,
+            "orders": format!("{}/orders", url),
+        });
+        self.account_urls.insert(key_id, url.clone());
+        self.accounts.insert(
+            url.clone(),
+            MockAccount {
+                jwk: signed.jwk,
+                resource: resource.clone(),
+                must_agree_to_terms: false,
+            },
+        );
+        located(json_response(201, resource), &url)
+    }
+
+    fn check_terms_agreement(&mut self, endpoint: Endpoint, signed: &Signed) -> Option<Response> {
+        let account_url = signed.account_url.as_deref()?;
+        if !self.accounts[account_url].must_agree_to_terms {
+            return None;
+        }
+        let agreeing = endpoint == Endpoint::Account
+            && serde_json::from_slice::<Value>(&signed.payload)
+                .is_ok_and(|update| update["termsOfServiceAgreed"] == true);
+        if agreeing {
+            return None;
+        }
+        let terms_url = self.directory()["meta"]["termsOfService"].clone();
+        let problem = AcmeProblem {
+            type_: Some(AcmeProblemType::UserActionRequired),
+            detail: Some("Terms of service have changed".to_string()),
+            instance: Some(format!("{}/agreement", self.base_url())),
+            status: Some(403),
+            ..Default::default()
+        };
+        let mut resp = self.problem_response(403, &problem);
+        resp.insert_header(
+            "Link",
+            format!("<{}>;rel=\"terms-of-service\"", terms_url.as_str().unwrap()),
+        );
+        Some(resp)
+    }
+
+    fn account(&mut self, url: &str, signed: Signed) -> Response {
+        if signed.account_url.as_deref() != Some(url) {
+            return self.problem(401, AcmeProblemType::Unauthorized, "not your account");
+        }
+        let account = self.accounts.get_mut(url).unwrap();
+        if !signed.is_post_as_get() {
+            let update: Value = serde_json::from_slice(&signed.payload).unwrap_or_default();
+            for field in ["status", "contact", "termsOfServiceAgreed"] {
+                if let Some(value) = update.get(field) {
+                    account.resource[field] = value.clone();
+                }
+            }
+            if update["termsOfServiceAgreed"] == true {
+                account.must_agree_to_terms = false;
+            }
+        }
+        json_response(200, account.resource.clone())
+    }
+
+    fn new_order(&mut self, signed: Signed) -> Response {
+        let req: NewOrderResource = match serde_json::from_slice(&signed.payload) {
+            Ok(req) => req,
+            Err(err) => return self.problem(400, AcmeProblemType::Malformed, &err.to_string()),
+        };
+        if req.identifiers.is_empty() {
+            return self.problem(400, AcmeProblemType::Malformed, "no identifiers");
+        }
+
+        let account_url = signed.account_url.unwrap_or_default();
+        let mut authorizations: Vec<String> = Vec::new();
+        for identifier in &req.identifiers {
+            let authz_url = match &identifier.ancestor_domain {
+                None => self.new_authorization(&account_url, identifier, false),
+                Some(ancestor) => {
+                    let is_subdomain = identifier
+                        .value
+                        .strip_suffix(ancestor.as_str())
+                        .is_some_and(|prefix| prefix.ends_with('.'));
+                    if !self.subdomain_auth_allowed || !is_subdomain {
+                        return self.problem(400, AcmeProblemType::Malformed, "bad ancestorDomain");
+                    }
+                    self.ancestor_authorization(&account_url, ancestor)
+                }
+            };
+            if !authorizations.contains(&authz_url) {
+                authorizations.push(authz_url);
+            }
+        }
+
+        let order_id = self.next_id();
+        let order_url = format!("{}/order/{}", self.base_url(), order_id);
+        let resource = OrderResource {
+            status: OrderStatus::Pending,
+            expires: Some((Utc::now() + Duration::days(7)).into()),
+            identifiers: req.identifiers,
+            not_before: req.not_before,
+            not_after: req.not_after,
+            error: None,
+            authorizations,
+            finalize: Some(format!("{}/finalize", order_url)),
+            certificate: None,
+            profile: req.profile,
+            delegation: req.delegation,
+            allow_certificate_get: req.allow_certificate_get,
+            location: None,
+            meta: Default::default(),
+        };
+        self.orders.insert(
+            order_url.clone(),
+            MockOrder {
+                account_url,
+                resource: resource.clone(),
+                polls_remaining: 0,
+            },
+        );
+        located(json_response(201, &resource), &order_url)
+    }
+
+    fn new_authz(&mut self, signed: Signed) -> Response {
+        let req: NewAuthorizationResource = match serde_json::from_slice(&signed.payload) {
+            Ok(req) => req,
+            Err(err) => return self.problem(400, AcmeProblemType::Malformed, &err.to_string()),
+        };
+        let account_url = signed.account_url.unwrap_or_default();
+        let subdomain_auth_allowed = req.subdomain_auth_allowed && self.subdomain_auth_allowed;
+        let authz_url =
+            self.new_authorization(&account_url, &req.identifier, subdomain_auth_allowed);
+        let resource = &self.authorizations[&authz_url].resource;
+        located(json_response(201, resource), &authz_url)
+    }
+
+    /// The account's usable authorization of `ancestor` with subdomains
+    /// allowed, created if needed.
+    fn ancestor_authorization(&mut self, account_url: &str, ancestor: &str) -> String {
+        let existing = self.authorizations.iter().find(|(_, authorization)| {
+            let resource = &authorization.resource;
+            authorization.account_url == account_url
+                && resource.subdomain_auth_allowed
+                && resource.identifier.value == ancestor
+                && matches!(
+                    resource.status,
+                    AuthorizationStatus::Pending | AuthorizationStatus::Valid
+                )
+        });
+        match existing {
+            Some((url, _)) => url.clone(),
+            None => self.new_authorization(account_url, &AcmeIdentifier::dns(ancestor), true),
+        }
+    }
+
+    fn new_authorization(
+        &mut self,
+        account_url: &str,
+        identifier: &AcmeIdentifier,
+        subdomain_auth_allowed: bool,
+    ) -> String {
+        let authz_id = self.next_id();
+        let authz_url = format!("{}/authz/{}", self.base_url(), authz_id);
+        let (value, wildcard) = match identifier.value.strip_prefix("*.") {
+            Some(base) => (base.to_string(), true),
+            None => (identifier.value.clone(), false),
+        };
+        let challenge_types = if value.ends_with(".onion") {
+            vec![ChallengeType::OnionCsr01]
+        } else if wildcard {
+            vec![ChallengeType::Dns01]
+        } else if identifier.is_ip() {
+            vec![ChallengeType::Http01]
+        } else {
+            vec![ChallengeType::Http01, ChallengeType::Dns01]
+        };
+        let challenges = challenge_types
+            .into_iter()
+            .map(|challenge_type| ChallengeResource {
+                url: format!("{}/chall/{}/{}", self.base_url(), authz_id, challenge_type),
+                status: ChallengeStatus::Pending,
+                validated: None,
+                error: None,
+                token: Some(base64url::encode(Sha256::digest(
+                    format!("token-{}-{}", authz_id, challenge_type).as_bytes(),
+                ))),
+                validation_record: Vec::new(),
+                additional_fields: match challenge_type {
+                    ChallengeType::OnionCsr01 => [(
+                        "nonce".to_string(),
+                        base64::encode(format!("nonce-{}", authz_id)).into(),
+                    )]
+                    .into_iter()
+                    .collect(),
+                    _ => Default::default(),
+                },
+                meta: Default::default(),
+                type_: challenge_type,
+            })
+            .collect();
+        let resource = AuthorizationResource {
+            identifier: AcmeIdentifier {
+                value,
+                type_: identifier.type_.clone(),
+                ancestor_domain: None,
+            },
+            status: AuthorizationStatus::Pending,
+            expires: Some((Utc::now() + Duration::days(7)).into()),
+            challenges,
+            wildcard,
+            subdomain_auth_allowed,
+            location: None,
+            meta: Default::default(),
+        };
+        self.authorizations.insert(
+            authz_url.clone(),
+            MockAuthorization {
+                account_url: account_url.to_string(),
+                resource,
+                polls_remaining: None,
+            },
+        );
+        authz_url
+    }
+
+    fn order(&mut self, url: &str, signed: Signed) -> Response {
+        if let Err(resp) = self.check_order_owner(url, &signed) {
+            return resp;
+        }
+        self.advance_order(url, true);
+        json_response(200, &self.orders[url].resource)
+    }
+
+    fn finalize(&mut self, url: &str, signed: Signed) -> Response {
+        let order_url = url.trim_end_matches("/finalize").to_string();
+        if let Err(resp) = self.check_order_owner(&order_url, &signed) {
+            return resp;
+        }
+        let csr = serde_json::from_slice::<Value>(&signed.payload)
+            .ok()
+            .and_then(|payload| Some(base64url::decode(payload["csr"].as_str()?).is_ok()));
+        if csr != Some(true) {
+            return self.problem(400, AcmeProblemType::BadCSR, "missing or invalid csr");
+        }
+
+        self.advance_order(&order_url, false);
+        let polls = self.processing_polls;
+        let order = self.orders.get_mut(&order_url).unwrap();
+        if order.resource.status != OrderStatus::Ready {
+            let detail = format!("order is {:?}", order.resource.status).to_ascii_lowercase();
+            return self.problem(403, AcmeProblemType::OrderNotReady, &detail);
+        }
+        order.resource.status = OrderStatus::Processing;
+        order.polls_remaining = polls;
+        self.advance_order(&order_url, false);
+        located(
+            json_response(200, &self.orders[&order_url].resource),
+            &order_url,
+        )
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn check_order_owner(&mut self, url: &str, signed: &Signed) -> Result<(), Response> {
+        match self.orders.get(url) {
+            Some(order) if Some(&order.account_url) == signed.account_url.as_ref() => Ok(()),
+            Some(_) => Err(self.problem(401, AcmeProblemType::Unauthorized, "not your order")),
+            None => Err(self.problem(404, AcmeProblemType::Malformed, "no such order")),
+        }
+    }
+
+    /// Moves an order on according to its authorizations and, if `poll` is
+    /// set, counts down its time in "processing".
+    fn advance_order(&mut self, url: &str, poll: bool) {
+        let statuses: Vec<_> = self.orders[url]
+            .resource
+            .authorizations
+            .iter()
+            .map(|authz_url| self.authorizations[authz_url].resource.status)
+            .collect();
+        let order = self.orders.get_mut(url).unwrap();
+        match order.resource.status {
+            OrderStatus::Pending => {
+                if statuses.iter().all(|s| *s == AuthorizationStatus::Valid) {
+                    order.resource.status = OrderStatus::Ready;
+                } else if statuses.iter().any(|s| {
+                    !matches!(s, AuthorizationStatus::Pending | AuthorizationStatus::Valid)
+                }) {
+                    order.resource.status = OrderStatus::Invalid;
+                    order.resource.error = Some(AcmeProblem {
+                        type_: Some(AcmeProblemType::Unauthorized),
+                        detail: Some("an authorization failed".to_string()),
+                        status: Some(403),
+                        ..Default::default()
+                    });
+                }
+            }
+            OrderStatus::Processing => {
+                if order.polls_remaining == 0 {
+                    order.resource.status = OrderStatus::Valid;
+                    order.resource.certificate = Some(url.replacen("/order/", "/cert/", 1));
+                } else if poll {
+                    order.polls_remaining -= 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn authorization(&mut self, url: &str, signed: Signed) -> Response {
+        if !signed.is_post_as_get() {
+            let update: Value = serde_json::from_slice(&signed.payload).unwrap_or_default();
+            if update["status"] == "deactivated" {
+                if let Some(authorization) = self.authorizations.get_mut(url) {
+                    authorization.resource.status = AuthorizationStatus::Deactivated;
+                }
+            }
+        }
+        self.advance_authorization(url, true);
+        match self.authorizations.get(url) {
+            Some(authorization) => json_response(200, &authorization.resource),
+            None => self.problem(404, AcmeProblemType::Malformed, "no such authorization"),
+        }
+    }
+
+    fn challenge(&mut self, url: &str, signed: Signed) -> Response {
+        let authz_url = url
+            .rsplit_once('/')
+            .map(|(prefix, _)| prefix.replacen("/chall/", "/authz/", 1))
+            .unwrap_or_default();
+        let polls = self.processing_polls;
+        let authorization = match self.authorizations.get_mut(&authz_url) {
+            Some(authorization) => authorization,
+            None => return self.problem(404, AcmeProblemType::Malformed, "no such challenge"),
+        };
+        let challenge = match authorization
+            .resource
+            .challenges
+            .iter_mut()
+            .find(|challenge| challenge.url == url)
+        {
+            Some(challenge) => challenge,
+            None => return self.problem(404, AcmeProblemType::Malformed, "no such challenge"),
+        };
+        if challenge.type_ == ChallengeType::OnionCsr01 && !signed.is_post_as_get() {
+            let csr = serde_json::from_slice::<Value>(&signed.payload)
+                .ok()
+                .and_then(|payload| payload["csr"].as_str().map(base64url::decode));
+            if !matches!(csr, Some(Ok(der)) if !der.is_empty()) {
+                return self.problem(400, AcmeProblemType::BadCSR, "onion-csr-01 needs a CSR");
+            }
+        }
+        // An empty object is a response; an empty payload is POST-as-GET
+        if !signed.is_post_as_get()
+            && challenge.status == ChallengeStatus::Pending
+            && authorization.polls_remaining.is_none()
+        {
+            challenge.status = ChallengeStatus::Processing;
+            authorization.polls_remaining = Some(polls);
+            self.advance_authorization(&authz_url, false);
+        }
+
+        let challenge = self.authorizations[&authz_url]
+            .resource
+            .challenges
+            .iter()
+            .find(|challenge| challenge.url == url)
+            .unwrap();
+        let mut resp = json_response(200, challenge);
+        resp.insert_header("Link", format!("<{}>;rel=\"up\"", authz_url));
+        resp
+    }
+
+    /// Completes validation of a responded-to challenge once its polls have
+    /// run out.
+    fn advance_authorization(&mut self, url: &str, poll: bool) {
+        let authorization = match self.authorizations.get_mut(url) {
+            Some(authorization) => authorization,
+            None => return,
+        };
+        match authorization.polls_remaining {
+            Some(0) => {}
+            Some(ref mut polls) if poll => {
+                *polls -= 1;
+                return;
+            }
+            _ => return,
+        }
+        authorization.polls_remaining = None;
+
+        let resource = &mut authorization.resource;
+        let fail = self
+            .failing_identifiers
+            .contains(&resource.identifier.value);
+        for challenge in &mut resource.challenges {
+            if challenge.status != ChallengeStatus::Processing {
+                continue;
+            }
+            if fail {
+                challenge.status = ChallengeStatus::Invalid;
+                challenge.error = Some(AcmeProblem {
+                    type_: Some(AcmeProblemType::IncorrectResponse),
+                    detail: Some(format!(
+                        "mock validation of {} failed",
+                        resource.identifier.value
+                    )),
+                    status: Some(403),
+                    ..Default::default()
+                });
+                challenge.validation_record = vec![ValidationRecord {
+                    hostname: Some(resource.identifier.value.clone()),
+                    addresses_resolved: vec!["192.0.2.1".to_string()],
+                    address_used: Some("192.0.2.1".to_string()),
+                    ..Default::default()
+                }];
+                resource.status = AuthorizationStatus::Invalid;
+            } else {
+                challenge.status = ChallengeStatus::Valid;
+                challenge.validated = Some(Utc::now().into());
+                resource.status = AuthorizationStatus::Valid;
+            }
+        }
+    }
+
+    fn certificate(&mut self, url: &str, signed: Signed) -> Response {
+        // Alternates are at "{certificate URL}/{index}"
+        let (cert_url, alternate) = match url.rsplit_once('/') {
+            Some((base, index)) if base.contains("/cert/") => (base, index.parse::<usize>().ok()),
+            _ => (url, None),
+        };
+        let order_url = cert_url.replacen("/cert/", "/order/", 1);
+        if let Err(resp) = self.check_order_owner(&order_url, &signed) {
+            return resp;
+        }
+        if self.orders[&order_url].resource.certificate.as_deref() != Some(cert_url) {
+            return self.problem(404, AcmeProblemType::Malformed, "no such certificate");
+        }
+        let chain = match alternate {
+            Some(index) => match self.alternate_certificate_chains.get(index) {
+                Some(chain) => chain.clone(),
+                None => return self.problem(404, AcmeProblemType::Malformed, "no such chain"),
+            },
+            None => self
+                .certificate_chain
+                .clone()
+                .unwrap_or_else(|| DEFAULT_CERTIFICATE_CHAIN.to_string()),
+        };
+        let mut body = Body::from_string(chain);
+        body.set_mime(
+            self.certificate_content_type
+                .as_deref()
+                .unwrap_or("application/pem-certificate-chain"),
+        );
+        let mut resp = Response::new(200);
+        resp.set_body(body);
+        if alternate.is_none() && !self.alternate_certificate_chains.is_empty() {
+            let links: Vec<_> = (0..self.alternate_certificate_chains.len())
+                .map(|index| format!("<{}/{}>;rel=\"alternate\"", cert_url, index))
+                .collect();
+            resp.insert_header("Link", links.join(", "));
+        }
+        resp
+    }
+
+    fn fault(&mut self, fault: Fault) -> Response {
+        match fault {
+            Fault::BadNonce => self.problem(400, AcmeProblemType::BadNonce, "injected bad nonce"),
+            Fault::RateLimited { retry_after_secs } => {
+                let mut resp = self.problem(
+                    429,
+                    AcmeProblemType::RateLimited,
+                    "too many requests: see https://acme.test/docs/rate-limits/#mock",
+                );
+                resp.insert_header("Retry-After", retry_after_secs.to_string());
+                resp
+            }
+            Fault::MalformedProblem => {
+                let mut body = Body::from_string("<html>internal error</html>".to_string());
+                body.set_mime(AcmeProblem::CONTENT_TYPE);
+                let mut resp = Response::new(500);
+                resp.set_body(body);
+                resp.insert_header("Replay-Nonce", self.new_nonce());
+                resp
+            }
+            Fault::Html => {
+                let mut body = Body::from_string("<html>please log in</html>".to_string());
+                body.set_mime("text/html");
+                let mut resp = Response::new(200);
+                resp.set_body(body);
+                resp
+            }
+            Fault::Problem { status, problem } => self.problem_response(status, &problem),
+            Fault::Oversized { len } => {
+                let padding = Padding {
+                    start: b"{}",
+                    remaining: len,
+                };
+                let mut body = Body::from_reader(padding, None);
+                body.set_mime("application/json");
+                let mut resp = Response::new(200);
+                resp.set_body(body);
+                resp.insert_header("Replay-Nonce", self.new_nonce());
+                resp
+            }
+            Fault::Hang => unreachable!("handled by MockAcmeServer::handle"),
+        }
+    }
+
+    fn problem(&mut self, status: u16, type_: AcmeProblemType, detail: &str) -> Response {
+        let problem = AcmeProblem {
+            type_: Some(type_),
+            detail: Some(detail.to_string()),
+            status: Some(status),
+            ..Default::default()
+        };
+        self.problem_response(status, &problem)
+    }
+
+    // https://www.rfc-editor.org/rfc/rfc8555.html#section-6.5: error
+    // responses carry a fresh nonce too
+    fn problem_response(&mut self, status: u16, problem: &AcmeProblem) -> Response {
+        let mut body = Body::from_json(problem).unwrap();
+        body.set_mime(AcmeProblem::CONTENT_TYPE);
+        let mut resp = Response::new(status);
+        resp.set_body(body);
+        resp.insert_header("Replay-Nonce", self.new_nonce());
+        resp
+    }
+}
+
+/// A body of `start` followed by spaces, `remaining` bytes in all.
+struct Padding {
+    start: &'static [u8],
+    remaining: usize,
+}
+
+impl AsyncRead for Padding {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let chunk = ready!(self.as_mut().poll_fill_buf(cx))?;
+        let n = chunk.len().min(buf.len());
+        buf[..n].copy_from_slice(&chunk[..n]);
+        self.consume(n);
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl AsyncBufRead for Padding {
+    fn poll_fill_buf(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<&[u8]>> {
+        const SPACES: &[u8] = &[b' '; 1024];
+        let this = self.get_mut();
+        let chunk = if this.start.is_empty() {
+            SPACES
+        } else {
+            this.start
+        };
+        Poll::Ready(Ok(&chunk[..chunk.len().min(this.remaining)]))
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        let start = self.start;
+        self.start = &start[amt.min(start.len())..];
+        self.remaining -= amt;
+    }
+}
+
+fn route(base_url: &str, url: &str) -> Option<Endpoint> {
+    let path = url.strip_prefix(base_url)?;
+    let segments: Vec<_> = path.trim_start_matches('/').split('/').collect();
+    Some(match segments.as_slice() {
+        ["directory"] => Endpoint::Directory,
+        ["new-nonce"] => Endpoint::NewNonce,
+        ["new-account"] => Endpoint::NewAccount,
+        ["account", _] => Endpoint::Account,
+        ["new-order"] => Endpoint::NewOrder,
+        ["new-authz"] => Endpoint::NewAuthz,
+        ["order", _] => Endpoint::Order,
+        ["order", _, "finalize"] => Endpoint::Finalize,
+        ["authz", _] => Endpoint::Authorization,
+        ["chall", _, _] => Endpoint::Challenge,
+        ["cert", _] | ["cert", _, _] => Endpoint::Certificate,
+        _ => return None,
+    })
+}
+
+fn json_response(status: u16, body: impl serde::Serialize) -> Response {
+    let mut resp = Response::new(status);
+    resp.set_body(Body::from_json(&body).unwrap());
+    resp
+}
+
+fn located(mut resp: Response, url: &str) -> Response {
+    resp.insert_header("Location", url);
+    resp
+}
+
+fn verify_signature(jwk: &str, alg: &str, input: &[u8], signature: &[u8]) -> Option<()> {
+    let key = PublicJwk::from_jwk(jwk).ok()?;
+    (key.jws_alg().as_str() == alg && key.jws_verify(input, signature)).then_some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        api::{account::Account, challenge::ChallengeState, order::OrderState},
+        error::{AcmeError, AcmeOp},
+        wire::client::RetryPolicy,
+    };
+
+    use super::*;
+
+    async fn register(server: &MockAcmeServer) -> Account {
+        let client = server.client().await.unwrap();
+        client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap()
+    }
+
+    fn problem_type(err: &AcmeError) -> Option<AcmeProblemType> {
+        err.problem()?.type_.clone()
+    }
+
+    #[async_std::test]
+    async fn order_flow() {
+        let server = MockAcmeServer::new();
+        server.set_processing_polls(2);
+        let account = register(&server).await;
+
+        let mut order = account.new_dns_order("example.com").await.unwrap();
+        let mut authorization = match order.state() {
+            OrderState::Pending(pending) => pending.get_only_authorization().await.unwrap(),
+            _ => panic!("expected pending order"),
+        };
+        let mut challenge = authorization
+            .find_challenge_type(&ChallengeType::Http01)
+            .unwrap();
+        match challenge.state() {
+            ChallengeState::Pending(pending) => pending.respond().await.unwrap(),
+            _ => panic!("expected pending challenge"),
+        };
+        assert_eq!(
+            authorization.status_changed().await.unwrap(),
+            AuthorizationStatus::Valid
+        );
+        assert_eq!(server.request_count(Endpoint::Authorization), 4);
+
+        assert_eq!(order.refresh().await.unwrap(), OrderStatus::Ready);
+        match order.state() {
+            OrderState::Ready(mut ready) => ready.finalize(b"csr").await.unwrap(),
+            _ => panic!("expected ready order"),
+        };
+        assert_eq!(order.status(), OrderStatus::Processing);
+        assert_eq!(order.status_changed().await.unwrap(), OrderStatus::Valid);
+
+        let chain = match order.state() {
+            OrderState::Valid(valid) => valid.get_certificate_chain().await.unwrap(),
+            _ => panic!("expected valid order"),
+        };
+        assert_eq!(chain, DEFAULT_CERTIFICATE_CHAIN);
+    }
+
+    #[async_std::test]
+    async fn failed_validation() {
+        let server = MockAcmeServer::new();
+        server.fail_validation("example.com");
+        let account = register(&server).await;
+
+        let mut order = account.new_dns_order("example.com").await.unwrap();
+        assert_eq!(order.status(), OrderStatus::Pending);
+        let mut authorization = order.get_only_authorization().await.unwrap();
+        let mut challenge = authorization
+            .find_challenge_type(&ChallengeType::Dns01)
+            .unwrap();
+        let state = match challenge.state() {
+            ChallengeState::Pending(pending) => pending.respond().await.unwrap(),
+            _ => panic!("expected pending challenge"),
+        };
+        match state {
+            ChallengeState::Invalid(invalid) => {
+                assert_eq!(
+                    invalid.error().unwrap().type_,
+                    Some(AcmeProblemType::IncorrectResponse)
+                );
+                assert_eq!(
+                    invalid.validation_records()[0].hostname.as_deref(),
+                    Some("example.com")
+                );
+            }
+            _ => panic!("expected invalid challenge"),
+        }
+        authorization.refresh().await.unwrap();
+        assert_eq!(authorization.status(), AuthorizationStatus::Invalid);
+        assert_eq!(order.refresh().await.unwrap(), OrderStatus::Invalid);
+    }
+
+    #[async_std::test]
+    async fn bad_nonce_retried_once() {
+        let server = MockAcmeServer::new();
+        let account = register(&server).await;
+
+        server.inject(Endpoint::NewOrder, Fault::BadNonce);
+        account.new_dns_order("example.com").await.unwrap();
+        assert_eq!(server.request_count(Endpoint::NewOrder), 2);
+
+        // Stale nonces are rejected like injected ones
+        server.invalidate_nonces();
+        account.new_dns_order("example.com").await.unwrap();
+    }
+
+    #[async_std::test]
+    async fn bad_nonce_storm() {
+        let server = MockAcmeServer::new();
+        let account = register(&server).await;
+
+        server.inject_times(Endpoint::NewOrder, Fault::BadNonce, 3);
+        let err = account.new_dns_order("example.com").await.err().unwrap();
+        assert_eq!(problem_type(&err), Some(AcmeProblemType::BadNonce));
+        assert_eq!(server.request_count(Endpoint::NewOrder), 2);
+
+        let mut client = server.client().await.unwrap();
+        client.set_retry_policy(RetryPolicy {
+            bad_nonce_retries: 5,
+            ..Default::default()
+        });
+        server.inject_times(Endpoint::NewAccount, Fault::BadNonce, 5);
+        client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+    }
+
+    #[async_std::test]
+    async fn rate_limited() {
+        let server = MockAcmeServer::new();
+        let account = register(&server).await;
+
+        server.inject(
+            Endpoint::NewOrder,
+            Fault::RateLimited {
+                retry_after_secs: 60,
+            },
+        );
+        let err = account.new_dns_order("example.com").await.err().unwrap();
+        assert_eq!(
+            err.context(),
+            Some((AcmeOp::NewOrder, "https://acme.test/new-order"))
+        );
+        assert!(err
+            .to_string()
+            .starts_with("newOrder at https://acme.test/new-order failed: rate limited"));
+        assert_eq!(problem_type(&err), Some(AcmeProblemType::RateLimited));
+        match err.into_without_context() {
+            AcmeError::RateLimited(err) => {
+                assert!(err.retry_after.unwrap() > Utc::now());
+                assert_eq!(err.limit_name.as_deref(), Some("mock"));
+            }
+            err => panic!("expected rate limited error, got {:?}", err),
+        }
+    }
+
+    #[async_std::test]
+    async fn malformed_problem() {
+        let server = MockAcmeServer::new();
+        let account = register(&server).await;
+
+        server.inject(Endpoint::NewOrder, Fault::MalformedProblem);
+        match account
+            .new_dns_order("example.com")
+            .await
+            .map_err(AcmeError::into_without_context)
+        {
+            Err(AcmeError::HttpError(err)) => assert_eq!(err.status(), 500),
+            res => panic!("expected http error, got {:?}", res.err()),
+        }
+    }
+
+    #[async_std::test]
+    async fn finalize_retries_order_not_ready() {
+        let server = MockAcmeServer::new();
+        let account = register(&server).await;
+        let mut order = account.new_dns_order("example.com").await.unwrap();
+        for url in order.resource().authorizations.clone() {
+            server.update_authorization(&url, |authz| authz.status = AuthorizationStatus::Valid);
+        }
+        order.refresh().await.unwrap();
+
+        server.inject(
+            Endpoint::Finalize,
+            Fault::Problem {
+                status: 403,
+                problem: Box::new(AcmeProblem {
+                    type_: Some(AcmeProblemType::OrderNotReady),
+                    ..Default::default()
+                }),
+            },
+        );
+        match order.state() {
+            OrderState::Ready(mut ready) => ready.finalize(b"csr").await.unwrap(),
+            _ => panic!("expected ready order"),
+        };
+        assert_eq!(server.request_count(Endpoint::Finalize), 2);
+        assert_eq!(order.status(), OrderStatus::Valid);
+    }
+
+    #[async_std::test]
+    async fn requests_are_authenticated() {
+        let server = MockAcmeServer::new();
+        let account = register(&server).await;
+        let other = register(&server).await;
+
+        let order = account.new_dns_order("example.com").await.unwrap();
+        let err = other.get_order(order.url()).await.err().unwrap();
+        assert_eq!(problem_type(&err), Some(AcmeProblemType::Unauthorized));
+    }
+}
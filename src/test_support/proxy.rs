@@ -0,0 +1,115 @@
+//! A CONNECT proxy on a local port, for testing transports' proxy support
+//! against a `MockHttpsServer`.
+
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+/// An HTTP proxy at http://127.0.0.1:<port> that only tunnels (CONNECT),
+/// and only with the Basic credentials it was started with. Stops when
+/// dropped.
+pub(crate) struct MockProxy {
+    addr: SocketAddr,
+    tunnels: Arc<AtomicUsize>,
+    accept_task: JoinHandle<()>,
+}
+
+impl MockProxy {
+    /// Must be called within a tokio runtime.
+    pub async fn start(username: &str, password: &str) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let credentials = Arc::new(format!(
+            "Basic {}",
+            base64::encode(format!("{}:{}", username, password))
+        ));
+        let tunnels = Arc::new(AtomicUsize::new(0));
+
+        let accept_tunnels = tunnels.clone();
+        let accept_task = tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else {
+                    continue;
+                };
+                let credentials = credentials.clone();
+                let tunnels = accept_tunnels.clone();
+                tokio::spawn(async move {
+                    let _ = tunnel(stream, &credentials, &tunnels).await;
+                });
+            }
+        });
+
+        Self {
+            addr,
+            tunnels,
+            accept_task,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// How many tunnels it has opened.
+    pub fn tunnels(&self) -> usize {
+        self.tunnels.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for MockProxy {
+    fn drop(&mut self) {
+        self.accept_task.abort();
+    }
+}
+
+async fn tunnel(
+    stream: TcpStream,
+    credentials: &str,
+    tunnels: &AtomicUsize,
+) -> std::io::Result<()> {
+    let mut stream = BufReader::new(stream);
+    let mut request_line = String::new();
+    stream.read_line(&mut request_line).await?;
+    let target = request_line
+        .strip_prefix("CONNECT ")
+        .and_then(|rest| rest.split_whitespace().next())
+        .unwrap_or("")
+        .to_string();
+    let mut authorized = false;
+    loop {
+        let mut line = String::new();
+        stream.read_line(&mut line).await?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            authorized |=
+                name.eq_ignore_ascii_case("Proxy-Authorization") && value.trim() == credentials;
+        }
+    }
+    if target.is_empty() || !authorized {
+        let resp = "HTTP/1.1 407 Proxy Authentication Required\r\n\
+            Proxy-Authenticate: Basic realm=\"mock\"\r\nContent-Length: 0\r\n\r\n";
+        return stream.get_mut().write_all(resp.as_bytes()).await;
+    }
+
+    let mut upstream = TcpStream::connect(target).await?;
+    stream
+        .get_mut()
+        .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+        .await?;
+    tunnels.fetch_add(1, Ordering::SeqCst);
+    tokio::io::copy_bidirectional(&mut stream, &mut upstream).await?;
+    Ok(())
+}
@@ -0,0 +1,266 @@
+//! Adapters for plugging other HTTP stacks in as the client's transport.
+//!
+//! With the "reqwest" feature, `ReqwestClient` adapts a `reqwest::Client`;
+//! with the "tower" feature, `TowerClient` adapts any `tower::Service` taking
+//! `http` requests, such as a hyper client with retry, timeout or metrics
+//! layers around it.
+//!
+//! Everywhere but wasm32, `HttpClient` is http-client's trait, so its
+//! clients plug in directly. http-client doesn't build for wasm32 (through
+//! http-types' default "cookies" feature it pulls in time 0.2 and stdweb),
+//! so there `HttpClient` is this crate's own trait of the same shape. In
+//! browsers and edge runtimes (the "web" feature), wrap a `fetch` binding in
+//! an `FnHttpClient`.
+//!
+//! With the "reqwest-rustls" feature, `ReqwestClient::with_config` (or
+//! `ClientBuilder::transport` and `ClientBuilder::proxy`) builds the
+//! `reqwest::Client` itself, trusting the roots of a `TransportConfig`,
+//! enforcing its `SpkiPins` and going through its proxy, with Basic
+//! authentication if given. Otherwise proxies, proxy authentication and
+//! private root CAs are configured on the `HttpClient` itself (e.g. isahc's
+//! `HttpClient::builder().proxy(..)` behind http-client's "curl_client"
+//! feature), since only the transport opens connections. Directory URLs may
+//! use any port and path; every other URL is taken from the directory and
+//! resources as given.
+
+use std::{fmt::Debug, future::Future, sync::Arc};
+
+use async_trait::async_trait;
+pub use http_types::{self, Body, Error, Request, Response};
+
+#[cfg(feature = "x509")]
+use crate::x509::spki_sha256;
+
+#[cfg(feature = "reqwest")]
+mod reqwest_client;
+#[cfg(feature = "reqwest")]
+pub use reqwest_client::ReqwestClient;
+#[cfg(feature = "reqwest-rustls")]
+pub use reqwest_client::{ProxyConfig, TransportConfig};
+#[cfg(feature = "tower")]
+mod tower_service;
+#[cfg(feature = "tower")]
+pub use tower_service::TowerClient;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use http_client::HttpClient;
+
+/// Sends the client's requests; http-client's `HttpClient` everywhere but
+/// wasm32.
+#[cfg(target_arch = "wasm32")]
+#[async_trait]
+pub trait HttpClient: Debug + Unpin + Send + Sync + 'static {
+    async fn send(&self, req: Request) -> Result<Response, Error>;
+}
+
+/// An `HttpClient` backed by an async function, e.g. one that converts the
+/// request for another HTTP stack, or that adds timeouts, retries or metrics
+/// around another `HttpClient`:
+///
+/// ```ignore
+/// let inner: Arc<dyn HttpClient> = ...;
+/// let http = FnHttpClient::new(move |req| {
+///     let inner = inner.clone();
+///     async move {
+///         let resp = inner.send(req).await;
+///         log::debug!("ACME request done");
+///         resp
+///     }
+/// });
+/// ```
+pub struct FnHttpClient<F> {
+    send: F,
+}
+
+impl<F, ResponseFuture> FnHttpClient<F>
+where
+    F: Fn(Request) -> ResponseFuture + Send + Sync + Unpin + 'static,
+    ResponseFuture: Future<Output = Result<Response, Error>> + Send,
+{
+    pub fn new(send: F) -> Self {
+        Self { send }
+    }
+}
+
+impl<F> Debug for FnHttpClient<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FnHttpClient").finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl<F, ResponseFuture> HttpClient for FnHttpClient<F>
+where
+    F: Fn(Request) -> ResponseFuture + Send + Sync + Unpin + 'static,
+    ResponseFuture: Future<Output = Result<Response, Error>> + Send,
+{
+    async fn send(&self, req: Request) -> Result<Response, Error> {
+        (self.send)(req).await
+    }
+}
+
+/// http-types bodies must be `Sync`, which the body streams of other HTTP
+/// stacks usually aren't. Reading takes `&mut self`, so the mutex is never
+/// actually contended.
+#[cfg(any(feature = "reqwest", feature = "tower"))]
+struct SyncRead<R>(std::sync::Mutex<R>);
+
+#[cfg(any(feature = "reqwest", feature = "tower"))]
+impl<R> SyncRead<R> {
+    fn new(reader: R) -> Self {
+        Self(std::sync::Mutex::new(reader))
+    }
+}
+
+#[cfg(any(feature = "reqwest", feature = "tower"))]
+impl<R: futures_io::AsyncRead + Unpin> futures_io::AsyncRead for SyncRead<R> {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut [u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        let reader = self.get_mut().0.get_mut().unwrap();
+        std::pin::Pin::new(reader).poll_read(cx, buf)
+    }
+}
+
+/// The User-Agent sent by clients from `Client::builder` unless another is
+/// set.
+pub const DEFAULT_USER_AGENT: &str =
+    concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// An `HttpClient` that adds a User-Agent header to requests without one,
+/// as RFC 8555 section 6.1 asks of ACME clients.
+#[derive(Debug)]
+pub struct UserAgent {
+    inner: Arc<dyn HttpClient>,
+    user_agent: String,
+}
+
+impl UserAgent {
+    pub fn new(inner: Arc<dyn HttpClient>, user_agent: impl Into<String>) -> Self {
+        Self {
+            inner,
+            user_agent: user_agent.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl HttpClient for UserAgent {
+    async fn send(&self, mut req: Request) -> Result<Response, Error> {
+        if req.header("User-Agent").is_none() {
+            req.insert_header("User-Agent", self.user_agent.as_str());
+        }
+        self.inner.send(req).await
+    }
+}
+
+/// Pinned server keys, for a TLS certificate verifier that should only
+/// accept the ACME server's own keys (or a private CA's), in addition to the
+/// root store.
+///
+/// `ReqwestClient::with_config` enforces them (see `TransportConfig`); for
+/// other `HttpClient`s, call `check_chain` from the verifier of their TLS
+/// stack.
+#[cfg(any(feature = "x509", feature = "reqwest-rustls"))]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SpkiPins(Vec<[u8; 32]>);
+
+#[cfg(any(feature = "x509", feature = "reqwest-rustls"))]
+impl SpkiPins {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the SHA-256 hash of a SubjectPublicKeyInfo; see
+    /// `x509::spki_sha256`.
+    pub fn sha256(mut self, hash: [u8; 32]) -> Self {
+        self.0.push(hash);
+        self
+    }
+
+    /// Adds a base64 pin, as printed by e.g. `openssl x509 -pubkey -noout |
+    /// openssl pkey -pubin -outform der | openssl dgst -sha256 -binary |
+    /// base64`.
+    pub fn base64(self, pin: &str) -> crate::AcmeResult<Self> {
+        let hash = base64::decode(pin.trim())
+            .ok()
+            .and_then(|hash| <[u8; 32]>::try_from(hash).ok())
+            .ok_or_else(|| {
+                crate::AcmeError::InvalidCertificate(format!("invalid SPKI pin {:?}", pin))
+            })?;
+        Ok(self.sha256(hash))
+    }
+
+    /// Accepts a server's DER certificate chain if the key of any certificate
+    /// in it is pinned (or no keys are), like HPKP.
+    pub fn check_chain<'c>(
+        &self,
+        chain: impl IntoIterator<Item = &'c [u8]>,
+    ) -> crate::AcmeResult<()> {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+        for cert_der in chain {
+            if self.0.contains(&spki_sha256(cert_der)?) {
+                return Ok(());
+            }
+        }
+        Err(crate::AcmeError::InvalidCertificate(
+            "no key in the server's chain is pinned".to_string(),
+        ))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Like `x509::spki_sha256`, for builds without openssl.
+#[cfg(all(feature = "reqwest-rustls", not(feature = "x509")))]
+fn spki_sha256(cert_der: &[u8]) -> crate::AcmeResult<[u8; 32]> {
+    use sha2::{Digest, Sha256};
+
+    let cert_der = rustls::pki_types::CertificateDer::from(cert_der);
+    let cert = webpki::EndEntityCert::try_from(&cert_der)
+        .map_err(|err| crate::AcmeError::InvalidCertificate(err.to_string()))?;
+    Ok(Sha256::digest(&cert.subject_public_key_info()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use crate::{
+        test_support::mock_server::{MockAcmeServer, MOCK_DIRECTORY_URL},
+        Client,
+    };
+
+    use super::*;
+
+    #[async_std::test]
+    async fn wraps_another_client() {
+        let server = MockAcmeServer::new();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counted = requests.clone();
+        let http = FnHttpClient::new(move |req| {
+            let server = server.clone();
+            counted.fetch_add(1, Ordering::SeqCst);
+            async move { server.send(req).await }
+        });
+
+        let client =
+            Client::for_directory_url(Arc::new(http) as Arc<dyn HttpClient>, MOCK_DIRECTORY_URL)
+                .await
+                .unwrap();
+        client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+        assert!(requests.load(Ordering::SeqCst) >= 3);
+    }
+}
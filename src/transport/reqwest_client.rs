@@ -0,0 +1,456 @@
+use std::{io, sync::Arc};
+
+use async_trait::async_trait;
+use futures_util::{io::BufReader, TryStreamExt};
+
+#[cfg(feature = "reqwest-rustls")]
+use rustls::{
+    client::{
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        WebPkiServerVerifier,
+    },
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    DigitallySignedStruct, SignatureScheme,
+};
+
+#[cfg(feature = "reqwest-rustls")]
+use super::SpkiPins;
+use super::SyncRead;
+use super::{http_types::StatusCode, Body, Error, HttpClient, Request, Response};
+#[cfg(feature = "reqwest-rustls")]
+use crate::{AcmeError, AcmeResult};
+
+/// An `HttpClient` that sends requests with a `reqwest::Client`, e.g.
+/// `lets_encrypt_client(ReqwestClient::new(reqwest::Client::new()))`.
+///
+/// Proxies, TLS roots and timeouts are those the `reqwest::Client` was
+/// built with, or those of a `TransportConfig` (`with_config`). reqwest
+/// needs a tokio runtime.
+#[derive(Clone, Debug, Default)]
+pub struct ReqwestClient {
+    client: reqwest::Client,
+}
+
+impl ReqwestClient {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+
+    /// Connects over rustls as `config` says; see
+    /// `TransportConfig::client_builder` to also set e.g. timeouts.
+    #[cfg(feature = "reqwest-rustls")]
+    pub fn with_config(config: &TransportConfig) -> AcmeResult<Self> {
+        let client = config.client_builder()?.build().map_err(|err| {
+            AcmeError::InvalidState(format!("couldn't build the reqwest client: {}", err))
+        })?;
+        Ok(Self::new(client))
+    }
+
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+}
+
+impl From<reqwest::Client> for ReqwestClient {
+    fn from(client: reqwest::Client) -> Self {
+        Self::new(client)
+    }
+}
+
+impl From<ReqwestClient> for Arc<dyn HttpClient> {
+    fn from(client: ReqwestClient) -> Self {
+        Arc::new(client)
+    }
+}
+
+/// How `ReqwestClient::with_config` connects to the ACME server, e.g. to
+/// trust only a private CA, reached through an authenticating proxy:
+///
+/// ```ignore
+/// let config = TransportConfig {
+///     root_certificates: vec![private_ca_der],
+///     built_in_roots: false,
+///     proxy: Some(ProxyConfig {
+///         url: "http://proxy.internal:3128".to_string(),
+///         basic_auth: Some(("acme".to_string(), proxy_password)),
+///     }),
+///     ..Default::default()
+/// };
+/// let client = Client::builder()
+///     .transport(config)
+///     .directory_url("https://acme.internal:8443/dir")
+///     .build()
+///     .await?;
+/// ```
+#[cfg(feature = "reqwest-rustls")]
+#[derive(Clone, Debug)]
+pub struct TransportConfig {
+    /// DER certificates of root CAs to trust, e.g. a private ACME server's.
+    pub root_certificates: Vec<Vec<u8>>,
+
+    /// Whether to also trust the Mozilla roots of webpki-roots (the
+    /// default).
+    pub built_in_roots: bool,
+
+    /// If any, a key that the server's chain must contain (besides being
+    /// issued by a trusted root). Only the certificates the server sends
+    /// are checked, which usually leaves out the root itself.
+    pub spki_pins: SpkiPins,
+
+    /// The proxy for all requests; if None, that of the `HTTPS_PROXY` (etc.)
+    /// environment variables.
+    pub proxy: Option<ProxyConfig>,
+}
+
+/// An HTTP(S) proxy, which https requests tunnel through with CONNECT.
+#[cfg(feature = "reqwest-rustls")]
+#[derive(Clone)]
+pub struct ProxyConfig {
+    /// e.g. "http://proxy.internal:3128".
+    pub url: String,
+
+    /// A username and password for the proxy's Basic authentication.
+    pub basic_auth: Option<(String, String)>,
+}
+
+#[cfg(feature = "reqwest-rustls")]
+impl std::fmt::Debug for ProxyConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProxyConfig")
+            .field("url", &self.url)
+            .field(
+                "basic_auth",
+                &self
+                    .basic_auth
+                    .as_ref()
+                    .map(|(username, _)| (username, "<redacted>")),
+            )
+            .finish()
+    }
+}
+
+#[cfg(feature = "reqwest-rustls")]
+impl Default for TransportConfig {
+    fn default() -> Self {
+        Self {
+            root_certificates: Vec::new(),
+            built_in_roots: true,
+            spki_pins: SpkiPins::new(),
+            proxy: None,
+        }
+    }
+}
+
+#[cfg(feature = "reqwest-rustls")]
+impl TransportConfig {
+    /// A `reqwest::ClientBuilder` that connects as configured; its TLS
+    /// settings must be left alone.
+    pub fn client_builder(&self) -> AcmeResult<reqwest::ClientBuilder> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut roots = rustls::RootCertStore::empty();
+        if self.built_in_roots {
+            roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        }
+        for root in &self.root_certificates {
+            roots
+                .add(CertificateDer::from(root.clone()))
+                .map_err(|err| AcmeError::InvalidCertificate(format!("root CA: {}", err)))?;
+        }
+        let verifier =
+            WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+                .build()
+                .map_err(|err| AcmeError::InvalidState(format!("TLS roots: {}", err)))?;
+        let verifier: Arc<dyn ServerCertVerifier> = if self.spki_pins.is_empty() {
+            verifier
+        } else {
+            Arc::new(PinnedVerifier {
+                inner: verifier,
+                pins: self.spki_pins.clone(),
+            })
+        };
+        let tls = rustls::ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(|err| AcmeError::InvalidState(format!("TLS versions: {}", err)))?
+            .dangerous()
+            .with_custom_certificate_verifier(verifier)
+            .with_no_client_auth();
+        let mut builder = reqwest::Client::builder().use_preconfigured_tls(tls);
+        if let Some(proxy) = &self.proxy {
+            let mut proxy_url = reqwest::Proxy::all(&proxy.url).map_err(|err| {
+                AcmeError::InvalidState(format!("proxy {:?}: {}", proxy.url, err))
+            })?;
+            if let Some((username, password)) = &proxy.basic_auth {
+                proxy_url = proxy_url.basic_auth(username, password);
+            }
+            builder = builder.proxy(proxy_url);
+        }
+        Ok(builder)
+    }
+}
+
+/// Verifies as `inner` does, then checks `pins` against the chain.
+#[cfg(feature = "reqwest-rustls")]
+#[derive(Debug)]
+struct PinnedVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    pins: SpkiPins,
+}
+
+#[cfg(feature = "reqwest-rustls")]
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+        let chain = std::iter::once(end_entity)
+            .chain(intermediates)
+            .map(|cert| cert.as_ref());
+        self.pins
+            .check_chain(chain)
+            .map_err(|err| rustls::Error::General(err.to_string()))?;
+        Ok(verified)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+#[async_trait]
+impl HttpClient for ReqwestClient {
+    async fn send(&self, mut req: Request) -> Result<Response, Error> {
+        let method = reqwest::Method::from_bytes(req.method().to_string().as_bytes())
+            .map_err(|err| Error::new(StatusCode::BadRequest, err))?;
+        let mut builder = self.client.request(method, req.url().as_str());
+        for (name, values) in req.iter() {
+            for value in values {
+                builder = builder.header(name.as_str(), value.as_str());
+            }
+        }
+        let body = req.take_body().into_bytes().await?;
+        let resp = builder
+            .body(body)
+            .send()
+            .await
+            .map_err(|err| Error::new(StatusCode::BadGateway, err))?;
+
+        let status = StatusCode::try_from(resp.status().as_u16())?;
+        let mut response = Response::new(status);
+        for (name, value) in resp.headers() {
+            // http-types only takes ASCII values; no ACME header needs more
+            if let Ok(value) = value.to_str() {
+                response.append_header(name.as_str(), value);
+            }
+        }
+        let content_length = resp.content_length().map(|len| len as usize);
+        let body = resp
+            .bytes_stream()
+            .map_err(io::Error::other)
+            .into_async_read();
+        response.set_body(Body::from_reader(
+            BufReader::new(SyncRead::new(body)),
+            content_length,
+        ));
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "reqwest-rustls")]
+    use sha2::{Digest, Sha256};
+
+    #[cfg(feature = "reqwest-rustls")]
+    use crate::test_support::proxy::MockProxy;
+    use crate::{test_support::https_server::MockHttpsServer, Client};
+
+    use super::*;
+
+    fn reqwest_client(server: &MockHttpsServer) -> ReqwestClient {
+        let ca = reqwest::Certificate::from_der(server.ca_der()).unwrap();
+        reqwest::Client::builder()
+            .add_root_certificate(ca)
+            .build()
+            .unwrap()
+            .into()
+    }
+
+    #[tokio::test]
+    async fn registers_account_over_https() {
+        let https = MockHttpsServer::start().await;
+        let client = Client::for_directory_url(reqwest_client(&https), https.directory_url())
+            .await
+            .unwrap();
+        let account = client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+        assert!(account.url().starts_with(&format!(
+            "https://localhost:{}/account/",
+            https.addr().port()
+        )));
+    }
+
+    #[tokio::test]
+    async fn untrusted_server() {
+        let https = MockHttpsServer::start().await;
+        let http = ReqwestClient::new(reqwest::Client::new());
+        let client = Client::for_directory_url(http, https.directory_url()).await;
+        assert!(client.is_err());
+    }
+
+    #[cfg(feature = "reqwest-rustls")]
+    fn private_ca(server: &MockHttpsServer) -> TransportConfig {
+        TransportConfig {
+            root_certificates: vec![server.ca_der().to_vec()],
+            built_in_roots: false,
+            ..Default::default()
+        }
+    }
+
+    #[cfg(feature = "reqwest-rustls")]
+    #[tokio::test]
+    async fn trusts_configured_roots() {
+        let https = MockHttpsServer::start().await;
+        Client::builder()
+            .transport(private_ca(&https))
+            .directory_url(https.directory_url())
+            .build()
+            .await
+            .unwrap();
+
+        let http = ReqwestClient::with_config(&TransportConfig::default()).unwrap();
+        let client = Client::for_directory_url(http, https.directory_url()).await;
+        assert!(client.is_err());
+
+        let no_roots = TransportConfig {
+            built_in_roots: false,
+            ..Default::default()
+        };
+        assert!(ReqwestClient::with_config(&no_roots).is_err());
+        let bad_root = TransportConfig {
+            root_certificates: vec![b"not a certificate".to_vec()],
+            ..Default::default()
+        };
+        assert!(ReqwestClient::with_config(&bad_root).is_err());
+    }
+
+    #[cfg(feature = "reqwest-rustls")]
+    #[tokio::test]
+    async fn enforces_spki_pins() {
+        let https = MockHttpsServer::start().await;
+        let ca_pin = crate::transport::spki_sha256(https.ca_der()).unwrap();
+        let (_, ca) = x509_parser::parse_x509_certificate(https.ca_der()).unwrap();
+        assert_eq!(
+            ca_pin,
+            <[u8; 32]>::from(Sha256::digest(ca.tbs_certificate.subject_pki.raw))
+        );
+
+        let pinned = TransportConfig {
+            spki_pins: SpkiPins::new().sha256([0; 32]).sha256(ca_pin),
+            ..private_ca(&https)
+        };
+        let http = ReqwestClient::with_config(&pinned).unwrap();
+        Client::for_directory_url(http, https.directory_url())
+            .await
+            .unwrap();
+
+        let mispinned = TransportConfig {
+            spki_pins: SpkiPins::new().sha256([0; 32]),
+            ..private_ca(&https)
+        };
+        let http = ReqwestClient::with_config(&mispinned).unwrap();
+        let client = Client::for_directory_url(http, https.directory_url()).await;
+        assert!(client.is_err());
+    }
+
+    #[cfg(feature = "reqwest-rustls")]
+    #[tokio::test]
+    async fn connects_through_proxy() {
+        let https = MockHttpsServer::start().await;
+        let proxy = MockProxy::start("acme", "hunter2").await;
+        let through_proxy = |password: &str| TransportConfig {
+            proxy: Some(ProxyConfig {
+                url: proxy.url(),
+                basic_auth: Some(("acme".to_string(), password.to_string())),
+            }),
+            ..private_ca(&https)
+        };
+        assert!(!format!("{:?}", through_proxy("hunter2")).contains("hunter2"));
+
+        let client = Client::builder()
+            .transport(through_proxy("hunter2"))
+            .directory_url(https.directory_url())
+            .build()
+            .await
+            .unwrap();
+        client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+        let tunnels = proxy.tunnels();
+        assert!(tunnels > 0);
+
+        let client = Client::builder()
+            .transport(through_proxy("wrong"))
+            .directory_url(https.directory_url())
+            .build()
+            .await;
+        assert!(client.is_err());
+        assert_eq!(proxy.tunnels(), tunnels);
+
+        Client::builder()
+            .transport(private_ca(&https))
+            .proxy(ProxyConfig {
+                url: proxy.url(),
+                basic_auth: Some(("acme".to_string(), "hunter2".to_string())),
+            })
+            .directory_url(https.directory_url())
+            .build()
+            .await
+            .unwrap();
+        assert!(proxy.tunnels() > tunnels);
+    }
+
+    #[cfg(feature = "reqwest-rustls")]
+    #[tokio::test]
+    async fn http_or_transport() {
+        let https = MockHttpsServer::start().await;
+        let client = Client::builder()
+            .http(reqwest_client(&https))
+            .transport(private_ca(&https))
+            .directory_url(https.directory_url())
+            .build()
+            .await;
+        assert!(matches!(client, Err(AcmeError::InvalidState(_))));
+    }
+}
@@ -0,0 +1,186 @@
+use std::{error::Error as StdError, fmt::Debug, io};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::{io::BufReader, TryStreamExt};
+use http_body::Body as HttpBody;
+use http_body_util::{BodyDataStream, Full};
+use tower_service::Service;
+
+use super::SyncRead;
+use super::{http_types::StatusCode, Body, Error, HttpClient, Request, Response};
+
+type BoxError = Box<dyn StdError + Send + Sync>;
+
+/// An `HttpClient` that sends requests through a `tower::Service`, e.g. a
+/// hyper-util client (with its own connection pool settings) behind the
+/// usual tower layers:
+///
+/// ```ignore
+/// let hyper = hyper_util::client::legacy::Client::builder(TokioExecutor::new())
+///     .pool_max_idle_per_host(2)
+///     .build::<_, Full<Bytes>>(https_connector);
+/// let service = tower::ServiceBuilder::new()
+///     .timeout(Duration::from_secs(30))
+///     .service(hyper);
+/// let client = lets_encrypt_client(TowerClient::new(service)).await?;
+/// ```
+///
+/// The service is cloned for each request, and waited on until ready.
+#[derive(Clone)]
+pub struct TowerClient<S> {
+    service: S,
+}
+
+impl<S> TowerClient<S> {
+    pub fn new(service: S) -> Self {
+        Self { service }
+    }
+}
+
+impl<S> Debug for TowerClient<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TowerClient").finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl<S, ResponseBody> HttpClient for TowerClient<S>
+where
+    S: Service<http::Request<Full<Bytes>>, Response = http::Response<ResponseBody>>
+        + Clone
+        + Send
+        + Sync
+        + Unpin
+        + 'static,
+    S::Future: Send,
+    S::Error: Into<BoxError>,
+    ResponseBody: HttpBody<Data = Bytes> + Send + 'static,
+    ResponseBody::Error: Into<BoxError>,
+{
+    async fn send(&self, mut req: Request) -> Result<Response, Error> {
+        let mut builder = http::Request::builder()
+            .method(req.method().to_string().as_str())
+            .uri(req.url().as_str());
+        for (name, values) in req.iter() {
+            for value in values {
+                builder = builder.header(name.as_str(), value.as_str());
+            }
+        }
+        let body = Full::new(Bytes::from(req.take_body().into_bytes().await?));
+        let request = builder
+            .body(body)
+            .map_err(|err| Error::new(StatusCode::BadRequest, err))?;
+
+        let mut service = self.service.clone();
+        futures_util::future::poll_fn(|cx| service.poll_ready(cx))
+            .await
+            .map_err(service_error)?;
+        let (parts, body) = service
+            .call(request)
+            .await
+            .map_err(service_error)?
+            .into_parts();
+
+        let mut response = Response::new(StatusCode::try_from(parts.status.as_u16())?);
+        for (name, value) in &parts.headers {
+            // http-types only takes ASCII values; no ACME header needs more
+            if let Ok(value) = value.to_str() {
+                response.append_header(name.as_str(), value);
+            }
+        }
+        let content_length = body.size_hint().exact().map(|len| len as usize);
+        let body = BodyDataStream::new(Box::pin(body))
+            .map_err(|err| io::Error::other(err.into()))
+            .into_async_read();
+        response.set_body(Body::from_reader(
+            BufReader::new(SyncRead::new(body)),
+            content_length,
+        ));
+        Ok(response)
+    }
+}
+
+fn service_error(err: impl Into<BoxError>) -> Error {
+    Error::new(StatusCode::BadGateway, anyhow::anyhow!(err.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use http_body_util::BodyExt;
+    use tower::ServiceBuilder;
+
+    use crate::{test_support::mock_server::MockAcmeServer, Client};
+
+    use super::*;
+
+    /// Converts requests for `server` between `http` and http-types.
+    async fn call_mock(
+        server: MockAcmeServer,
+        req: http::Request<Full<Bytes>>,
+    ) -> Result<http::Response<Full<Bytes>>, BoxError> {
+        let (parts, body) = req.into_parts();
+        let mut mock_req = Request::new(
+            parts.method.as_str().parse().unwrap(),
+            parts.uri.to_string().as_str(),
+        );
+        for (name, value) in &parts.headers {
+            mock_req.append_header(name.as_str(), value.to_str()?);
+        }
+        mock_req.set_body(body.collect().await?.to_bytes().to_vec());
+
+        let mut resp = server.send(mock_req).await?;
+        let mut builder = http::Response::builder().status(u16::from(resp.status()));
+        for (name, values) in resp.iter() {
+            for value in values {
+                builder = builder.header(name.as_str(), value.as_str());
+            }
+        }
+        let body = resp.body_bytes().await?;
+        Ok(builder.body(Full::new(Bytes::from(body)))?)
+    }
+
+    #[tokio::test]
+    async fn registers_account_through_layers() {
+        let server = MockAcmeServer::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+        let service = ServiceBuilder::new()
+            .concurrency_limit(1)
+            .service(tower::service_fn(move |req| {
+                counted.fetch_add(1, Ordering::SeqCst);
+                call_mock(server.clone(), req)
+            }));
+
+        let client = Client::for_directory_url(
+            Arc::new(TowerClient::new(service)) as Arc<dyn HttpClient>,
+            crate::test_support::mock_server::MOCK_DIRECTORY_URL,
+        )
+        .await
+        .unwrap();
+        client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+        // Directory, newNonce and newAccount
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn service_errors() {
+        let service = tower::service_fn(|_: http::Request<Full<Bytes>>| async {
+            Err::<http::Response<Full<Bytes>>, BoxError>("connection refused".into())
+        });
+        let err = TowerClient::new(service)
+            .send(Request::get("https://acme.test/directory"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::BadGateway);
+        assert!(err.to_string().contains("connection refused"), "{}", err);
+    }
+}
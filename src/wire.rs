@@ -3,7 +3,12 @@ pub mod authorization;
 pub mod challenge;
 pub mod client;
 pub mod common;
+pub mod delegation;
 pub mod directory;
 pub mod identifier;
 pub mod order;
 pub mod problem;
+pub mod quirks;
+pub mod redact;
+pub mod renewal_info;
+pub mod transcript;
@@ -1,11 +1,13 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use super::common::{is_false, LocationResource, ResourceStatus};
+use crate::error::AcmeOp;
+
+use super::common::{is_false, LocationResource, MetaResource, ResourceMeta, ResourceStatus};
 
 /// ACME Account resource
 /// https://datatracker.ietf.org/doc/html/rfc8555#section-7.1.2
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct AccountResource {
     /// The status of this account.
@@ -37,9 +39,26 @@ pub struct AccountResource {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub orders: Option<String>,
 
+    /// For an Identifier Owner's account, a URL from which the list of
+    /// delegations configured for it can be fetched.
+    /// https://www.rfc-editor.org/rfc/rfc9115.html#section-2.3.1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delegations: Option<String>,
+
     /// The URL of this resource, as returned in the Location header.
     #[serde(skip)]
     pub location: Option<String>,
+
+    #[serde(skip)]
+    pub meta: ResourceMeta,
+}
+
+impl MetaResource for AccountResource {
+    const FETCH_OPERATION: AcmeOp = AcmeOp::GetAccount;
+
+    fn meta_mut(&mut self) -> &mut ResourceMeta {
+        &mut self.meta
+    }
 }
 
 impl LocationResource for AccountResource {
@@ -79,10 +98,11 @@ pub struct NewAccountResource {
     pub external_account_binding: Option<Value>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum AccountStatus {
     /// Account objects are created in the "valid" state
+    #[default]
     Valid,
 
     /// "deactivated" should be used to indicate client-initiated deactivation
@@ -92,12 +112,6 @@ pub enum AccountStatus {
     Revoked,
 }
 
-impl Default for AccountStatus {
-    fn default() -> Self {
-        Self::Valid
-    }
-}
-
 impl ResourceStatus for AccountStatus {
     fn is_failure(&self) -> bool {
         !matches!(self, Self::Valid)
@@ -105,6 +119,7 @@ impl ResourceStatus for AccountStatus {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use serde_json::json;
 
@@ -1,15 +1,17 @@
 use chrono::{DateTime, FixedOffset};
 use serde::{Deserialize, Serialize};
 
+use crate::error::AcmeOp;
+
 use super::{
-    challenge::ChallengeResource,
-    common::{is_false, LocationResource, ResourceStatus},
+    challenge::{ChallengeResource, ChallengeStatus},
+    common::{is_false, LocationResource, MetaResource, ResourceMeta, ResourceStatus},
     identifier::AcmeIdentifier,
 };
 
 /// ACME Authorization resource
 /// https://datatracker.ietf.org/doc/html/rfc8555#section-7.1.4
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct AuthorizationResource {
     /// The identifier that the account is authorized to represent.
@@ -41,9 +43,54 @@ pub struct AuthorizationResource {
     #[serde(default, skip_serializing_if = "is_false")]
     pub wildcard: bool,
 
+    /// Whether this authorization also covers subdomains of the identifier.
+    /// https://www.rfc-editor.org/rfc/rfc9444.html#section-4.1
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub subdomain_auth_allowed: bool,
+
     /// The URL of this resource, as returned in the Location header.
     #[serde(skip)]
     pub location: Option<String>,
+
+    #[serde(skip)]
+    pub meta: ResourceMeta,
+}
+
+impl MetaResource for AuthorizationResource {
+    const FETCH_OPERATION: AcmeOp = AcmeOp::GetAuthorization;
+
+    fn meta_mut(&mut self) -> &mut ResourceMeta {
+        &mut self.meta
+    }
+
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-7.1.4
+    fn violations(&self) -> Vec<String> {
+        let mut violations = Vec::new();
+        if self.status == AuthorizationStatus::Valid {
+            if self.expires.is_none() {
+                violations.push("valid authorization has no expires".to_string());
+            }
+            let validated = self
+                .challenges
+                .iter()
+                .any(|challenge| challenge.status == ChallengeStatus::Valid);
+            if !validated {
+                violations.push("valid authorization has no valid challenge".to_string());
+            }
+        }
+        if self.wildcard && self.identifier.value.starts_with("*.") {
+            violations.push("wildcard authorization's identifier has a \"*.\" prefix".to_string());
+        }
+        for challenge in &self.challenges {
+            violations.extend(
+                challenge
+                    .violations()
+                    .into_iter()
+                    .map(|violation| format!("{}: {}", challenge.url, violation)),
+            );
+        }
+        violations
+    }
 }
 
 impl LocationResource for AuthorizationResource {
@@ -52,6 +99,19 @@ impl LocationResource for AuthorizationResource {
     }
 }
 
+/// ACME newAuthz (pre-authorization) request
+/// https://www.rfc-editor.org/rfc/rfc8555.html#section-7.4.1
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NewAuthorizationResource {
+    pub identifier: AcmeIdentifier,
+
+    /// Asks for an authorization that also covers subdomains.
+    /// https://www.rfc-editor.org/rfc/rfc9444.html#section-4.2
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub subdomain_auth_allowed: bool,
+}
+
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum AuthorizationStatus {
@@ -86,6 +146,7 @@ impl ResourceStatus for AuthorizationStatus {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use serde_json::json;
 
@@ -121,5 +182,66 @@ mod tests {
         assert_eq!(authz.identifier, AcmeIdentifier::dns("www.example.org"),);
         assert_eq!(authz.challenges.len(), 1);
         assert_eq!(authz.wildcard, false);
+        assert!(!authz.subdomain_auth_allowed);
+        assert!(authz.violations().is_empty());
+    }
+
+    #[test]
+    fn authorization_violations() {
+        let authz = AuthorizationResource::deserialize(json!({
+            "status": "valid",
+            "identifier": { "type": "dns", "value": "*.example.org" },
+            "challenges": [
+              {
+                "url": "https://example.com/acme/chall/prV_B7yEyA4",
+                "type": "dns-01",
+                "status": "pending",
+                "error": { "type": "urn:ietf:params:acme:error:dns" }
+              }
+            ],
+            "wildcard": true
+        }))
+        .unwrap();
+        assert_eq!(
+            authz.violations(),
+            [
+                "valid authorization has no expires",
+                "valid authorization has no valid challenge",
+                "wildcard authorization's identifier has a \"*.\" prefix",
+                "https://example.com/acme/chall/prV_B7yEyA4: pending challenge has an error",
+                "https://example.com/acme/chall/prV_B7yEyA4: dns-01 challenge has no token",
+            ]
+        );
+    }
+
+    #[test]
+    fn rfc9444_subdomain_authorization() {
+        let new_authz = serde_json::to_value(NewAuthorizationResource {
+            identifier: AcmeIdentifier::dns("example.org"),
+            subdomain_auth_allowed: true,
+        })
+        .unwrap();
+        assert_eq!(
+            new_authz,
+            json!({
+                "identifier": {"type": "dns", "value": "example.org"},
+                "subdomainAuthAllowed": true
+            })
+        );
+
+        let authz = AuthorizationResource::deserialize(json!({
+            "status": "pending",
+            "identifier": {"type": "dns", "value": "example.org"},
+            "challenges": [],
+            "subdomainAuthAllowed": true
+        }))
+        .unwrap();
+        assert!(authz.subdomain_auth_allowed);
+
+        let identifier = AcmeIdentifier::dns("foo.example.org").with_ancestor_domain("example.org");
+        assert_eq!(
+            serde_json::to_value(identifier).unwrap(),
+            json!({"type": "dns", "value": "foo.example.org", "ancestorDomain": "example.org"})
+        );
     }
 }
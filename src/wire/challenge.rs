@@ -1,11 +1,174 @@
+use std::{fmt::Display, str::FromStr};
+
 use chrono::{DateTime, FixedOffset};
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
 
-use super::{common::ResourceStatus, problem::AcmeProblem};
+use crate::{
+    base64url,
+    error::{AcmeError, AcmeOp, AcmeResult},
+};
+
+use super::{
+    common::{MetaResource, ResourceMeta, ResourceStatus},
+    problem::AcmeProblem,
+};
 
 pub static CHALLENGE_TYPE_DNS_01: &str = "dns-01";
 pub static CHALLENGE_TYPE_HTTP_01: &str = "http-01";
+pub static CHALLENGE_TYPE_TLS_ALPN_01: &str = "tls-alpn-01";
+pub static CHALLENGE_TYPE_ONION_CSR_01: &str = "onion-csr-01";
+
+/// https://www.iana.org/assignments/acme/acme.xhtml#acme-validation-methods
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(from = "String", into = "String")]
+pub enum ChallengeType {
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-8.3
+    Http01,
+
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-8.4
+    Dns01,
+
+    /// https://www.rfc-editor.org/rfc/rfc8737.html
+    TlsAlpn01,
+
+    /// https://www.rfc-editor.org/rfc/rfc9799.html#section-3.2
+    OnionCsr01,
+
+    Other(String),
+}
+
+impl ChallengeType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Http01 => CHALLENGE_TYPE_HTTP_01,
+            Self::Dns01 => CHALLENGE_TYPE_DNS_01,
+            Self::TlsAlpn01 => CHALLENGE_TYPE_TLS_ALPN_01,
+            Self::OnionCsr01 => CHALLENGE_TYPE_ONION_CSR_01,
+            Self::Other(other) => other,
+        }
+    }
+}
+
+impl From<&str> for ChallengeType {
+    fn from(s: &str) -> Self {
+        if s == CHALLENGE_TYPE_HTTP_01 {
+            Self::Http01
+        } else if s == CHALLENGE_TYPE_DNS_01 {
+            Self::Dns01
+        } else if s == CHALLENGE_TYPE_TLS_ALPN_01 {
+            Self::TlsAlpn01
+        } else if s == CHALLENGE_TYPE_ONION_CSR_01 {
+            Self::OnionCsr01
+        } else {
+            Self::Other(s.to_string())
+        }
+    }
+}
+
+impl From<String> for ChallengeType {
+    fn from(s: String) -> Self {
+        match Self::from(s.as_str()) {
+            Self::Other(_) => Self::Other(s),
+            known => known,
+        }
+    }
+}
+
+impl From<ChallengeType> for String {
+    fn from(challenge_type: ChallengeType) -> Self {
+        match challenge_type {
+            ChallengeType::Other(other) => other,
+            known => known.as_str().to_string(),
+        }
+    }
+}
+
+impl FromStr for ChallengeType {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(s.into())
+    }
+}
+
+impl Display for ChallengeType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A challenge's type-specific fields; see `ChallengeResource::details`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChallengeDetails<'a> {
+    Http01 {
+        token: &'a str,
+    },
+    Dns01 {
+        token: &'a str,
+    },
+    TlsAlpn01 {
+        token: &'a str,
+    },
+    /// The response is a CSR carrying `nonce`; see
+    /// `x509::CsrBuilder::onion_csr01_nonces`.
+    OnionCsr01 {
+        /// Standard (not URL-safe) base64, with padding.
+        nonce: &'a str,
+        /// The onion service's client authorization key, if it requires one.
+        auth_key: Option<&'a Value>,
+    },
+    Other {
+        type_: &'a str,
+        token: Option<&'a str>,
+        additional_fields: &'a Map<String, Value>,
+    },
+}
+
+/// The payloads of challenge types that aren't answered with an empty
+/// object; see `ChallengeStatePending::respond_with`.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum ChallengeResponse {
+    /// https://www.rfc-editor.org/rfc/rfc9799.html#section-3.2
+    OnionCsr01 {
+        /// base64url DER CSR
+        csr: String,
+    },
+
+    /// https://datatracker.ietf.org/doc/html/draft-acme-device-attest#section-4
+    DeviceAttest01 {
+        /// base64url WebAuthn attestation object (CBOR)
+        #[serde(rename = "attObj")]
+        att_obj: String,
+    },
+
+    /// https://www.rfc-editor.org/rfc/rfc9447.html#section-3
+    TkAuth01 {
+        /// The TNAuthList authority token
+        atc: String,
+    },
+}
+
+impl ChallengeResponse {
+    pub fn onion_csr01(csr_der: impl AsRef<[u8]>) -> Self {
+        Self::OnionCsr01 {
+            csr: base64url::encode(csr_der),
+        }
+    }
+
+    pub fn device_attest01(attestation_object: impl AsRef<[u8]>) -> Self {
+        Self::DeviceAttest01 {
+            att_obj: base64url::encode(attestation_object),
+        }
+    }
+
+    pub fn tkauth01(authority_token: impl Into<String>) -> Self {
+        Self::TkAuth01 {
+            atc: authority_token.into(),
+        }
+    }
+}
 
 /// ACME Challenge resource
 /// https://datatracker.ietf.org/doc/html/rfc8555#section-8
@@ -14,7 +177,7 @@ pub static CHALLENGE_TYPE_HTTP_01: &str = "http-01";
 pub struct ChallengeResource {
     /// The type of challenge encoded in the object.
     #[serde(rename = "type")]
-    pub type_: String,
+    pub type_: ChallengeType,
 
     /// The URL to which a response can be posted.
     pub url: String,
@@ -41,11 +204,181 @@ pub struct ChallengeResource {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub token: Option<String>,
 
+    /// What the server did while validating the challenge.
+    ///
+    /// NOTE: Not in RFC 8555, but sent by Boulder and Pebble.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub validation_record: Vec<ValidationRecord>,
+
     /// All additional fields are specified by the challenge type.
     ///
     /// NOTE: Since "token" is widely used it has its own field.
     #[serde(flatten)]
     pub additional_fields: Map<String, Value>,
+
+    #[serde(skip)]
+    pub meta: ResourceMeta,
+}
+
+impl MetaResource for ChallengeResource {
+    const FETCH_OPERATION: AcmeOp = AcmeOp::GetChallenge;
+
+    fn meta_mut(&mut self) -> &mut ResourceMeta {
+        &mut self.meta
+    }
+
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-8
+    fn violations(&self) -> Vec<String> {
+        let mut violations = Vec::new();
+        // A challenge being retried may carry the last attempt's error while
+        // "processing" (section 8.2)
+        if self.error.is_some()
+            && !matches!(
+                self.status,
+                ChallengeStatus::Invalid | ChallengeStatus::Processing
+            )
+        {
+            violations.push(format!("{:?} challenge has an error", self.status).to_lowercase());
+        }
+        if self.status == ChallengeStatus::Valid && self.validated.is_none() {
+            violations.push("valid challenge has no validated time".to_string());
+        }
+        let needs_token = matches!(
+            self.type_,
+            ChallengeType::Http01 | ChallengeType::Dns01 | ChallengeType::TlsAlpn01
+        );
+        if needs_token && self.token.is_none() {
+            violations.push(format!("{} challenge has no token", self.type_.as_str()));
+        }
+        violations
+    }
+}
+
+/// RFC 8555 requires at least 128 bits of entropy, i.e. 22 base64url
+/// characters.
+pub const MIN_TOKEN_LENGTH: usize = 22;
+
+/// Far longer than any CA's tokens, but short enough for a file name.
+pub const MAX_TOKEN_LENGTH: usize = 128;
+
+/// Checks that a token is unpadded base64url of `MIN_TOKEN_LENGTH` to
+/// `MAX_TOKEN_LENGTH` characters, as RFC 8555 section 8.1 requires, so that
+/// it can't escape a path, URL or DNS name it is put in.
+pub fn validate_token(token: &str) -> AcmeResult<()> {
+    if !(MIN_TOKEN_LENGTH..=MAX_TOKEN_LENGTH).contains(&token.len()) {
+        return Err(AcmeError::InvalidToken(format!(
+            "length {} is outside {}..={}",
+            token.len(),
+            MIN_TOKEN_LENGTH,
+            MAX_TOKEN_LENGTH
+        )));
+    }
+    match token
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || *c == '-' || *c == '_'))
+    {
+        Some(c) => Err(AcmeError::InvalidToken(format!(
+            "{:?} isn't a base64url character",
+            c
+        ))),
+        None => Ok(()),
+    }
+}
+
+impl ChallengeResource {
+    /// The token, checked with `validate_token`.
+    pub fn validated_token(&self) -> AcmeResult<&str> {
+        let token = self
+            .token
+            .as_deref()
+            .ok_or(AcmeError::MissingExpectedField("token"))?;
+        validate_token(token)?;
+        Ok(token)
+    }
+
+    /// The type-specific fields, checking that those required by known
+    /// challenge types are present and that their tokens are valid.
+    pub fn details(&self) -> AcmeResult<ChallengeDetails<'_>> {
+        let token = || self.validated_token();
+        Ok(match &self.type_ {
+            ChallengeType::Http01 => ChallengeDetails::Http01 { token: token()? },
+            ChallengeType::Dns01 => ChallengeDetails::Dns01 { token: token()? },
+            ChallengeType::TlsAlpn01 => ChallengeDetails::TlsAlpn01 { token: token()? },
+            ChallengeType::OnionCsr01 => ChallengeDetails::OnionCsr01 {
+                nonce: self
+                    .additional_fields
+                    .get("nonce")
+                    .and_then(Value::as_str)
+                    .ok_or(AcmeError::MissingExpectedField("nonce"))?,
+                auth_key: self.additional_fields.get("authKey"),
+            },
+            ChallengeType::Other(type_) => ChallengeDetails::Other {
+                type_,
+                token: self.token.as_deref(),
+                additional_fields: &self.additional_fields,
+            },
+        })
+    }
+}
+
+/// One step of a validation attempt, e.g. one HTTP request (redirects add
+/// more) or DNS lookup. Fields vary by challenge type and server.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationRecord {
+    /// The URL requested, for http-01.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+
+    /// Sent as a string by Boulder.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_port",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub port: Option<u16>,
+
+    /// The addresses the hostname resolved to.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub addresses_resolved: Vec<String>,
+
+    /// The address that was finally connected to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address_used: Option<String>,
+
+    /// Addresses that were tried and failed before `address_used`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub addresses_tried: Vec<String>,
+
+    /// The DNS resolvers that were queried.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub resolver_addrs: Vec<String>,
+
+    #[serde(flatten)]
+    pub additional_fields: Map<String, Value>,
+}
+
+fn deserialize_port<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u16>, D::Error> {
+    use serde::de::Error;
+
+    match Option::<Value>::deserialize(deserializer)? {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::Number(port)) => port
+            .as_u64()
+            .and_then(|port| u16::try_from(port).ok())
+            .map(Some)
+            .ok_or_else(|| D::Error::custom(format!("invalid port {}", port))),
+        Some(Value::String(port)) => port
+            .parse()
+            .map(Some)
+            .map_err(|_| D::Error::custom(format!("invalid port {:?}", port))),
+        Some(other) => Err(D::Error::custom(format!("invalid port {}", other))),
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
@@ -78,6 +411,22 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn challenge_responses() {
+        assert_eq!(
+            serde_json::to_value(ChallengeResponse::onion_csr01([0xfb, 0xff])).unwrap(),
+            json!({"csr": "-_8"})
+        );
+        assert_eq!(
+            serde_json::to_value(ChallengeResponse::device_attest01(b"att")).unwrap(),
+            json!({"attObj": "YXR0"})
+        );
+        assert_eq!(
+            serde_json::to_value(ChallengeResponse::tkauth01("eyJ0eXAi")).unwrap(),
+            json!({"atc": "eyJ0eXAi"})
+        );
+    }
+
     #[test]
     fn rfc8555_challenge_example() {
         let chal = ChallengeResource::deserialize(json!({
@@ -90,12 +439,151 @@ mod tests {
         .unwrap();
 
         assert_eq!(chal.url, "https://example.com/acme/chall/prV_B7yEyA4");
-        assert_eq!(chal.type_, "http-01");
+        assert_eq!(chal.type_, ChallengeType::Http01);
         assert_eq!(chal.status, ChallengeStatus::Valid);
-        assert_eq!(chal.token.unwrap(), "DGyRejmCefe7v4NfDGDKfA");
+        assert_eq!(
+            chal.details().unwrap(),
+            ChallengeDetails::Http01 {
+                token: "DGyRejmCefe7v4NfDGDKfA"
+            }
+        );
         assert_eq!(
             chal.validated.unwrap(),
             DateTime::parse_from_rfc3339("2014-12-01T12:05:58.16Z").unwrap()
         );
     }
+
+    #[test]
+    fn token_validation() {
+        validate_token("DGyRejmCefe7v4NfDGDKfA").unwrap();
+        validate_token(&"a".repeat(MAX_TOKEN_LENGTH)).unwrap();
+        for token in [
+            "",
+            "short",
+            "../../../../etc/passwd",
+            "DGyRejmCefe7v4NfDGDKfA==",
+            "DGyRejmCefe7v4NfDGDKfA/x",
+            "DGyRejmCefe7v4NfDGDKfA.example.com",
+            "DGyRejmCefe7v4NfDGDKfé",
+            &"a".repeat(MAX_TOKEN_LENGTH + 1),
+        ] {
+            assert!(
+                matches!(validate_token(token), Err(AcmeError::InvalidToken(_))),
+                "{:?}",
+                token
+            );
+        }
+
+        let chal = ChallengeResource::deserialize(json!({
+            "url": "https://example.com/acme/chall/prV_B7yEyA4",
+            "type": "dns-01",
+            "status": "pending",
+            "token": "../../.well-known/other-token",
+        }))
+        .unwrap();
+        assert!(matches!(chal.details(), Err(AcmeError::InvalidToken(_))));
+    }
+
+    #[test]
+    fn validation_record() {
+        // As sent by Boulder
+        let chal = ChallengeResource::deserialize(json!({
+            "type": "http-01",
+            "status": "invalid",
+            "url": "https://example.com/acme/chall/z7b1Qw",
+            "token": "DGyRejmCefe7v4NfDGDKfA",
+            "error": {
+                "type": "urn:ietf:params:acme:error:connection",
+                "detail": "Timeout during connect (likely firewall problem)",
+                "status": 400
+            },
+            "validationRecord": [{
+                "url": "http://example.com/.well-known/acme-challenge/DGyRejmCefe7v4NfDGDKfA",
+                "hostname": "example.com",
+                "port": "80",
+                "addressesResolved": ["192.0.2.1", "2001:db8::1"],
+                "addressUsed": "192.0.2.1",
+                "addressesTried": ["2001:db8::1"],
+                "resolverAddrs": ["A:10.0.0.1:53"]
+            }, {
+                "hostname": "www.example.com",
+                "port": 443
+            }]
+        }))
+        .unwrap();
+
+        let records = &chal.validation_record;
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].hostname.as_deref(), Some("example.com"));
+        assert_eq!(records[0].port, Some(80));
+        assert_eq!(records[0].address_used.as_deref(), Some("192.0.2.1"));
+        assert_eq!(records[0].addresses_tried, ["2001:db8::1"]);
+        assert_eq!(records[1].port, Some(443));
+        assert!(!chal.additional_fields.contains_key("validationRecord"));
+    }
+
+    #[test]
+    fn unknown_challenge_type() {
+        let chal = ChallengeResource::deserialize(json!({
+                "url": "https://example.com/acme/chall/Rg5dV14Gh1Q",
+                "type": "email-reply-00",
+                "status": "pending",
+                "from": "acme-challenge@example.com"
+        }))
+        .unwrap();
+
+        assert_eq!(
+            chal.type_,
+            ChallengeType::Other("email-reply-00".to_string())
+        );
+        match chal.details().unwrap() {
+            ChallengeDetails::Other {
+                type_,
+                token,
+                additional_fields,
+            } => {
+                assert_eq!(type_, "email-reply-00");
+                assert_eq!(token, None);
+                assert!(additional_fields.contains_key("from"));
+            }
+            details => panic!("unexpected {:?}", details),
+        }
+        assert_eq!(
+            serde_json::to_value(&chal).unwrap()["type"],
+            json!("email-reply-00")
+        );
+
+        let mut chal = chal;
+        chal.type_ = ChallengeType::Dns01;
+        assert!(chal.details().is_err());
+        assert_eq!(
+            serde_json::to_value(&chal).unwrap()["type"],
+            json!("dns-01")
+        );
+    }
+
+    #[test]
+    fn onion_csr01_challenge() {
+        let chal = ChallengeResource::deserialize(json!({
+            "url": "https://example.com/acme/chall/bbc625c5",
+            "type": "onion-csr-01",
+            "status": "pending",
+            "nonce": "bI6/MRqV4gw=",
+            "authKey": {"kty": "OKP", "crv": "X25519", "x": "jaKUm5aWdLB4PpVw8WSPYlu9bbRbAmOFBmq0u1SX_a0"}
+        }))
+        .unwrap();
+
+        assert_eq!(chal.type_, ChallengeType::OnionCsr01);
+        match chal.details().unwrap() {
+            ChallengeDetails::OnionCsr01 { nonce, auth_key } => {
+                assert_eq!(nonce, "bI6/MRqV4gw=");
+                assert_eq!(auth_key.unwrap()["crv"], json!("X25519"));
+            }
+            details => panic!("unexpected {:?}", details),
+        }
+
+        let mut chal = chal;
+        chal.additional_fields.remove("nonce");
+        assert!(chal.details().is_err());
+    }
 }
@@ -1,41 +1,519 @@
 use std::{
     collections::VecDeque,
-    sync::{Arc, Mutex},
+    sync::{Arc, RwLock},
+    time::Duration,
 };
 
-use http_client::{Body, HttpClient, Request, Response};
-use serde::{de::DeserializeOwned, Serialize};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures_channel::oneshot;
+use serde::Serialize;
 use serde_json::{Map, Value};
 
 use super::{
     account::{AccountResource, AccountStatus, NewAccountResource},
-    authorization::AuthorizationResource,
+    authorization::{AuthorizationResource, AuthorizationStatus, NewAuthorizationResource},
     challenge::ChallengeResource,
-    common::LocationResource,
+    common::{
+        buffer_body, check_content_type, json_body, parse_cache_expiry, parse_links,
+        parse_retry_after, LocationResource, MetaResource, SignedResponse,
+    },
+    delegation::{DelegationList, DelegationResource},
     directory::DirectoryResource,
     order::{FinalizeOrder, NewOrderResource, OrderResource},
     problem::{AcmeProblem, AcmeProblemType},
+    quirks::Quirks,
+    renewal_info::RenewalInfo,
+    transcript::Transcript,
 };
+use crate::transport::{http_types::StatusCode, Body, HttpClient, Request, Response};
 use crate::{
-    crypto::jws::{self, jws_flattened, Jws, JwsHeader, JwsSigner},
-    error::{AcmeError, AcmeResult},
+    clock::{system_clock, Clock, SystemClock},
+    crypto::jws::{self, canonical_json, Jws, JwsHeader, JwsSigner, Payload},
+    error::{AcmeError, AcmeOp, AcmeResult},
+    sleep::{self, default_sleeper, Sleeper},
 };
 
+/// Clones are cheap and share the directory and nonce pool, e.g. between the
+/// accounts of one `Client`; the other settings are per clone.
+#[derive(Clone)]
 pub struct AcmeClient {
     http: Arc<dyn HttpClient>,
-    directory: DirectoryResource,
-    nonces: Mutex<VecDeque<String>>,
+    directory: Arc<RwLock<CachedDirectory>>,
+    nonces: Arc<NoncePool>,
+    retry_policy: RetryPolicy,
+    timeouts: Timeouts,
+    body_limits: BodyLimits,
+    directory_url: Option<String>,
+    auto_refresh_directory: bool,
+    sleeper: Arc<dyn Sleeper>,
+    clock: Arc<dyn Clock>,
+    poll_interval: Duration,
+    transcript: Option<Arc<Transcript>>,
+    nonce_source: Option<Arc<dyn NonceSource>>,
+    canonical_json: bool,
+    quirks: Arc<Quirks>,
+    strict: bool,
+}
+
+/// Supplies the nonces for signed requests; see
+/// `AcmeClient::with_nonce_source`. Without one, the client uses
+/// `PooledNonces`. A closure returning a fixed nonce builds reproducible JWS
+/// fixtures.
+#[async_trait]
+pub trait NonceSource: Send + Sync {
+    /// The nonce for the next request `client` signs.
+    async fn next_nonce(&self, client: &AcmeClient) -> AcmeResult<String>;
+}
+
+#[async_trait]
+impl<F> NonceSource for F
+where
+    F: Fn() -> String + Send + Sync,
+{
+    async fn next_nonce(&self, _client: &AcmeClient) -> AcmeResult<String> {
+        Ok(self())
+    }
+}
+
+/// Nonces from the Replay-Nonce headers of earlier responses, shared by the
+/// clones of a client, falling back to the newNonce endpoint when none are
+/// left.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PooledNonces;
+
+#[async_trait]
+impl NonceSource for PooledNonces {
+    async fn next_nonce(&self, client: &AcmeClient) -> AcmeResult<String> {
+        client.pooled_nonce().await
+    }
+}
+
+/// A fresh nonce from the newNonce endpoint for every request, never one
+/// from a response, e.g. where an audit requires each nonce to be fetched
+/// right before use. Costs a round trip per request.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FreshNonces;
+
+#[async_trait]
+impl NonceSource for FreshNonces {
+    async fn next_nonce(&self, client: &AcmeClient) -> AcmeResult<String> {
+        client.new_nonce().await
+    }
+}
+
+/// Nonces given up front, used in order, e.g. to replay a recorded session
+/// or script a test. Running out is an `AcmeError::InvalidState`.
+#[derive(Debug, Default)]
+pub struct ScriptedNonces {
+    nonces: std::sync::Mutex<VecDeque<String>>,
+}
+
+impl ScriptedNonces {
+    pub fn new(nonces: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            nonces: std::sync::Mutex::new(nonces.into_iter().map(Into::into).collect()),
+        }
+    }
+
+    /// Appends `nonce` to the script.
+    pub fn push(&self, nonce: impl Into<String>) {
+        self.nonces.lock().unwrap().push_back(nonce.into());
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.nonces.lock().unwrap().len()
+    }
+}
+
+#[async_trait]
+impl NonceSource for ScriptedNonces {
+    async fn next_nonce(&self, _client: &AcmeClient) -> AcmeResult<String> {
+        self.nonces
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| AcmeError::InvalidState("scripted nonces ran out".to_string()))
+    }
+}
+
+/// Nonces from Replay-Nonce headers, oldest first, shared by concurrent
+/// requests.
+///
+/// A request that finds the pool empty queues for the next nonce and, unless
+/// enough newNonce fetches are already under way for the queue, fetches one.
+/// Fetched nonces and those from responses go to the longest waiting request,
+/// so a burst of requests waits about one round trip for its nonces rather
+/// than one each, and fetches that turn out not to be needed fill the pool.
+#[derive(Default)]
+struct NoncePool {
+    // Never held across an await, so a plain lock does (and is never
+    // contended on wasm).
+    state: std::sync::Mutex<NoncePoolState>,
+}
+
+#[derive(Default)]
+struct NoncePoolState {
+    // With the order in which they arrived
+    nonces: VecDeque<(u64, String)>,
+    next_seq: u64,
+    waiters: VecDeque<oneshot::Sender<String>>,
+    // newNonce requests in flight for the waiters
+    fetching: usize,
+}
+
+/// Beyond this, the oldest pooled nonces are dropped; servers expire unused
+/// nonces anyway.
+const MAX_POOLED_NONCES: usize = 64;
+
+impl NoncePool {
+    /// Takes a pooled nonce, or else joins the queue; the caller should
+    /// fetch a nonce for the queue if the flag is set.
+    fn take_or_wait(&self) -> Result<String, (oneshot::Receiver<String>, bool)> {
+        let mut state = self.state.lock().unwrap();
+        if let Some((_, nonce)) = state.nonces.pop_front() {
+            return Ok(nonce);
+        }
+        state.waiters.retain(|waiter| !waiter.is_canceled());
+        let (sender, receiver) = oneshot::channel();
+        state.waiters.push_back(sender);
+        let fetch = state.fetching < state.waiters.len();
+        if fetch {
+            state.fetching += 1;
+        }
+        Err((receiver, fetch))
+    }
+
+    /// Hands `nonce` to the longest waiting request, or else pools it.
+    fn push(&self, nonce: String) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        state.next_seq += 1;
+        let seq = state.next_seq;
+        let mut nonce = nonce;
+        while let Some(waiter) = state.waiters.pop_front() {
+            match waiter.send(nonce) {
+                Ok(()) => return seq,
+                // Given up waiting, e.g. timed out
+                Err(unsent) => nonce = unsent,
+            }
+        }
+        state.nonces.push_back((seq, nonce));
+        if state.nonces.len() > MAX_POOLED_NONCES {
+            state.nonces.pop_front();
+        }
+        seq
+    }
+
+    /// Drops the nonces that arrived before `seq`, or all of them.
+    fn discard_before(&self, seq: Option<u64>) {
+        let mut state = self.state.lock().unwrap();
+        let seq = seq.unwrap_or(u64::MAX);
+        state.nonces.retain(|(arrived, _)| *arrived >= seq);
+    }
+}
+
+/// A newNonce fetch counted by `NoncePool::take_or_wait`, ended when dropped.
+struct NonceFetch<'a>(&'a NoncePool);
+
+impl Drop for NonceFetch<'_> {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap();
+        state.fetching -= 1;
+        state.waiters.retain(|waiter| !waiter.is_canceled());
+        // If the fetch failed (or was dropped), a waiter counting on it is
+        // turned away to fetch its own.
+        if state.fetching < state.waiters.len() {
+            state.waiters.pop_back();
+        }
+    }
+}
+
+struct CachedDirectory {
+    resource: Arc<DirectoryResource>,
+    expires: Option<DateTime<Utc>>,
 }
 
 pub static NO_PAYLOAD: Option<()> = None;
 
+/// https://www.rfc-editor.org/rfc/rfc8555.html#section-9.1
+pub static PEM_CERTIFICATE_CHAIN: &str = "application/pem-certificate-chain";
+
+/// Certificate formats a server may offer by content negotiation.
+/// https://www.rfc-editor.org/rfc/rfc8555.html#section-7.4.2
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CertificateFormat {
+    /// The PEM chain every server supports.
+    PemChain,
+    /// The end-entity certificate alone, in DER.
+    Der,
+    /// A degenerate (certificates-only) PKCS#7 SignedData of the chain.
+    Pkcs7,
+}
+
+impl CertificateFormat {
+    pub fn media_type(self) -> &'static str {
+        match self {
+            CertificateFormat::PemChain => PEM_CERTIFICATE_CHAIN,
+            CertificateFormat::Der => "application/pkix-cert",
+            CertificateFormat::Pkcs7 => "application/pkcs7-mime",
+        }
+    }
+}
+
+/// How long to wait between polls of a pending resource, by default.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Controls automatic retries of requests that failed for transient reasons.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries after a badNonce error (with a fresh nonce).
+    pub bad_nonce_retries: u32,
+
+    /// Retries of finalize after an orderNotReady error, if refreshing the
+    /// order shows it has become ready.
+    pub order_not_ready_retries: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        // Like certbot, retry exactly once on badNonce error
+        Self {
+            bad_nonce_retries: 1,
+            order_not_ready_retries: 1,
+        }
+    }
+}
+
+/// Limits on how long requests may take, timed with the client's `Sleeper`.
+/// None, the default, means no limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Timeouts {
+    /// Each HTTP request, until the response headers arrive. This includes
+    /// connecting, which `HttpClient` doesn't expose separately.
+    pub request: Option<Duration>,
+
+    /// Reading each response body. Setting this buffers bodies, including
+    /// `get_certificate_chain_body`.
+    pub read: Option<Duration>,
+
+    /// A whole operation: a directory fetch, or a signed request including
+    /// getting a nonce and any badNonce retries.
+    pub total: Option<Duration>,
+}
+
+/// The largest response bodies the client reads, in bytes, so that a
+/// broken or hostile server can't exhaust memory; a larger body fails with
+/// `AcmeError::ResponseTooLarge` before it is parsed. None means no limit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BodyLimits {
+    pub directory: Option<usize>,
+
+    /// Accounts, orders, authorizations, challenges and the other JSON
+    /// resources.
+    pub resource: Option<usize>,
+
+    /// Certificates, in any format. Setting this buffers
+    /// `get_certificate_chain_body`.
+    pub certificate: Option<usize>,
+
+    /// Unsuccessful responses, e.g. problem documents.
+    pub error: Option<usize>,
+}
+
+impl Default for BodyLimits {
+    fn default() -> Self {
+        Self {
+            directory: Some(64 * 1024),
+            resource: Some(1024 * 1024),
+            certificate: Some(1024 * 1024),
+            error: Some(64 * 1024),
+        }
+    }
+}
+
+impl BodyLimits {
+    /// The limit for a response with `status` to a request whose successful
+    /// responses are limited to `limit`.
+    fn for_status(&self, status: StatusCode, limit: Option<usize>) -> Option<usize> {
+        if status.is_success() {
+            limit
+        } else {
+            self.error
+        }
+    }
+}
+
 impl AcmeClient {
     pub fn new(http: impl Into<Arc<dyn HttpClient>>, directory: DirectoryResource) -> Self {
+        let quirks = Arc::new(Quirks::detect(&directory));
         Self {
             http: http.into(),
-            directory,
+            directory: Arc::new(RwLock::new(CachedDirectory {
+                resource: Arc::new(directory),
+                expires: None,
+            })),
             nonces: Default::default(),
+            retry_policy: Default::default(),
+            timeouts: Default::default(),
+            body_limits: Default::default(),
+            directory_url: None,
+            auto_refresh_directory: false,
+            sleeper: default_sleeper(),
+            clock: system_clock(),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            transcript: None,
+            nonce_source: None,
+            canonical_json: false,
+            quirks,
+            strict: false,
+        }
+    }
+
+    /// Replaces the workarounds detected from the directory.
+    pub fn with_quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = Arc::new(quirks);
+        self
+    }
+
+    pub fn quirks(&self) -> &Quirks {
+        &self.quirks
+    }
+
+    /// Rejects orders, authorizations and challenges that break RFC 8555 in
+    /// ways the client could otherwise work around, such as a valid order
+    /// without expires, with `AcmeError::NonConformant` listing every
+    /// violation. For developing against new server implementations.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Fails if strict and `resource` has violations.
+    fn conforming<R: MetaResource>(&self, resource: R) -> AcmeResult<R> {
+        if self.strict {
+            let violations = resource.violations();
+            if !violations.is_empty() {
+                return Err(AcmeError::NonConformant { violations });
+            }
         }
+        Ok(resource)
+    }
+
+    /// Takes nonces from `nonce_source` rather than `PooledNonces`. Nonces in
+    /// responses are still pooled, for a later switch back.
+    pub fn with_nonce_source(mut self, nonce_source: Option<Arc<dyn NonceSource>>) -> Self {
+        self.nonce_source = nonce_source;
+        self
+    }
+
+    /// Serializes payloads with sorted object keys; see `canonical_json`.
+    pub fn with_canonical_json(mut self, canonical_json: bool) -> Self {
+        self.canonical_json = canonical_json;
+        self
+    }
+
+    /// Records requests and responses in `transcript`; see `Transcript`.
+    pub fn with_transcript(mut self, transcript: Option<Arc<Transcript>>) -> Self {
+        self.transcript = transcript;
+        self
+    }
+
+    pub fn transcript(&self) -> Option<&Arc<Transcript>> {
+        self.transcript.as_ref()
+    }
+
+    /// Sets the timer used for polling; see `crate::sleep`.
+    pub fn with_sleeper(mut self, sleeper: Arc<dyn Sleeper>) -> Self {
+        self.sleeper = sleeper;
+        self
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    pub fn sleeper(&self) -> &Arc<dyn Sleeper> {
+        &self.sleeper
+    }
+
+    /// Sets the clock used for expiry checks and directory freshness; see
+    /// `crate::clock`.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    pub fn poll_interval(&self) -> Duration {
+        self.poll_interval
+    }
+
+    /// Waits the poll interval before a resource is fetched again.
+    pub async fn poll_sleep(&self) {
+        self.sleeper.sleep(self.poll_interval).await
+    }
+
+    /// Records when the directory stops being fresh, as reported by the
+    /// Cache-Control or Expires header it was served with.
+    pub fn with_directory_expires(self, expires: Option<DateTime<Utc>>) -> Self {
+        self.directory.write().unwrap().expires = expires;
+        self
+    }
+
+    pub fn directory_expires(&self) -> Option<DateTime<Utc>> {
+        self.directory.read().unwrap().expires
+    }
+
+    /// If enabled, a stale directory is re-fetched before it is next used.
+    /// Requires the directory URL to be known.
+    pub fn with_directory_auto_refresh(mut self, auto_refresh: bool) -> Self {
+        self.auto_refresh_directory = auto_refresh;
+        self
+    }
+
+    /// Records the URL the directory was fetched from.
+    pub fn with_directory_url(mut self, directory_url: Option<String>) -> Self {
+        self.directory_url = directory_url;
+        self
+    }
+
+    pub fn directory_url(&self) -> Option<&str> {
+        self.directory_url.as_deref()
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    pub fn timeouts(&self) -> &Timeouts {
+        &self.timeouts
+    }
+
+    pub fn with_body_limits(mut self, body_limits: BodyLimits) -> Self {
+        self.body_limits = body_limits;
+        self
+    }
+
+    pub fn body_limits(&self) -> &BodyLimits {
+        &self.body_limits
     }
 
     pub async fn for_directory_url(
@@ -43,22 +521,122 @@ impl AcmeClient {
         directory_url: &str,
     ) -> AcmeResult<AcmeClient> {
         let http_arc = http.into();
-        let directory: DirectoryResource =
-            Self::get_directory(http_arc.as_ref(), directory_url).await?;
-        Ok(Self::new(http_arc, directory))
+        let (directory, expires) = Self::fetch_directory(http_arc.as_ref(), directory_url).await?;
+        Ok(Self::new(http_arc, directory)
+            .with_directory_url(Some(directory_url.to_string()))
+            .with_directory_expires(expires))
     }
 
     pub async fn get_directory(
         http: &(impl HttpClient + ?Sized),
         directory_url: impl AsRef<str>,
     ) -> AcmeResult<DirectoryResource> {
+        Ok(Self::fetch_directory(http, directory_url).await?.0)
+    }
+
+    /// Fetches the directory along with when it stops being fresh.
+    pub async fn fetch_directory(
+        http: &(impl HttpClient + ?Sized),
+        directory_url: impl AsRef<str>,
+    ) -> AcmeResult<(DirectoryResource, Option<DateTime<Utc>>)> {
+        Self::fetch_directory_with_limits(http, directory_url, &Default::default(), &SystemClock)
+            .await
+    }
+
+    /// Like `fetch_directory`, with `body_limits` in place of the defaults
+    /// and freshness measured by `clock`.
+    pub async fn fetch_directory_with_limits(
+        http: &(impl HttpClient + ?Sized),
+        directory_url: impl AsRef<str>,
+        body_limits: &BodyLimits,
+        clock: &dyn Clock,
+    ) -> AcmeResult<(DirectoryResource, Option<DateTime<Utc>>)> {
         let mut resp = http.send(Request::get(directory_url.as_ref())).await?;
-        http_error_result(&mut resp).await?;
-        Ok(resp.body_json().await?)
+        let limit = body_limits.for_status(resp.status(), body_limits.directory);
+        buffer_body(&mut resp, limit).await?;
+        let now = clock.now();
+        http_error_result(&mut resp, &Quirks::default(), now).await?;
+        let expires = parse_cache_expiry(
+            resp.header("Cache-Control").map(|v| v.as_str()),
+            resp.header("Expires").map(|v| v.as_str()),
+            now,
+        );
+        Ok((json_body(&mut resp).await?, expires))
+    }
+
+    pub fn directory(&self) -> Arc<DirectoryResource> {
+        self.directory.read().unwrap().resource.clone()
+    }
+
+    /// Re-fetches the directory from the directory URL. Nonces already
+    /// obtained are kept; if the server rejects them after a change, the
+    /// usual badNonce retry applies.
+    pub async fn refresh_directory(&self) -> AcmeResult<Arc<DirectoryResource>> {
+        let directory_url = self.directory_url.as_deref().ok_or_else(|| {
+            AcmeError::InvalidState("directory URL unknown; can't refresh directory".to_string())
+        })?;
+        let (resource, expires) = self
+            .timeout(self.timeouts.total, AcmeOp::GetDirectory, async {
+                Self::fetch_directory_with_limits(
+                    self.http.as_ref(),
+                    directory_url,
+                    &self.body_limits,
+                    self.clock.as_ref(),
+                )
+                .await
+            })
+            .await?;
+        let resource = Arc::new(resource);
+        *self.directory.write().unwrap() = CachedDirectory {
+            resource: resource.clone(),
+            expires,
+        };
+        Ok(resource)
+    }
+
+    /// The directory, first refreshed if it is stale and auto-refresh is on.
+    async fn current_directory(&self) -> AcmeResult<Arc<DirectoryResource>> {
+        if self.auto_refresh_directory && self.directory_url.is_some() {
+            let stale = self
+                .directory_expires()
+                .is_some_and(|expires| expires <= self.clock.now());
+            if stale {
+                return self.refresh_directory().await;
+            }
+        }
+        Ok(self.directory())
+    }
+
+    /// Gets a fresh nonce from the newNonce endpoint, bypassing the pool.
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-7.2
+    pub async fn new_nonce(&self) -> AcmeResult<String> {
+        let directory = self.current_directory().await?;
+        let url = directory.new_nonce.as_str();
+        let res = async {
+            let mut resp = self
+                .send(
+                    AcmeOp::NewNonce,
+                    Request::head(url),
+                    None,
+                    self.body_limits.resource,
+                )
+                .await?;
+            http_error_result(&mut resp, &self.quirks, self.clock.now()).await?;
+            get_replay_nonce(&resp).ok_or(AcmeError::MissingExpectedHeader("Replay-Nonce"))
+        };
+        res.await
+            .map_err(|err| err.with_context(AcmeOp::NewNonce, url))
     }
 
-    pub fn directory(&self) -> &DirectoryResource {
-        &self.directory
+    /// Fills the nonce pool with `count` nonces from the newNonce endpoint
+    /// (at most the pool's capacity of 64), so that the next requests can be
+    /// sent without waiting for one.
+    pub async fn prefetch_nonces(&self, count: usize) -> AcmeResult<()> {
+        for _ in 0..count.min(MAX_POOLED_NONCES) {
+            let nonce = self.new_nonce().await?;
+            self.nonces.push(nonce);
+        }
+        Ok(())
     }
 
     /// https://www.rfc-editor.org/rfc/rfc8555.html#section-7.3
@@ -68,15 +646,33 @@ impl AcmeClient {
         public_jwk: &impl Serialize,
         new_account: &'_ NewAccountResource,
     ) -> AcmeResult<AccountResource> {
+        let directory = self.current_directory().await?;
         self.request_resource(
+            AcmeOp::NewAccount,
             signer,
-            &self.directory.new_account,
+            &directory.new_account,
             Auth::Jwk(public_jwk),
             Some(new_account),
         )
         .await
     }
 
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-7.3.2
+    pub async fn get_account(
+        &self,
+        signer: &impl JwsSigner,
+        account_url: &str,
+    ) -> AcmeResult<AccountResource> {
+        self.request_resource(
+            AcmeOp::GetAccount,
+            signer,
+            account_url,
+            Auth::kid(account_url),
+            NO_PAYLOAD,
+        )
+        .await
+    }
+
     /// https://www.rfc-editor.org/rfc/rfc8555.html#section-7.3.2
     pub async fn update_account(
         &self,
@@ -84,12 +680,56 @@ impl AcmeClient {
         account_url: &str,
         account: &AccountResource,
     ) -> AcmeResult<AccountResource> {
-        self.request_resource(signer, account_url, Auth::kid(account_url), Some(account))
-            .await
+        self.request_resource(
+            AcmeOp::UpdateAccount,
+            signer,
+            account_url,
+            Auth::kid(account_url),
+            Some(account),
+        )
+        .await
+    }
+
+    /// Replaces the account's contact URLs, sending only the contact field.
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-7.3.2
+    pub async fn update_account_contacts(
+        &self,
+        signer: &impl JwsSigner,
+        account_url: &str,
+        contact: &[String],
+    ) -> AcmeResult<AccountResource> {
+        let update = serde_json::json!({ "contact": contact });
+        self.request_resource(
+            AcmeOp::UpdateAccount,
+            signer,
+            account_url,
+            Auth::kid(account_url),
+            Some(update),
+        )
+        .await
     }
 
     // TODO: account key rollover: https://www.rfc-editor.org/rfc/rfc8555.html#section-7.3.5
 
+    /// Agrees to the server's current terms of service, e.g. after a
+    /// userActionRequired error for changed terms.
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-7.3.3
+    pub async fn agree_to_terms(
+        &self,
+        signer: &impl JwsSigner,
+        account_url: &str,
+    ) -> AcmeResult<AccountResource> {
+        let agreement = serde_json::json!({ "termsOfServiceAgreed": true });
+        self.request_resource(
+            AcmeOp::UpdateAccount,
+            signer,
+            account_url,
+            Auth::kid(account_url),
+            Some(agreement),
+        )
+        .await
+    }
+
     /// https://www.rfc-editor.org/rfc/rfc8555.html#section-7.3.6
     pub async fn account_deactivate(
         &self,
@@ -101,6 +741,7 @@ impl AcmeClient {
             ..Default::default()
         };
         self.request_resource(
+            AcmeOp::DeactivateAccount,
             signer,
             account_url,
             Auth::<'_, ()>::Kid(account_url),
@@ -116,15 +757,65 @@ impl AcmeClient {
         account_url: &str,
         new_order: &NewOrderResource,
     ) -> AcmeResult<OrderResource> {
+        let directory = self.current_directory().await?;
         self.request_resource(
+            AcmeOp::NewOrder,
             signer,
-            &self.directory.new_order,
+            &directory.new_order,
             Auth::kid(account_url),
             Some(new_order),
         )
         .await
     }
 
+    /// Servers needn't send a Location header with an order they return, so
+    /// the order's `location` is `order_url` unless they do.
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-7.1.3
+    pub async fn get_order(
+        &self,
+        signer: &impl JwsSigner,
+        account_url: &str,
+        order_url: &str,
+    ) -> AcmeResult<OrderResource> {
+        let resp = self
+            .request(
+                AcmeOp::GetOrder,
+                signer,
+                order_url,
+                Auth::kid(account_url),
+                NO_PAYLOAD,
+            )
+            .await?;
+        OrderResource::from_fetched_response(order_url, resp, self.clock.now())
+            .await
+            .and_then(|order| self.conforming(order))
+            .map_err(|err| err.with_context(AcmeOp::GetOrder, order_url))
+    }
+
+    /// Pre-authorizes an identifier, e.g. an ancestor domain for later
+    /// subdomain orders (RFC 9444).
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-7.4.1
+    pub async fn new_authorization(
+        &self,
+        signer: &impl JwsSigner,
+        account_url: &str,
+        new_authorization: &NewAuthorizationResource,
+    ) -> AcmeResult<AuthorizationResource> {
+        let directory = self.current_directory().await?;
+        let new_authz_url = directory
+            .new_authz
+            .as_deref()
+            .ok_or(AcmeError::MissingExpectedField("newAuthz"))?;
+        self.request_resource(
+            AcmeOp::NewAuthorization,
+            signer,
+            new_authz_url,
+            Auth::kid(account_url),
+            Some(new_authorization),
+        )
+        .await
+    }
+
     pub async fn finalize_order(
         &self,
         signer: &impl JwsSigner,
@@ -133,6 +824,7 @@ impl AcmeClient {
         finalize_order: &FinalizeOrder,
     ) -> AcmeResult<OrderResource> {
         self.request_resource(
+            AcmeOp::Finalize,
             signer,
             finalize_url,
             Auth::kid(account_url),
@@ -141,16 +833,183 @@ impl AcmeClient {
         .await
     }
 
+    /// A Content-Type other than "application/pem-certificate-chain" is an
+    /// `UnexpectedContentType` error; a missing one is accepted.
     pub async fn get_certificate_chain(
         &self,
         signer: &impl JwsSigner,
         account_url: &str,
         certificate_url: &str,
     ) -> AcmeResult<String> {
+        let body = self
+            .get_certificate_chain_body(signer, account_url, certificate_url)
+            .await?;
+        Ok(body.into_string().await?)
+    }
+
+    /// Like `get_certificate_chain`, but returns the PEM chain as an
+    /// unbuffered body, which implements `AsyncRead`. The Content-Type is
+    /// checked as for `get_certificate_chain`.
+    pub async fn get_certificate_chain_body(
+        &self,
+        signer: &impl JwsSigner,
+        account_url: &str,
+        certificate_url: &str,
+    ) -> AcmeResult<Body> {
         let mut resp = self
-            .request(signer, certificate_url, Auth::kid(account_url), NO_PAYLOAD)
+            .download_certificate(
+                signer,
+                account_url,
+                certificate_url,
+                CertificateFormat::PemChain,
+            )
             .await?;
-        Ok(resp.body_string().await?)
+        Ok(resp.take_body())
+    }
+
+    /// Downloads the certificate in another `format`, if the server offers
+    /// it; if it doesn't, its response is usually the PEM chain, which is an
+    /// `UnexpectedContentType` error.
+    pub async fn get_certificate(
+        &self,
+        signer: &impl JwsSigner,
+        account_url: &str,
+        certificate_url: &str,
+        format: CertificateFormat,
+    ) -> AcmeResult<Vec<u8>> {
+        let mut resp = self
+            .download_certificate(signer, account_url, certificate_url, format)
+            .await?;
+        Ok(resp.body_bytes().await?)
+    }
+
+    /// POST-as-GETs a certificate, accepting only `format`.
+    async fn download_certificate(
+        &self,
+        signer: &impl JwsSigner,
+        account_url: &str,
+        certificate_url: &str,
+        format: CertificateFormat,
+    ) -> AcmeResult<Response> {
+        let resp = self
+            .request_accepting(
+                AcmeOp::DownloadCertificate,
+                Some(format.media_type()),
+                signer,
+                certificate_url,
+                Auth::kid(account_url),
+                NO_PAYLOAD,
+            )
+            .await?;
+        check_certificate_content_type(&resp, format)
+            .map_err(|err| err.with_context(AcmeOp::DownloadCertificate, certificate_url))?;
+        Ok(resp)
+    }
+
+    /// Like `get_certificate_chain`, also returning the URLs of any
+    /// alternate chains the server offers (Link rel="alternate").
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-7.4.2
+    pub async fn get_certificate_chain_with_alternates(
+        &self,
+        signer: &impl JwsSigner,
+        account_url: &str,
+        certificate_url: &str,
+    ) -> AcmeResult<(String, Vec<String>)> {
+        let (body, alternates) = self
+            .get_certificate_chain_body_with_alternates(signer, account_url, certificate_url)
+            .await?;
+        let chain = body.into_string().await.map_err(|err| {
+            AcmeError::from(err).with_context(AcmeOp::DownloadCertificate, certificate_url)
+        })?;
+        Ok((chain, alternates))
+    }
+
+    /// Like `get_certificate_chain_with_alternates`, with the chain as an
+    /// unbuffered body.
+    pub async fn get_certificate_chain_body_with_alternates(
+        &self,
+        signer: &impl JwsSigner,
+        account_url: &str,
+        certificate_url: &str,
+    ) -> AcmeResult<(Body, Vec<String>)> {
+        let mut resp = self
+            .download_certificate(
+                signer,
+                account_url,
+                certificate_url,
+                CertificateFormat::PemChain,
+            )
+            .await?;
+        let alternates = match resp.header("Link") {
+            Some(values) => parse_links(values.iter().map(|v| v.as_str()), "alternate"),
+            None => Vec::new(),
+        };
+        Ok((resp.take_body(), alternates))
+    }
+
+    /// Downloads a certificate chain with an unauthenticated GET, as allowed
+    /// for orders created with "allow-certificate-get", e.g. by a CDN that a
+    /// certificate was delegated to.
+    /// https://www.rfc-editor.org/rfc/rfc9115.html#section-2.3.3
+    pub async fn get_certificate_chain_unauthenticated(
+        &self,
+        certificate_url: &str,
+    ) -> AcmeResult<String> {
+        self.timeout(self.timeouts.total, AcmeOp::DownloadCertificate, async {
+            let mut req = Request::get(certificate_url);
+            req.insert_header("Accept", PEM_CERTIFICATE_CHAIN);
+            let mut resp = self
+                .send(
+                    AcmeOp::DownloadCertificate,
+                    req,
+                    None,
+                    self.body_limits.certificate,
+                )
+                .await?;
+            http_error_result(&mut resp, &self.quirks, self.clock.now()).await?;
+            check_certificate_content_type(&resp, CertificateFormat::PemChain)?;
+            Ok(resp.body_string().await?)
+        })
+        .await
+        .map_err(|err| err.with_context(AcmeOp::DownloadCertificate, certificate_url))
+    }
+
+    /// https://www.rfc-editor.org/rfc/rfc9115.html#section-2.3.1
+    pub async fn get_delegations(
+        &self,
+        signer: &impl JwsSigner,
+        account_url: &str,
+        delegations_url: &str,
+    ) -> AcmeResult<DelegationList> {
+        let mut resp = self
+            .request(
+                AcmeOp::GetDelegation,
+                signer,
+                delegations_url,
+                Auth::kid(account_url),
+                NO_PAYLOAD,
+            )
+            .await?;
+        json_body(&mut resp).await
+    }
+
+    /// https://www.rfc-editor.org/rfc/rfc9115.html#section-2.3.1.1
+    pub async fn get_delegation(
+        &self,
+        signer: &impl JwsSigner,
+        account_url: &str,
+        delegation_url: &str,
+    ) -> AcmeResult<DelegationResource> {
+        let mut resp = self
+            .request(
+                AcmeOp::GetDelegation,
+                signer,
+                delegation_url,
+                Auth::kid(account_url),
+                NO_PAYLOAD,
+            )
+            .await?;
+        json_body(&mut resp).await
     }
 
     pub async fn get_authorization(
@@ -160,6 +1019,7 @@ impl AcmeClient {
         authorization_url: &str,
     ) -> AcmeResult<AuthorizationResource> {
         self.request_resource(
+            AcmeOp::GetAuthorization,
             signer,
             authorization_url,
             Auth::kid(account_url),
@@ -168,6 +1028,24 @@ impl AcmeClient {
         .await
     }
 
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-7.5.2
+    pub async fn deactivate_authorization(
+        &self,
+        signer: &impl JwsSigner,
+        account_url: &str,
+        authorization_url: &str,
+    ) -> AcmeResult<AuthorizationResource> {
+        let deactivate = serde_json::json!({ "status": AuthorizationStatus::Deactivated });
+        self.request_resource(
+            AcmeOp::DeactivateAuthorization,
+            signer,
+            authorization_url,
+            Auth::kid(account_url),
+            Some(deactivate),
+        )
+        .await
+    }
+
     pub async fn respond_challenge(
         &self,
         signer: &impl JwsSigner,
@@ -176,53 +1054,216 @@ impl AcmeClient {
         response: Option<Map<String, Value>>,
     ) -> AcmeResult<ChallengeResource> {
         let payload = response.unwrap_or_default();
-        let mut resp = self
-            .request(signer, challenge_url, Auth::kid(account_url), Some(payload))
+        let resp = self
+            .request(
+                AcmeOp::RespondChallenge,
+                signer,
+                challenge_url,
+                Auth::kid(account_url),
+                Some(payload),
+            )
             .await?;
-        Ok(resp.body_json().await?)
+        ChallengeResource::from_response(challenge_url, resp, self.clock.now())
+            .await
+            .and_then(|challenge| self.conforming(challenge))
+    }
+
+    /// https://datatracker.ietf.org/doc/html/draft-ietf-acme-ari#section-4.2
+    pub async fn get_renewal_info(&self, cert_id: &str) -> AcmeResult<RenewalInfo> {
+        let directory = self.current_directory().await?;
+        let renewal_info_url = directory
+            .renewal_info
+            .as_deref()
+            .ok_or(AcmeError::MissingExpectedField("renewalInfo"))?;
+        let url = format!("{}/{}", renewal_info_url.trim_end_matches('/'), cert_id);
+        self.timeout(self.timeouts.total, AcmeOp::GetRenewalInfo, async {
+            let mut resp = self
+                .send(
+                    AcmeOp::GetRenewalInfo,
+                    Request::get(url.as_str()),
+                    None,
+                    self.body_limits.resource,
+                )
+                .await?;
+            http_error_result(&mut resp, &self.quirks, self.clock.now()).await?;
+            json_body(&mut resp).await
+        })
+        .await
+        .map_err(|err| err.with_context(AcmeOp::GetRenewalInfo, &url))
     }
 
-    pub async fn get_resource<R: DeserializeOwned>(
+    pub async fn get_resource<R: MetaResource>(
         &self,
         signer: &impl JwsSigner,
         account_url: &str,
         resource_url: &str,
     ) -> AcmeResult<R> {
-        let mut resp = self
-            .request(signer, resource_url, Auth::kid(account_url), NO_PAYLOAD)
+        let operation = R::FETCH_OPERATION;
+        let resp = self
+            .request(
+                operation,
+                signer,
+                resource_url,
+                Auth::kid(account_url),
+                NO_PAYLOAD,
+            )
+            .await?;
+        R::from_response(resource_url, resp, self.clock.now())
+            .await
+            .and_then(|resource| self.conforming(resource))
+            .map_err(|err| err.with_context(operation, resource_url))
+    }
+
+    /// POST-as-GETs `url` and returns the response as received, e.g. for a
+    /// CA-specific endpoint this crate has no method for. Error responses
+    /// are still errors, after the usual badNonce retries.
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-6.3
+    pub async fn post_as_get(
+        &self,
+        signer: &impl JwsSigner,
+        account_url: &str,
+        url: &str,
+    ) -> AcmeResult<SignedResponse> {
+        let resp = self
+            .request(
+                AcmeOp::Fetch,
+                signer,
+                url,
+                Auth::kid(account_url),
+                NO_PAYLOAD,
+            )
+            .await?;
+        SignedResponse::from_response(resp)
+            .await
+            .map_err(|err| err.with_context(AcmeOp::Fetch, url))
+    }
+
+    /// Like `post_as_get`, signing `payload` instead of an empty payload,
+    /// e.g. to update a CA-specific resource.
+    pub async fn post(
+        &self,
+        signer: &impl JwsSigner,
+        account_url: &str,
+        url: &str,
+        payload: &impl Serialize,
+    ) -> AcmeResult<SignedResponse> {
+        let resp = self
+            .request(
+                AcmeOp::Post,
+                signer,
+                url,
+                Auth::kid(account_url),
+                Some(payload),
+            )
             .await?;
-        Ok(resp.body_json().await?)
+        SignedResponse::from_response(resp)
+            .await
+            .map_err(|err| err.with_context(AcmeOp::Post, url))
     }
 
     async fn request_resource<R: LocationResource>(
         &self,
+        operation: AcmeOp,
         signer: &impl JwsSigner,
         url: &str,
         auth: Auth<'_, impl Serialize>,
         payload: Option<impl Serialize>,
     ) -> AcmeResult<R> {
-        R::from_response(self.request(signer, url, auth, payload).await?).await
+        let resp = self.request(operation, signer, url, auth, payload).await?;
+        R::from_located_response(url, resp, self.clock.now())
+            .await
+            .and_then(|resource| self.conforming(resource))
+            .map_err(|err| err.with_context(operation, url))
     }
 
+    /// Sends a signed request, with the badNonce retries of the retry
+    /// policy. Errors carry `operation` and `url` as context.
     async fn request(
         &self,
+        operation: AcmeOp,
         signer: &impl JwsSigner,
         url: &str,
         auth: Auth<'_, impl Serialize>,
         payload: Option<impl Serialize>,
     ) -> AcmeResult<Response> {
-        let mut res = self.request_once(signer, url, &auth, &payload).await;
-        if let Err(AcmeError::AcmeProblem(ref problem)) = res {
-            // Like certbot, retry exactly once on badNonce error
-            if problem.has_type(AcmeProblemType::BadNonce) {
-                res = self.request_once(signer, url, &auth, &payload).await
+        self.request_accepting(operation, None, signer, url, auth, payload)
+            .await
+    }
+
+    /// Like `request`, with an Accept header if `accept` is given.
+    async fn request_accepting(
+        &self,
+        operation: AcmeOp,
+        accept: Option<&str>,
+        signer: &impl JwsSigner,
+        url: &str,
+        auth: Auth<'_, impl Serialize>,
+        payload: Option<impl Serialize>,
+    ) -> AcmeResult<Response> {
+        self.timeout(self.timeouts.total, operation, async {
+            let mut retries = self.retry_policy.bad_nonce_retries;
+            let res = loop {
+                let res = self
+                    .request_once(operation, accept, signer, url, &auth, &payload)
+                    .await;
+                match res {
+                    Err(AcmeError::AcmeProblem(ref problem))
+                        if retries > 0 && problem.has_type(AcmeProblemType::BadNonce) =>
+                    {
+                        retries -= 1;
+                    }
+                    res => break res,
+                }
+            };
+            match (res, &auth) {
+                (Err(AcmeError::AcmeProblem(problem)), &Auth::Kid(account_url))
+                    if problem.has_type(AcmeProblemType::Unauthorized) =>
+                {
+                    // Boxed, as this rarely taken path would otherwise
+                    // double the size of every request future
+                    let check = Box::pin(self.unusable_account_status(signer, account_url));
+                    Err(match check.await {
+                        Some(status) => AcmeError::AccountUnusable(status),
+                        None => AcmeError::AcmeProblem(problem),
+                    })
+                }
+                (res, _) => res,
             }
-        }
-        res
+        })
+        .await
+        .map_err(|err| err.with_context(operation, url))
+    }
+
+    /// Whether a request refused as unauthorized was refused because the
+    /// account itself is no longer valid, which a POST-as-GET of the account
+    /// (as in `Account::refresh`) tells apart from e.g. a request for another
+    /// account's order. `None` if that fetch fails too: RFC 8555 section
+    /// 7.3.6 lets servers refuse every request from a deactivated account.
+    async fn unusable_account_status(
+        &self,
+        signer: &impl JwsSigner,
+        account_url: &str,
+    ) -> Option<AccountStatus> {
+        let auth = Auth::kid(account_url);
+        let mut resp = self
+            .request_once(
+                AcmeOp::GetAccount,
+                None,
+                signer,
+                account_url,
+                &auth,
+                &NO_PAYLOAD,
+            )
+            .await
+            .ok()?;
+        let account: AccountResource = json_body(&mut resp).await.ok()?;
+        (account.status != AccountStatus::Valid).then_some(account.status)
     }
 
     async fn request_once(
         &self,
+        operation: AcmeOp,
+        accept: Option<&str>,
         signer: &impl JwsSigner,
         url: &str,
         auth: &Auth<'_, impl Serialize>,
@@ -232,8 +1273,16 @@ impl AcmeClient {
 
         let mut req = Request::post(url);
         req.set_body(&jws);
+        if let Some(accept) = accept {
+            req.insert_header("Accept", accept);
+        }
 
-        let mut resp = self.http.send(req).await?;
+        // Only certificate downloads ask for a particular media type
+        let limit = match accept {
+            Some(_) => self.body_limits.certificate,
+            None => self.body_limits.resource,
+        };
+        let mut resp = self.send(operation, req, Some(&jws), limit).await?;
         self.handle_response_headers(&mut resp).await?;
         Ok(resp)
     }
@@ -257,35 +1306,119 @@ impl AcmeClient {
             jwk,
         };
 
-        let payload_bytes = if let Some(p) = payload {
-            serde_json::to_vec(&p)?
-        } else {
-            Vec::new()
+        let payload_bytes = match payload {
+            Some(p) if self.canonical_json => Some(canonical_json(p)?),
+            Some(p) => Some(serde_json::to_vec(p)?),
+            None => None,
         };
+        let payload = match &payload_bytes {
+            Some(bytes) => Payload::Attached(bytes),
+            None => Payload::Empty,
+        };
+        jws::sign(signer, &jws_header, payload).map_err(AcmeError::CryptoError)
+    }
+
+    /// Sends `req`, recording it in the transcript if there is one. A
+    /// successful response's body may be at most `limit` bytes.
+    async fn send(
+        &self,
+        operation: AcmeOp,
+        req: Request,
+        jws: Option<&Jws>,
+        limit: Option<usize>,
+    ) -> AcmeResult<Response> {
+        let entry = self
+            .transcript
+            .as_ref()
+            .map(|transcript| transcript.request_entry(&req, jws));
+        let mut res = self
+            .timeout(self.timeouts.request, operation, async {
+                Ok(self.http.send(req).await?)
+            })
+            .await;
+        if let Ok(resp) = &mut res {
+            let limit = self.body_limits.for_status(resp.status(), limit);
+            if limit.is_some() || self.timeouts.read.is_some() {
+                self.timeout(self.timeouts.read, operation, buffer_body(resp, limit))
+                    .await?;
+            }
+        }
+        if let (Some(transcript), Some(entry)) = (&self.transcript, entry) {
+            transcript.record(entry, &mut res).await?;
+        }
+        res
+    }
 
-        jws_flattened(signer, &jws_header, &payload_bytes).map_err(AcmeError::CryptoError)
+    /// Runs `future`, failing with `AcmeError::Timeout` after `limit`. The
+    /// sleep is dropped as soon as `future` finishes, which cancels its
+    /// timer.
+    async fn timeout<T>(
+        &self,
+        limit: Option<Duration>,
+        operation: AcmeOp,
+        future: impl std::future::Future<Output = AcmeResult<T>>,
+    ) -> AcmeResult<T> {
+        match limit {
+            Some(limit) => sleep::timeout(self.sleeper.as_ref(), limit, future)
+                .await
+                .unwrap_or(Err(AcmeError::Timeout { operation })),
+            None => future.await,
+        }
     }
 
     async fn get_nonce(&self) -> AcmeResult<String> {
-        {
-            let mut nonces = self.nonces.lock().unwrap();
-            if let Some(nonce) = nonces.pop_front() {
+        match &self.nonce_source {
+            Some(nonce_source) => nonce_source.next_nonce(self).await,
+            None => PooledNonces.next_nonce(self).await,
+        }
+    }
+
+    /// Takes a nonce from the pool, or the newNonce endpoint if it's empty;
+    /// see `PooledNonces`.
+    pub async fn pooled_nonce(&self) -> AcmeResult<String> {
+        loop {
+            let (waiting, fetch) = match self.nonces.take_or_wait() {
+                Ok(nonce) => return Ok(nonce),
+                Err(waiting) => waiting,
+            };
+            if fetch {
+                let fetching = NonceFetch(&self.nonces);
+                match self.new_nonce().await {
+                    Ok(nonce) => {
+                        self.nonces.push(nonce);
+                    }
+                    Err(err) => {
+                        // A response may have served this request meanwhile
+                        let mut waiting = waiting;
+                        let served = waiting.try_recv();
+                        // Leave the queue before ending the fetch
+                        drop(waiting);
+                        drop(fetching);
+                        return match served {
+                            Ok(Some(nonce)) => Ok(nonce),
+                            _ => Err(err),
+                        };
+                    }
+                }
+            }
+            if let Ok(nonce) = waiting.await {
                 return Ok(nonce);
             }
+            // The fetch this was waiting on failed; fetch one instead
         }
-        let req = Request::head(self.directory.new_nonce.as_str());
-        let mut resp = self.http.send(req).await?;
-        http_error_result(&mut resp).await?;
-        get_replay_nonce(&resp).ok_or(AcmeError::MissingExpectedHeader("Replay-Nonce"))
     }
 
     async fn handle_response_headers(&self, resp: &mut Response) -> Result<(), AcmeError> {
-        if let Some(nonce) = get_replay_nonce(resp) {
-            let mut nonces = self.nonces.lock().unwrap();
-            nonces.push_back(nonce);
+        let received = get_replay_nonce(resp).map(|nonce| self.nonces.push(nonce));
+        let res = http_error_result(resp, &self.quirks, self.clock.now()).await;
+        if let Err(AcmeError::AcmeProblem(problem)) = &res {
+            // If the server forgot its nonces, e.g. on a restart, the pooled
+            // ones are stale too; drop them rather than retry with each.
+            if problem.has_type(AcmeProblemType::BadNonce) {
+                self.nonces.discard_before(received);
+            }
         }
-        http_error_result(resp).await?;
-        Ok(())
+        res
     }
 }
 
@@ -300,11 +1433,19 @@ impl<'a> Auth<'a, ()> {
     }
 }
 
+fn check_certificate_content_type(resp: &Response, format: CertificateFormat) -> AcmeResult<()> {
+    check_content_type(resp, |essence| essence == format.media_type())
+}
+
 fn get_replay_nonce(resp: &Response) -> Option<String> {
     Some(resp.header("Replay-Nonce")?.last().as_str().to_owned())
 }
 
-async fn http_error_result(resp: &mut Response) -> AcmeResult<()> {
+async fn http_error_result(
+    resp: &mut Response,
+    quirks: &Quirks,
+    now: DateTime<Utc>,
+) -> AcmeResult<()> {
     let status = resp.status();
     if status.is_success() || status.is_informational() {
         return Ok(());
@@ -312,15 +1453,27 @@ async fn http_error_result(resp: &mut Response) -> AcmeResult<()> {
 
     if resp
         .content_type()
-        .map(|ct| ct.essence() == AcmeProblem::CONTENT_TYPE)
+        .map(|ct| quirks.is_error_document(ct.essence()))
         .unwrap_or(false)
     {
-        if let Ok(problem) = resp.body_json().await {
-            return Err(AcmeError::AcmeProblem(problem));
+        if let Ok(mut problem) = resp.body_json::<AcmeProblem>().await {
+            quirks.normalize_problem(&mut problem, status.into());
+            problem.retry_after = resp
+                .header("Retry-After")
+                .and_then(|values| parse_retry_after(values.last().as_str(), now));
+            if let Some(values) = resp.header("Link") {
+                problem.terms_of_service =
+                    parse_links(values.iter().map(|v| v.as_str()), "terms-of-service")
+                        .into_iter()
+                        .next();
+            }
+            return Err(AcmeError::from_problem(problem));
         }
     }
 
-    Err(AcmeError::from(http_client::Error::from_str(status, "")))
+    Err(AcmeError::from(crate::transport::Error::from_str(
+        status, "",
+    )))
 }
 
 impl From<&Jws> for Body {
@@ -330,3 +1483,340 @@ impl From<&Jws> for Body {
         body
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use serde_json::json;
+
+    use crate::{
+        crypto::{account_key::GenerateAccountKey, es256::Es256AccountKey, jws::jws_verify},
+        test_support::mock_server::{Endpoint, Fault, MockAcmeServer, MOCK_DIRECTORY_URL},
+        wire::quirks::ServerSoftware,
+    };
+
+    use super::*;
+
+    #[async_std::test]
+    async fn deterministic_request_body() {
+        let server = MockAcmeServer::new();
+        let client = AcmeClient::for_directory_url(server.http(), MOCK_DIRECTORY_URL)
+            .await
+            .unwrap()
+            .with_nonce_source(Some(Arc::new(|| "fixed-nonce".to_string())))
+            .with_canonical_json(true);
+        let key = Es256AccountKey::generate();
+        let url = "https://acme.test/order/1";
+        let payload = Some(json!({"z": 1, "a": {"c": 2, "b": 3}}));
+
+        let jws = client
+            .build_request_body(&key, url, &Auth::kid("acct"), &payload)
+            .await
+            .unwrap();
+        let again = client
+            .build_request_body(&key, url, &Auth::kid("acct"), &payload)
+            .await
+            .unwrap();
+        assert_eq!(
+            serde_json::to_string(&jws).unwrap(),
+            serde_json::to_string(&again).unwrap()
+        );
+        assert_eq!(server.request_count(Endpoint::NewNonce), 0);
+
+        let verified = jws_verify(&jws, &key).unwrap();
+        assert_eq!(verified.header["nonce"], "fixed-nonce");
+        assert_eq!(verified.payload, br#"{"a":{"b":3,"c":2},"z":1}"#);
+    }
+
+    #[async_std::test]
+    async fn nonce_sources() {
+        let server = MockAcmeServer::new();
+        let scripted = Arc::new(ScriptedNonces::new(["first", "second"]));
+        let client = AcmeClient::for_directory_url(server.http(), MOCK_DIRECTORY_URL)
+            .await
+            .unwrap()
+            .with_nonce_source(Some(scripted.clone()));
+        let key = Es256AccountKey::generate();
+        let url = "https://acme.test/order/1";
+        for expected in ["first", "second"] {
+            let jws = client
+                .build_request_body(&key, url, &Auth::kid("acct"), &NO_PAYLOAD)
+                .await
+                .unwrap();
+            assert_eq!(jws_verify(&jws, &key).unwrap().header["nonce"], expected);
+        }
+        assert_eq!(scripted.remaining(), 0);
+        assert!(matches!(
+            client
+                .build_request_body(&key, url, &Auth::kid("acct"), &NO_PAYLOAD)
+                .await,
+            Err(AcmeError::InvalidState(_))
+        ));
+
+        // Every request fetches its own nonce, though responses bring some
+        let mut client = crate::Client::for_directory_url(server.http(), MOCK_DIRECTORY_URL)
+            .await
+            .unwrap();
+        client.set_nonce_source(Arc::new(FreshNonces));
+        let account = client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+        let nonce_fetches = server.request_count(Endpoint::NewNonce);
+        for _ in 0..3 {
+            account
+                .client()
+                .get_account(account.key(), account.url())
+                .await
+                .unwrap();
+        }
+        assert_eq!(server.request_count(Endpoint::NewNonce), nonce_fetches + 3);
+    }
+
+    /// Yields before every request, so that concurrent requests interleave
+    /// as they would over a network. Tracks the most newNonce requests in
+    /// flight at once.
+    #[derive(Debug)]
+    struct Interleaved {
+        server: MockAcmeServer,
+        nonce_fetches: AtomicUsize,
+        peak_nonce_fetches: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl HttpClient for Interleaved {
+        async fn send(&self, req: Request) -> Result<Response, crate::transport::Error> {
+            let is_nonce_fetch = req.method() == crate::transport::http_types::Method::Head;
+            if is_nonce_fetch {
+                let in_flight = self.nonce_fetches.fetch_add(1, Ordering::SeqCst) + 1;
+                self.peak_nonce_fetches
+                    .fetch_max(in_flight, Ordering::SeqCst);
+            }
+            async_std::task::yield_now().await;
+            let resp = self.server.send(req).await;
+            if is_nonce_fetch {
+                self.nonce_fetches.fetch_sub(1, Ordering::SeqCst);
+            }
+            resp
+        }
+    }
+
+    #[async_std::test]
+    async fn concurrent_requests() {
+        let server = MockAcmeServer::new();
+        let interleaved = Arc::new(Interleaved {
+            server: server.clone(),
+            nonce_fetches: AtomicUsize::new(0),
+            peak_nonce_fetches: AtomicUsize::new(0),
+        });
+        let http: Arc<dyn HttpClient> = interleaved.clone();
+        let account = crate::Client::for_directory_url(http, MOCK_DIRECTORY_URL)
+            .await
+            .unwrap()
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+        let fetch_accounts = || {
+            futures_util::future::join_all(
+                (0..100).map(|_| account.client().get_account(account.key(), account.url())),
+            )
+        };
+
+        let nonce_fetches = server.request_count(Endpoint::NewNonce);
+        for res in fetch_accounts().await {
+            res.unwrap();
+        }
+        assert_eq!(server.request_count(Endpoint::Account), 100);
+        // The newAccount response left one nonce in the pool; the other 99
+        // requests each fetch one, all at once
+        assert_eq!(server.request_count(Endpoint::NewNonce) - nonce_fetches, 99);
+        assert_eq!(interleaved.peak_nonce_fetches.load(Ordering::SeqCst), 99);
+
+        // After a restart every pooled nonce is stale, but a single badNonce
+        // retry is enough
+        for _ in 0..10 {
+            let nonce = account.client().new_nonce().await.unwrap();
+            account.client().nonces.push(nonce);
+        }
+        server.invalidate_nonces();
+        account
+            .client()
+            .get_account(account.key(), account.url())
+            .await
+            .unwrap();
+        for res in fetch_accounts().await {
+            res.unwrap();
+        }
+    }
+
+    #[async_std::test]
+    async fn retry_after_by_clock() {
+        let server = MockAcmeServer::new();
+        let mut client = server.client().await.unwrap();
+        let now: DateTime<Utc> = DateTime::parse_from_rfc3339("2030-01-01T00:00:00Z")
+            .unwrap()
+            .into();
+        client.set_clock(crate::clock::ManualClock::new(now));
+        let account = client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+        server.inject(
+            Endpoint::NewOrder,
+            Fault::RateLimited {
+                retry_after_secs: 60,
+            },
+        );
+        match account
+            .new_dns_order("example.com")
+            .await
+            .err()
+            .unwrap()
+            .into_without_context()
+        {
+            AcmeError::RateLimited(err) => {
+                assert_eq!(
+                    err.retry_after.unwrap(),
+                    now + chrono::Duration::seconds(60)
+                )
+            }
+            err => panic!("expected rate limited error, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn nonce_pool_queue() {
+        let pool = NoncePool::default();
+        let (mut first, fetch) = pool.take_or_wait().unwrap_err();
+        assert!(fetch);
+        let first_fetch = NonceFetch(&pool);
+        let (mut second, fetch) = pool.take_or_wait().unwrap_err();
+        assert!(fetch);
+        let second_fetch = NonceFetch(&pool);
+
+        // A response serves the first request, so the second fetch is
+        // enough for a third
+        pool.push("from-response".to_string());
+        assert_eq!(first.try_recv().unwrap().as_deref(), Some("from-response"));
+        let (mut third, fetch) = pool.take_or_wait().unwrap_err();
+        assert!(!fetch);
+
+        // If the first fetch fails, the third request is turned away to
+        // fetch its own
+        drop(first);
+        drop(first_fetch);
+        assert!(third.try_recv().is_err());
+        pool.push("fetched".to_string());
+        drop(second_fetch);
+        assert_eq!(second.try_recv().unwrap().as_deref(), Some("fetched"));
+
+        // With nobody waiting, nonces are pooled
+        pool.push("spare".to_string());
+        assert_eq!(pool.take_or_wait().ok().as_deref(), Some("spare"));
+    }
+
+    #[async_std::test]
+    async fn quirks() {
+        let server = MockAcmeServer::new();
+        let account = server
+            .client()
+            .await
+            .unwrap()
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+        let requests = server.request_count(Endpoint::Account);
+        let step_bad_nonce = Fault::Problem {
+            status: 400,
+            problem: Box::new(AcmeProblem {
+                type_: Some(AcmeProblemType::Other(
+                    "urn:step:acme:error:badNonce".to_string(),
+                )),
+                ..Default::default()
+            }),
+        };
+
+        server.inject(Endpoint::Account, step_bad_nonce.clone());
+        let err = account
+            .client()
+            .get_account(account.key(), account.url())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.problem().unwrap().type_,
+            Some(AcmeProblemType::Other(
+                "urn:step:acme:error:badNonce".to_string()
+            ))
+        );
+
+        let client = account
+            .client()
+            .clone()
+            .with_quirks(Quirks::for_software(ServerSoftware::StepCa));
+        server.inject(Endpoint::Account, step_bad_nonce);
+        client
+            .get_account(account.key(), account.url())
+            .await
+            .unwrap();
+        assert_eq!(server.request_count(Endpoint::Account), requests + 3);
+    }
+
+    #[async_std::test]
+    async fn html_instead_of_json() {
+        let server = MockAcmeServer::new();
+        let account = server
+            .client()
+            .await
+            .unwrap()
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+
+        server.inject(Endpoint::Account, Fault::Html);
+        let err = account
+            .client()
+            .get_account(account.key(), account.url())
+            .await
+            .unwrap_err();
+        assert_eq!(err.context(), Some((AcmeOp::GetAccount, account.url())));
+        match err.into_without_context() {
+            AcmeError::UnexpectedContentType(content_type) => assert_eq!(content_type, "text/html"),
+            err => panic!("expected unexpected content type error, got {:?}", err),
+        }
+    }
+
+    #[async_std::test]
+    async fn post_as_get() {
+        let server = MockAcmeServer::new();
+        let account = server
+            .client()
+            .await
+            .unwrap()
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+        let order = account.new_dns_order("example.com").await.unwrap();
+
+        let resp = account
+            .client()
+            .post_as_get(account.key(), account.url(), order.url())
+            .await
+            .unwrap();
+        assert_eq!(resp.status, 200);
+        assert_eq!(resp.header("content-type"), Some("application/json"));
+        let resource: OrderResource = resp.json().unwrap();
+        assert_eq!(resource.authorizations, order.resource().authorizations);
+
+        let err = account
+            .client()
+            .post_as_get(account.key(), account.url(), "https://acme.test/nowhere")
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.context(),
+            Some((AcmeOp::Fetch, "https://acme.test/nowhere"))
+        );
+        assert!(err.problem().is_some());
+    }
+}
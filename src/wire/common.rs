@@ -1,14 +1,224 @@
+use std::{collections::BTreeMap, pin::Pin};
+
 use async_trait::async_trait;
-use http_client::Response;
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use futures_io::AsyncRead;
+use futures_util::future::BoxFuture;
 use serde::de::DeserializeOwned;
 
-use crate::error::{AcmeError, AcmeResult};
+use crate::error::{AcmeError, AcmeOp, AcmeResult};
+use crate::transport::{Body, Response};
 
 // Serde skip_serialization_if helper
 pub(crate) fn is_false(value: &bool) -> bool {
     !value
 }
 
+/// Fails with `UnexpectedContentType` unless a response's Content-Type is
+/// one that `accept` allows; a missing Content-Type is accepted.
+pub(crate) fn check_content_type(resp: &Response, accept: impl Fn(&str) -> bool) -> AcmeResult<()> {
+    if let Some(content_type) = resp.header("Content-Type") {
+        if !accept(&media_type_essence(content_type.as_str())) {
+            return Err(AcmeError::UnexpectedContentType(
+                content_type.as_str().to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The lowercase type/subtype of a Content-Type value, without parameters.
+fn media_type_essence(content_type: &str) -> String {
+    let essence = content_type.split(';').next().unwrap_or("").trim();
+    essence.to_ascii_lowercase()
+}
+
+fn is_json(essence: &str) -> bool {
+    essence == "application/json" || essence.ends_with("+json")
+}
+
+/// Deserializes a JSON response body, first checking that it is JSON so
+/// that e.g. an HTML page from a proxy isn't reported as a parse error.
+pub(crate) async fn json_body<T: DeserializeOwned>(resp: &mut Response) -> AcmeResult<T> {
+    check_content_type(resp, is_json)?;
+    Ok(resp.body_json().await?)
+}
+
+/// Replaces a response's body with a buffered copy, failing with
+/// `ResponseTooLarge` as soon as its Content-Length or the bytes read so far
+/// exceed `limit`. The future is boxed, as it would otherwise add to the size
+/// of every request future.
+pub(crate) fn buffer_body(
+    resp: &mut Response,
+    limit: Option<usize>,
+) -> BoxFuture<'_, AcmeResult<()>> {
+    Box::pin(async move {
+        let mut body = resp.take_body();
+        let mime = body.mime().clone();
+        let bytes = match limit {
+            Some(limit) => {
+                if body.len().is_some_and(|len| len > limit) {
+                    return Err(AcmeError::ResponseTooLarge { limit });
+                }
+                let mut bytes = Vec::new();
+                let mut buf = vec![0; 8192];
+                loop {
+                    let n = futures_util::future::poll_fn(|cx| {
+                        Pin::new(&mut body).poll_read(cx, &mut buf)
+                    })
+                    .await
+                    .map_err(crate::transport::Error::from)?;
+                    if n == 0 {
+                        break;
+                    }
+                    if bytes.len() + n > limit {
+                        return Err(AcmeError::ResponseTooLarge { limit });
+                    }
+                    bytes.extend_from_slice(&buf[..n]);
+                }
+                bytes
+            }
+            None => body.into_bytes().await?,
+        };
+        let mut body = Body::from(bytes);
+        body.set_mime(mime);
+        resp.set_body(body);
+        Ok(())
+    })
+}
+
+/// A response to a signed request, as received; see
+/// `AcmeClient::post_as_get`.
+#[derive(Clone, Debug)]
+pub struct SignedResponse {
+    pub status: u16,
+
+    /// Header values by lowercase name.
+    pub headers: BTreeMap<String, Vec<String>>,
+
+    pub body: Vec<u8>,
+}
+
+impl SignedResponse {
+    pub(crate) async fn from_response(mut resp: Response) -> AcmeResult<Self> {
+        let headers = resp
+            .iter()
+            .map(|(name, values)| {
+                let values = values.iter().map(|v| v.to_string()).collect();
+                (name.as_str().to_ascii_lowercase(), values)
+            })
+            .collect();
+        Ok(Self {
+            status: resp.status().into(),
+            headers,
+            body: resp.body_bytes().await?,
+        })
+    }
+
+    /// The first value of the header `name`.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .and_then(|values| values.first())
+            .map(String::as_str)
+    }
+
+    /// The targets of the Link headers with relation type `rel`.
+    pub fn links(&self, rel: &str) -> Vec<String> {
+        match self.headers.get("link") {
+            Some(values) => parse_links(values.iter().map(String::as_str), rel),
+            None => Vec::new(),
+        }
+    }
+
+    /// Deserializes the body, which must be JSON.
+    pub fn json<T: DeserializeOwned>(&self) -> AcmeResult<T> {
+        if let Some(content_type) = self.header("Content-Type") {
+            if !is_json(&media_type_essence(content_type)) {
+                return Err(AcmeError::UnexpectedContentType(content_type.to_string()));
+            }
+        }
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+}
+
+/// Parses a Retry-After header value, either delay-seconds (from `now`) or
+/// an HTTP-date.
+/// https://datatracker.ietf.org/doc/html/rfc7231#section-7.1.3
+pub(crate) fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<DateTime<FixedOffset>> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u32>() {
+        Some((now + Duration::seconds(seconds.into())).into())
+    } else {
+        DateTime::parse_from_rfc2822(value).ok()
+    }
+}
+
+/// Returns the target URLs of the links with relation type `rel` in a
+/// response's Link header values.
+/// https://datatracker.ietf.org/doc/html/rfc8288#section-3
+pub(crate) fn parse_links<'a>(values: impl IntoIterator<Item = &'a str>, rel: &str) -> Vec<String> {
+    let mut links = Vec::new();
+    for value in values {
+        let mut rest = value;
+        while let Some(start) = rest.find('<') {
+            let end = match rest[start..].find('>') {
+                Some(end) => start + end,
+                None => break,
+            };
+            let target = &rest[start + 1..end];
+            let params_end = rest[end..].find('<').map_or(rest.len(), |next| end + next);
+            let has_rel = rest[end + 1..params_end]
+                .split([';', ','])
+                .filter_map(|param| param.trim().strip_prefix("rel="))
+                .any(|rels| rels.trim_matches('"').split_whitespace().any(|r| r == rel));
+            if has_rel {
+                links.push(target.to_string());
+            }
+            rest = &rest[params_end..];
+        }
+    }
+    links
+}
+
+/// When a response stops being fresh, from its Cache-Control header or else
+/// its Expires header. Responses that may not be reused are already stale.
+/// https://datatracker.ietf.org/doc/html/rfc7234#section-4.2.1
+pub(crate) fn parse_cache_expiry(
+    cache_control: Option<&str>,
+    expires: Option<&str>,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    if let Some(cache_control) = cache_control {
+        let directives: Vec<_> = cache_control
+            .split(',')
+            .map(|directive| directive.trim().to_ascii_lowercase())
+            .collect();
+        if directives
+            .iter()
+            .any(|directive| directive == "no-cache" || directive == "no-store")
+        {
+            return Some(now);
+        }
+        let max_age = directives.iter().find_map(|directive| {
+            directive
+                .strip_prefix("max-age=")?
+                .trim_matches('"')
+                .parse::<u32>()
+                .ok()
+        });
+        if let Some(seconds) = max_age {
+            return Some(now + Duration::seconds(seconds.into()));
+        }
+    }
+    // An invalid Expires value means the response is already stale
+    expires.map(|expires| {
+        DateTime::parse_from_rfc2822(expires.trim())
+            .map(|expires| expires.with_timezone(&Utc))
+            .unwrap_or(now)
+    })
+}
+
 pub trait ResourceStatus: std::fmt::Debug + Copy + Sized {
     fn is_failure(&self) -> bool;
 
@@ -30,8 +240,73 @@ pub trait ResourceStatus: std::fmt::Debug + Copy + Sized {
     }
 }
 
+/// Response metadata kept alongside a resource: where it was fetched from
+/// and the headers that higher layers (polling, alternate chains, terms of
+/// service) need.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ResourceMeta {
+    /// The URL the response was received from.
+    pub url: Option<String>,
+
+    /// When the server asked to be polled again, from the Retry-After header.
+    pub retry_after: Option<DateTime<FixedOffset>>,
+
+    /// Link rel="up", e.g. from a challenge to its authorization or from a
+    /// certificate to its issuer.
+    pub up: Vec<String>,
+
+    /// Link rel="alternate", e.g. alternate certificate chains.
+    pub alternate: Vec<String>,
+
+    /// Link rel="next", the next page of a paginated list.
+    pub next: Option<String>,
+
+    /// Link rel="terms-of-service".
+    pub terms_of_service: Option<String>,
+}
+
+impl ResourceMeta {
+    pub(crate) fn from_response(url: &str, resp: &Response, now: DateTime<Utc>) -> Self {
+        let links = |rel| match resp.header("Link") {
+            Some(values) => parse_links(values.iter().map(|v| v.as_str()), rel),
+            None => Vec::new(),
+        };
+        Self {
+            url: Some(url.to_string()),
+            retry_after: resp
+                .header("Retry-After")
+                .and_then(|values| parse_retry_after(values.last().as_str(), now)),
+            up: links("up"),
+            alternate: links("alternate"),
+            next: links("next").into_iter().next(),
+            terms_of_service: links("terms-of-service").into_iter().next(),
+        }
+    }
+}
+
+#[async_trait]
+pub trait MetaResource: DeserializeOwned + Send {
+    /// Which operation a failed fetch of this resource is reported as.
+    const FETCH_OPERATION: AcmeOp = AcmeOp::Fetch;
+
+    fn meta_mut(&mut self) -> &mut ResourceMeta;
+
+    /// The ways this resource breaks requirements of RFC 8555 that
+    /// deserializing it doesn't enforce; see `AcmeClient::with_strict`.
+    fn violations(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Parses a response for `url`, received at `now`.
+    async fn from_response(url: &str, mut resp: Response, now: DateTime<Utc>) -> AcmeResult<Self> {
+        let mut resource: Self = json_body(&mut resp).await?;
+        *resource.meta_mut() = ResourceMeta::from_response(url, &resp, now);
+        Ok(resource)
+    }
+}
+
 #[async_trait]
-pub(crate) trait LocationResource: DeserializeOwned + Send {
+pub(crate) trait LocationResource: MetaResource {
     fn location_mut(&mut self) -> &mut Option<String>;
 
     fn take_location(&mut self) -> AcmeResult<String> {
@@ -40,11 +315,169 @@ pub(crate) trait LocationResource: DeserializeOwned + Send {
             .ok_or(AcmeError::MissingExpectedHeader("Location"))
     }
 
-    async fn from_response(mut resp: Response) -> AcmeResult<Self> {
-        let mut resource: Self = resp.body_json().await?;
-        if let Some(values) = resp.header("Location") {
-            *resource.location_mut() = Some(values.last().as_str().to_owned());
-        }
+    async fn from_located_response(
+        url: &str,
+        resp: Response,
+        now: DateTime<Utc>,
+    ) -> AcmeResult<Self> {
+        let location = resp
+            .header("Location")
+            .map(|values| values.last().as_str().to_owned());
+        let mut resource = Self::from_response(url, resp, now).await?;
+        *resource.location_mut() = location;
         Ok(resource)
     }
+
+    /// Like `from_located_response`, for a fetch of the resource itself,
+    /// whose URL stands in for a missing Location header.
+    async fn from_fetched_response(
+        url: &str,
+        resp: Response,
+        now: DateTime<Utc>,
+    ) -> AcmeResult<Self> {
+        let mut resource = Self::from_located_response(url, resp, now).await?;
+        resource
+            .location_mut()
+            .get_or_insert_with(|| url.to_string());
+        Ok(resource)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2030-01-01T00:00:00Z")
+            .unwrap()
+            .into()
+    }
+
+    #[test]
+    fn parse_retry_after_seconds() {
+        let retry_after = parse_retry_after("120", now()).unwrap();
+        assert_eq!(retry_after, now() + Duration::seconds(120));
+    }
+
+    #[test]
+    fn parse_retry_after_http_date() {
+        assert_eq!(
+            parse_retry_after("Fri, 31 Dec 1999 23:59:59 GMT", now()).unwrap(),
+            DateTime::parse_from_rfc3339("1999-12-31T23:59:59Z").unwrap()
+        );
+        assert!(parse_retry_after("soon", now()).is_none());
+    }
+
+    #[test]
+    fn parse_link_headers() {
+        // https://www.rfc-editor.org/rfc/rfc8555.html#section-7.3.3
+        let values = [
+            r#"<https://example.com/acme/terms/2017-6-02>;rel="terms-of-service""#,
+            r#"<https://example.com/acme/directory>;rel="index", <https://example.com/acme/cert/alt/1>;rel="alternate""#,
+        ];
+        assert_eq!(
+            parse_links(values, "terms-of-service"),
+            ["https://example.com/acme/terms/2017-6-02"]
+        );
+        assert_eq!(
+            parse_links(values, "alternate"),
+            ["https://example.com/acme/cert/alt/1"]
+        );
+        assert!(parse_links(values, "up").is_empty());
+    }
+
+    #[test]
+    fn parse_cache_expiry_headers() {
+        let max_age = parse_cache_expiry(Some("public, max-age=3600"), Some("0"), now());
+        assert_eq!(max_age, Some(now() + Duration::seconds(3600)));
+        assert_eq!(
+            parse_cache_expiry(Some("max-age=60, no-cache"), None, now()),
+            Some(now())
+        );
+
+        assert_eq!(
+            parse_cache_expiry(Some("public"), Some("Fri, 31 Dec 1999 23:59:59 GMT"), now())
+                .unwrap(),
+            DateTime::parse_from_rfc3339("1999-12-31T23:59:59Z").unwrap()
+        );
+        assert_eq!(parse_cache_expiry(None, Some("0"), now()), Some(now()));
+        assert!(parse_cache_expiry(Some("public"), None, now()).is_none());
+    }
+
+    #[test]
+    fn resource_meta_from_headers() {
+        let mut resp = Response::new(200);
+        resp.insert_header("Retry-After", "Fri, 31 Dec 1999 23:59:59 GMT");
+        resp.append_header("Link", r#"<https://example.com/acme/authz/1>;rel="up""#);
+        resp.append_header(
+            "Link",
+            r#"<https://example.com/acme/cert/1/1>;rel="alternate", <https://example.com/acme/cert/1/2>;rel="alternate""#,
+        );
+        let meta = ResourceMeta::from_response("https://example.com/acme/chall/1", &resp, now());
+        assert_eq!(
+            meta.url.as_deref(),
+            Some("https://example.com/acme/chall/1")
+        );
+        assert_eq!(
+            meta.retry_after,
+            Some(DateTime::parse_from_rfc3339("1999-12-31T23:59:59Z").unwrap())
+        );
+        assert_eq!(meta.up, ["https://example.com/acme/authz/1"]);
+        assert_eq!(meta.alternate.len(), 2);
+        assert_eq!(meta.next, None);
+        assert_eq!(meta.terms_of_service, None);
+    }
+
+    #[async_std::test]
+    async fn buffer_body_limit() {
+        let mut resp = Response::new(200);
+        resp.set_body(Body::from_string("{}".to_string()));
+        buffer_body(&mut resp, Some(2)).await.unwrap();
+        assert_eq!(resp.body_string().await.unwrap(), "{}");
+
+        resp.set_body(Body::from_string("{ }".to_string()));
+        assert!(matches!(
+            buffer_body(&mut resp, Some(2)).await,
+            Err(AcmeError::ResponseTooLarge { limit: 2 })
+        ));
+    }
+
+    #[test]
+    fn resources_survive_corruption() {
+        use crate::wire::{
+            authorization::AuthorizationResource, challenge::ChallengeResource,
+            directory::DirectoryResource, order::OrderResource, problem::AcmeProblem,
+        };
+
+        // Every prefix of each (valid) resource, and the resource with each
+        // byte replaced, must parse or fail cleanly
+        fn corrupt<T: DeserializeOwned>(json: &str) {
+            serde_json::from_str::<T>(json).unwrap();
+            let bytes = json.as_bytes();
+            for i in 0..bytes.len() {
+                let _ = serde_json::from_slice::<T>(&bytes[..i]);
+                for replacement in [b'"', b'{', b']', b'0', b'-', 0xff] {
+                    let mut corrupted = bytes.to_vec();
+                    corrupted[i] = replacement;
+                    let _ = serde_json::from_slice::<T>(&corrupted);
+                }
+            }
+        }
+
+        corrupt::<DirectoryResource>(
+            r#"{"newNonce":"https://example.com/acme/new-nonce","newAccount":"https://example.com/acme/new-account","newOrder":"https://example.com/acme/new-order","revokeCert":"https://example.com/acme/revoke-cert","keyChange":"https://example.com/acme/key-change","meta":{"termsOfService":"https://example.com/acme/terms/2017-5-30","caaIdentities":["example.com"],"externalAccountRequired":false}}"#,
+        );
+        corrupt::<OrderResource>(
+            r#"{"status":"valid","expires":"2016-01-20T14:09:07.99Z","identifiers":[{"type":"dns","value":"www.example.org"}],"notBefore":"2016-01-01T00:00:00Z","authorizations":["https://example.com/acme/authz/PAniVnsZcis"],"finalize":"https://example.com/acme/order/TOlocE8rfgo/finalize","certificate":"https://example.com/acme/cert/mAt3xBGaobw"}"#,
+        );
+        corrupt::<AuthorizationResource>(
+            r#"{"status":"pending","expires":"2016-01-02T14:09:30Z","identifier":{"type":"dns","value":"www.example.org"},"challenges":[{"type":"dns-01","url":"https://example.com/acme/chall/Rg5dV14Gh1Q","status":"pending","token":"DGyRejmCefe7v4NfDGDKfA"},{"type":"tls-alpn-01","url":"https://example.com/acme/chall/PCt92wr-oA","status":"pending","token":"DGyRejmCefe7v4NfDGDKfA","port":443}],"wildcard":false}"#,
+        );
+        corrupt::<ChallengeResource>(
+            r#"{"type":"http-01","url":"https://example.com/acme/chall/prV_B7yEyA4","status":"invalid","validated":"2014-12-01T12:05:58.16Z","token":"LoqXcYV8q5ONbJQxbmR7SCTNo3tiAXDfowyjxAjEuX0","error":{"type":"urn:ietf:params:acme:error:connection","detail":"refused"}}"#,
+        );
+        corrupt::<AcmeProblem>(
+            r#"{"type":"urn:ietf:params:acme:error:malformed","detail":"Some of the identifiers requested were rejected","status":400,"subproblems":[{"type":"urn:ietf:params:acme:error:malformed","detail":"Invalid underscore in DNS name","identifier":{"type":"dns","value":"_example.org"}}]}"#,
+        );
+    }
 }
@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// The value of a CSR template field that the client must fill in.
+pub const TEMPLATE_REQUIRED: &str = "**";
+
+/// The value of a CSR template field that the client may fill in or omit.
+pub const TEMPLATE_OPTIONAL: &str = "*";
+
+/// The list of delegations an Identifier Owner has configured for an
+/// account, at the account's "delegations" URL.
+/// https://www.rfc-editor.org/rfc/rfc9115.html#section-2.3.1
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct DelegationList {
+    pub delegations: Vec<String>,
+}
+
+/// ACME delegation configuration object
+/// https://www.rfc-editor.org/rfc/rfc9115.html#section-2.3.1.1
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DelegationResource {
+    /// The certificates the delegate may request; see `CsrTemplate`.
+    #[serde(rename = "csr-template")]
+    pub csr_template: CsrTemplate,
+
+    /// Maps each delegated name to the name under the delegate's control that
+    /// it is (or must be) a CNAME alias of.
+    #[serde(
+        rename = "cname-map",
+        default,
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub cname_map: BTreeMap<String, String>,
+}
+
+/// Constraints on the CSR for a delegated certificate. Each field is either
+/// fixed, or `TEMPLATE_REQUIRED` or `TEMPLATE_OPTIONAL` for a value chosen by
+/// the delegate.
+/// https://www.rfc-editor.org/rfc/rfc9115.html#section-4
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CsrTemplate {
+    /// The acceptable key types, in order of preference.
+    pub key_types: Vec<KeyTypeTemplate>,
+
+    /// Subject attributes, keyed by e.g. "commonName", "country",
+    /// "stateOrProvince", "locality", "organization" or
+    /// "organizationalUnit".
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub subject: BTreeMap<String, String>,
+
+    pub extensions: ExtensionsTemplate,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyTypeTemplate {
+    /// e.g. "id-ecPublicKey", "rsaEncryption" or "id-Ed25519".
+    #[serde(rename = "PublicKeyType")]
+    pub public_key_type: String,
+
+    /// The curve of an "id-ecPublicKey" key, e.g. "secp256r1".
+    #[serde(
+        rename = "namedCurve",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub named_curve: Option<String>,
+
+    /// The key size in bits.
+    #[serde(
+        rename = "PublicKeyLength",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub public_key_length: Option<u32>,
+
+    /// e.g. "ecdsa-with-SHA256" or "sha256WithRSAEncryption".
+    #[serde(
+        rename = "SignatureType",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub signature_type: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtensionsTemplate {
+    pub subject_alt_name: SubjectAltNameTemplate,
+
+    /// e.g. "digitalSignature" or "keyEncipherment".
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub key_usage: Vec<String>,
+
+    /// e.g. "serverAuth" or "clientAuth".
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extended_key_usage: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SubjectAltNameTemplate {
+    #[serde(rename = "DNS", default, skip_serializing_if = "Vec::is_empty")]
+    pub dns: Vec<String>,
+
+    #[serde(rename = "IP", default, skip_serializing_if = "Vec::is_empty")]
+    pub ip: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn rfc9115_delegation_example() {
+        let delegation = DelegationResource::deserialize(json!({
+            "csr-template": {
+                "keyTypes": [
+                    {
+                        "PublicKeyType": "id-ecPublicKey",
+                        "namedCurve": "secp256r1",
+                        "SignatureType": "ecdsa-with-SHA256"
+                    },
+                    {
+                        "PublicKeyType": "rsaEncryption",
+                        "PublicKeyLength": 2048,
+                        "SignatureType": "sha256WithRSAEncryption"
+                    }
+                ],
+                "subject": {
+                    "country": "CA",
+                    "stateOrProvince": "**",
+                    "locality": "**"
+                },
+                "extensions": {
+                    "subjectAltName": {
+                        "DNS": ["abc.ido.example"]
+                    },
+                    "keyUsage": ["digitalSignature"],
+                    "extendedKeyUsage": ["serverAuth"]
+                }
+            },
+            "cname-map": {
+                "abc.ido.example": "abc.ndc.example"
+            }
+        }))
+        .unwrap();
+
+        let template = &delegation.csr_template;
+        assert_eq!(template.key_types.len(), 2);
+        assert_eq!(template.key_types[1].public_key_length, Some(2048));
+        assert_eq!(template.subject["stateOrProvince"], TEMPLATE_REQUIRED);
+        assert_eq!(
+            template.extensions.subject_alt_name.dns,
+            ["abc.ido.example"]
+        );
+        assert_eq!(delegation.cname_map["abc.ido.example"], "abc.ndc.example");
+    }
+}
@@ -1,4 +1,8 @@
-use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
+
 /// ACME Directory resource
 /// https://datatracker.ietf.org/doc/html/rfc8555#section-7.1.1
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -23,10 +27,29 @@ pub struct DirectoryResource {
     /// Key change URL
     pub key_change: String,
 
+    /// ACME Renewal Information URL
+    /// https://datatracker.ietf.org/doc/html/draft-ietf-acme-ari#section-4
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub renewal_info: Option<String>,
+
+    /// NOTE: Required by RFC 8555, but left out by some servers with no
+    /// metadata to give, e.g. older step-ca releases.
+    #[serde(default)]
     pub meta: DirectoryMetadata,
+
+    /// Fields added by extensions this crate doesn't know about.
+    #[serde(flatten)]
+    pub additional_fields: Map<String, Value>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+impl DirectoryResource {
+    /// Deserializes the additional field `name`, if present and of type `T`.
+    pub fn additional_field<T: DeserializeOwned>(&self, name: &str) -> Option<T> {
+        typed_field(&self.additional_fields, name)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct DirectoryMetadata {
     /// A URL identifying the current terms of service.
@@ -52,9 +75,68 @@ pub struct DirectoryMetadata {
     /// associating the new account with an external account.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub external_account_required: Option<bool>,
+
+    /// A map of certificate profile names to human-readable descriptions of
+    /// those profiles.
+    /// https://datatracker.ietf.org/doc/html/draft-aaron-acme-profiles#section-3
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, String>,
+
+    /// Fields added by extensions this crate doesn't know about; see the
+    /// typed getters below for some of them.
+    #[serde(flatten)]
+    pub additional_fields: Map<String, Value>,
+}
+
+impl DirectoryMetadata {
+    /// Deserializes the additional field `name`, if present and of type `T`.
+    pub fn additional_field<T: DeserializeOwned>(&self, name: &str) -> Option<T> {
+        typed_field(&self.additional_fields, name)
+    }
+
+    /// Whether the server issues for subdomains of an identifier that an
+    /// account has authorized, with an ancestor domain authorization.
+    /// https://www.rfc-editor.org/rfc/rfc9444.html#section-4.4
+    pub fn subdomain_auth_allowed(&self) -> bool {
+        self.additional_field("subdomainAuthAllowed")
+            .unwrap_or(false)
+    }
+
+    /// https://www.rfc-editor.org/rfc/rfc8739.html#section-3.1.2
+    pub fn auto_renewal(&self) -> Option<AutoRenewalMetadata> {
+        self.additional_field("auto-renewal")
+    }
+
+    /// Whether the server supports delegated certificates.
+    /// https://www.rfc-editor.org/rfc/rfc9115.html#section-2.3.1
+    pub fn delegation_enabled(&self) -> bool {
+        self.additional_field("delegation-enabled").unwrap_or(false)
+    }
+}
+
+/// Limits on STAR (short-term, automatically renewed) certificates.
+/// https://www.rfc-editor.org/rfc/rfc8739.html#section-3.1.2
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AutoRenewalMetadata {
+    /// Minimum acceptable certificate lifetime, in seconds.
+    #[serde(rename = "min-lifetime")]
+    pub min_lifetime: u64,
+
+    /// Maximum duration of a STAR order, in seconds.
+    #[serde(rename = "max-duration")]
+    pub max_duration: u64,
+
+    /// Whether the server allows unauthenticated GETs of STAR certificates.
+    #[serde(rename = "allow-certificate-get", default)]
+    pub allow_certificate_get: bool,
+}
+
+fn typed_field<T: DeserializeOwned>(fields: &Map<String, Value>, name: &str) -> Option<T> {
+    T::deserialize(fields.get(name)?).ok()
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use serde_json::json;
 
@@ -93,6 +175,7 @@ mod tests {
             "https://example.com/acme/revoke-cert"
         );
         assert_eq!(directory.key_change, "https://example.com/acme/key-change");
+        assert!(directory.renewal_info.is_none());
 
         assert_eq!(
             directory.meta.terms_of_service.unwrap(),
@@ -101,5 +184,71 @@ mod tests {
         assert_eq!(directory.meta.website.unwrap(), "https://www.example.com/");
         assert_eq!(directory.meta.caa_identities, ["example.com"]);
         assert_eq!(directory.meta.external_account_required.unwrap(), false);
+        assert!(directory.meta.profiles.is_empty());
+    }
+
+    #[test]
+    fn profiles_directory_meta() {
+        let meta = DirectoryMetadata::deserialize(json!({
+            "profiles": {
+                "classic": "The same profile you're accustomed to",
+                "shortlived": "A short-lived cert profile"
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(
+            meta.profiles.keys().collect::<Vec<_>>(),
+            ["classic", "shortlived"]
+        );
+    }
+
+    #[test]
+    fn extension_fields() {
+        let directory = DirectoryResource::deserialize(json!({
+            "newNonce": "https://example.com/acme/new-nonce",
+            "newAccount": "https://example.com/acme/new-account",
+            "newOrder": "https://example.com/acme/new-order",
+            "revokeCert": "https://example.com/acme/revoke-cert",
+            "keyChange": "https://example.com/acme/key-change",
+            "newFeature": "https://example.com/acme/new-feature",
+            "meta": {
+                "subdomainAuthAllowed": true,
+                "auto-renewal": {
+                    "min-lifetime": 86400,
+                    "max-duration": 31536000,
+                    "allow-certificate-get": true
+                },
+                "delegation-enabled": "yes",
+                "newLimit": 3
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(
+            directory.additional_field::<String>("newFeature").unwrap(),
+            "https://example.com/acme/new-feature"
+        );
+        assert!(!directory.additional_fields.contains_key("meta"));
+
+        let meta = &directory.meta;
+        assert!(meta.subdomain_auth_allowed());
+        assert_eq!(
+            meta.auto_renewal(),
+            Some(AutoRenewalMetadata {
+                min_lifetime: 86400,
+                max_duration: 31536000,
+                allow_certificate_get: true,
+            })
+        );
+        // Mistyped
+        assert!(!meta.delegation_enabled());
+        assert_eq!(meta.additional_field::<u32>("newLimit"), Some(3));
+        assert_eq!(meta.additional_field::<String>("newLimit"), None);
+
+        // Round trip
+        let value = serde_json::to_value(&directory).unwrap();
+        assert_eq!(value["newFeature"], "https://example.com/acme/new-feature");
+        assert_eq!(value["meta"]["newLimit"], 3);
     }
 }
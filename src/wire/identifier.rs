@@ -1,7 +1,14 @@
+use std::net::IpAddr;
+
 use serde::{Deserialize, Serialize};
 
+use crate::{api::dns_identifier::IdentifierValidator, error::AcmeResult};
+
 pub static IDENTIFIER_TYPE_DNS: &str = "dns";
 
+/// https://datatracker.ietf.org/doc/html/rfc8738
+pub static IDENTIFIER_TYPE_IP: &str = "ip";
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct AcmeIdentifier {
     /// The type of identifier.
@@ -10,6 +17,16 @@ pub struct AcmeIdentifier {
 
     /// The identifier itself.
     pub value: String,
+
+    /// In a newOrder request, an ancestor domain of `value` whose
+    /// authorization (with subdomains allowed) should cover it.
+    /// https://www.rfc-editor.org/rfc/rfc9444.html#section-4.3
+    #[serde(
+        rename = "ancestorDomain",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub ancestor_domain: Option<String>,
 }
 
 impl AcmeIdentifier {
@@ -17,9 +34,31 @@ impl AcmeIdentifier {
         Self {
             type_: IDENTIFIER_TYPE_DNS.to_string(),
             value: name.into(),
+            ancestor_domain: None,
+        }
+    }
+
+    /// Like `dns`, but validates and normalizes `name` first; see
+    /// `IdentifierValidator`.
+    pub fn try_dns(name: &str) -> AcmeResult<Self> {
+        IdentifierValidator::default()
+            .normalize_dns_name(name)
+            .map(Self::dns)
+    }
+
+    pub fn ip(addr: IpAddr) -> Self {
+        Self {
+            type_: IDENTIFIER_TYPE_IP.to_string(),
+            value: addr.to_string(),
+            ancestor_domain: None,
         }
     }
 
+    pub fn with_ancestor_domain(mut self, ancestor_domain: impl Into<String>) -> Self {
+        self.ancestor_domain = Some(ancestor_domain.into());
+        self
+    }
+
     pub fn is_dns(&self) -> bool {
         self.type_ == IDENTIFIER_TYPE_DNS
     }
@@ -31,4 +70,16 @@ impl AcmeIdentifier {
             None
         }
     }
+
+    pub fn is_ip(&self) -> bool {
+        self.type_ == IDENTIFIER_TYPE_IP
+    }
+
+    pub fn ip_addr(&self) -> Option<IpAddr> {
+        if self.is_ip() {
+            self.value.parse().ok()
+        } else {
+            None
+        }
+    }
 }
@@ -1,15 +1,17 @@
 use chrono::{DateTime, FixedOffset};
 use serde::{Deserialize, Serialize};
 
+use crate::error::AcmeOp;
+
 use super::{
-    common::{LocationResource, ResourceStatus},
+    common::{LocationResource, MetaResource, ResourceMeta, ResourceStatus},
     identifier::AcmeIdentifier,
     problem::AcmeProblem,
 };
 
 /// ACME Order resource
 /// https://datatracker.ietf.org/doc/html/rfc8555#section-7.1.3
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct OrderResource {
     /// The status of this order.
@@ -58,9 +60,62 @@ pub struct OrderResource {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub certificate: Option<String>,
 
+    /// The name of the profile the order was created with.
+    /// https://datatracker.ietf.org/doc/html/draft-aaron-acme-profiles#section-4
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+
+    /// The delegation the order was created under.
+    /// https://www.rfc-editor.org/rfc/rfc9115.html#section-2.3.2
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delegation: Option<String>,
+
+    /// Whether the certificate may be fetched with an unauthenticated GET.
+    /// https://www.rfc-editor.org/rfc/rfc9115.html#section-2.3.3
+    #[serde(
+        rename = "allow-certificate-get",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub allow_certificate_get: Option<bool>,
+
     /// The URL of this resource, as returned in the Location header.
     #[serde(skip)]
     pub location: Option<String>,
+
+    #[serde(skip)]
+    pub meta: ResourceMeta,
+}
+
+impl MetaResource for OrderResource {
+    const FETCH_OPERATION: AcmeOp = AcmeOp::GetOrder;
+
+    fn meta_mut(&mut self) -> &mut ResourceMeta {
+        &mut self.meta
+    }
+
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-7.1.3
+    fn violations(&self) -> Vec<String> {
+        let mut violations = Vec::new();
+        if self.identifiers.is_empty() {
+            violations.push("order has no identifiers".to_string());
+        }
+        if self.authorizations.is_empty() {
+            violations.push("order has no authorizations".to_string());
+        }
+        if self.finalize.is_none() {
+            violations.push("order has no finalize URL".to_string());
+        }
+        if self.expires.is_none()
+            && matches!(self.status, OrderStatus::Pending | OrderStatus::Valid)
+        {
+            violations.push(format!("{:?} order has no expires", self.status).to_lowercase());
+        }
+        if self.status == OrderStatus::Valid && self.certificate.is_none() {
+            violations.push("valid order has no certificate URL".to_string());
+        }
+        violations
+    }
 }
 
 impl LocationResource for OrderResource {
@@ -71,7 +126,7 @@ impl LocationResource for OrderResource {
 
 /// ACME newOrder resource
 /// https://datatracker.ietf.org/doc/html/rfc8555#section-7.4
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct NewOrderResource {
     /// An array of identifier objects that the order pertains to.
@@ -84,6 +139,33 @@ pub struct NewOrderResource {
     /// The requested value of the notAfter field in the certificate
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub not_after: Option<DateTime<FixedOffset>>,
+
+    /// The name of the profile to use, as advertised in the directory's
+    /// "profiles" metadata.
+    /// https://datatracker.ietf.org/doc/html/draft-aaron-acme-profiles#section-4
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+
+    /// The ARI CertID of a certificate this order is intended to replace.
+    /// https://datatracker.ietf.org/doc/html/draft-ietf-acme-ari#section-5
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replaces: Option<String>,
+
+    /// A delegation configuration URL from the Identifier Owner's account,
+    /// for a delegated certificate.
+    /// https://www.rfc-editor.org/rfc/rfc9115.html#section-2.3.2
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delegation: Option<String>,
+
+    /// Asks for the certificate to be fetchable with an unauthenticated GET,
+    /// e.g. by a CDN the names are delegated to.
+    /// https://www.rfc-editor.org/rfc/rfc9115.html#section-2.3.3
+    #[serde(
+        rename = "allow-certificate-get",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub allow_certificate_get: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
@@ -156,6 +238,7 @@ mod tests {
         .unwrap();
 
         assert_eq!(order.status, OrderStatus::Valid);
+        assert!(order.violations().is_empty());
         assert_eq!(
             order.expires.unwrap(),
             DateTime::parse_from_rfc3339("2016-01-20T14:09:07.99Z").unwrap()
@@ -192,6 +275,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn order_violations() {
+        let order = OrderResource::deserialize(json!({
+            "status": "valid",
+            "identifiers": [],
+        }))
+        .unwrap();
+        assert_eq!(
+            order.violations(),
+            [
+                "order has no identifiers",
+                "order has no authorizations",
+                "order has no finalize URL",
+                "valid order has no expires",
+                "valid order has no certificate URL",
+            ]
+        );
+    }
+
     #[test]
     fn rfc8555_new_order_example() {
         let new_order = NewOrderResource {
@@ -201,6 +303,7 @@ mod tests {
             ],
             not_before: Some(DateTime::parse_from_rfc3339("2016-01-01T00:04:00+04:00").unwrap()),
             not_after: Some(DateTime::parse_from_rfc3339("2016-01-08T00:04:00+04:00").unwrap()),
+            ..Default::default()
         };
         assert_eq!(
             serde_json::to_value(new_order).unwrap(),
@@ -1,9 +1,16 @@
-use std::fmt::{Debug, Display};
+use std::{
+    fmt::{Debug, Display},
+    str::FromStr,
+};
 
+use chrono::{DateTime, FixedOffset};
 use serde::{de::IntoDeserializer, Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::{Map, Value};
 
-use super::identifier::AcmeIdentifier;
+use super::{
+    identifier::AcmeIdentifier,
+    redact::{redact, redact_json},
+};
 
 /// ACME Problem document
 /// https://datatracker.ietf.org/doc/html/rfc8555#section-6.7
@@ -52,6 +59,17 @@ pub struct AcmeProblem {
     /// details object with additional members.
     #[serde(flatten)]
     pub extensions: Map<String, Value>,
+
+    /// The time given by the Retry-After header of the response that carried
+    /// this problem, if any.
+    #[serde(skip)]
+    pub retry_after: Option<DateTime<FixedOffset>>,
+
+    /// The "terms-of-service" Link header of the response that carried this
+    /// problem, if any.
+    /// https://www.rfc-editor.org/rfc/rfc8555.html#section-7.3.3
+    #[serde(skip)]
+    pub terms_of_service: Option<String>,
 }
 
 impl AcmeProblem {
@@ -60,17 +78,181 @@ impl AcmeProblem {
     pub fn has_type(&self, problem_type: AcmeProblemType) -> bool {
         self.type_ == Some(problem_type)
     }
+
+    /// When the request may be retried, per the response's Retry-After header.
+    pub fn retry_after(&self) -> Option<DateTime<FixedOffset>> {
+        self.retry_after
+    }
+
+    /// The name of the exceeded rate limit, taken from a rate limit
+    /// documentation link in the detail, e.g. Let's Encrypt's
+    /// "https://letsencrypt.org/docs/rate-limits/#new-orders-per-account".
+    pub fn rate_limit_name(&self) -> Option<&str> {
+        let detail = self.detail.as_deref()?;
+        let (_, rest) = detail.split_once("rate-limits/#")?;
+        let name = rest
+            .split(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+            .next()?;
+        if name.is_empty() {
+            None
+        } else {
+            Some(name)
+        }
+    }
+}
+
+impl AcmeProblem {
+    /// A message for showing to end users, e.g. the customers of a hosting
+    /// control panel, as opposed to the server's `detail`, which is meant for
+    /// operators and varies between CAs and releases. It depends only on the
+    /// problem type, identifier and Retry-After time (and is in English);
+    /// translations can be keyed by `type_`. The subproblems of a compound
+    /// problem get a sentence each.
+    pub fn user_message(&self) -> String {
+        use AcmeProblemType::*;
+
+        if !self.subproblems.is_empty() && matches!(self.type_, None | Some(Compound)) {
+            return self
+                .subproblems
+                .iter()
+                .map(Self::user_message)
+                .collect::<Vec<_>>()
+                .join(" ");
+        }
+        let name = self
+            .identifier
+            .as_ref()
+            .map_or("the requested name", |identifier| identifier.value.as_str());
+        let for_name = self
+            .identifier
+            .as_ref()
+            .map(|identifier| format!(" for {}", identifier.value))
+            .unwrap_or_default();
+        let Some(type_) = &self.type_ else {
+            return format!("The certificate authority reported an error{}.", for_name);
+        };
+        match type_ {
+            AccountDoesNotExist => {
+                "The certificate authority has no record of this account.".to_string()
+            }
+            AlreadyRevoked => "The certificate has already been revoked.".to_string(),
+            BadCSR => format!("The certificate request{} was rejected.", for_name),
+            BadNonce => {
+                "The certificate authority was briefly unavailable; please try again.".to_string()
+            }
+            BadPublicKey => {
+                "The certificate authority doesn't accept this type or size of key.".to_string()
+            }
+            BadRevocationReason => {
+                "The certificate authority doesn't accept this revocation reason.".to_string()
+            }
+            BadSignatureAlgorithm => {
+                "The certificate authority doesn't accept the account's signature algorithm."
+                    .to_string()
+            }
+            Caa => format!(
+                "The DNS CAA records of {} don't allow this certificate authority to issue \
+                 certificates for it.",
+                name
+            ),
+            Compound => format!(
+                "Several problems prevented issuing a certificate{}.",
+                for_name
+            ),
+            Connection => format!(
+                "The certificate authority couldn't connect to {} to validate it.",
+                name
+            ),
+            Dns => format!(
+                "The certificate authority couldn't look up {} in DNS to validate it.",
+                name
+            ),
+            ExternalAccountRequired => {
+                "The certificate authority requires an account with it to be linked first."
+                    .to_string()
+            }
+            IncorrectResponse => format!(
+                "The certificate authority got an unexpected response when validating {}.",
+                name
+            ),
+            InvalidContact => "The contact email address isn't valid.".to_string(),
+            Malformed => "The certificate authority rejected the request as invalid.".to_string(),
+            OrderNotReady => {
+                "The certificate isn't ready to be issued yet; please try again shortly."
+                    .to_string()
+            }
+            RateLimited => {
+                let when = match self.retry_after {
+                    Some(retry_after) => format!(
+                        "after {}",
+                        retry_after
+                            .with_timezone(&chrono::Utc)
+                            .format("%Y-%m-%d %H:%M UTC")
+                    ),
+                    None => "later".to_string(),
+                };
+                format!(
+                    "Too many certificates were requested recently{}; please try again {}.",
+                    for_name, when
+                )
+            }
+            RejectedIdentifier => format!(
+                "The certificate authority won't issue certificates for {}.",
+                name
+            ),
+            ServerInternal => {
+                "The certificate authority had an internal error; please try again later."
+                    .to_string()
+            }
+            Tls => format!(
+                "The secure connection to {} failed when the certificate authority validated it.",
+                name
+            ),
+            Unauthorized => format!(
+                "The certificate authority couldn't confirm control of {}.",
+                name
+            ),
+            UnsupportedContact => {
+                "The certificate authority doesn't support this kind of contact address."
+                    .to_string()
+            }
+            UnsupportedIdentifier => format!(
+                "The certificate authority doesn't issue certificates for names like {}.",
+                name
+            ),
+            UserActionRequired => match &self.instance {
+                Some(instance) => format!(
+                    "The certificate authority needs the account holder to visit {}.",
+                    instance
+                ),
+                None => {
+                    "The certificate authority needs the account holder to take action.".to_string()
+                }
+            },
+            AlreadyReplaced => "The certificate has already been renewed.".to_string(),
+            Other(_) => format!("The certificate authority reported an error{}.", for_name),
+        }
+    }
+
+    /// A copy with emails, challenge tokens and account or resource IDs in
+    /// URLs replaced, for logs that leave our control; see `redact::redact`.
+    pub fn redacted(&self) -> Self {
+        let mut problem = self.clone();
+        problem.detail = problem.detail.as_deref().map(redact);
+        problem.instance = problem.instance.as_deref().map(redact);
+        if let Some(identifier) = &mut problem.identifier {
+            identifier.value = redact(&identifier.value);
+        }
+        problem.extensions.values_mut().for_each(redact_json);
+        problem.subproblems = problem.subproblems.iter().map(Self::redacted).collect();
+        problem
+    }
 }
 
 impl Display for AcmeProblem {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if self.type_.is_some() && self.detail.is_some() {
-            write!(
-                f,
-                "{:?}: {:?}",
-                self.type_.as_ref().unwrap(),
-                self.detail.as_ref().unwrap()
-            )
+        if let (Some(type_), Some(detail)) = (&self.type_, &self.detail) {
+            write!(f, "{:?}: {:?}", type_, detail)
         } else {
             write!(f, "{:?}", self)
         }
@@ -175,10 +357,60 @@ pub enum AcmeProblemType {
     #[serde(rename = "urn:ietf:params:acme:error:userActionRequired")]
     UserActionRequired,
 
+    /// The request specified a predecessor certificate which has already been
+    /// marked as replaced
+    /// https://datatracker.ietf.org/doc/html/draft-ietf-acme-ari#section-7.4
+    #[serde(rename = "urn:ietf:params:acme:error:alreadyReplaced")]
+    AlreadyReplaced,
+
     #[serde(skip_deserializing)]
     Other(String),
 }
 
+impl AcmeProblemType {
+    /// Whether the same request may succeed if simply retried (possibly after
+    /// a delay), without changes by the client.
+    pub fn is_retryable(&self) -> bool {
+        use AcmeProblemType::*;
+        matches!(
+            self,
+            BadNonce | RateLimited | ServerInternal | OrderNotReady
+        )
+    }
+
+    /// Whether this problem describes a failed identifier validation.
+    pub fn is_validation_error(&self) -> bool {
+        use AcmeProblemType::*;
+        matches!(
+            self,
+            Caa | Connection | Dns | IncorrectResponse | Tls | Unauthorized
+        )
+    }
+}
+
+impl FromStr for AcmeProblemType {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(AcmeProblemType::deserialize(
+            IntoDeserializer::<serde::de::value::Error>::into_deserializer(s),
+        )
+        .unwrap_or_else(|_| AcmeProblemType::Other(s.to_string())))
+    }
+}
+
+impl Display for AcmeProblemType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AcmeProblemType::Other(other) => f.write_str(other),
+            known => match serde_json::to_value(known) {
+                Ok(Value::String(urn)) => f.write_str(&urn),
+                _ => Err(std::fmt::Error),
+            },
+        }
+    }
+}
+
 // Workaround for https://github.com/serde-rs/serde/issues/912
 fn serialize_problem_type<S>(
     value: &Option<AcmeProblemType>,
@@ -200,12 +432,7 @@ where
     D: Deserializer<'de>,
 {
     let s = String::deserialize(deserializer)?;
-    Ok(Some(
-        AcmeProblemType::deserialize(IntoDeserializer::<'de, D::Error>::into_deserializer(
-            s.as_str(),
-        ))
-        .unwrap_or(AcmeProblemType::Other(s)),
-    ))
+    Ok(Some(s.parse().unwrap()))
 }
 
 #[cfg(test)]
@@ -239,6 +466,7 @@ mod tests {
         }))
         .unwrap();
 
+        assert!(problem.rate_limit_name().is_none());
         assert_eq!(problem.type_.unwrap(), AcmeProblemType::Malformed);
         assert_eq!(
             problem.detail.unwrap(),
@@ -253,4 +481,118 @@ mod tests {
             AcmeIdentifier::dns("example.net")
         );
     }
+
+    #[test]
+    fn rate_limit_name() {
+        let problem = AcmeProblem::deserialize(json!({
+            "type": "urn:ietf:params:acme:error:rateLimited",
+            "detail": "too many new orders (300) from this account in the last 3h0m0s, retry after 2025-01-01 00:00:00 UTC: see https://letsencrypt.org/docs/rate-limits/#new-orders-per-account",
+            "status": 429
+        }))
+        .unwrap();
+        assert_eq!(problem.rate_limit_name(), Some("new-orders-per-account"));
+    }
+
+    #[test]
+    fn redacted_problem() {
+        let problem = AcmeProblem::deserialize(json!({
+            "type": "urn:ietf:params:acme:error:invalidContact",
+            "detail": "contact mailto:admin@example.com of https://acme.test/acct/42 is invalid",
+            "instance": "https://acme.test/problems/8f3a",
+            "subproblems": [{
+                "type": "urn:ietf:params:acme:error:malformed",
+                "detail": "invalid email",
+                "identifier": {"type": "email", "value": "admin@example.com"}
+            }],
+            "contact": ["mailto:admin@example.com"]
+        }))
+        .unwrap();
+
+        let redacted = problem.redacted();
+        assert_eq!(
+            redacted.detail.as_deref(),
+            Some("contact mailto:<redacted> of https://acme.test/acct/<redacted> is invalid")
+        );
+        assert_eq!(
+            redacted.instance.as_deref(),
+            Some("https://acme.test/problems/<redacted>")
+        );
+        assert_eq!(
+            redacted.subproblems[0].identifier.as_ref().unwrap().value,
+            "<redacted>"
+        );
+        assert_eq!(redacted.extensions["contact"], json!(["mailto:<redacted>"]));
+        assert_eq!(redacted.type_, problem.type_);
+        assert!(!redacted.to_string().contains("admin@"));
+    }
+
+    #[test]
+    fn user_message() {
+        let problem: AcmeProblem = serde_json::from_value(json!({
+            "type": "urn:ietf:params:acme:error:rejectedIdentifier",
+            "detail": "Error creating new order :: Cannot issue for \"example.invalid\"",
+            "subproblems": [
+                {
+                    "type": "urn:ietf:params:acme:error:dns",
+                    "detail": "NXDOMAIN looking up A for bad.example.com",
+                    "identifier": { "type": "dns", "value": "bad.example.com" }
+                },
+                {
+                    "type": "urn:ietf:params:acme:error:rejectedIdentifier",
+                    "identifier": { "type": "dns", "value": "example.invalid" }
+                }
+            ]
+        }))
+        .unwrap();
+        assert_eq!(
+            problem.user_message(),
+            "The certificate authority won't issue certificates for the requested name."
+        );
+        let compound = AcmeProblem {
+            type_: Some(AcmeProblemType::Compound),
+            ..problem
+        };
+        assert_eq!(
+            compound.user_message(),
+            "The certificate authority couldn't look up bad.example.com in DNS to validate it. \
+             The certificate authority won't issue certificates for example.invalid."
+        );
+
+        let rate_limited = AcmeProblem {
+            type_: Some(AcmeProblemType::RateLimited),
+            detail: Some("too many new orders (300) from this account".to_string()),
+            retry_after: DateTime::parse_from_rfc3339("2025-01-01T01:30:00+01:00").ok(),
+            ..Default::default()
+        };
+        assert_eq!(
+            rate_limited.user_message(),
+            "Too many certificates were requested recently; please try again after \
+             2025-01-01 00:30 UTC."
+        );
+        assert_eq!(
+            AcmeProblem::default().user_message(),
+            "The certificate authority reported an error."
+        );
+    }
+
+    #[test]
+    fn problem_type_from_str_round_trip() {
+        let urn = "urn:ietf:params:acme:error:badCSR";
+        let problem_type: AcmeProblemType = urn.parse().unwrap();
+        assert_eq!(problem_type, AcmeProblemType::BadCSR);
+        assert_eq!(problem_type.to_string(), urn);
+
+        let urn = "urn:example:error:custom";
+        let problem_type: AcmeProblemType = urn.parse().unwrap();
+        assert_eq!(problem_type, AcmeProblemType::Other(urn.to_string()));
+        assert_eq!(problem_type.to_string(), urn);
+    }
+
+    #[test]
+    fn problem_type_predicates() {
+        assert!(AcmeProblemType::BadNonce.is_retryable());
+        assert!(!AcmeProblemType::BadCSR.is_retryable());
+        assert!(AcmeProblemType::Dns.is_validation_error());
+        assert!(!AcmeProblemType::Malformed.is_validation_error());
+    }
 }
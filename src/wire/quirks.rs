@@ -0,0 +1,240 @@
+use serde_json::Value;
+
+use super::{
+    directory::DirectoryResource,
+    problem::{AcmeProblem, AcmeProblemType},
+};
+
+const RFC8555_PROBLEM_NAMESPACE: &str = "urn:ietf:params:acme:error:";
+
+/// ACME server implementations with known deviations from RFC 8555, as told
+/// apart by the URLs in their directories.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ServerSoftware {
+    /// Let's Encrypt's Boulder.
+    Boulder,
+    /// Pebble, Let's Encrypt's test server.
+    Pebble,
+    /// smallstep step-ca.
+    StepCa,
+    /// HashiCorp Vault's PKI secrets engine.
+    Vault,
+    Unknown,
+}
+
+impl ServerSoftware {
+    pub fn detect(directory: &DirectoryResource) -> Self {
+        let new_account = url_path(&directory.new_account);
+        let segments: Vec<&str> = new_account.split('/').collect();
+        if directory.new_nonce.ends_with("/nonce-plz") {
+            ServerSoftware::Pebble
+        } else if new_account.ends_with("/acme/new-acct") {
+            ServerSoftware::Boulder
+        } else if new_account.starts_with("/v1/") && new_account.ends_with("/acme/new-account") {
+            ServerSoftware::Vault
+        } else if let [.., "acme", _provisioner, "new-account"] = segments[..] {
+            ServerSoftware::StepCa
+        } else {
+            ServerSoftware::Unknown
+        }
+    }
+}
+
+/// Workarounds for a server's deviations from RFC 8555. `AcmeClient::new`
+/// picks them with `Quirks::detect`; override with `AcmeClient::with_quirks`
+/// or `Client::set_quirks`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Quirks {
+    /// Problem type prefixes used in place of "urn:ietf:params:acme:error:".
+    /// Types in these namespaces are read as the RFC 8555 type of the same
+    /// name, if there is one.
+    pub problem_namespaces: Vec<String>,
+
+    /// Error responses may be plain JSON rather than problem documents, like
+    /// Vault's `{"errors": [...]}` for requests that fail before reaching
+    /// its ACME handlers.
+    pub json_errors: bool,
+}
+
+impl Quirks {
+    pub fn detect(directory: &DirectoryResource) -> Self {
+        Self::for_software(ServerSoftware::detect(directory))
+    }
+
+    pub fn for_software(software: ServerSoftware) -> Self {
+        match software {
+            // Leftovers of the pre-RFC draft's namespace
+            ServerSoftware::Boulder => Self {
+                problem_namespaces: vec!["urn:acme:error:".to_string()],
+                ..Default::default()
+            },
+            ServerSoftware::StepCa => Self {
+                problem_namespaces: vec!["urn:step:acme:error:".to_string()],
+                ..Default::default()
+            },
+            ServerSoftware::Vault => Self {
+                json_errors: true,
+                ..Default::default()
+            },
+            ServerSoftware::Pebble | ServerSoftware::Unknown => Self::default(),
+        }
+    }
+
+    /// Whether an error response with this content type essence holds an
+    /// error document.
+    pub(crate) fn is_error_document(&self, essence: &str) -> bool {
+        essence == AcmeProblem::CONTENT_TYPE || (self.json_errors && essence == "application/json")
+    }
+
+    /// Rewrites a problem document from an error response with `status` into
+    /// its RFC 8555 form.
+    pub(crate) fn normalize_problem(&self, problem: &mut AcmeProblem, status: u16) {
+        if let Some(AcmeProblemType::Other(type_)) = &problem.type_ {
+            let standard = self
+                .problem_namespaces
+                .iter()
+                .find_map(|namespace| type_.strip_prefix(namespace.as_str()))
+                .map(|name| {
+                    format!("{}{}", RFC8555_PROBLEM_NAMESPACE, name)
+                        .parse::<AcmeProblemType>()
+                        .unwrap()
+                });
+            if let Some(standard) = standard.filter(|t| !matches!(t, AcmeProblemType::Other(_))) {
+                problem.type_ = Some(standard);
+            }
+        }
+        if self.json_errors && problem.type_.is_none() && problem.detail.is_none() {
+            if let Some(Value::Array(errors)) = problem.extensions.get("errors") {
+                let errors: Vec<&str> = errors.iter().filter_map(Value::as_str).collect();
+                problem.detail = Some(errors.join("; "));
+                problem.status.get_or_insert(status);
+            }
+        }
+        for subproblem in &mut problem.subproblems {
+            self.normalize_problem(subproblem, status);
+        }
+    }
+}
+
+fn url_path(url: &str) -> &str {
+    let after_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let path = after_scheme.find('/').map_or("", |i| &after_scheme[i..]);
+    path.split(['?', '#']).next().unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+
+    fn directory(base: &str, paths: [&str; 5]) -> DirectoryResource {
+        let [new_nonce, new_account, new_order, revoke_cert, key_change] = paths;
+        DirectoryResource::deserialize(json!({
+            "newNonce": format!("{}{}", base, new_nonce),
+            "newAccount": format!("{}{}", base, new_account),
+            "newOrder": format!("{}{}", base, new_order),
+            "revokeCert": format!("{}{}", base, revoke_cert),
+            "keyChange": format!("{}{}", base, key_change),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn detect_server_software() {
+        let acme_paths = [
+            "/new-nonce",
+            "/new-account",
+            "/new-order",
+            "/revoke-cert",
+            "/key-change",
+        ];
+        let cases = [
+            (
+                directory(
+                    "https://localhost:14000",
+                    [
+                        "/nonce-plz",
+                        "/sign-me-up",
+                        "/order-plz",
+                        "/revoke-cert",
+                        "/rollover-account-key",
+                    ],
+                ),
+                ServerSoftware::Pebble,
+            ),
+            (
+                directory(
+                    "https://acme-v02.api.letsencrypt.org/acme",
+                    [
+                        "/new-nonce",
+                        "/new-acct",
+                        "/new-order",
+                        "/revoke-cert",
+                        "/key-change",
+                    ],
+                ),
+                ServerSoftware::Boulder,
+            ),
+            (
+                directory("https://ca.internal:9000/acme/acme", acme_paths),
+                ServerSoftware::StepCa,
+            ),
+            (
+                directory("https://vault.internal:8200/v1/pki/acme", acme_paths),
+                ServerSoftware::Vault,
+            ),
+            (
+                directory(
+                    "https://vault.internal:8200/v1/pki/roles/web/acme",
+                    acme_paths,
+                ),
+                ServerSoftware::Vault,
+            ),
+            (
+                directory("https://acme.test", acme_paths),
+                ServerSoftware::Unknown,
+            ),
+        ];
+        for (directory, software) in cases {
+            assert_eq!(
+                ServerSoftware::detect(&directory),
+                software,
+                "{}",
+                directory.new_account
+            );
+        }
+    }
+
+    #[test]
+    fn normalize_problems() {
+        let step_ca = Quirks::for_software(ServerSoftware::StepCa);
+        let mut problem = AcmeProblem::deserialize(json!({
+            "type": "urn:step:acme:error:badNonce",
+            "subproblems": [{"type": "urn:step:acme:error:notImplemented"}]
+        }))
+        .unwrap();
+        step_ca.normalize_problem(&mut problem, 400);
+        assert!(problem.has_type(AcmeProblemType::BadNonce));
+        assert_eq!(
+            problem.subproblems[0].type_,
+            Some(AcmeProblemType::Other(
+                "urn:step:acme:error:notImplemented".to_string()
+            ))
+        );
+
+        let mut problem = AcmeProblem::deserialize(json!({
+            "errors": ["permission denied", "1 error occurred"]
+        }))
+        .unwrap();
+        Quirks::default().normalize_problem(&mut problem, 403);
+        assert_eq!(problem.detail, None);
+        Quirks::for_software(ServerSoftware::Vault).normalize_problem(&mut problem, 403);
+        assert_eq!(
+            problem.detail.as_deref(),
+            Some("permission denied; 1 error occurred")
+        );
+        assert_eq!(problem.status, Some(403));
+    }
+}
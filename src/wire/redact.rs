@@ -0,0 +1,132 @@
+//! Redaction of identifying details from text bound for logs, e.g. problem
+//! details and transcripts shipped to a third-party log aggregator.
+
+use serde_json::Value;
+
+pub(crate) const REDACTED: &str = "<redacted>";
+
+/// URL path segments at least this long are taken to be IDs.
+const MIN_ID_SEGMENT_LENGTH: usize = 22;
+
+/// Replaces email addresses, challenge tokens (and key authorizations), and
+/// the parts of URLs that may identify an account or its resources with
+/// "<redacted>". URL path segments containing a digit or as long as a token
+/// are taken to be IDs, e.g. ".../acme/acct/<redacted>"; query strings,
+/// fragments and credentials go too. Domain names are kept.
+pub fn redact(text: &str) -> String {
+    let mut redacted = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("https://") || rest.starts_with("http://") {
+            let len = rest
+                .find(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '<' | '>' | '\\'))
+                .unwrap_or(rest.len());
+            let url = rest[..len].trim_end_matches(['.', ',', ';', ':', ')']);
+            redacted.push_str(&redact_url(url));
+            rest = &rest[url.len()..];
+        } else if is_word_char(c) {
+            let len = rest.find(|c| !is_word_char(c)).unwrap_or(rest.len());
+            redacted.push_str(&redact_word(&rest[..len]));
+            rest = &rest[len..];
+        } else {
+            redacted.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    redacted
+}
+
+/// Applies `redact` to every string in a JSON value.
+pub(crate) fn redact_json(value: &mut Value) {
+    match value {
+        Value::String(s) => *s = redact(s),
+        Value::Array(values) => values.iter_mut().for_each(redact_json),
+        Value::Object(map) => map.values_mut().for_each(redact_json),
+        _ => {}
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '%' | '+' | '-' | '@')
+}
+
+fn is_base64url(s: &str) -> bool {
+    s.bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+fn redact_word(word: &str) -> String {
+    if let Some((local, domain)) = word.split_once('@') {
+        if !local.is_empty() && domain.contains('.') {
+            return REDACTED.to_string();
+        }
+    }
+    word.split('.')
+        .map(|part| {
+            if part.len() >= MIN_ID_SEGMENT_LENGTH && is_base64url(part) {
+                REDACTED
+            } else {
+                part
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+fn redact_url(url: &str) -> String {
+    let (scheme, rest) = url.split_once("://").unwrap_or(("", url));
+    let rest = rest.split('#').next().unwrap_or("");
+    let (rest, query) = match rest.split_once('?') {
+        Some((rest, _)) => (rest, true),
+        None => (rest, false),
+    };
+    let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let host = match authority.rsplit_once('@') {
+        Some((_, host)) => format!("{}@{}", REDACTED, host),
+        None => authority.to_string(),
+    };
+    let path = path
+        .split('/')
+        .map(|segment| {
+            if segment.bytes().any(|b| b.is_ascii_digit()) || segment.len() >= MIN_ID_SEGMENT_LENGTH
+            {
+                REDACTED
+            } else {
+                segment
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+    let mut redacted = format!("{}://{}{}", scheme, host, path);
+    if query {
+        redacted.push('?');
+        redacted.push_str(REDACTED);
+    }
+    redacted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_text() {
+        assert_eq!(
+            redact("contact mailto:admin@example.com is invalid"),
+            "contact mailto:<redacted> is invalid"
+        );
+        assert_eq!(
+            redact("\"https://acme.test/acme/acct/123456\", see https://acme.test/docs."),
+            "\"https://acme.test/acme/acct/<redacted>\", see https://acme.test/docs."
+        );
+        assert_eq!(
+            redact("https://user:pw@acme.test/order/1/2?page=3#x"),
+            "https://<redacted>@acme.test/order/<redacted>/<redacted>?<redacted>"
+        );
+        assert_eq!(
+            redact("expected LoqXcYV8q5ONbJQxbmR7SCTNo3tiAXDfowyjxAjEuX0.9jg46WB3rR_AHD-EBXdN7cBkH1WOu0tA3M9fm21mqTI for example.com"),
+            "expected <redacted>.<redacted> for example.com"
+        );
+        assert_eq!(redact("DNS problem: NXDOMAIN"), "DNS problem: NXDOMAIN");
+    }
+}
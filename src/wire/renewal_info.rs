@@ -0,0 +1,105 @@
+use chrono::{DateTime, Duration, FixedOffset};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::base64url;
+
+/// ACME Renewal Information (ARI) resource
+/// https://datatracker.ietf.org/doc/html/draft-ietf-acme-ari#section-4.2
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RenewalInfo {
+    /// The window within which the server recommends the certificate be renewed.
+    pub suggested_window: SuggestedWindow,
+
+    /// A URL pointing to a page which may explain why the suggested renewal
+    /// window is what it is.
+    #[serde(
+        default,
+        rename = "explanationURL",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub explanation_url: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SuggestedWindow {
+    pub start: DateTime<FixedOffset>,
+    pub end: DateTime<FixedOffset>,
+}
+
+impl SuggestedWindow {
+    /// Picks a uniformly random time within the window, as recommended to
+    /// spread renewal load.
+    pub fn random_time(&self) -> DateTime<FixedOffset> {
+        let span = (self.end - self.start).num_seconds();
+        if span <= 0 {
+            return self.start;
+        }
+        self.start + Duration::seconds(rand::rngs::OsRng.gen_range(0..span))
+    }
+}
+
+impl RenewalInfo {
+    /// See `SuggestedWindow::random_time`.
+    pub fn random_renewal_time(&self) -> DateTime<FixedOffset> {
+        self.suggested_window.random_time()
+    }
+}
+
+/// Computes the ARI certificate identifier from the certificate's Authority Key
+/// Identifier keyIdentifier and its serial number, given as the content octets
+/// of its DER encoding (i.e. including any leading zero byte).
+/// https://datatracker.ietf.org/doc/html/draft-ietf-acme-ari#section-4.1
+pub fn ari_cert_id(authority_key_id: &[u8], serial_der: &[u8]) -> String {
+    format!(
+        "{}.{}",
+        base64url::encode(authority_key_id),
+        base64url::encode(serial_der)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn ari_renewal_info_example() {
+        let info = RenewalInfo::deserialize(json!({
+            "suggestedWindow": {
+                "start": "2025-01-02T04:00:00Z",
+                "end": "2025-01-03T04:00:00Z"
+            },
+            "explanationURL": "https://acme.example.com/docs/ari"
+        }))
+        .unwrap();
+
+        let window = &info.suggested_window;
+        assert_eq!(
+            window.start,
+            DateTime::parse_from_rfc3339("2025-01-02T04:00:00Z").unwrap()
+        );
+        assert_eq!(
+            info.explanation_url.as_deref().unwrap(),
+            "https://acme.example.com/docs/ari"
+        );
+
+        let renewal_time = info.random_renewal_time();
+        assert!(window.start <= renewal_time && renewal_time < window.end);
+    }
+
+    #[test]
+    fn ari_cert_id_example() {
+        let aki = [
+            0x69, 0x88, 0x5B, 0x6B, 0x87, 0x46, 0x40, 0x41, 0xE1, 0xB3, 0x7B, 0x84, 0x7B, 0xA0,
+            0xAE, 0x2C, 0xDE, 0x01, 0xC8, 0xD4,
+        ];
+        let serial = [0x00, 0x87, 0x65, 0x43, 0x21];
+        assert_eq!(
+            ari_cert_id(&aki, &serial),
+            "aYhba4dGQEHhs3uEe6CuLN4ByNQ.AIdlQyE"
+        );
+    }
+}
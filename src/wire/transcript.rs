@@ -0,0 +1,258 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::Mutex,
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::transport::{Body, Request, Response};
+use crate::{base64url, crypto::jws::Jws, error::AcmeResult};
+
+use super::redact::{redact, redact_json, REDACTED};
+
+/// A bounded log of the requests an `AcmeClient` sent and the responses it
+/// received, e.g. for a support request to the CA. Nonces are redacted;
+/// see `to_redacted_json` for logs that go to third parties.
+///
+/// Recording buffers every response body, so only enable it when needed.
+#[derive(Debug)]
+pub struct Transcript {
+    capacity: usize,
+    entries: Mutex<VecDeque<TranscriptEntry>>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct TranscriptEntry {
+    pub time: DateTime<Utc>,
+    pub method: String,
+    pub url: String,
+
+    /// The decoded JWS protected header, for signed requests.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protected: Option<Value>,
+
+    /// The decoded JWS payload; an empty string for POST-as-GET.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload: Option<Value>,
+
+    /// None if no response was received.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<TranscriptResponse>,
+
+    /// Why no response was received.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl TranscriptEntry {
+    /// A copy without the account key and with emails, challenge tokens and
+    /// account or resource IDs in URLs replaced; see `redact::redact`.
+    pub fn redacted(&self) -> Self {
+        let mut entry = self.clone();
+        entry.url = redact(&entry.url);
+        if let Some(Value::Object(protected)) = &mut entry.protected {
+            if let Some(jwk) = protected.get_mut("jwk") {
+                *jwk = Value::from(REDACTED);
+            }
+        }
+        entry.protected.iter_mut().for_each(redact_json);
+        entry.payload.iter_mut().for_each(redact_json);
+        if let Some(response) = &mut entry.response {
+            for values in response.headers.values_mut() {
+                for value in values {
+                    *value = redact(value);
+                }
+            }
+            response.body = redact(&response.body);
+        }
+        entry.error = entry.error.as_deref().map(redact);
+        entry
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct TranscriptResponse {
+    pub status: u16,
+    pub headers: BTreeMap<String, Vec<String>>,
+    pub body: String,
+}
+
+impl Transcript {
+    /// Keeps up to `capacity` entries, dropping the oldest.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn entries(&self) -> Vec<TranscriptEntry> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// The entries as a JSON array.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(&*self.entries.lock().unwrap())
+    }
+
+    /// The entries as a JSON array, with each `TranscriptEntry::redacted`.
+    pub fn to_redacted_json(&self) -> serde_json::Result<String> {
+        let entries: Vec<_> = self
+            .entries
+            .lock()
+            .unwrap()
+            .iter()
+            .map(TranscriptEntry::redacted)
+            .collect();
+        serde_json::to_string_pretty(&entries)
+    }
+
+    pub(crate) fn request_entry(&self, req: &Request, jws: Option<&Jws>) -> TranscriptEntry {
+        let (protected, payload) = match jws {
+            Some(jws) => {
+                let mut protected = decode_json(&jws.protected);
+                if let Some(nonce) = protected.get_mut("nonce") {
+                    *nonce = Value::from(REDACTED);
+                }
+                (Some(protected), Some(decode_json(&jws.payload)))
+            }
+            None => (None, None),
+        };
+        TranscriptEntry {
+            time: Utc::now(),
+            method: req.method().to_string(),
+            url: req.url().to_string(),
+            protected,
+            payload,
+            response: None,
+            error: None,
+        }
+    }
+
+    /// Records `entry` with `res`, buffering the response body so that it
+    /// can still be read by the caller.
+    pub(crate) async fn record(
+        &self,
+        mut entry: TranscriptEntry,
+        res: &mut AcmeResult<Response>,
+    ) -> AcmeResult<()> {
+        match res {
+            Ok(resp) => {
+                let body = resp.take_body();
+                let mime = body.mime().clone();
+                let bytes = body.into_bytes().await?;
+                let mut headers = BTreeMap::new();
+                for (name, values) in resp.iter() {
+                    let values = if name.as_str().eq_ignore_ascii_case("replay-nonce") {
+                        vec![REDACTED.to_string()]
+                    } else {
+                        values.iter().map(|v| v.to_string()).collect()
+                    };
+                    headers.insert(name.to_string(), values);
+                }
+                entry.response = Some(TranscriptResponse {
+                    status: resp.status().into(),
+                    headers,
+                    body: String::from_utf8_lossy(&bytes).into_owned(),
+                });
+                let mut body = Body::from(bytes);
+                body.set_mime(mime);
+                resp.set_body(body);
+            }
+            Err(err) => entry.error = Some(err.to_string()),
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        if self.capacity > 0 {
+            entries.push_back(entry);
+        }
+        Ok(())
+    }
+}
+
+/// Decodes a base64url JSON value, falling back to the raw string.
+fn decode_json(b64: &str) -> Value {
+    let bytes = match base64url::decode(b64) {
+        Ok(bytes) => bytes,
+        Err(_) => return Value::from(b64),
+    };
+    if bytes.is_empty() {
+        return Value::from("");
+    }
+    serde_json::from_slice(&bytes)
+        .unwrap_or_else(|_| Value::from(String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::test_support::mock_server::MockAcmeServer;
+
+    use super::*;
+
+    #[async_std::test]
+    async fn records_requests() {
+        let server = MockAcmeServer::new();
+        let mut client = server.client().await.unwrap();
+        let transcript = Arc::new(Transcript::new(3));
+        client.set_transcript(transcript.clone());
+        let account = client
+            .register_account("admin@example.com".to_string(), true)
+            .await
+            .unwrap();
+
+        let entries = transcript.entries();
+        assert_eq!(entries[0].method, "HEAD");
+        let new_account = &entries[1];
+        assert_eq!(new_account.method, "POST");
+        assert_eq!(new_account.protected.as_ref().unwrap()["nonce"], REDACTED);
+        assert_eq!(
+            new_account.payload.as_ref().unwrap()["contact"][0],
+            "mailto:admin@example.com"
+        );
+        let response = new_account.response.as_ref().unwrap();
+        assert_eq!(response.status, 201);
+        assert_eq!(response.headers["replay-nonce"], [REDACTED]);
+        assert!(response.body.contains("\"valid\""));
+
+        // Problem documents still reach the caller; the oldest entry is dropped
+        for _ in 0..2 {
+            let err = account
+                .get_order("https://example.com/acme/order/missing")
+                .await
+                .err()
+                .unwrap();
+            assert!(err.problem().is_some(), "{:?}", err);
+        }
+        let entries = transcript.entries();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[2].payload.as_ref().unwrap(), "");
+        assert!(transcript.to_json().unwrap().starts_with('['));
+
+        let redacted = transcript.to_redacted_json().unwrap();
+        assert!(!redacted.contains(account.url()), "{}", redacted);
+        assert!(redacted.contains("https://acme.test/account/<redacted>"));
+        let new_account = new_account.redacted();
+        assert_eq!(new_account.protected.as_ref().unwrap()["jwk"], REDACTED);
+        assert_eq!(
+            new_account.payload.as_ref().unwrap()["contact"][0],
+            "mailto:<redacted>"
+        );
+        let response = new_account.response.unwrap();
+        assert!(!response.body.contains("admin@example.com"));
+        assert_eq!(
+            response.headers["location"],
+            ["https://acme.test/account/<redacted>"]
+        );
+    }
+}
@@ -0,0 +1,662 @@
+use std::net::IpAddr;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use foreign_types::ForeignType;
+use openssl::{
+    asn1::{Asn1Object, Asn1Time, Asn1TimeRef},
+    ec::{EcGroup, EcKey},
+    error::ErrorStack,
+    hash::MessageDigest,
+    nid::Nid,
+    pkcs12::Pkcs12,
+    pkcs7::Pkcs7,
+    pkey::{Id, PKey, Private},
+    rsa::Rsa,
+    stack::Stack,
+    x509::{
+        extension::{self, SubjectAlternativeName},
+        X509NameBuilder, X509Req, X509ReqBuilder, X509VerifyResult, X509,
+    },
+};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use crate::{
+    clock::{Clock, SystemClock},
+    wire::{identifier::AcmeIdentifier, renewal_info},
+    AcmeError, AcmeResult,
+};
+
+use super::{
+    CsrBuilder, ExtendedKeyUsage, IssuedCertificate, KeyType, KeyUsage, PrivateKeyInput,
+    SubjectAttribute, APPLICANT_SIGNING_NONCE_OID, CA_SIGNING_NONCE_OID,
+};
+
+pub fn generate_key_and_csr(name: impl AsRef<str>) -> AcmeResult<(Zeroizing<String>, Vec<u8>)> {
+    build_csr(CsrBuilder::new().dns(name.as_ref()))
+}
+
+pub(super) fn can_generate(_key_type: KeyType) -> bool {
+    true
+}
+
+pub(super) fn generate_private_key(
+    key_type: KeyType,
+) -> AcmeResult<(Zeroizing<String>, Zeroizing<Vec<u8>>)> {
+    let key = generate_key(key_type)?;
+    Ok((
+        private_key_pem(&key)?,
+        Zeroizing::new(key.private_key_to_pkcs8()?),
+    ))
+}
+
+fn private_key_pem(key: &PKey<Private>) -> AcmeResult<Zeroizing<String>> {
+    // from_utf8 takes over the buffer without copying it
+    Ok(Zeroizing::new(
+        String::from_utf8(key.private_key_to_pem_pkcs8()?).unwrap(),
+    ))
+}
+
+fn generate_key(key_type: KeyType) -> AcmeResult<PKey<Private>> {
+    let ec_key = |nid| -> AcmeResult<PKey<Private>> {
+        let ec_group = EcGroup::from_curve_name(nid)?;
+        Ok(PKey::from_ec_key(EcKey::generate(ec_group.as_ref())?)?)
+    };
+    match key_type {
+        KeyType::P256 => ec_key(Nid::X9_62_PRIME256V1),
+        KeyType::P384 => ec_key(Nid::SECP384R1),
+        KeyType::P521 => ec_key(Nid::SECP521R1),
+        KeyType::Rsa2048 => Ok(PKey::from_rsa(Rsa::generate(2048)?)?),
+        KeyType::Rsa3072 => Ok(PKey::from_rsa(Rsa::generate(3072)?)?),
+        KeyType::Rsa4096 => Ok(PKey::from_rsa(Rsa::generate(4096)?)?),
+        KeyType::Ed25519 => Ok(PKey::generate_ed25519()?),
+    }
+}
+
+/// The digest to sign a CSR with, matching the key's strength as the
+/// Baseline Requirements expect for ECDSA; Ed25519 takes none.
+fn signing_digest(key: &PKey<Private>) -> AcmeResult<MessageDigest> {
+    Ok(match key.id() {
+        Id::ED25519 => MessageDigest::null(),
+        Id::EC => match key.ec_key()?.group().curve_name() {
+            Some(Nid::SECP384R1) => MessageDigest::sha384(),
+            Some(Nid::SECP521R1) => MessageDigest::sha512(),
+            _ => MessageDigest::sha256(),
+        },
+        _ => MessageDigest::sha256(),
+    })
+}
+
+pub(super) fn build_csr(builder: CsrBuilder) -> AcmeResult<(Zeroizing<String>, Vec<u8>)> {
+    let key = match &builder.key {
+        Some(PrivateKeyInput::Pem(pem)) => PKey::private_key_from_pem(pem)?,
+        Some(PrivateKeyInput::Der(der)) => PKey::private_key_from_der(der)?,
+        None => generate_key(builder.key_type)?,
+    };
+    let key_pem = private_key_pem(&key)?;
+
+    let mut csr = X509ReqBuilder::new()?;
+    csr.set_pubkey(key.as_ref())?;
+    if !builder.subject.is_empty() {
+        let mut subject = X509NameBuilder::new()?;
+        for (attribute, value) in &builder.subject {
+            let nid = match attribute {
+                SubjectAttribute::CommonName => Nid::COMMONNAME,
+                SubjectAttribute::Country => Nid::COUNTRYNAME,
+                SubjectAttribute::StateOrProvince => Nid::STATEORPROVINCENAME,
+                SubjectAttribute::Locality => Nid::LOCALITYNAME,
+                SubjectAttribute::Organization => Nid::ORGANIZATIONNAME,
+                SubjectAttribute::OrganizationalUnit => Nid::ORGANIZATIONALUNITNAME,
+            };
+            subject.append_entry_by_nid(nid, value)?;
+        }
+        csr.set_subject_name(&subject.build())?;
+    }
+    let mut san = SubjectAlternativeName::new();
+    for name in &builder.dns_names {
+        san.dns(name);
+    }
+    for addr in &builder.ip_addrs {
+        san.ip(&addr.to_string());
+    }
+    let mut extensions = Stack::new()?;
+    extensions.push(san.build(&csr.x509v3_context(None))?)?;
+    if !builder.key_usage.is_empty() {
+        let mut key_usage = extension::KeyUsage::new();
+        key_usage.critical();
+        for usage in &builder.key_usage {
+            match usage {
+                KeyUsage::DigitalSignature => key_usage.digital_signature(),
+                KeyUsage::KeyEncipherment => key_usage.key_encipherment(),
+                KeyUsage::KeyAgreement => key_usage.key_agreement(),
+            };
+        }
+        extensions.push(key_usage.build()?)?;
+    }
+    if !builder.extended_key_usage.is_empty() {
+        let mut extended_key_usage = extension::ExtendedKeyUsage::new();
+        for usage in &builder.extended_key_usage {
+            match usage {
+                ExtendedKeyUsage::ServerAuth => extended_key_usage.server_auth(),
+                ExtendedKeyUsage::ClientAuth => extended_key_usage.client_auth(),
+            };
+        }
+        extensions.push(extended_key_usage.build()?)?;
+    }
+    csr.add_extensions(extensions.as_ref())?;
+    let digest = signing_digest(&key)?;
+    // X509ReqBuilder can't add attributes, so those requests are finished
+    // (and signed) through the FFI
+    let csr_der = match &builder.signing_nonces {
+        None => {
+            csr.sign(key.as_ref(), digest)?;
+            csr.build().to_der()?
+        }
+        Some(nonces) => {
+            let csr = csr.build();
+            add_octet_string_attribute(&csr, CA_SIGNING_NONCE_OID, &nonces.ca)?;
+            add_octet_string_attribute(&csr, APPLICANT_SIGNING_NONCE_OID, &nonces.applicant)?;
+            // SAFETY: all pointers are valid for the duration of the call
+            cvt(unsafe {
+                openssl_sys::X509_REQ_sign(csr.as_ptr(), key.as_ptr(), digest.as_ptr())
+            })?;
+            csr.to_der()?
+        }
+    };
+
+    Ok((key_pem, csr_der))
+}
+
+fn add_octet_string_attribute(csr: &X509Req, oid: &[u64], value: &[u8]) -> AcmeResult<()> {
+    let oid = oid.iter().map(u64::to_string).collect::<Vec<_>>().join(".");
+    let obj = Asn1Object::from_str(&oid)?;
+    let len = value
+        .len()
+        .try_into()
+        .map_err(|_| AcmeError::InvalidState(format!("attribute {} is too long", oid)))?;
+    // SAFETY: all pointers are valid for the duration of the call, and
+    // OpenSSL copies the value
+    cvt(unsafe {
+        openssl_sys::X509_REQ_add1_attr_by_OBJ(
+            csr.as_ptr(),
+            obj.as_ptr(),
+            openssl_sys::V_ASN1_OCTET_STRING,
+            value.as_ptr(),
+            len,
+        )
+    })
+}
+
+fn cvt(ret: std::os::raw::c_int) -> AcmeResult<()> {
+    if ret <= 0 {
+        Err(ErrorStack::get().into())
+    } else {
+        Ok(())
+    }
+}
+
+pub fn ari_cert_id(cert_pem: impl AsRef<[u8]>) -> AcmeResult<String> {
+    let cert = X509::from_pem(cert_pem.as_ref())?;
+    let aki = cert
+        .authority_key_id()
+        .ok_or(AcmeError::MissingExpectedField("authorityKeyIdentifier"))?;
+    let mut serial = cert.serial_number().to_bn()?.to_vec();
+    // DER INTEGERs are signed; restore the leading zero of positive serials
+    if serial.first().is_none_or(|b| b & 0x80 != 0) {
+        serial.insert(0, 0);
+    }
+    Ok(renewal_info::ari_cert_id(aki.as_slice(), &serial))
+}
+
+/// The SHA-256 hash of a DER certificate's SubjectPublicKeyInfo, as used
+/// for key pinning (RFC 7469 "pin-sha256").
+pub fn spki_sha256(cert_der: impl AsRef<[u8]>) -> AcmeResult<[u8; 32]> {
+    let cert = X509::from_der(cert_der.as_ref())?;
+    let spki = cert.public_key()?.public_key_to_der()?;
+    Ok(Sha256::digest(&spki).into())
+}
+
+/// The leaf certificate of a PEM chain, in DER.
+pub fn leaf_der(fullchain_pem: &str) -> AcmeResult<Vec<u8>> {
+    Ok(X509::from_pem(fullchain_pem.as_bytes())?.to_der()?)
+}
+
+/// A PEM chain as a certificates-only PKCS#7 SignedData (DER), like
+/// `openssl crl2pkcs7 -nocrl`, e.g. for Windows' certificate import.
+pub fn chain_to_pkcs7(fullchain_pem: &str) -> AcmeResult<Vec<u8>> {
+    let chain = X509::stack_from_pem(fullchain_pem.as_bytes())?;
+    // SAFETY: the PKCS7 is owned (and freed) by `pkcs7`, and
+    // PKCS7_add_certificate takes its own reference to each certificate
+    unsafe {
+        let pkcs7 = Pkcs7::from_ptr(openssl_sys::PKCS7_new());
+        cvt(openssl_sys::PKCS7_set_type(
+            pkcs7.as_ptr(),
+            openssl_sys::NID_pkcs7_signed,
+        ))?;
+        cvt(openssl_sys::PKCS7_content_new(
+            pkcs7.as_ptr(),
+            openssl_sys::NID_pkcs7_data,
+        ))?;
+        for cert in &chain {
+            cvt(openssl_sys::PKCS7_add_certificate(
+                pkcs7.as_ptr(),
+                cert.as_ptr(),
+            ))?;
+        }
+        Ok(pkcs7.to_der()?)
+    }
+}
+
+/// A PEM chain and its PEM private key as a password-protected PKCS#12
+/// file, e.g. for Java keystores.
+pub fn chain_to_pkcs12(fullchain_pem: &str, key_pem: &str, password: &str) -> AcmeResult<Vec<u8>> {
+    let mut chain = X509::stack_from_pem(fullchain_pem.as_bytes())?.into_iter();
+    let leaf = chain
+        .next()
+        .ok_or_else(|| AcmeError::InvalidCertificate("empty certificate chain".to_string()))?;
+    let key = PKey::private_key_from_pem(key_pem.as_bytes())?;
+    let mut ca = Stack::new()?;
+    for cert in chain {
+        ca.push(cert)?;
+    }
+    Ok(Pkcs12::builder()
+        .pkey(&key)
+        .cert(&leaf)
+        .ca(ca)
+        .build2(password)?
+        .to_der()?)
+}
+
+/// The common name of the issuer of the topmost (closest to the root)
+/// certificate in a PEM chain, as matched by certbot's `--preferred-chain`.
+pub fn chain_top_issuer(fullchain_pem: &str) -> AcmeResult<Option<String>> {
+    let chain = X509::stack_from_pem(fullchain_pem.as_bytes())?;
+    let top = match chain.last() {
+        Some(top) => top,
+        None => return Ok(None),
+    };
+    Ok(top
+        .issuer_name()
+        .entries_by_nid(Nid::COMMONNAME)
+        .next()
+        .map(|entry| String::from_utf8_lossy(entry.data().as_slice()).into_owned()))
+}
+
+/// How far in the future a new certificate's notBefore may be, to allow for
+/// clock skew between us and the CA.
+const MAX_NOT_BEFORE_SKEW_HOURS: i64 = 1;
+
+/// Checks a downloaded certificate chain before it is deployed: each
+/// certificate must be signed by the next, the leaf must cover every ordered
+/// identifier and carry the CSR's public key, and it must be currently valid
+/// by the system clock (`Order::get_verified_certificate` and
+/// `issue_verified` use the client's `Clock` instead). This doesn't check
+/// that the chain leads to a trusted root.
+pub fn verify_certificate(
+    fullchain_pem: &str,
+    identifiers: &[AcmeIdentifier],
+    csr_der: impl AsRef<[u8]>,
+) -> AcmeResult<IssuedCertificate> {
+    verify_certificate_at(fullchain_pem, identifiers, csr_der, SystemClock.now())
+}
+
+/// Like `verify_certificate`, checking validity at `now` rather than the
+/// system time.
+pub fn verify_certificate_at(
+    fullchain_pem: &str,
+    identifiers: &[AcmeIdentifier],
+    csr_der: impl AsRef<[u8]>,
+    now: DateTime<Utc>,
+) -> AcmeResult<IssuedCertificate> {
+    let invalid = |msg: String| AcmeError::InvalidCertificate(msg);
+    let chain = X509::stack_from_pem(fullchain_pem.as_bytes())
+        .map_err(|err| invalid(format!("unparseable chain: {}", err)))?;
+    let leaf = chain
+        .first()
+        .ok_or_else(|| invalid("empty certificate chain".to_string()))?;
+
+    for (idx, pair) in chain.windows(2).enumerate() {
+        let (subject, issuer) = (&pair[0], &pair[1]);
+        if issuer.issued(subject) != X509VerifyResult::OK
+            || !subject.verify(issuer.public_key()?.as_ref())?
+        {
+            return Err(invalid(format!(
+                "certificate {} is not issued by the next in the chain",
+                idx
+            )));
+        }
+    }
+
+    let csr = X509Req::from_der(csr_der.as_ref())?;
+    if !csr.public_key()?.public_eq(leaf.public_key()?.as_ref()) {
+        return Err(invalid("public key doesn't match the CSR".to_string()));
+    }
+
+    let mut names = Vec::new();
+    for name in leaf.subject_alt_names().iter().flatten() {
+        if let Some(dns_name) = name.dnsname() {
+            names.push(AcmeIdentifier::dns(dns_name));
+        } else if let Some(addr) = name.ipaddress().and_then(ip_addr_from_bytes) {
+            names.push(AcmeIdentifier::ip(addr));
+        }
+    }
+    for identifier in identifiers {
+        let covered = names.iter().any(|name| {
+            name.type_ == identifier.type_
+                && match (name.ip_addr(), identifier.ip_addr()) {
+                    (Some(a), Some(b)) => a == b,
+                    _ => name.value.eq_ignore_ascii_case(&identifier.value),
+                }
+        });
+        if !covered {
+            return Err(invalid(format!("{} is missing", identifier.value)));
+        }
+    }
+
+    let not_before = asn1_time_to_utc(leaf.not_before())?;
+    let not_after = asn1_time_to_utc(leaf.not_after())?;
+    if not_before >= not_after
+        || not_after <= now
+        || not_before > now + Duration::hours(MAX_NOT_BEFORE_SKEW_HOURS)
+    {
+        return Err(invalid(format!(
+            "validity {} to {} is not current",
+            not_before, not_after
+        )));
+    }
+
+    Ok(IssuedCertificate {
+        fullchain_pem: fullchain_pem.to_string(),
+        not_before,
+        not_after,
+        identifiers: names,
+    })
+}
+
+fn ip_addr_from_bytes(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => Some(IpAddr::from(<[u8; 4]>::try_from(bytes).ok()?)),
+        16 => Some(IpAddr::from(<[u8; 16]>::try_from(bytes).ok()?)),
+        _ => None,
+    }
+}
+
+fn asn1_time_to_utc(time: &Asn1TimeRef) -> AcmeResult<DateTime<Utc>> {
+    let diff = Asn1Time::from_unix(0)?.diff(time)?;
+    let secs = i64::from(diff.days) * 86400 + i64::from(diff.secs);
+    Ok(Utc.timestamp(secs, 0))
+}
+
+impl From<ErrorStack> for AcmeError {
+    fn from(err: ErrorStack) -> Self {
+        AcmeError::CryptoError(err.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smoke_test() {
+        generate_key_and_csr("example.com").unwrap();
+    }
+
+    fn sign_certificate(
+        subject_key: &PKey<Private>,
+        issuer: Option<(&X509, &PKey<Private>)>,
+        dns_names: &[&str],
+        days: u32,
+    ) -> X509 {
+        use openssl::{
+            asn1::Asn1Time,
+            x509::{
+                extension::{AuthorityKeyIdentifier, BasicConstraints, SubjectKeyIdentifier},
+                X509NameBuilder,
+            },
+        };
+
+        let mut name = X509NameBuilder::new().unwrap();
+        let cn = dns_names.first().copied().unwrap_or("Test CA");
+        name.append_entry_by_nid(Nid::COMMONNAME, cn).unwrap();
+        let name = name.build();
+
+        let mut cert = X509::builder().unwrap();
+        cert.set_version(2).unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_pubkey(subject_key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(days).unwrap())
+            .unwrap();
+        let (issuer_cert, issuer_key) = match issuer {
+            Some((cert, key)) => (Some(cert), key),
+            None => (None, subject_key),
+        };
+        match issuer_cert {
+            Some(issuer_cert) => cert.set_issuer_name(issuer_cert.subject_name()).unwrap(),
+            None => {
+                cert.set_issuer_name(&name).unwrap();
+                let ca = BasicConstraints::new().critical().ca().build().unwrap();
+                cert.append_extension(ca).unwrap();
+            }
+        }
+        let ski = SubjectKeyIdentifier::new()
+            .build(&cert.x509v3_context(issuer_cert.map(|c| c.as_ref()), None))
+            .unwrap();
+        cert.append_extension(ski).unwrap();
+        if let Some(issuer_cert) = issuer_cert {
+            let aki = AuthorityKeyIdentifier::new()
+                .keyid(true)
+                .build(&cert.x509v3_context(Some(issuer_cert), None))
+                .unwrap();
+            cert.append_extension(aki).unwrap();
+        }
+        if !dns_names.is_empty() {
+            let mut san = SubjectAlternativeName::new();
+            for dns_name in dns_names {
+                san.dns(dns_name);
+            }
+            let san = san
+                .build(&cert.x509v3_context(issuer_cert.map(|c| c.as_ref()), None))
+                .unwrap();
+            cert.append_extension(san).unwrap();
+        }
+        cert.sign(issuer_key, MessageDigest::sha256()).unwrap();
+        cert.build()
+    }
+
+    #[test]
+    fn csr_digest_matches_key() {
+        // AlgorithmIdentifier OIDs
+        let ecdsa_with_sha384 = [0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x03];
+        let ecdsa_with_sha512 = [0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x04];
+        for (key_type, oid) in [
+            (KeyType::P384, ecdsa_with_sha384),
+            (KeyType::P521, ecdsa_with_sha512),
+        ] {
+            let (_, csr_der) = CsrBuilder::new()
+                .dns("example.com")
+                .key_type(key_type)
+                .build()
+                .unwrap();
+            assert!(
+                csr_der.windows(oid.len()).any(|w| w == oid),
+                "{:?}",
+                key_type
+            );
+            let csr = X509Req::from_der(&csr_der).unwrap();
+            assert!(csr.verify(&csr.public_key().unwrap()).unwrap());
+        }
+    }
+
+    #[test]
+    fn chain_top_issuer_names_root() {
+        let root_key = generate_key(KeyType::P256).unwrap();
+        let root = sign_certificate(&root_key, None, &["ISRG Root X1"], 1);
+        let intermediate_key = generate_key(KeyType::P256).unwrap();
+        let intermediate =
+            sign_certificate(&intermediate_key, Some((&root, &root_key)), &["R3"], 1);
+        let leaf_key = generate_key(KeyType::P256).unwrap();
+        let leaf = sign_certificate(
+            &leaf_key,
+            Some((&intermediate, &intermediate_key)),
+            &["example.com"],
+            1,
+        );
+
+        let mut pem = String::new();
+        for cert in [&leaf, &intermediate] {
+            pem.push_str(std::str::from_utf8(&cert.to_pem().unwrap()).unwrap());
+        }
+        assert_eq!(chain_top_issuer(&pem).unwrap().unwrap(), "ISRG Root X1");
+        assert_eq!(chain_top_issuer("").unwrap_or_default(), None);
+    }
+
+    #[test]
+    fn spki_pins() {
+        use crate::transport::SpkiPins;
+
+        let root_key = generate_key(KeyType::P256).unwrap();
+        let root = sign_certificate(&root_key, None, &["Private Root"], 1);
+        let leaf_key = generate_key(KeyType::P256).unwrap();
+        let leaf = sign_certificate(&leaf_key, Some((&root, &root_key)), &["acme.test"], 1);
+        let chain = [leaf.to_der().unwrap(), root.to_der().unwrap()];
+        let chain = || chain.iter().map(Vec::as_slice);
+
+        let root_pin = spki_sha256(root.to_der().unwrap()).unwrap();
+        assert_eq!(
+            root_pin,
+            <[u8; 32]>::from(Sha256::digest(&root_key.public_key_to_der().unwrap()))
+        );
+        SpkiPins::new().check_chain(chain()).unwrap();
+        SpkiPins::new()
+            .base64(&base64::encode(root_pin))
+            .unwrap()
+            .check_chain(chain())
+            .unwrap();
+
+        let other_key = generate_key(KeyType::P256).unwrap();
+        let other = sign_certificate(&other_key, None, &["Other Root"], 1);
+        let pins = SpkiPins::new().sha256(spki_sha256(other.to_der().unwrap()).unwrap());
+        assert!(matches!(
+            pins.check_chain(chain()),
+            Err(AcmeError::InvalidCertificate(_))
+        ));
+        assert!(SpkiPins::new().base64("not a pin").is_err());
+    }
+
+    #[test]
+    fn chain_formats() {
+        let root_key = generate_key(KeyType::P256).unwrap();
+        let root = sign_certificate(&root_key, None, &["Test Root"], 1);
+        let leaf_key = generate_key(KeyType::P256).unwrap();
+        let leaf = sign_certificate(&leaf_key, Some((&root, &root_key)), &["example.com"], 1);
+        let mut pem = String::new();
+        for cert in [&leaf, &root] {
+            pem.push_str(std::str::from_utf8(&cert.to_pem().unwrap()).unwrap());
+        }
+
+        assert_eq!(leaf_der(&pem).unwrap(), leaf.to_der().unwrap());
+
+        let pkcs7 = Pkcs7::from_der(&chain_to_pkcs7(&pem).unwrap()).unwrap();
+        let certs = pkcs7.signed().unwrap().certificates().unwrap();
+        let ders: Vec<_> = certs.iter().map(|cert| cert.to_der().unwrap()).collect();
+        assert_eq!(ders, [leaf.to_der().unwrap(), root.to_der().unwrap()]);
+
+        let key_pem = String::from_utf8(leaf_key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+        let pkcs12 = chain_to_pkcs12(&pem, &key_pem, "hunter2").unwrap();
+        let parsed = Pkcs12::from_der(&pkcs12)
+            .unwrap()
+            .parse2("hunter2")
+            .unwrap();
+        assert_eq!(
+            parsed.cert.unwrap().to_der().unwrap(),
+            leaf.to_der().unwrap()
+        );
+        assert!(parsed.pkey.unwrap().public_eq(&leaf_key));
+        assert_eq!(parsed.ca.unwrap().len(), 1);
+        assert!(Pkcs12::from_der(&pkcs12).unwrap().parse2("wrong").is_err());
+        assert!(chain_to_pkcs12("", &key_pem, "hunter2").is_err());
+    }
+
+    #[test]
+    fn verify_issued_certificate() {
+        let ca_key = generate_key(KeyType::P256).unwrap();
+        let ca = sign_certificate(&ca_key, None, &[], 3650);
+        let (key_pem, csr_der) = CsrBuilder::new()
+            .dns("example.com")
+            .dns("*.example.com")
+            .build()
+            .unwrap();
+        let key = PKey::private_key_from_pem(key_pem.as_bytes()).unwrap();
+        let chain = |leaf: &X509| {
+            let mut pem = String::from_utf8(leaf.to_pem().unwrap()).unwrap();
+            pem.push_str(std::str::from_utf8(&ca.to_pem().unwrap()).unwrap());
+            pem
+        };
+        let identifiers = [
+            AcmeIdentifier::dns("example.com"),
+            AcmeIdentifier::dns("*.example.com"),
+        ];
+
+        let leaf = sign_certificate(
+            &key,
+            Some((&ca, &ca_key)),
+            &["example.com", "*.example.com"],
+            90,
+        );
+        let issued = verify_certificate(&chain(&leaf), &identifiers, &csr_der).unwrap();
+        assert_eq!(issued.identifiers, identifiers);
+        assert!(issued.not_after > Utc::now() + Duration::days(89));
+
+        // Missing identifier
+        let partial = sign_certificate(&key, Some((&ca, &ca_key)), &["example.com"], 90);
+        let err = verify_certificate(&chain(&partial), &identifiers, &csr_der).unwrap_err();
+        assert!(matches!(err, AcmeError::InvalidCertificate(_)), "{:?}", err);
+
+        // Wrong key
+        let other_key = generate_key(KeyType::P256).unwrap();
+        let wrong_key = sign_certificate(
+            &other_key,
+            Some((&ca, &ca_key)),
+            &["example.com", "*.example.com"],
+            90,
+        );
+        let err = verify_certificate(&chain(&wrong_key), &identifiers, &csr_der).unwrap_err();
+        assert!(matches!(err, AcmeError::InvalidCertificate(_)), "{:?}", err);
+
+        // Not signed by the next certificate
+        let other_ca = sign_certificate(&other_key, None, &[], 3650);
+        let mut broken = String::from_utf8(leaf.to_pem().unwrap()).unwrap();
+        broken.push_str(std::str::from_utf8(&other_ca.to_pem().unwrap()).unwrap());
+        let err = verify_certificate(&broken, &identifiers, &csr_der).unwrap_err();
+        assert!(matches!(err, AcmeError::InvalidCertificate(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn csr_signature_verifies() {
+        for key_type in [KeyType::P256, KeyType::Rsa2048, KeyType::Ed25519] {
+            let (key_pem, csr_der) = CsrBuilder::new()
+                .dns("example.com")
+                .key_type(key_type)
+                .build()
+                .unwrap();
+            let key = PKey::private_key_from_pem(key_pem.as_bytes()).unwrap();
+            let csr = X509Req::from_der(&csr_der).unwrap();
+            assert!(csr.verify(&key).unwrap(), "{:?}", key_type);
+        }
+
+        // Signed through the FFI
+        let (key_pem, csr_der) = CsrBuilder::new()
+            .dns("example.com")
+            .key_type(KeyType::Ed25519)
+            .onion_csr01_nonces("bI6/MRqV4gw=")
+            .unwrap()
+            .build()
+            .unwrap();
+        let key = PKey::private_key_from_pem(key_pem.as_bytes()).unwrap();
+        let csr = X509Req::from_der(&csr_der).unwrap();
+        assert!(csr.verify(&key).unwrap());
+    }
+}
@@ -0,0 +1,139 @@
+use rcgen::{
+    Attribute, CertificateParams, DistinguishedName, DnType, ExtendedKeyUsagePurpose, KeyPair,
+    KeyUsagePurpose, SanType, SignatureAlgorithm, PKCS_ECDSA_P256_SHA256, PKCS_ECDSA_P384_SHA384,
+    PKCS_ED25519,
+};
+use rustls_pki_types::PrivateKeyDer;
+use zeroize::Zeroizing;
+
+use crate::{AcmeError, AcmeResult};
+
+use super::{
+    CsrBuilder, ExtendedKeyUsage, KeyType, KeyUsage, PrivateKeyInput, SubjectAttribute,
+    APPLICANT_SIGNING_NONCE_OID, CA_SIGNING_NONCE_OID,
+};
+
+pub fn generate_key_and_csr(name: impl AsRef<str>) -> AcmeResult<(Zeroizing<String>, Vec<u8>)> {
+    build_csr(CsrBuilder::new().dns(name.as_ref()))
+}
+
+pub(super) fn can_generate(key_type: KeyType) -> bool {
+    signature_algorithm(key_type).is_ok()
+}
+
+pub(super) fn generate_private_key(
+    key_type: KeyType,
+) -> AcmeResult<(Zeroizing<String>, Zeroizing<Vec<u8>>)> {
+    let key = KeyPair::generate_for(signature_algorithm(key_type)?)?;
+    Ok((
+        Zeroizing::new(key.serialize_pem()),
+        Zeroizing::new(key.serialize_der()),
+    ))
+}
+
+/// Each algorithm's digest matches its key size (SHA-384 for P-384).
+fn signature_algorithm(key_type: KeyType) -> AcmeResult<&'static SignatureAlgorithm> {
+    match key_type {
+        KeyType::P256 => Ok(&PKCS_ECDSA_P256_SHA256),
+        KeyType::P384 => Ok(&PKCS_ECDSA_P384_SHA384),
+        KeyType::Ed25519 => Ok(&PKCS_ED25519),
+        KeyType::P521 | KeyType::Rsa2048 | KeyType::Rsa3072 | KeyType::Rsa4096 => {
+            Err(AcmeError::CryptoError(anyhow::anyhow!(
+                "{:?} key generation is not supported by the rcgen backend",
+                key_type
+            )))
+        }
+    }
+}
+
+pub(super) fn build_csr(builder: CsrBuilder) -> AcmeResult<(Zeroizing<String>, Vec<u8>)> {
+    let key = match &builder.key {
+        Some(PrivateKeyInput::Pem(pem)) => {
+            let pem = std::str::from_utf8(pem).map_err(|err| AcmeError::CryptoError(err.into()))?;
+            KeyPair::from_pem(pem)?
+        }
+        Some(PrivateKeyInput::Der(der)) => {
+            let der = PrivateKeyDer::try_from(der.as_slice())
+                .map_err(|err| AcmeError::CryptoError(anyhow::anyhow!(err)))?;
+            KeyPair::try_from(&der)?
+        }
+        None => KeyPair::generate_for(signature_algorithm(builder.key_type)?)?,
+    };
+
+    let mut params = CertificateParams::default();
+    params.distinguished_name = DistinguishedName::new();
+    for (attribute, value) in &builder.subject {
+        let dn_type = match attribute {
+            SubjectAttribute::CommonName => DnType::CommonName,
+            SubjectAttribute::Country => DnType::CountryName,
+            SubjectAttribute::StateOrProvince => DnType::StateOrProvinceName,
+            SubjectAttribute::Locality => DnType::LocalityName,
+            SubjectAttribute::Organization => DnType::OrganizationName,
+            SubjectAttribute::OrganizationalUnit => DnType::OrganizationalUnitName,
+        };
+        params.distinguished_name.push(dn_type, value.as_str());
+    }
+    params.key_usages = builder
+        .key_usage
+        .iter()
+        .map(|usage| match usage {
+            KeyUsage::DigitalSignature => KeyUsagePurpose::DigitalSignature,
+            KeyUsage::KeyEncipherment => KeyUsagePurpose::KeyEncipherment,
+            KeyUsage::KeyAgreement => KeyUsagePurpose::KeyAgreement,
+        })
+        .collect();
+    params.extended_key_usages = builder
+        .extended_key_usage
+        .iter()
+        .map(|usage| match usage {
+            ExtendedKeyUsage::ServerAuth => ExtendedKeyUsagePurpose::ServerAuth,
+            ExtendedKeyUsage::ClientAuth => ExtendedKeyUsagePurpose::ClientAuth,
+        })
+        .collect();
+    for name in &builder.dns_names {
+        params
+            .subject_alt_names
+            .push(SanType::DnsName(name.clone().try_into()?));
+    }
+    for addr in &builder.ip_addrs {
+        params.subject_alt_names.push(SanType::IpAddress(*addr));
+    }
+    let mut attrs = Vec::new();
+    if let Some(nonces) = &builder.signing_nonces {
+        attrs.push(Attribute {
+            oid: CA_SIGNING_NONCE_OID,
+            values: octet_string_set(&nonces.ca),
+        });
+        attrs.push(Attribute {
+            oid: APPLICANT_SIGNING_NONCE_OID,
+            values: octet_string_set(&nonces.applicant),
+        });
+    }
+    let csr = params.serialize_request_with_attributes(&key, attrs)?;
+
+    Ok((Zeroizing::new(key.serialize_pem()), csr.der().to_vec()))
+}
+
+/// DER `SET { OCTET STRING value }`, as an attribute's values.
+fn octet_string_set(value: &[u8]) -> Vec<u8> {
+    fn tag_length_value(tag: u8, value: &[u8]) -> Vec<u8> {
+        let mut der = vec![tag];
+        if value.len() < 0x80 {
+            der.push(value.len() as u8);
+        } else {
+            let len = value.len().to_be_bytes();
+            let skip = len.iter().take_while(|&&b| b == 0).count();
+            der.push(0x80 | (len.len() - skip) as u8);
+            der.extend_from_slice(&len[skip..]);
+        }
+        der.extend_from_slice(value);
+        der
+    }
+    tag_length_value(0x31, &tag_length_value(0x04, value))
+}
+
+impl From<rcgen::Error> for AcmeError {
+    fn from(err: rcgen::Error) -> Self {
+        AcmeError::CryptoError(err.into())
+    }
+}